@@ -0,0 +1,262 @@
+mod output;
+
+#[cfg(test)]
+mod tests;
+
+use crate::export::export_flows;
+use crate::flow::filter::{DisplayFilter, FlowFilter};
+use crate::flow::{Conversation, Flow, IPAddress};
+use crate::layers::dissector::DissectorRegistry;
+use crate::parser::{ParseOptions, parse_captures};
+use crate::parser::state::ParseState;
+use anyhow::anyhow;
+use clap::ValueEnum;
+pub use output::{CaptureStats, DnsQueryRecord, FlowRecord, HttpRecord, ProtocolStats};
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+// Exit codes are part of the scripting interface; keep them stable. 1 is left for
+// unexpected failures such as the logger failing to start.
+
+/// The command succeeded.
+pub const EXIT_SUCCESS: u8 = 0;
+/// The command line was invalid. This is also what clap exits with.
+pub const EXIT_USAGE: u8 = 2;
+/// The filter expression did not compile.
+pub const EXIT_INVALID_FILTER: u8 = 3;
+/// The capture could not be opened or parsed.
+pub const EXIT_CAPTURE_ERROR: u8 = 4;
+/// Results could not be written (stdout closed, export path not writable, ...).
+pub const EXIT_OUTPUT_ERROR: u8 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OutputFormat {
+    /// Aligned columns for people.
+    #[default]
+    Table,
+    /// RFC 4180 CSV with a header row.
+    Csv,
+    /// A single JSON document.
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CliErrorKind {
+    InvalidFilter,
+    Capture,
+    Output,
+}
+
+#[derive(Debug)]
+pub struct CliError {
+    kind: CliErrorKind,
+    error: anyhow::Error,
+}
+
+impl CliError {
+    fn new(kind: CliErrorKind, error: impl Into<anyhow::Error>) -> Self {
+        Self {
+            kind,
+            error: error.into(),
+        }
+    }
+
+    fn output(error: impl Into<anyhow::Error>) -> Self {
+        Self::new(CliErrorKind::Output, error)
+    }
+
+    pub fn kind(&self) -> CliErrorKind {
+        self.kind
+    }
+
+    pub fn exit_code(&self) -> u8 {
+        match self.kind {
+            CliErrorKind::InvalidFilter => EXIT_INVALID_FILTER,
+            CliErrorKind::Capture => EXIT_CAPTURE_ERROR,
+            CliErrorKind::Output => EXIT_OUTPUT_ERROR,
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#}", self.error)
+    }
+}
+
+impl std::error::Error for CliError {}
+
+/// Lists the flows matching `filter`, oldest first.
+pub fn run_flows(
+    paths: &[PathBuf],
+    registry: &DissectorRegistry,
+    filter: Option<&str>,
+    format: OutputFormat,
+    out: &mut impl Write,
+) -> Result<(), CliError> {
+    let filter = compile_filter(filter)?;
+    let state = load(paths, registry)?;
+    let flows = matching_flows(&state, filter);
+    let records: Vec<FlowRecord> = flows
+        .iter()
+        .map(|flow| FlowRecord::new(flow, state.first_packet_ts))
+        .collect();
+    info!(paths = ?paths, flows = records.len(), "Listing flows");
+    output::write_flows(out, &records, format)
+        .and_then(|()| out.flush())
+        .map_err(CliError::output)
+}
+
+/// Lists the HTTP transactions of the flows matching `filter`, in request order.
+pub fn run_http(
+    paths: &[PathBuf],
+    registry: &DissectorRegistry,
+    filter: Option<&str>,
+    format: OutputFormat,
+    out: &mut impl Write,
+) -> Result<(), CliError> {
+    let filter = compile_filter(filter)?;
+    let state = load(paths, registry)?;
+    let flows = matching_flows(&state, filter);
+    let mut records: Vec<HttpRecord> = flows
+        .iter()
+        .flat_map(|flow| {
+            flow.http
+                .iter()
+                .map(|transaction| HttpRecord::new(flow, transaction, state.first_packet_ts))
+        })
+        .collect();
+    records.sort_by(|a, b| a.start.total_cmp(&b.start));
+    info!(paths = ?paths, transactions = records.len(), "Listing HTTP transactions");
+    output::write_http(out, &records, format)
+        .and_then(|()| out.flush())
+        .map_err(CliError::output)
+}
+
+/// Lists the DNS queries and responses of the flows matching `filter`, in query order.
+pub fn run_dns(
+    paths: &[PathBuf],
+    registry: &DissectorRegistry,
+    filter: Option<&str>,
+    format: OutputFormat,
+    out: &mut impl Write,
+) -> Result<(), CliError> {
+    let filter = compile_filter(filter)?;
+    let state = load(paths, registry)?;
+    let flows = matching_flows(&state, filter);
+    let mut records: Vec<DnsQueryRecord> = flows
+        .iter()
+        .flat_map(|flow| {
+            flow.dns
+                .iter()
+                .map(|transaction| DnsQueryRecord::new(flow, transaction, state.first_packet_ts))
+        })
+        .collect();
+    records.sort_by(|a, b| a.start.total_cmp(&b.start));
+    info!(paths = ?paths, transactions = records.len(), "Listing DNS transactions");
+    output::write_dns(out, &records, format)
+        .and_then(|()| out.flush())
+        .map_err(CliError::output)
+}
+
+/// Prints summary statistics for the whole capture.
+pub fn run_stats(
+    paths: &[PathBuf],
+    registry: &DissectorRegistry,
+    format: OutputFormat,
+    out: &mut impl Write,
+) -> Result<(), CliError> {
+    let state = load(paths, registry)?;
+    let stats = CaptureStats::new(paths, &state);
+    output::write_stats(out, &stats, format)
+        .and_then(|()| out.flush())
+        .map_err(CliError::output)
+}
+
+/// Writes the packets of the flows matching `filter` to `destination` as pcapng.
+pub fn run_export(
+    paths: &[PathBuf],
+    registry: &DissectorRegistry,
+    filter: Option<&str>,
+    destination: &Path,
+    out: &mut impl Write,
+) -> Result<(), CliError> {
+    let filter = compile_filter(filter)?;
+    let state = load(paths, registry)?;
+    let flows = matching_flows(&state, filter);
+    let summary = export_flows(
+        destination,
+        flows.iter().copied(),
+        &state.interfaces,
+        &state.name_resolutions,
+    )
+    .map_err(CliError::output)?;
+    writeln!(
+        out,
+        "Exported {} packets from {} flows to {}",
+        summary.packets,
+        flows.len(),
+        destination.display()
+    )
+    .and_then(|()| out.flush())
+    .map_err(CliError::output)
+}
+
+/// Parses the captures at `paths`, merged into one when there are several.
+fn load(paths: &[PathBuf], registry: &DissectorRegistry) -> Result<ParseState, CliError> {
+    parse_captures(paths, registry, ParseOptions::default(), |_| {}).map_err(|error| {
+        let names: Vec<String> = paths.iter().map(|path| path.display().to_string()).collect();
+        CliError::new(
+            CliErrorKind::Capture,
+            error.context(format!("Failed to read {}", names.join(", "))),
+        )
+    })
+}
+
+/// Compiles the `--filter` expression, so a mistake in it is reported before a long
+/// capture is parsed.
+fn compile_filter(filter: Option<&str>) -> Result<DisplayFilter, CliError> {
+    DisplayFilter::compile(filter.unwrap_or_default()).map_err(|error| {
+        CliError::new(
+            CliErrorKind::InvalidFilter,
+            anyhow!("Invalid filter: {error}"),
+        )
+    })
+}
+
+/// Flows accepted by `filter` in a stable order: by start time, then by endpoints,
+/// protocol and conversation.
+fn matching_flows(state: &ParseState, filter: DisplayFilter) -> Vec<&Flow> {
+    let filter = FlowFilter::compiled(
+        filter,
+        state.first_packet_ts,
+        false,
+        Some(&state.name_resolutions),
+    )
+    .with_sources(&state.sources);
+
+    let mut flows: Vec<&Flow> = state
+        .flows
+        .values()
+        .filter(|flow| filter.matches_flow(flow))
+        .collect();
+    flows.sort_by(|a, b| {
+        a.timestamp
+            .total_cmp(&b.timestamp)
+            .then_with(|| sort_key(a).cmp(&sort_key(b)))
+    });
+    flows
+}
+
+fn sort_key(flow: &Flow) -> (IPAddress, u16, IPAddress, u16, Option<u8>, Conversation) {
+    (
+        flow.source.ip,
+        flow.source.port,
+        flow.destination.ip,
+        flow.destination.port,
+        flow.protocol.number(),
+        flow.conversation,
+    )
+}
@@ -0,0 +1,526 @@
+use super::OutputFormat;
+use crate::flow::dns::DnsTransaction;
+use crate::flow::filter::FlowFormatter;
+use crate::flow::http::HttpTransaction;
+use crate::flow::{Flow, Protocol};
+use crate::parser::dns::format_record;
+use crate::parser::state::ParseState;
+use serde::Serialize;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// One row of `wirecrab flows`. Field names are the JSON keys and CSV header.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FlowRecord {
+    /// Start time in seconds since the Unix epoch.
+    pub start: f64,
+    /// Start time in seconds since the first packet of the capture.
+    pub relative_start: f64,
+    pub duration: f64,
+    pub protocol: String,
+    pub src_ip: String,
+    pub src_port: u16,
+    pub dst_ip: String,
+    pub dst_port: u16,
+    pub packets: usize,
+    pub bytes: usize,
+    pub issues: usize,
+    /// What tells apart flows without ports, e.g. `echo ID 7`; empty for TCP and UDP.
+    pub conversation: String,
+}
+
+/// One row of `wirecrab http`: a request and its response. Fields of a side missing
+/// from the capture are empty.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HttpRecord {
+    /// Time of the request (or lone response) in seconds since the Unix epoch.
+    pub start: f64,
+    /// Same, in seconds since the first packet of the capture.
+    pub relative_start: f64,
+    pub src_ip: String,
+    pub src_port: u16,
+    pub dst_ip: String,
+    pub dst_port: u16,
+    pub method: Option<String>,
+    pub host: Option<String>,
+    pub uri: Option<String>,
+    pub status: Option<u16>,
+    pub reason: Option<String>,
+    pub content_type: Option<String>,
+    pub request_bytes: Option<usize>,
+    pub response_bytes: Option<usize>,
+    /// Seconds from the request headers to the response headers.
+    pub response_time: Option<f64>,
+}
+
+/// One row of `wirecrab dns`: a query and its response. Response fields are empty
+/// for unanswered queries.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DnsQueryRecord {
+    /// Time of the query (or lone response) in seconds since the Unix epoch.
+    pub start: f64,
+    /// Same, in seconds since the first packet of the capture.
+    pub relative_start: f64,
+    pub src_ip: String,
+    pub src_port: u16,
+    pub dst_ip: String,
+    pub dst_port: u16,
+    pub id: u16,
+    pub name: String,
+    pub qtype: String,
+    pub rcode: Option<String>,
+    /// Answer records, e.g. `example.com A 93.184.216.34`.
+    pub answers: Vec<String>,
+    /// Seconds from the query to its response.
+    pub latency: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProtocolStats {
+    pub protocol: String,
+    pub flows: usize,
+    pub packets: usize,
+    pub bytes: usize,
+}
+
+/// Output of `wirecrab stats`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CaptureStats {
+    pub file: String,
+    pub packets: usize,
+    pub flows: usize,
+    pub bytes: usize,
+    pub first_packet: Option<f64>,
+    pub last_packet: Option<f64>,
+    pub duration: f64,
+    pub interfaces: usize,
+    pub skipped_packets: usize,
+    pub name_resolutions: usize,
+    pub tcp_issues: usize,
+    pub protocols: Vec<ProtocolStats>,
+}
+
+impl FlowRecord {
+    pub fn new(flow: &Flow, capture_start: Option<f64>) -> Self {
+        let last = flow
+            .packets
+            .iter()
+            .map(|packet| packet.timestamp)
+            .fold(flow.timestamp, f64::max);
+        Self {
+            start: flow.timestamp,
+            relative_start: flow.timestamp - capture_start.unwrap_or(flow.timestamp),
+            duration: last - flow.timestamp,
+            protocol: FlowFormatter::protocol(&flow.protocol),
+            src_ip: flow.source.ip.to_string(),
+            src_port: flow.source.port,
+            dst_ip: flow.destination.ip.to_string(),
+            dst_port: flow.destination.port,
+            packets: flow.packets.len(),
+            bytes: flow.total_bytes(),
+            issues: flow.analysis.issues(),
+            conversation: flow.conversation.to_string(),
+        }
+    }
+
+    const HEADER: [&'static str; 12] = [
+        "start",
+        "relative_start",
+        "duration",
+        "protocol",
+        "src_ip",
+        "src_port",
+        "dst_ip",
+        "dst_port",
+        "packets",
+        "bytes",
+        "issues",
+        "conversation",
+    ];
+
+    fn fields(&self) -> [String; 12] {
+        [
+            format!("{:.6}", self.start),
+            format!("{:.6}", self.relative_start),
+            format!("{:.6}", self.duration),
+            self.protocol.clone(),
+            self.src_ip.clone(),
+            self.src_port.to_string(),
+            self.dst_ip.clone(),
+            self.dst_port.to_string(),
+            self.packets.to_string(),
+            self.bytes.to_string(),
+            self.issues.to_string(),
+            self.conversation.clone(),
+        ]
+    }
+}
+
+impl HttpRecord {
+    pub fn new(flow: &Flow, transaction: &HttpTransaction, capture_start: Option<f64>) -> Self {
+        let request = transaction.request.as_ref();
+        let response = transaction.response.as_ref();
+        let start = transaction.timestamp();
+        Self {
+            start,
+            relative_start: start - capture_start.unwrap_or(start),
+            src_ip: flow.source.ip.to_string(),
+            src_port: flow.source.port,
+            dst_ip: flow.destination.ip.to_string(),
+            dst_port: flow.destination.port,
+            method: request.map(|request| request.method.clone()),
+            host: request.and_then(|request| request.host.clone()),
+            uri: request.map(|request| request.uri.clone()),
+            status: response.map(|response| response.status),
+            reason: response.map(|response| response.reason.clone()),
+            content_type: response
+                .and_then(|response| response.content_type.clone())
+                .or_else(|| request.and_then(|request| request.content_type.clone())),
+            request_bytes: request.map(|request| request.body_length),
+            response_bytes: response.map(|response| response.body_length),
+            response_time: transaction.response_time(),
+        }
+    }
+
+    const HEADER: [&'static str; 15] = [
+        "start",
+        "relative_start",
+        "src_ip",
+        "src_port",
+        "dst_ip",
+        "dst_port",
+        "method",
+        "host",
+        "uri",
+        "status",
+        "reason",
+        "content_type",
+        "request_bytes",
+        "response_bytes",
+        "response_time",
+    ];
+
+    fn fields(&self) -> [String; 15] {
+        let text = |value: &Option<String>| value.clone().unwrap_or_default();
+        let number = |value: Option<usize>| value.map(|v| v.to_string()).unwrap_or_default();
+        [
+            format!("{:.6}", self.start),
+            format!("{:.6}", self.relative_start),
+            self.src_ip.clone(),
+            self.src_port.to_string(),
+            self.dst_ip.clone(),
+            self.dst_port.to_string(),
+            text(&self.method),
+            text(&self.host),
+            text(&self.uri),
+            self.status.map(|v| v.to_string()).unwrap_or_default(),
+            text(&self.reason),
+            text(&self.content_type),
+            number(self.request_bytes),
+            number(self.response_bytes),
+            self.response_time
+                .map(|v| format!("{v:.6}"))
+                .unwrap_or_default(),
+        ]
+    }
+}
+
+impl DnsQueryRecord {
+    pub fn new(flow: &Flow, transaction: &DnsTransaction, capture_start: Option<f64>) -> Self {
+        let start = transaction.timestamp();
+        Self {
+            start,
+            relative_start: start - capture_start.unwrap_or(start),
+            src_ip: flow.source.ip.to_string(),
+            src_port: flow.source.port,
+            dst_ip: flow.destination.ip.to_string(),
+            dst_port: flow.destination.port,
+            id: transaction.id,
+            name: transaction.name.clone(),
+            qtype: transaction.query_type_name(),
+            rcode: transaction.rcode_name(),
+            answers: transaction
+                .response
+                .iter()
+                .flat_map(|response| response.answers.iter().map(format_record))
+                .collect(),
+            latency: transaction.latency(),
+        }
+    }
+
+    const HEADER: [&'static str; 12] = [
+        "start",
+        "relative_start",
+        "src_ip",
+        "src_port",
+        "dst_ip",
+        "dst_port",
+        "id",
+        "name",
+        "qtype",
+        "rcode",
+        "answers",
+        "latency",
+    ];
+
+    fn fields(&self) -> [String; 12] {
+        [
+            format!("{:.6}", self.start),
+            format!("{:.6}", self.relative_start),
+            self.src_ip.clone(),
+            self.src_port.to_string(),
+            self.dst_ip.clone(),
+            self.dst_port.to_string(),
+            format!("0x{:04x}", self.id),
+            self.name.clone(),
+            self.qtype.clone(),
+            self.rcode.clone().unwrap_or_default(),
+            self.answers.join("; "),
+            self.latency.map(|v| format!("{v:.6}")).unwrap_or_default(),
+        ]
+    }
+}
+
+impl CaptureStats {
+    pub fn new(paths: &[PathBuf], state: &ParseState) -> Self {
+        let timestamps = state
+            .flows
+            .values()
+            .flat_map(|flow| flow.packets.iter().map(|packet| packet.timestamp));
+        let last_packet = timestamps.reduce(f64::max);
+        let first_packet = state.first_packet_ts;
+
+        let mut protocols: Vec<ProtocolStats> = Vec::new();
+        let mut flows: Vec<&Flow> = state.flows.values().collect();
+        flows.sort_by_key(|flow| protocol_order(flow.protocol));
+        for flow in flows {
+            let protocol = FlowFormatter::protocol(&flow.protocol);
+            if protocols
+                .last()
+                .is_none_or(|stats| stats.protocol != protocol)
+            {
+                protocols.push(ProtocolStats {
+                    protocol,
+                    flows: 0,
+                    packets: 0,
+                    bytes: 0,
+                });
+            }
+            let stats = protocols.last_mut().expect("pushed above");
+            stats.flows += 1;
+            stats.packets += flow.packets.len();
+            stats.bytes += flow.total_bytes();
+        }
+
+        Self {
+            file: paths
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+            packets: state.packet_count,
+            flows: state.flows.len(),
+            bytes: protocols.iter().map(|stats| stats.bytes).sum(),
+            first_packet,
+            last_packet,
+            duration: match (first_packet, last_packet) {
+                (Some(first), Some(last)) => last - first,
+                _ => 0.0,
+            },
+            interfaces: state.interfaces.len(),
+            skipped_packets: state.skipped_packet_count(),
+            name_resolutions: state.name_resolutions.len(),
+            tcp_issues: state
+                .flows
+                .values()
+                .map(|flow| flow.analysis.issues())
+                .sum(),
+            protocols,
+        }
+    }
+
+    /// Flattened `(metric, value)` pairs used by the table and CSV formats.
+    fn metrics(&self) -> Vec<(String, String)> {
+        let optional = |value: Option<f64>| value.map(|v| format!("{v:.6}")).unwrap_or_default();
+        let mut metrics = vec![
+            ("file".to_string(), self.file.clone()),
+            ("packets".to_string(), self.packets.to_string()),
+            ("flows".to_string(), self.flows.to_string()),
+            ("bytes".to_string(), self.bytes.to_string()),
+            ("first_packet".to_string(), optional(self.first_packet)),
+            ("last_packet".to_string(), optional(self.last_packet)),
+            ("duration".to_string(), format!("{:.6}", self.duration)),
+            ("interfaces".to_string(), self.interfaces.to_string()),
+            (
+                "skipped_packets".to_string(),
+                self.skipped_packets.to_string(),
+            ),
+            (
+                "name_resolutions".to_string(),
+                self.name_resolutions.to_string(),
+            ),
+            ("tcp_issues".to_string(), self.tcp_issues.to_string()),
+        ];
+        for stats in &self.protocols {
+            let name = stats.protocol.to_lowercase();
+            metrics.push((format!("{name}.flows"), stats.flows.to_string()));
+            metrics.push((format!("{name}.packets"), stats.packets.to_string()));
+            metrics.push((format!("{name}.bytes"), stats.bytes.to_string()));
+        }
+        metrics
+    }
+}
+
+fn protocol_order(protocol: Protocol) -> (u8, u8) {
+    match protocol {
+        Protocol::TCP => (0, 0),
+        Protocol::UDP => (1, 0),
+        Protocol::Other(number) => (2, number),
+        Protocol::ARP => (3, 0),
+    }
+}
+
+pub fn write_flows(
+    out: &mut impl Write,
+    records: &[FlowRecord],
+    format: OutputFormat,
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Json => write_json(out, &records),
+        OutputFormat::Csv => {
+            write_csv_row(out, FlowRecord::HEADER)?;
+            for record in records {
+                write_csv_row(out, record.fields())?;
+            }
+            Ok(())
+        }
+        OutputFormat::Table => {
+            let rows: Vec<[String; 12]> = records.iter().map(FlowRecord::fields).collect();
+            write_table(out, &FlowRecord::HEADER, &rows)
+        }
+    }
+}
+
+pub fn write_http(
+    out: &mut impl Write,
+    records: &[HttpRecord],
+    format: OutputFormat,
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Json => write_json(out, &records),
+        OutputFormat::Csv => {
+            write_csv_row(out, HttpRecord::HEADER)?;
+            for record in records {
+                write_csv_row(out, record.fields())?;
+            }
+            Ok(())
+        }
+        OutputFormat::Table => {
+            let rows: Vec<[String; 15]> = records.iter().map(HttpRecord::fields).collect();
+            write_table(out, &HttpRecord::HEADER, &rows)
+        }
+    }
+}
+
+pub fn write_dns(
+    out: &mut impl Write,
+    records: &[DnsQueryRecord],
+    format: OutputFormat,
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Json => write_json(out, &records),
+        OutputFormat::Csv => {
+            write_csv_row(out, DnsQueryRecord::HEADER)?;
+            for record in records {
+                write_csv_row(out, record.fields())?;
+            }
+            Ok(())
+        }
+        OutputFormat::Table => {
+            let rows: Vec<[String; 12]> = records.iter().map(DnsQueryRecord::fields).collect();
+            write_table(out, &DnsQueryRecord::HEADER, &rows)
+        }
+    }
+}
+
+pub fn write_stats(
+    out: &mut impl Write,
+    stats: &CaptureStats,
+    format: OutputFormat,
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Json => write_json(out, stats),
+        OutputFormat::Csv => {
+            write_csv_row(out, ["metric", "value"])?;
+            for (metric, value) in stats.metrics() {
+                write_csv_row(out, [metric, value])?;
+            }
+            Ok(())
+        }
+        OutputFormat::Table => {
+            let metrics = stats.metrics();
+            let width = metrics
+                .iter()
+                .map(|(metric, _)| metric.len())
+                .max()
+                .unwrap_or(0);
+            for (metric, value) in metrics {
+                writeln!(out, "{metric:<width$}  {value}")?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn write_json(out: &mut impl Write, value: &impl Serialize) -> io::Result<()> {
+    serde_json::to_writer_pretty(&mut *out, value)?;
+    writeln!(out)
+}
+
+fn write_csv_row<const N: usize>(
+    out: &mut impl Write,
+    fields: [impl AsRef<str>; N],
+) -> io::Result<()> {
+    let row: Vec<String> = fields
+        .iter()
+        .map(|field| csv_escape(field.as_ref()))
+        .collect();
+    writeln!(out, "{}", row.join(","))
+}
+
+fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn write_table<const N: usize>(
+    out: &mut impl Write,
+    header: &[&str; N],
+    rows: &[[String; N]],
+) -> io::Result<()> {
+    let mut widths = header.map(str::len);
+    for row in rows {
+        for (width, field) in widths.iter_mut().zip(row) {
+            *width = (*width).max(field.chars().count());
+        }
+    }
+
+    let line = |fields: Vec<&str>| {
+        fields
+            .iter()
+            .zip(widths)
+            .map(|(field, width)| format!("{field:<width$}"))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+    writeln!(out, "{}", line(header.to_vec()))?;
+    for row in rows {
+        writeln!(out, "{}", line(row.iter().map(String::as_str).collect()))?;
+    }
+    Ok(())
+}
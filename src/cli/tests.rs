@@ -0,0 +1,408 @@
+use super::*;
+use crate::export::write_pcapng;
+use crate::flow::{IPAddress, Packet, Protocol};
+use crate::parser::parse_capture;
+use crate::parser::state::CaptureInterface;
+use etherparse::PacketBuilder;
+use pcap_parser::Linktype;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::slice;
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("wirecrab_cli_{}_{name}", std::process::id()))
+}
+
+fn packet(seconds: u64, src: [u8; 4], dst: [u8; 4], tcp: bool) -> Packet {
+    let builder =
+        PacketBuilder::ethernet2([1, 2, 3, 4, 5, 6], [6, 5, 4, 3, 2, 1]).ipv4(src, dst, 64);
+    let mut data = Vec::new();
+    if tcp {
+        let builder = builder.tcp(40000, 443, 1, 1024);
+        builder.write(&mut data, b"hello").unwrap();
+    } else {
+        let builder = builder.udp(5353, 53);
+        builder.write(&mut data, b"hi").unwrap();
+    }
+    Packet {
+        timestamp: seconds as f64,
+        src_ip: IPAddress::V4(src),
+        dst_ip: IPAddress::V4(dst),
+        length: data.len() as u16,
+        original_length: data.len() as u32,
+        data: data.into(),
+        timestamp_ticks: seconds * 1_000_000,
+        ..Default::default()
+    }
+}
+
+/// Writes a capture with one UDP flow at t=1 and one TCP flow of two packets at t=2.
+fn write_capture(name: &str) -> PathBuf {
+    let interfaces = [CaptureInterface {
+        linktype: Linktype::ETHERNET,
+        snaplen: 65535,
+        ts_resolution: 6,
+        ts_offset: 0,
+        name: None,
+        description: None,
+    }];
+    let packets = [
+        packet(2, [10, 0, 0, 1], [192, 0, 2, 1], true),
+        packet(1, [10, 0, 0, 2], [192, 0, 2, 53], false),
+        packet(3, [10, 0, 0, 1], [192, 0, 2, 1], true),
+    ];
+    let mut capture = Vec::new();
+    write_pcapng(&mut capture, &packets, &interfaces, &HashMap::new()).unwrap();
+    let path = temp_path(name);
+    std::fs::write(&path, capture).unwrap();
+    path
+}
+
+fn registry() -> DissectorRegistry {
+    DissectorRegistry::default()
+}
+
+fn run(command: impl FnOnce(&mut Vec<u8>) -> Result<(), CliError>) -> String {
+    let mut out = Vec::new();
+    command(&mut out).expect("command succeeds");
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn flows_csv_lists_matching_flows_oldest_first() {
+    let path = write_capture("flows.pcapng");
+    let all = run(|out| {
+        run_flows(
+            slice::from_ref(&path),
+            &registry(),
+            None,
+            OutputFormat::Csv,
+            out,
+        )
+    });
+    let tcp = run(|out| {
+        run_flows(
+            slice::from_ref(&path),
+            &registry(),
+            Some("tcp.port == 443"),
+            OutputFormat::Csv,
+            out,
+        )
+    });
+    let _ = std::fs::remove_file(&path);
+
+    let lines: Vec<&str> = all.lines().collect();
+    assert_eq!(
+        lines[0],
+        "start,relative_start,duration,protocol,src_ip,src_port,dst_ip,dst_port,packets,bytes,issues,conversation"
+    );
+    assert_eq!(lines.len(), 3);
+    assert!(lines[1].starts_with("1.000000,0.000000,0.000000,UDP,10.0.0.2,5353,"));
+    assert!(lines[2].starts_with("2.000000,1.000000,1.000000,TCP,10.0.0.1,40000,"));
+    assert_eq!(tcp.lines().count(), 2);
+}
+
+#[test]
+fn flows_and_stats_json_are_machine_readable() {
+    let path = write_capture("json.pcapng");
+    let flows = run(|out| {
+        run_flows(
+            slice::from_ref(&path),
+            &registry(),
+            None,
+            OutputFormat::Json,
+            out,
+        )
+    });
+    let stats = run(|out| run_stats(slice::from_ref(&path), &registry(), OutputFormat::Json, out));
+    let _ = std::fs::remove_file(&path);
+
+    let flows: serde_json::Value = serde_json::from_str(&flows).unwrap();
+    assert_eq!(flows.as_array().map(Vec::len), Some(2));
+    assert_eq!(flows[1]["protocol"], "TCP");
+    assert_eq!(flows[1]["packets"], 2);
+
+    let stats: serde_json::Value = serde_json::from_str(&stats).unwrap();
+    assert_eq!(stats["packets"], 3);
+    assert_eq!(stats["flows"], 2);
+    assert_eq!(stats["duration"], 2.0);
+    assert_eq!(stats["protocols"][0]["protocol"], "TCP");
+    assert_eq!(stats["protocols"][1]["flows"], 1);
+}
+
+#[test]
+fn errors_map_to_stable_exit_codes() {
+    let path = write_capture("errors.pcapng");
+    let mut out = Vec::new();
+    let invalid = run_flows(
+        slice::from_ref(&path),
+        &registry(),
+        Some("tcp.port =="),
+        OutputFormat::Table,
+        &mut out,
+    )
+    .expect_err("incomplete filter");
+    // The filter is checked before the capture is read.
+    let invalid_before_capture = run_export(
+        &[temp_path("missing.pcapng")],
+        &registry(),
+        Some("tcp.port =="),
+        &temp_path("unused.pcapng"),
+        &mut out,
+    )
+    .expect_err("incomplete filter");
+    let missing = run_stats(
+        &[temp_path("missing.pcapng")],
+        &registry(),
+        OutputFormat::Table,
+        &mut out,
+    )
+    .expect_err("missing capture");
+    let unwritable = run_export(
+        slice::from_ref(&path),
+        &registry(),
+        None,
+        &temp_path("missing/out.pcapng"),
+        &mut out,
+    )
+    .expect_err("missing directory");
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(invalid.exit_code(), EXIT_INVALID_FILTER);
+    assert!(invalid.to_string().starts_with("Invalid filter: "));
+    assert_eq!(invalid_before_capture.exit_code(), EXIT_INVALID_FILTER);
+    assert_eq!(missing.exit_code(), EXIT_CAPTURE_ERROR);
+    assert_eq!(unwritable.exit_code(), EXIT_OUTPUT_ERROR);
+    assert!(out.is_empty());
+}
+
+#[test]
+fn export_writes_only_matching_flows() {
+    let path = write_capture("source.pcapng");
+    let destination = temp_path("exported.pcapng");
+    let message = run(|out| {
+        run_export(
+            slice::from_ref(&path),
+            &registry(),
+            Some("udp"),
+            &destination,
+            out,
+        )
+    });
+    let exported = parse_capture(&destination, |_| {}).expect("parse export");
+    let _ = std::fs::remove_file(&path);
+    let _ = std::fs::remove_file(&destination);
+
+    assert!(message.starts_with("Exported 1 packets from 1 flows to "));
+    assert_eq!(exported.packet_count, 1);
+    assert_eq!(
+        exported.flows.values().next().unwrap().protocol,
+        Protocol::UDP
+    );
+}
+
+fn http_packet(seconds: u64, from_client: bool, seq: u32, payload: &[u8]) -> Packet {
+    let (src, dst, src_port, dst_port) = if from_client {
+        ([10, 0, 0, 1], [192, 0, 2, 80], 40000, 80)
+    } else {
+        ([192, 0, 2, 80], [10, 0, 0, 1], 80, 40000)
+    };
+    let builder = PacketBuilder::ethernet2([1, 2, 3, 4, 5, 6], [6, 5, 4, 3, 2, 1])
+        .ipv4(src, dst, 64)
+        .tcp(src_port, dst_port, seq, 1024)
+        .ack(1);
+    let mut data = Vec::new();
+    builder.write(&mut data, payload).unwrap();
+    Packet {
+        timestamp: seconds as f64,
+        src_ip: IPAddress::V4(src),
+        dst_ip: IPAddress::V4(dst),
+        length: data.len() as u16,
+        original_length: data.len() as u32,
+        data: data.into(),
+        timestamp_ticks: seconds * 1_000_000,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn http_csv_lists_transactions() {
+    let interfaces = [CaptureInterface {
+        linktype: Linktype::ETHERNET,
+        snaplen: 65535,
+        ts_resolution: 6,
+        ts_offset: 0,
+        name: None,
+        description: None,
+    }];
+    let packets = [
+        http_packet(
+            1,
+            true,
+            1,
+            b"GET /a?x=1,2 HTTP/1.1\r\nHost: example.com\r\n\r\n",
+        ),
+        http_packet(
+            3,
+            false,
+            1,
+            b"HTTP/1.1 404 Not Found\r\nContent-Type: text/plain\r\nContent-Length: 2\r\n\r\nno",
+        ),
+    ];
+    let mut capture = Vec::new();
+    write_pcapng(&mut capture, &packets, &interfaces, &HashMap::new()).unwrap();
+    let path = temp_path("http.pcapng");
+    std::fs::write(&path, capture).unwrap();
+    let csv = run(|out| {
+        run_http(
+            slice::from_ref(&path),
+            &registry(),
+            None,
+            OutputFormat::Csv,
+            out,
+        )
+    });
+    let _ = std::fs::remove_file(&path);
+
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "start,relative_start,src_ip,src_port,dst_ip,dst_port,method,host,uri,status,reason,content_type,request_bytes,response_bytes,response_time",
+            "1.000000,0.000000,10.0.0.1,40000,192.0.2.80,80,GET,example.com,\"/a?x=1,2\",404,Not Found,text/plain,0,2,2.000000",
+        ]
+    );
+}
+
+fn dns_packet(seconds: u64, from_client: bool, payload: &[u8]) -> Packet {
+    let (src, dst, src_port, dst_port) = if from_client {
+        ([10, 0, 0, 1], [192, 0, 2, 53], 40000, 53)
+    } else {
+        ([192, 0, 2, 53], [10, 0, 0, 1], 53, 40000)
+    };
+    let builder = PacketBuilder::ethernet2([1, 2, 3, 4, 5, 6], [6, 5, 4, 3, 2, 1])
+        .ipv4(src, dst, 64)
+        .udp(src_port, dst_port);
+    let mut data = Vec::new();
+    builder.write(&mut data, payload).unwrap();
+    Packet {
+        timestamp: seconds as f64,
+        src_ip: IPAddress::V4(src),
+        dst_ip: IPAddress::V4(dst),
+        length: data.len() as u16,
+        original_length: data.len() as u32,
+        data: data.into(),
+        timestamp_ticks: seconds * 1_000_000,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn dns_json_lists_queries_with_answers() {
+    let interfaces = [CaptureInterface {
+        linktype: Linktype::ETHERNET,
+        snaplen: 65535,
+        ts_resolution: 6,
+        ts_offset: 0,
+        name: None,
+        description: None,
+    }];
+    // Query 0xbeef for example.com A, and its response with one answer.
+    let question = b"\x07example\x03com\x00\x00\x01\x00\x01";
+    let mut query = b"\xbe\xef\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00".to_vec();
+    query.extend_from_slice(question);
+    let mut response = b"\xbe\xef\x81\x80\x00\x01\x00\x01\x00\x00\x00\x00".to_vec();
+    response.extend_from_slice(question);
+    response.extend_from_slice(b"\xc0\x0c\x00\x01\x00\x01\x00\x00\x01\x2c\x00\x04\x5d\xb8\xd7\x0e");
+    let packets = [dns_packet(1, true, &query), dns_packet(2, false, &response)];
+    let mut capture = Vec::new();
+    write_pcapng(&mut capture, &packets, &interfaces, &HashMap::new()).unwrap();
+    let path = temp_path("dns.pcapng");
+    std::fs::write(&path, capture).unwrap();
+    let json = run(|out| {
+        run_dns(
+            slice::from_ref(&path),
+            &registry(),
+            Some("dns.rcode == NOERROR"),
+            OutputFormat::Json,
+            out,
+        )
+    });
+    let _ = std::fs::remove_file(&path);
+
+    let records: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(records.as_array().map(Vec::len), Some(1));
+    let record = &records[0];
+    assert_eq!(record["id"], 0xbeef);
+    assert_eq!(record["name"], "example.com");
+    assert_eq!(record["qtype"], "A");
+    assert_eq!(record["rcode"], "NOERROR");
+    assert_eq!(record["answers"][0], "example.com A 93.184.215.14");
+    assert_eq!(record["latency"], 1.0);
+}
+
+fn echo_packet(seconds: u64, src: [u8; 4], id: u16) -> Packet {
+    let builder = PacketBuilder::ethernet2([1, 2, 3, 4, 5, 6], [6, 5, 4, 3, 2, 1])
+        .ipv4(src, [192, 0, 2, 1], 64)
+        .icmpv4_echo_request(id, 1);
+    let mut data = Vec::new();
+    builder.write(&mut data, b"ping").unwrap();
+    Packet {
+        timestamp: seconds as f64,
+        src_ip: IPAddress::V4(src),
+        dst_ip: IPAddress::V4([192, 0, 2, 1]),
+        length: data.len() as u16,
+        original_length: data.len() as u32,
+        data: data.into(),
+        timestamp_ticks: seconds * 1_000_000,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn flows_starting_together_are_ordered_by_address_then_conversation() {
+    let interfaces = [CaptureInterface {
+        linktype: Linktype::ETHERNET,
+        snaplen: 65535,
+        ts_resolution: 6,
+        ts_offset: 0,
+        name: None,
+        description: None,
+    }];
+    let packets = [
+        echo_packet(1, [10, 0, 0, 10], 1),
+        echo_packet(1, [10, 0, 0, 9], 8),
+        echo_packet(1, [10, 0, 0, 9], 3),
+    ];
+    let mut capture = Vec::new();
+    write_pcapng(&mut capture, &packets, &interfaces, &HashMap::new()).unwrap();
+    let path = temp_path("echo.pcapng");
+    std::fs::write(&path, capture).unwrap();
+    let csv = run(|out| {
+        run_flows(
+            slice::from_ref(&path),
+            &registry(),
+            None,
+            OutputFormat::Csv,
+            out,
+        )
+    });
+    let _ = std::fs::remove_file(&path);
+
+    let flows: Vec<(&str, &str)> = csv
+        .lines()
+        .skip(1)
+        .map(|line| {
+            let fields: Vec<&str> = line.split(',').collect();
+            (fields[4], fields[fields.len() - 1])
+        })
+        .collect();
+    assert_eq!(
+        flows,
+        [
+            ("10.0.0.9", "echo ID 3"),
+            ("10.0.0.9", "echo ID 8"),
+            ("10.0.0.10", "echo ID 1"),
+        ]
+    );
+}
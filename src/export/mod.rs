@@ -0,0 +1,232 @@
+use crate::flow::{Flow, IPAddress, Packet};
+use crate::parser::state::CaptureInterface;
+use anyhow::{Context, Result};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use tracing::info;
+
+#[cfg(test)]
+mod tests;
+
+const SHB_TYPE: u32 = 0x0A0D_0D0A;
+const IDB_TYPE: u32 = 0x0000_0001;
+const NRB_TYPE: u32 = 0x0000_0004;
+const EPB_TYPE: u32 = 0x0000_0006;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+const OPT_END: u16 = 0;
+const OPT_SHB_USERAPPL: u16 = 4;
+const OPT_IF_NAME: u16 = 2;
+const OPT_IF_DESCRIPTION: u16 = 3;
+const OPT_IF_TSRESOL: u16 = 9;
+const OPT_IF_TSOFFSET: u16 = 14;
+
+const NRB_RECORD_END: u16 = 0;
+const NRB_RECORD_IPV4: u16 = 1;
+const NRB_RECORD_IPV6: u16 = 2;
+
+/// Microsecond resolution is the pcapng default and needs no `if_tsresol` option.
+const DEFAULT_TS_RESOLUTION: u8 = 6;
+
+/// What was written by an export.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ExportSummary {
+    pub packets: usize,
+    pub interfaces: usize,
+    pub name_records: usize,
+}
+
+/// Writes the packets of `flows` to a new pcapng file, merged in capture order.
+pub fn export_flows<'a>(
+    path: &Path,
+    flows: impl IntoIterator<Item = &'a Flow>,
+    interfaces: &[CaptureInterface],
+    name_resolutions: &HashMap<IPAddress, Vec<String>>,
+) -> Result<ExportSummary> {
+    let packets = flows.into_iter().flat_map(|flow| flow.packets.iter());
+    export_packets(path, packets, interfaces, name_resolutions)
+}
+
+/// Writes `packets` to a new pcapng file, merged in capture order.
+pub fn export_packets<'a>(
+    path: &Path,
+    packets: impl IntoIterator<Item = &'a Packet>,
+    interfaces: &[CaptureInterface],
+    name_resolutions: &HashMap<IPAddress, Vec<String>>,
+) -> Result<ExportSummary> {
+    let file = File::create(path)
+        .with_context(|| format!("Failed to create export file {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    let summary = write_pcapng(&mut writer, packets, interfaces, name_resolutions)?;
+    writer.flush().context("Failed to write export file")?;
+    info!(
+        path = ?path,
+        packets = summary.packets,
+        interfaces = summary.interfaces,
+        name_records = summary.name_records,
+        "Exported packets to pcapng"
+    );
+    Ok(summary)
+}
+
+/// Serializes `packets` as a single-section pcapng stream.
+///
+/// Only the interfaces the packets were captured on are written, with their original
+/// linktype, snaplen, timestamp resolution and offset, and the packets keep their raw
+/// timestamps. Name resolutions are included for the addresses that appear in the packets.
+pub fn write_pcapng<'a, W: Write>(
+    writer: &mut W,
+    packets: impl IntoIterator<Item = &'a Packet>,
+    interfaces: &[CaptureInterface],
+    name_resolutions: &HashMap<IPAddress, Vec<String>>,
+) -> Result<ExportSummary> {
+    let mut packets: Vec<&Packet> = packets.into_iter().collect();
+    packets.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+
+    if let Some(packet) = packets.iter().find(|p| p.interface >= interfaces.len()) {
+        anyhow::bail!(
+            "Packet at {:.6} references unknown interface {}",
+            packet.timestamp,
+            packet.interface
+        );
+    }
+    // Interfaces keep their relative order; ids are renumbered to skip unused ones.
+    let used: BTreeSet<usize> = packets.iter().map(|packet| packet.interface).collect();
+    let interface_ids: HashMap<usize, u32> = used
+        .iter()
+        .enumerate()
+        .map(|(id, &index)| (index, id as u32))
+        .collect();
+
+    write_block(writer, SHB_TYPE, &section_header_body())?;
+    for &index in &used {
+        write_block(writer, IDB_TYPE, &interface_body(&interfaces[index]))?;
+    }
+
+    let mut seen = HashSet::new();
+    let addresses: Vec<IPAddress> = packets
+        .iter()
+        .flat_map(|packet| [packet.src_ip, packet.dst_ip])
+        .filter(|ip| name_resolutions.contains_key(ip) && seen.insert(*ip))
+        .collect();
+    if !addresses.is_empty() {
+        write_block(
+            writer,
+            NRB_TYPE,
+            &name_resolution_body(&addresses, name_resolutions),
+        )?;
+    }
+
+    for packet in &packets {
+        write_block(
+            writer,
+            EPB_TYPE,
+            &enhanced_packet_body(packet, interface_ids[&packet.interface])?,
+        )?;
+    }
+
+    Ok(ExportSummary {
+        packets: packets.len(),
+        interfaces: interface_ids.len(),
+        name_records: addresses.len(),
+    })
+}
+
+fn section_header_body() -> Vec<u8> {
+    let mut body = Vec::new();
+    body.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+    body.extend_from_slice(&1u16.to_le_bytes());
+    body.extend_from_slice(&0u16.to_le_bytes());
+    // Section length is unknown while streaming.
+    body.extend_from_slice(&(-1i64).to_le_bytes());
+    let application = concat!("Wirecrab ", env!("CARGO_PKG_VERSION"));
+    push_option(&mut body, OPT_SHB_USERAPPL, application.as_bytes());
+    push_option(&mut body, OPT_END, &[]);
+    body
+}
+
+fn interface_body(interface: &CaptureInterface) -> Vec<u8> {
+    let mut body = Vec::new();
+    body.extend_from_slice(&(interface.linktype.0 as u16).to_le_bytes());
+    body.extend_from_slice(&0u16.to_le_bytes());
+    body.extend_from_slice(&interface.snaplen.to_le_bytes());
+    if let Some(name) = &interface.name {
+        push_option(&mut body, OPT_IF_NAME, name.as_bytes());
+    }
+    if let Some(description) = &interface.description {
+        push_option(&mut body, OPT_IF_DESCRIPTION, description.as_bytes());
+    }
+    if interface.ts_resolution != DEFAULT_TS_RESOLUTION {
+        push_option(&mut body, OPT_IF_TSRESOL, &[interface.ts_resolution]);
+    }
+    if interface.ts_offset != 0 {
+        push_option(
+            &mut body,
+            OPT_IF_TSOFFSET,
+            &interface.ts_offset.to_le_bytes(),
+        );
+    }
+    push_option(&mut body, OPT_END, &[]);
+    body
+}
+
+fn name_resolution_body(
+    addresses: &[IPAddress],
+    name_resolutions: &HashMap<IPAddress, Vec<String>>,
+) -> Vec<u8> {
+    let mut body = Vec::new();
+    for ip in addresses {
+        let (record_type, mut value) = match ip {
+            IPAddress::V4(octets) => (NRB_RECORD_IPV4, octets.to_vec()),
+            IPAddress::V6(octets) => (NRB_RECORD_IPV6, octets.to_vec()),
+        };
+        for name in &name_resolutions[ip] {
+            value.extend_from_slice(name.as_bytes());
+            value.push(0);
+        }
+        push_option(&mut body, record_type, &value);
+    }
+    push_option(&mut body, NRB_RECORD_END, &[]);
+    body
+}
+
+fn enhanced_packet_body(packet: &Packet, interface_id: u32) -> Result<Vec<u8>> {
+    let data = packet
+        .data
+        .load()
+        .context("Failed to read packet from the capture file")?;
+    let captured = data.len() as u32;
+    let mut body = Vec::with_capacity(20 + data.len() + 3);
+    body.extend_from_slice(&interface_id.to_le_bytes());
+    body.extend_from_slice(&((packet.timestamp_ticks >> 32) as u32).to_le_bytes());
+    body.extend_from_slice(&(packet.timestamp_ticks as u32).to_le_bytes());
+    body.extend_from_slice(&captured.to_le_bytes());
+    body.extend_from_slice(&packet.original_length.max(captured).to_le_bytes());
+    body.extend_from_slice(&data);
+    pad(&mut body);
+    Ok(body)
+}
+
+/// Appends a type-length-value entry padded to 32 bits, the layout shared by block
+/// options and name resolution records.
+fn push_option(body: &mut Vec<u8>, code: u16, value: &[u8]) {
+    body.extend_from_slice(&code.to_le_bytes());
+    body.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    body.extend_from_slice(value);
+    pad(body);
+}
+
+fn pad(body: &mut Vec<u8>) {
+    body.resize(body.len().next_multiple_of(4), 0);
+}
+
+fn write_block<W: Write>(writer: &mut W, block_type: u32, body: &[u8]) -> Result<()> {
+    let total_length = (body.len() + 12) as u32;
+    writer.write_all(&block_type.to_le_bytes())?;
+    writer.write_all(&total_length.to_le_bytes())?;
+    writer.write_all(body)?;
+    writer.write_all(&total_length.to_le_bytes())?;
+    Ok(())
+}
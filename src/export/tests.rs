@@ -0,0 +1,164 @@
+use super::{export_flows, write_pcapng};
+use crate::flow::{IPAddress, Packet};
+use crate::parser::parse_capture;
+use crate::parser::state::CaptureInterface;
+use etherparse::PacketBuilder;
+use pcap_parser::Linktype;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("wirecrab_export_{}_{name}", std::process::id()))
+}
+
+fn udp_frame(src: [u8; 4], dst: [u8; 4], payload: &[u8]) -> Vec<u8> {
+    let builder = PacketBuilder::ethernet2([1, 2, 3, 4, 5, 6], [6, 5, 4, 3, 2, 1])
+        .ipv4(src, dst, 64)
+        .udp(5000, 6000);
+    let mut frame = Vec::with_capacity(builder.size(payload.len()));
+    builder.write(&mut frame, payload).unwrap();
+    frame
+}
+
+fn interface(ts_resolution: u8, name: Option<&str>) -> CaptureInterface {
+    CaptureInterface {
+        linktype: Linktype::ETHERNET,
+        snaplen: 65535,
+        ts_resolution,
+        ts_offset: 0,
+        name: name.map(str::to_string),
+        description: None,
+    }
+}
+
+fn packet(interface: usize, timestamp_ticks: u64, unit: f64, data: Vec<u8>) -> Packet {
+    Packet {
+        timestamp: timestamp_ticks as f64 / unit,
+        src_ip: IPAddress::V4([10, 0, 0, 1]),
+        dst_ip: IPAddress::V4([10, 0, 0, 2]),
+        src_port: Some(5000),
+        dst_port: Some(6000),
+        length: data.len() as u16,
+        original_length: data.len() as u32 + 100,
+        data: data.into(),
+        interface,
+        timestamp_ticks,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn exported_capture_round_trips_through_parser() {
+    let interfaces = vec![interface(6, Some("unused")), interface(9, Some("eth0"))];
+    let frame = udp_frame([10, 0, 0, 1], [10, 0, 0, 2], b"hello");
+    let ticks = 1_700_000_000_123_456_789;
+    let packets = [
+        packet(1, ticks + 1_000, 1e9, frame.clone()),
+        packet(1, ticks, 1e9, frame.clone()),
+    ];
+    let mut names = HashMap::new();
+    names.insert(
+        IPAddress::V4([10, 0, 0, 2]),
+        vec!["server.example".to_string(), "alias.example".to_string()],
+    );
+    names.insert(IPAddress::V4([192, 0, 2, 1]), vec!["other".to_string()]);
+
+    let path = temp_path("roundtrip.pcapng");
+    let mut output = Vec::new();
+    let summary = write_pcapng(&mut output, &packets, &interfaces, &names).expect("export");
+    std::fs::write(&path, &output).unwrap();
+    let state = parse_capture(&path, |_| {}).expect("parse exported capture");
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(summary.packets, 2);
+    assert_eq!(summary.interfaces, 1);
+    assert_eq!(summary.name_records, 1);
+    assert_eq!(state.interfaces, vec![interface(9, Some("eth0"))]);
+    assert_eq!(state.packet_count, 2);
+    assert_eq!(state.name_resolutions.len(), 1);
+    assert_eq!(
+        state.name_resolutions[&IPAddress::V4([10, 0, 0, 2])],
+        vec!["server.example".to_string(), "alias.example".to_string()]
+    );
+
+    let flow = state.flows.values().next().expect("one flow");
+    let exported: Vec<(u64, usize, u32)> = flow
+        .packets
+        .iter()
+        .map(|p| (p.timestamp_ticks, p.interface, p.original_length))
+        .collect();
+    // Packets are written in capture order, not in the order they were passed.
+    assert_eq!(
+        exported,
+        vec![
+            (ticks, 0, frame.len() as u32 + 100),
+            (ticks + 1_000, 0, frame.len() as u32 + 100),
+        ]
+    );
+    assert_eq!(
+        flow.packets[0].data.load().unwrap().as_ref(),
+        frame.as_slice()
+    );
+}
+
+#[test]
+fn exports_filtered_flows_to_file() {
+    let interfaces = vec![interface(6, None)];
+    let path = temp_path("flows.pcapng");
+    let capture = temp_path("source.pcapng");
+
+    let packets = [
+        packet(
+            0,
+            1_000_000,
+            1e6,
+            udp_frame([10, 0, 0, 1], [10, 0, 0, 2], b"a"),
+        ),
+        packet(
+            0,
+            2_000_000,
+            1e6,
+            udp_frame([10, 0, 0, 3], [10, 0, 0, 4], b"b"),
+        ),
+    ];
+    let mut source = Vec::new();
+    write_pcapng(&mut source, &packets, &interfaces, &HashMap::new()).unwrap();
+    std::fs::write(&capture, source).unwrap();
+    let state = parse_capture(&capture, |_| {}).expect("parse source");
+    let _ = std::fs::remove_file(&capture);
+    assert_eq!(state.flows.len(), 2);
+
+    let selected = state
+        .flows
+        .values()
+        .filter(|flow| flow.source.ip == IPAddress::V4([10, 0, 0, 3]));
+    let summary = export_flows(&path, selected, &state.interfaces, &state.name_resolutions)
+        .expect("export flows");
+    let exported = parse_capture(&path, |_| {}).expect("parse export");
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(summary.packets, 1);
+    assert_eq!(exported.flows.len(), 1);
+    let flow = exported.flows.values().next().unwrap();
+    assert_eq!(flow.source.ip, IPAddress::V4([10, 0, 0, 3]));
+    assert_eq!(flow.timestamp, 2.0);
+}
+
+#[test]
+fn rejects_packets_from_unknown_interfaces() {
+    let packets = [packet(
+        3,
+        0,
+        1e6,
+        udp_frame([10, 0, 0, 1], [10, 0, 0, 2], b""),
+    )];
+    let mut output = Vec::new();
+    let error = write_pcapng(
+        &mut output,
+        &packets,
+        &[interface(6, None)],
+        &HashMap::new(),
+    )
+    .expect_err("interface 3 does not exist");
+    assert!(error.to_string().contains("unknown interface 3"));
+}
@@ -0,0 +1,127 @@
+/// A decoded DNS message, as carried by one UDP datagram or TCP length-prefixed frame.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DnsMessage {
+    pub id: u16,
+    pub is_response: bool,
+    pub opcode: u8,
+    pub rcode: u8,
+    pub questions: Vec<DnsQuestion>,
+    pub answers: Vec<DnsRecord>,
+    pub authorities: Vec<DnsRecord>,
+    pub additionals: Vec<DnsRecord>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DnsQuestion {
+    pub name: String,
+    pub record_type: u16,
+    pub class: u16,
+}
+
+/// A resource record with its data in presentation format, e.g. `10 mail.example.com`
+/// for an MX record. Data of types without a known layout is shown as hex.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DnsRecord {
+    pub name: String,
+    pub record_type: u16,
+    pub class: u16,
+    pub ttl: u32,
+    pub data: String,
+}
+
+/// A query and the response that answered it. Either side may be missing when the
+/// capture only holds the other one.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DnsTransaction {
+    pub id: u16,
+    /// Name and type of the first question.
+    pub name: String,
+    pub query_type: u16,
+    /// Capture time of the query.
+    pub query_timestamp: Option<f64>,
+    pub response: Option<DnsResponse>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DnsResponse {
+    pub rcode: u8,
+    pub answers: Vec<DnsRecord>,
+    /// Capture time of the response.
+    pub timestamp: f64,
+}
+
+impl DnsTransaction {
+    /// Seconds from the query to its response.
+    pub fn latency(&self) -> Option<f64> {
+        Some(self.response.as_ref()?.timestamp - self.query_timestamp?)
+    }
+
+    /// When the transaction started, i.e. its query or else its response.
+    pub fn timestamp(&self) -> f64 {
+        self.query_timestamp
+            .or_else(|| self.response.as_ref().map(|response| response.timestamp))
+            .unwrap_or_default()
+    }
+
+    pub fn query_type_name(&self) -> String {
+        record_type_name(self.query_type)
+    }
+
+    pub fn rcode_name(&self) -> Option<String> {
+        self.response
+            .as_ref()
+            .map(|response| rcode_name(response.rcode))
+    }
+}
+
+/// Mnemonic of a record type, e.g. `AAAA`, or `TYPE65280` when unknown (RFC 3597).
+pub fn record_type_name(record_type: u16) -> String {
+    let name = match record_type {
+        1 => "A",
+        2 => "NS",
+        5 => "CNAME",
+        6 => "SOA",
+        12 => "PTR",
+        13 => "HINFO",
+        15 => "MX",
+        16 => "TXT",
+        28 => "AAAA",
+        33 => "SRV",
+        35 => "NAPTR",
+        39 => "DNAME",
+        41 => "OPT",
+        43 => "DS",
+        46 => "RRSIG",
+        47 => "NSEC",
+        48 => "DNSKEY",
+        50 => "NSEC3",
+        52 => "TLSA",
+        64 => "SVCB",
+        65 => "HTTPS",
+        99 => "SPF",
+        252 => "AXFR",
+        255 => "ANY",
+        257 => "CAA",
+        other => return format!("TYPE{other}"),
+    };
+    name.to_owned()
+}
+
+/// Mnemonic of a response code, e.g. `NXDOMAIN`.
+pub fn rcode_name(rcode: u8) -> String {
+    let name = match rcode {
+        0 => "NOERROR",
+        1 => "FORMERR",
+        2 => "SERVFAIL",
+        3 => "NXDOMAIN",
+        4 => "NOTIMP",
+        5 => "REFUSED",
+        6 => "YXDOMAIN",
+        7 => "YXRRSET",
+        8 => "NXRRSET",
+        9 => "NOTAUTH",
+        10 => "NOTZONE",
+        other => return format!("RCODE{other}"),
+    };
+    name.to_owned()
+}
@@ -0,0 +1,490 @@
+use super::FilterError;
+use super::fields::{EvalContext, Field, FieldKind, FieldValue, search_text};
+use super::lexer::{CompareOp, Token, TokenKind, tokenize};
+use crate::flow::IPAddress;
+use std::net::IpAddr;
+
+/// Timestamps are shown with microsecond precision, so a bare time matches within half of that.
+const TIME_TOLERANCE: f64 = 5e-7;
+
+/// A compiled display filter expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    /// A field on its own: true when it has any value (`tcp`, `tcp.port`).
+    Present(Field),
+    Compare {
+        field: Field,
+        op: CompareOp,
+        value: Literal,
+    },
+    In {
+        field: Field,
+        set: Vec<SetItem>,
+    },
+    /// Bare address or network: either end of the flow.
+    AnyAddress(Network),
+    /// Bare integer: either port of the flow.
+    AnyPort(u64),
+    /// Bare decimal: the flow's start time relative to the capture.
+    StartTime(f64),
+    /// Bare word or quoted string: case-insensitive search of names, protocol and tags.
+    Text(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Integer(u64),
+    Time(f64),
+    Network(Network),
+    /// Host name, matched against name resolutions.
+    Host(String),
+    Text(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SetItem {
+    Value(Literal),
+    Range(Literal, Literal),
+}
+
+/// An address with a prefix length; a plain address uses the full length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Network {
+    address: IPAddress,
+    prefix: u32,
+}
+
+pub fn parse(query: &str) -> Result<Option<Expr>, FilterError> {
+    let tokens = tokenize(query)?;
+    if tokens.is_empty() {
+        return Ok(None);
+    }
+
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        end: query.chars().count(),
+    };
+    let expr = parser.parse_or()?;
+    if let Some(token) = parser.peek() {
+        return Err(FilterError::new(
+            token.position,
+            format!("unexpected {}", describe(&token.kind)),
+        ));
+    }
+    Ok(Some(expr))
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Query length, reported as the position of "unexpected end" errors.
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Result<Token, FilterError> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| FilterError::new(self.end, "unexpected end of filter"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn eat(&mut self, kind: &TokenKind) -> bool {
+        if self.peek().is_some_and(|token| &token.kind == kind) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, FilterError> {
+        let mut expr = self.parse_and()?;
+        while self.eat(&TokenKind::Or) {
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, FilterError> {
+        let mut expr = self.parse_not()?;
+        while self.eat(&TokenKind::And) {
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_not()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr, FilterError> {
+        if self.eat(&TokenKind::Not) {
+            return Ok(Expr::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, FilterError> {
+        let token = self.next()?;
+        match token.kind {
+            TokenKind::LParen => {
+                let expr = self.parse_or()?;
+                match self.next() {
+                    Ok(Token {
+                        kind: TokenKind::RParen,
+                        ..
+                    }) => Ok(expr),
+                    Ok(other) => Err(FilterError::new(
+                        other.position,
+                        format!("expected ')' but found {}", describe(&other.kind)),
+                    )),
+                    Err(_) => Err(FilterError::new(token.position, "unclosed '('")),
+                }
+            }
+            TokenKind::Word(word) => match Field::lookup(&word) {
+                Some(field) => self.parse_field(field, &word, token.position),
+                None if self.comparison_follows() => Err(FilterError::new(
+                    token.position,
+                    format!("unknown field '{word}'"),
+                )),
+                None => Ok(bare_word(&word)),
+            },
+            TokenKind::Str(text) => Ok(Expr::Text(text.to_lowercase())),
+            other => Err(FilterError::new(
+                token.position,
+                format!("expected a field or value but found {}", describe(&other)),
+            )),
+        }
+    }
+
+    fn comparison_follows(&self) -> bool {
+        self.peek()
+            .is_some_and(|token| matches!(token.kind, TokenKind::Compare(_) | TokenKind::In))
+    }
+
+    fn parse_field(
+        &mut self,
+        field: Field,
+        name: &str,
+        position: usize,
+    ) -> Result<Expr, FilterError> {
+        if !self.comparison_follows() {
+            return Ok(Expr::Present(field));
+        }
+        if field.kind() == FieldKind::Presence {
+            return Err(FilterError::new(
+                position,
+                format!("'{name}' is a protocol and cannot be compared"),
+            ));
+        }
+
+        let operator = self.next()?;
+        match operator.kind {
+            TokenKind::In => Ok(Expr::In {
+                field,
+                set: self.parse_set(field)?,
+            }),
+            TokenKind::Compare(op) => {
+                check_operator(field, op, name, operator.position)?;
+                let value = self.next()?;
+                let value = literal(field, &value)?;
+                Ok(Expr::Compare { field, op, value })
+            }
+            _ => unreachable!("comparison_follows checked the operator"),
+        }
+    }
+
+    fn parse_set(&mut self, field: Field) -> Result<Vec<SetItem>, FilterError> {
+        let open = self.next()?;
+        if open.kind != TokenKind::LBrace {
+            return Err(FilterError::new(
+                open.position,
+                format!("expected '{{' but found {}", describe(&open.kind)),
+            ));
+        }
+
+        let mut items = Vec::new();
+        loop {
+            let token = self
+                .next()
+                .map_err(|_| FilterError::new(open.position, "unclosed '{'"))?;
+            match &token.kind {
+                TokenKind::RBrace if !items.is_empty() => return Ok(items),
+                TokenKind::RBrace => return Err(FilterError::new(token.position, "empty set")),
+                TokenKind::Comma => continue,
+                TokenKind::Word(word) if word.contains("..") => {
+                    let (low, high) = word.split_once("..").unwrap_or_default();
+                    let high_position = token.position + low.chars().count() + 2;
+                    let low = literal_from_word(field, low, token.position)?;
+                    let high = literal_from_word(field, high, high_position)?;
+                    items.push(SetItem::Range(low, high));
+                }
+                _ => items.push(SetItem::Value(literal(field, &token)?)),
+            }
+        }
+    }
+}
+
+fn check_operator(
+    field: Field,
+    op: CompareOp,
+    name: &str,
+    position: usize,
+) -> Result<(), FilterError> {
+    let kind = field.kind();
+    let valid = match op {
+        CompareOp::Contains => kind == FieldKind::Text,
+        CompareOp::Gt | CompareOp::Ge | CompareOp::Lt | CompareOp::Le => {
+            matches!(kind, FieldKind::Integer | FieldKind::Time)
+        }
+        CompareOp::Eq | CompareOp::Ne => true,
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(FilterError::new(
+            position,
+            format!("operator cannot be applied to '{name}'"),
+        ))
+    }
+}
+
+fn literal(field: Field, token: &Token) -> Result<Literal, FilterError> {
+    match &token.kind {
+        TokenKind::Word(word) => literal_from_word(field, word, token.position),
+        TokenKind::Str(text) => match field.kind() {
+            FieldKind::Text => Ok(Literal::Text(text.clone())),
+            FieldKind::Address => Ok(Literal::Host(text.to_lowercase())),
+            _ => literal_from_word(field, text, token.position),
+        },
+        other => Err(FilterError::new(
+            token.position,
+            format!("expected a value but found {}", describe(other)),
+        )),
+    }
+}
+
+fn literal_from_word(field: Field, word: &str, position: usize) -> Result<Literal, FilterError> {
+    let invalid = |what: &str| FilterError::new(position, format!("'{word}' is not {what}"));
+    match field.kind() {
+        FieldKind::Integer => parse_integer(word)
+            .map(Literal::Integer)
+            .ok_or_else(|| invalid("a number")),
+        FieldKind::Time => word
+            .parse::<f64>()
+            .ok()
+            .filter(|value| value.is_finite())
+            .map(Literal::Time)
+            .ok_or_else(|| invalid("a time in seconds")),
+        FieldKind::Address => match parse_network(word) {
+            Some(network) => Ok(Literal::Network(network)),
+            None if word.contains('/') || word.is_empty() => Err(invalid("an address or network")),
+            None => Ok(Literal::Host(word.to_lowercase())),
+        },
+        FieldKind::Text => Ok(Literal::Text(word.to_string())),
+        FieldKind::Presence => Err(invalid("comparable")),
+    }
+}
+
+/// Parses decimal or `0x` hex integers with an optional SI (`k`, `M`, `G`) or binary
+/// (`Ki`, `Mi`, `Gi`) multiplier, so `flow.bytes > 1M` reads naturally.
+pub fn parse_integer(word: &str) -> Option<u64> {
+    if let Some(hex) = word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")) {
+        return u64::from_str_radix(hex, 16).ok();
+    }
+
+    let digits_end = word
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(word.len());
+    let (digits, suffix) = word.split_at(digits_end);
+    let multiplier: u64 = match suffix {
+        "" => 1,
+        "k" | "K" => 1_000,
+        "M" => 1_000_000,
+        "G" => 1_000_000_000,
+        "Ki" => 1 << 10,
+        "Mi" => 1 << 20,
+        "Gi" => 1 << 30,
+        _ => return None,
+    };
+    digits.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+fn parse_network(word: &str) -> Option<Network> {
+    let (address, prefix) = match word.split_once('/') {
+        Some((address, prefix)) => (address, Some(prefix.parse::<u32>().ok()?)),
+        None => (word, None),
+    };
+    let address = match address.parse::<IpAddr>().ok()? {
+        IpAddr::V4(ip) => IPAddress::V4(ip.octets()),
+        IpAddr::V6(ip) => IPAddress::V6(ip.octets()),
+    };
+    let max = match address {
+        IPAddress::V4(_) => 32,
+        IPAddress::V6(_) => 128,
+    };
+    let prefix = prefix.unwrap_or(max);
+    (prefix <= max).then_some(Network { address, prefix })
+}
+
+/// Interprets a word that isn't a field name by its shape.
+fn bare_word(word: &str) -> Expr {
+    if let Some(network) = parse_network(word) {
+        return Expr::AnyAddress(network);
+    }
+    if word.chars().all(|c| c.is_ascii_digit())
+        && let Ok(port) = word.parse::<u64>()
+    {
+        return Expr::AnyPort(port);
+    }
+    if word.contains('.')
+        && let Ok(time) = word.parse::<f64>()
+    {
+        return Expr::StartTime(time);
+    }
+    let lower = word.to_lowercase();
+    if let Some(number) = lower
+        .strip_prefix("proto-")
+        .and_then(|n| n.parse::<u64>().ok())
+    {
+        return Expr::Compare {
+            field: Field::IpProto,
+            op: CompareOp::Eq,
+            value: Literal::Integer(number),
+        };
+    }
+    Expr::Text(lower)
+}
+
+fn describe(kind: &TokenKind) -> String {
+    match kind {
+        TokenKind::LParen => "'('".into(),
+        TokenKind::RParen => "')'".into(),
+        TokenKind::LBrace => "'{'".into(),
+        TokenKind::RBrace => "'}'".into(),
+        TokenKind::Comma => "','".into(),
+        TokenKind::And => "'&&'".into(),
+        TokenKind::Or => "'||'".into(),
+        TokenKind::Not => "'!'".into(),
+        TokenKind::In => "'in'".into(),
+        TokenKind::Compare(_) => "an operator".into(),
+        TokenKind::Str(text) => format!("\"{text}\""),
+        TokenKind::Word(word) => format!("'{word}'"),
+    }
+}
+
+impl Network {
+    fn contains(&self, ip: &IPAddress) -> bool {
+        let (network, candidate): (&[u8], &[u8]) = match (&self.address, ip) {
+            (IPAddress::V4(a), IPAddress::V4(b)) => (a, b),
+            (IPAddress::V6(a), IPAddress::V6(b)) => (a, b),
+            _ => return false,
+        };
+        let full_bytes = (self.prefix / 8) as usize;
+        let remaining_bits = self.prefix % 8;
+        if network[..full_bytes] != candidate[..full_bytes] {
+            return false;
+        }
+        if remaining_bits == 0 {
+            return true;
+        }
+        let mask = 0xFFu8 << (8 - remaining_bits);
+        network[full_bytes] & mask == candidate[full_bytes] & mask
+    }
+}
+
+impl Expr {
+    pub fn eval(&self, ctx: &EvalContext, include_names: bool) -> bool {
+        match self {
+            Expr::And(left, right) => {
+                left.eval(ctx, include_names) && right.eval(ctx, include_names)
+            }
+            Expr::Or(left, right) => {
+                left.eval(ctx, include_names) || right.eval(ctx, include_names)
+            }
+            Expr::Not(inner) => !inner.eval(ctx, include_names),
+            Expr::Present(field) => !field.values(ctx).is_empty(),
+            Expr::Compare { field, op, value } => {
+                let values = field.values(ctx);
+                match op {
+                    // `!=` holds when no value equals, so `ip.addr != x` excludes x on either end.
+                    CompareOp::Ne => !values.iter().any(|v| equals(v, value, ctx)),
+                    _ => values.iter().any(|v| compare(v, *op, value, ctx)),
+                }
+            }
+            Expr::In { field, set } => field.values(ctx).iter().any(|v| {
+                set.iter().any(|item| match item {
+                    SetItem::Value(literal) => equals(v, literal, ctx),
+                    SetItem::Range(low, high) => {
+                        compare(v, CompareOp::Ge, low, ctx) && compare(v, CompareOp::Le, high, ctx)
+                    }
+                })
+            }),
+            Expr::AnyAddress(network) => {
+                let flow = ctx.flow;
+                network.contains(&flow.source.ip) || network.contains(&flow.destination.ip)
+            }
+            Expr::AnyPort(port) => {
+                let flow = ctx.flow;
+                flow.protocol.has_ports()
+                    && (flow.source.port as u64 == *port || flow.destination.port as u64 == *port)
+            }
+            Expr::StartTime(time) => match Field::FlowStart.values(ctx).first() {
+                Some(FieldValue::Time(start)) => (start - time).abs() < TIME_TOLERANCE,
+                _ => false,
+            },
+            Expr::Text(needle) => search_text(ctx, include_names)
+                .iter()
+                .any(|text| text.to_lowercase().contains(needle)),
+        }
+    }
+}
+
+fn equals(value: &FieldValue, literal: &Literal, ctx: &EvalContext) -> bool {
+    compare(value, CompareOp::Eq, literal, ctx)
+}
+
+fn compare(value: &FieldValue, op: CompareOp, literal: &Literal, ctx: &EvalContext) -> bool {
+    use std::cmp::Ordering;
+
+    let ordering = match (value, literal) {
+        (FieldValue::Integer(a), Literal::Integer(b)) => a.cmp(b),
+        (FieldValue::Time(a), Literal::Time(b)) => match a.partial_cmp(b) {
+            Some(ordering) => ordering,
+            None => return false,
+        },
+        (FieldValue::Address(ip), Literal::Network(network)) => {
+            return op == CompareOp::Eq && network.contains(ip);
+        }
+        (FieldValue::Address(ip), Literal::Host(host)) => {
+            return op == CompareOp::Eq
+                && ctx
+                    .name_resolutions
+                    .and_then(|names| names.get(ip))
+                    .is_some_and(|names| names.iter().any(|name| name.eq_ignore_ascii_case(host)));
+        }
+        (FieldValue::Text(text), Literal::Text(needle)) if op == CompareOp::Contains => {
+            return text.contains(needle.as_str());
+        }
+        (FieldValue::Text(text), Literal::Text(other)) => text.as_str().cmp(other.as_str()),
+        _ => return false,
+    };
+
+    match op {
+        CompareOp::Eq => ordering == Ordering::Equal,
+        CompareOp::Ne => ordering != Ordering::Equal,
+        CompareOp::Gt => ordering == Ordering::Greater,
+        CompareOp::Ge => ordering != Ordering::Less,
+        CompareOp::Lt => ordering == Ordering::Less,
+        CompareOp::Le => ordering != Ordering::Greater,
+        CompareOp::Contains => false,
+    }
+}
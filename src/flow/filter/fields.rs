@@ -0,0 +1,537 @@
+use super::FlowFormatter;
+use crate::flow::dns::{DnsTransaction, rcode_name};
+use crate::flow::tls::{TlsInfo, cipher_suite_name};
+use crate::flow::tunnel::{Encapsulation, TunnelKind};
+use crate::flow::{Flow, IPAddress, Packet, Protocol};
+use std::collections::HashMap;
+
+/// Type of the values a field produces, which decides how literals are parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldKind {
+    /// Protocol presence, e.g. `tcp`; only valid without a comparison.
+    Presence,
+    Integer,
+    /// Seconds, compared as floating point.
+    Time,
+    Address,
+    Text,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressFamily {
+    V4,
+    V6,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Either,
+    Source,
+    Destination,
+}
+
+/// A kind of layer packets are carried in, for the `vlan`, `mpls` and tunnel fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layer {
+    Vlan,
+    Mpls,
+    IpInIp,
+    Gre,
+    Vxlan,
+    Geneve,
+    /// Any tunnel between two IP endpoints.
+    Tunnel,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Protocol(Protocol),
+    Ip(AddressFamily),
+    Address(AddressFamily, Side),
+    IpProto,
+    Port(Protocol, Side),
+    FrameLen,
+    FrameTimeRelative,
+    FrameInterface,
+    FrameSource,
+    Tag,
+    Encapsulated(Layer),
+    VlanId,
+    MplsLabel,
+    GreKey,
+    VxlanVni,
+    GeneveVni,
+    TunnelAddress(Side),
+    FlowBytes,
+    FlowPackets,
+    FlowStart,
+    FlowDuration,
+    FlowIssues,
+    FlowRetransmissions,
+    FlowDuplicateAcks,
+    FlowOutOfOrder,
+    FlowZeroWindows,
+    Tls,
+    TlsVersion,
+    TlsServerName,
+    TlsAlpn,
+    TlsCipherSuite,
+    TlsOfferedCipherSuite,
+    TlsCertSubject,
+    TlsCertIssuer,
+    TlsCertSan,
+    TlsJa3,
+    TlsJa3s,
+    TlsJa4,
+    Dns,
+    DnsQueryName,
+    DnsQueryType,
+    DnsResponseCode,
+    DnsAnswer,
+    DnsLatency,
+}
+
+/// Every field name understood by the filter language.
+pub const FIELD_NAMES: &[(&str, Field)] = &[
+    ("tcp", Field::Protocol(Protocol::TCP)),
+    ("udp", Field::Protocol(Protocol::UDP)),
+    ("icmp", Field::Protocol(Protocol::ICMP)),
+    ("icmpv6", Field::Protocol(Protocol::ICMPV6)),
+    ("arp", Field::Protocol(Protocol::ARP)),
+    ("igmp", Field::Protocol(Protocol::IGMP)),
+    ("esp", Field::Protocol(Protocol::ESP)),
+    ("ah", Field::Protocol(Protocol::AH)),
+    ("ospf", Field::Protocol(Protocol::OSPF)),
+    ("ip", Field::Ip(AddressFamily::V4)),
+    ("ipv6", Field::Ip(AddressFamily::V6)),
+    ("ip.addr", Field::Address(AddressFamily::V4, Side::Either)),
+    ("ip.src", Field::Address(AddressFamily::V4, Side::Source)),
+    (
+        "ip.dst",
+        Field::Address(AddressFamily::V4, Side::Destination),
+    ),
+    ("ipv6.addr", Field::Address(AddressFamily::V6, Side::Either)),
+    ("ipv6.src", Field::Address(AddressFamily::V6, Side::Source)),
+    (
+        "ipv6.dst",
+        Field::Address(AddressFamily::V6, Side::Destination),
+    ),
+    ("ip.proto", Field::IpProto),
+    ("tcp.port", Field::Port(Protocol::TCP, Side::Either)),
+    ("tcp.srcport", Field::Port(Protocol::TCP, Side::Source)),
+    ("tcp.dstport", Field::Port(Protocol::TCP, Side::Destination)),
+    ("udp.port", Field::Port(Protocol::UDP, Side::Either)),
+    ("udp.srcport", Field::Port(Protocol::UDP, Side::Source)),
+    ("udp.dstport", Field::Port(Protocol::UDP, Side::Destination)),
+    ("frame.len", Field::FrameLen),
+    ("frame.time_relative", Field::FrameTimeRelative),
+    ("frame.interface_id", Field::FrameInterface),
+    ("frame.source", Field::FrameSource),
+    ("tag", Field::Tag),
+    ("vlan", Field::Encapsulated(Layer::Vlan)),
+    ("vlan.id", Field::VlanId),
+    ("mpls", Field::Encapsulated(Layer::Mpls)),
+    ("mpls.label", Field::MplsLabel),
+    ("ipip", Field::Encapsulated(Layer::IpInIp)),
+    ("gre", Field::Encapsulated(Layer::Gre)),
+    ("gre.key", Field::GreKey),
+    ("vxlan", Field::Encapsulated(Layer::Vxlan)),
+    ("vxlan.vni", Field::VxlanVni),
+    ("geneve", Field::Encapsulated(Layer::Geneve)),
+    ("geneve.vni", Field::GeneveVni),
+    ("tunnel", Field::Encapsulated(Layer::Tunnel)),
+    ("tunnel.addr", Field::TunnelAddress(Side::Either)),
+    ("tunnel.src", Field::TunnelAddress(Side::Source)),
+    ("tunnel.dst", Field::TunnelAddress(Side::Destination)),
+    ("flow.bytes", Field::FlowBytes),
+    ("flow.packets", Field::FlowPackets),
+    ("flow.start", Field::FlowStart),
+    ("flow.duration", Field::FlowDuration),
+    ("flow.issues", Field::FlowIssues),
+    ("flow.retransmissions", Field::FlowRetransmissions),
+    ("flow.dup_acks", Field::FlowDuplicateAcks),
+    ("flow.out_of_order", Field::FlowOutOfOrder),
+    ("flow.zero_windows", Field::FlowZeroWindows),
+    ("tls", Field::Tls),
+    ("tls.version", Field::TlsVersion),
+    ("tls.sni", Field::TlsServerName),
+    ("tls.alpn", Field::TlsAlpn),
+    ("tls.cipher", Field::TlsCipherSuite),
+    ("tls.offered_cipher", Field::TlsOfferedCipherSuite),
+    ("tls.cert.subject", Field::TlsCertSubject),
+    ("tls.cert.issuer", Field::TlsCertIssuer),
+    ("tls.cert.san", Field::TlsCertSan),
+    ("tls.ja3", Field::TlsJa3),
+    ("tls.ja3s", Field::TlsJa3s),
+    ("tls.ja4", Field::TlsJa4),
+    ("dns", Field::Dns),
+    ("dns.qname", Field::DnsQueryName),
+    ("dns.qtype", Field::DnsQueryType),
+    ("dns.rcode", Field::DnsResponseCode),
+    ("dns.answer", Field::DnsAnswer),
+    ("dns.latency", Field::DnsLatency),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldValue {
+    Integer(u64),
+    Time(f64),
+    Address(IPAddress),
+    Text(String),
+}
+
+/// What a filter is evaluated against: a flow, optionally narrowed to one of its packets.
+/// Without a packet, packet fields such as `frame.len` or `tag` take the values of every
+/// packet in the flow.
+pub struct EvalContext<'a> {
+    pub flow: &'a Flow,
+    pub packet: Option<&'a Packet>,
+    pub timestamp_origin: Option<f64>,
+    pub name_resolutions: Option<&'a HashMap<IPAddress, Vec<String>>>,
+    /// Names of the capture files, which `Packet::source` indexes.
+    pub sources: &'a [String],
+}
+
+impl Field {
+    pub fn lookup(name: &str) -> Option<Field> {
+        FIELD_NAMES
+            .iter()
+            .find(|(candidate, _)| candidate.eq_ignore_ascii_case(name))
+            .map(|(_, field)| *field)
+    }
+
+    pub fn kind(self) -> FieldKind {
+        match self {
+            Field::Protocol(_)
+            | Field::Ip(_)
+            | Field::Encapsulated(_)
+            | Field::Tls
+            | Field::Dns => FieldKind::Presence,
+            Field::Address(..) | Field::TunnelAddress(_) => FieldKind::Address,
+            Field::FrameTimeRelative
+            | Field::FlowStart
+            | Field::FlowDuration
+            | Field::DnsLatency => FieldKind::Time,
+            Field::Tag
+            | Field::FrameSource
+            | Field::TlsVersion
+            | Field::TlsServerName
+            | Field::TlsAlpn
+            | Field::TlsCipherSuite
+            | Field::TlsOfferedCipherSuite
+            | Field::TlsCertSubject
+            | Field::TlsCertIssuer
+            | Field::TlsCertSan
+            | Field::TlsJa3
+            | Field::TlsJa3s
+            | Field::TlsJa4
+            | Field::DnsQueryName
+            | Field::DnsQueryType
+            | Field::DnsResponseCode
+            | Field::DnsAnswer => FieldKind::Text,
+            _ => FieldKind::Integer,
+        }
+    }
+
+    pub fn values(self, ctx: &EvalContext) -> Vec<FieldValue> {
+        let flow = ctx.flow;
+        match self {
+            Field::Protocol(protocol) => {
+                if flow.protocol == protocol {
+                    vec![FieldValue::Integer(1)]
+                } else {
+                    Vec::new()
+                }
+            }
+            Field::Ip(family) => {
+                if flow.protocol != Protocol::ARP && family_of(&flow.source.ip) == family {
+                    vec![FieldValue::Integer(1)]
+                } else {
+                    Vec::new()
+                }
+            }
+            Field::Address(family, side) => {
+                let (src, dst) = match ctx.packet {
+                    Some(packet) => (packet.src_ip, packet.dst_ip),
+                    None => (flow.source.ip, flow.destination.ip),
+                };
+                select(side, src, dst)
+                    .into_iter()
+                    .filter(|ip| family_of(ip) == family)
+                    .map(FieldValue::Address)
+                    .collect()
+            }
+            Field::IpProto => flow
+                .protocol
+                .number()
+                .map(|number| FieldValue::Integer(number as u64))
+                .into_iter()
+                .collect(),
+            Field::Port(protocol, side) => {
+                if flow.protocol != protocol {
+                    return Vec::new();
+                }
+                let (src, dst) = match ctx.packet {
+                    Some(packet) => (packet.src_port, packet.dst_port),
+                    None => (Some(flow.source.port), Some(flow.destination.port)),
+                };
+                select(side, src, dst)
+                    .into_iter()
+                    .flatten()
+                    .map(|port| FieldValue::Integer(port as u64))
+                    .collect()
+            }
+            Field::FrameLen => packets(ctx)
+                .map(|packet| FieldValue::Integer(packet.length as u64))
+                .collect(),
+            Field::FrameTimeRelative => packets(ctx)
+                .map(|packet| FieldValue::Time(relative(packet.timestamp, ctx)))
+                .collect(),
+            Field::FrameInterface => packets(ctx)
+                .map(|packet| FieldValue::Integer(packet.interface as u64))
+                .collect(),
+            Field::FrameSource => {
+                let mut sources: Vec<usize> = packets(ctx).map(|packet| packet.source).collect();
+                sources.sort_unstable();
+                sources.dedup();
+                sources
+                    .into_iter()
+                    .filter_map(|source| ctx.sources.get(source))
+                    .map(|name| FieldValue::Text(name.clone()))
+                    .collect()
+            }
+            Field::Tag => packets(ctx)
+                .flat_map(|packet| packet.tags.iter())
+                .map(|tag| FieldValue::Text(tag.clone()))
+                .collect(),
+            Field::Encapsulated(layer) => {
+                if encapsulations(ctx).any(|encapsulation| layer.matches(encapsulation)) {
+                    vec![FieldValue::Integer(1)]
+                } else {
+                    Vec::new()
+                }
+            }
+            Field::VlanId
+            | Field::MplsLabel
+            | Field::GreKey
+            | Field::VxlanVni
+            | Field::GeneveVni
+            | Field::TunnelAddress(_) => {
+                let mut values = Vec::new();
+                for encapsulation in encapsulations(ctx) {
+                    for value in encapsulation_values(self, encapsulation) {
+                        if !values.contains(&value) {
+                            values.push(value);
+                        }
+                    }
+                }
+                values
+            }
+            Field::FlowBytes => vec![FieldValue::Integer(flow.total_bytes() as u64)],
+            Field::FlowPackets => vec![FieldValue::Integer(flow.packets.len() as u64)],
+            Field::FlowStart => vec![FieldValue::Time(relative(flow.timestamp, ctx))],
+            Field::FlowDuration => {
+                let last = flow
+                    .packets
+                    .iter()
+                    .map(|packet| packet.timestamp)
+                    .fold(flow.timestamp, f64::max);
+                vec![FieldValue::Time(last - flow.timestamp)]
+            }
+            Field::FlowIssues => vec![FieldValue::Integer(flow.analysis.issues() as u64)],
+            Field::FlowRetransmissions => counter(flow.analysis.retransmissions),
+            Field::FlowDuplicateAcks => counter(flow.analysis.duplicate_acks),
+            Field::FlowOutOfOrder => counter(flow.analysis.out_of_order),
+            Field::FlowZeroWindows => counter(flow.analysis.zero_windows),
+            Field::Tls => {
+                if flow.tls.is_some() {
+                    vec![FieldValue::Integer(1)]
+                } else {
+                    Vec::new()
+                }
+            }
+            Field::TlsVersion
+            | Field::TlsServerName
+            | Field::TlsAlpn
+            | Field::TlsCipherSuite
+            | Field::TlsOfferedCipherSuite
+            | Field::TlsCertSubject
+            | Field::TlsCertIssuer
+            | Field::TlsCertSan
+            | Field::TlsJa3
+            | Field::TlsJa3s
+            | Field::TlsJa4 => tls_values(self, flow.tls.as_ref()),
+            Field::Dns => {
+                if flow.dns.is_empty() {
+                    Vec::new()
+                } else {
+                    vec![FieldValue::Integer(1)]
+                }
+            }
+            Field::DnsQueryName
+            | Field::DnsQueryType
+            | Field::DnsResponseCode
+            | Field::DnsAnswer
+            | Field::DnsLatency => flow
+                .dns
+                .iter()
+                .flat_map(|transaction| dns_values(self, transaction))
+                .collect(),
+        }
+    }
+}
+
+impl Layer {
+    fn matches(self, encapsulation: &Encapsulation) -> bool {
+        match (self, encapsulation) {
+            (Layer::Vlan, Encapsulation::Vlan(_)) | (Layer::Mpls, Encapsulation::Mpls(_)) => true,
+            (_, Encapsulation::Tunnel { kind, .. }) => matches!(
+                (self, kind),
+                (Layer::Tunnel, _)
+                    | (Layer::IpInIp, TunnelKind::IpInIp)
+                    | (Layer::Gre, TunnelKind::Gre { .. })
+                    | (Layer::Vxlan, TunnelKind::Vxlan { .. })
+                    | (Layer::Geneve, TunnelKind::Geneve { .. })
+            ),
+            _ => false,
+        }
+    }
+}
+
+/// Values one VLAN tag, MPLS label or tunnel gives the `vlan.id`, `mpls.label`,
+/// `gre.key`, `vxlan.vni`, `geneve.vni` and `tunnel.*` fields.
+fn encapsulation_values(field: Field, encapsulation: &Encapsulation) -> Vec<FieldValue> {
+    match (field, *encapsulation) {
+        (Field::VlanId, Encapsulation::Vlan(id)) => vec![FieldValue::Integer(id as u64)],
+        (Field::MplsLabel, Encapsulation::Mpls(label)) => vec![FieldValue::Integer(label as u64)],
+        (Field::TunnelAddress(side), Encapsulation::Tunnel { src, dst, .. }) => {
+            select(side, src, dst)
+                .into_iter()
+                .map(FieldValue::Address)
+                .collect()
+        }
+        (field, Encapsulation::Tunnel { kind, .. }) => match (field, kind) {
+            (Field::GreKey, TunnelKind::Gre { key: Some(key) })
+            | (Field::VxlanVni, TunnelKind::Vxlan { vni: key })
+            | (Field::GeneveVni, TunnelKind::Geneve { vni: key }) => {
+                vec![FieldValue::Integer(key as u64)]
+            }
+            _ => Vec::new(),
+        },
+        _ => Vec::new(),
+    }
+}
+
+/// Values of the `dns.*` fields for one transaction. Response codes match by name
+/// (`NXDOMAIN`) as well as by number (`3`).
+fn dns_values(field: Field, transaction: &DnsTransaction) -> Vec<FieldValue> {
+    let response = transaction.response.as_ref();
+    let texts: Vec<String> = match field {
+        Field::DnsQueryName => vec![transaction.name.clone()],
+        Field::DnsQueryType => vec![transaction.query_type_name()],
+        Field::DnsResponseCode => response
+            .map(|response| vec![rcode_name(response.rcode), response.rcode.to_string()])
+            .unwrap_or_default(),
+        Field::DnsAnswer => response
+            .into_iter()
+            .flat_map(|response| response.answers.iter())
+            .map(|record| record.data.clone())
+            .collect(),
+        Field::DnsLatency => {
+            return transaction
+                .latency()
+                .map(FieldValue::Time)
+                .into_iter()
+                .collect();
+        }
+        _ => Vec::new(),
+    };
+    texts.into_iter().map(FieldValue::Text).collect()
+}
+
+/// Values of the `tls.*` text fields; none for flows without TLS.
+fn tls_values(field: Field, tls: Option<&TlsInfo>) -> Vec<FieldValue> {
+    let Some(tls) = tls else {
+        return Vec::new();
+    };
+    let certificates = tls.certificates.iter();
+    let texts: Vec<String> = match field {
+        Field::TlsVersion => tls.version_name().into_iter().collect(),
+        Field::TlsServerName => tls.server_name.iter().cloned().collect(),
+        Field::TlsAlpn => tls.offered_alpn.iter().chain(&tls.alpn).cloned().collect(),
+        Field::TlsCipherSuite => tls.cipher_suite_name().into_iter().collect(),
+        Field::TlsOfferedCipherSuite => tls
+            .offered_cipher_suites
+            .iter()
+            .map(|id| cipher_suite_name(*id))
+            .collect(),
+        Field::TlsCertSubject => certificates.map(|cert| cert.subject.clone()).collect(),
+        Field::TlsCertIssuer => certificates.map(|cert| cert.issuer.clone()).collect(),
+        Field::TlsCertSan => certificates
+            .flat_map(|cert| cert.subject_alt_names.iter().cloned())
+            .collect(),
+        Field::TlsJa3 => tls.ja3.iter().cloned().collect(),
+        Field::TlsJa3s => tls.ja3s.iter().cloned().collect(),
+        Field::TlsJa4 => tls.ja4.iter().cloned().collect(),
+        _ => Vec::new(),
+    };
+    texts.into_iter().map(FieldValue::Text).collect()
+}
+
+pub fn family_of(ip: &IPAddress) -> AddressFamily {
+    match ip {
+        IPAddress::V4(_) => AddressFamily::V4,
+        IPAddress::V6(_) => AddressFamily::V6,
+    }
+}
+
+/// Text a free-text search term is compared with: the protocol label, both endpoints,
+/// packet tags, the TLS server name, DNS query names and, when names are preferred,
+/// resolved host names.
+pub fn search_text(ctx: &EvalContext, include_names: bool) -> Vec<String> {
+    let flow = ctx.flow;
+    let mut texts = vec![FlowFormatter::protocol(&flow.protocol)];
+    for endpoint in [&flow.source, &flow.destination] {
+        texts.push(FlowFormatter::endpoint(endpoint, false, None));
+        if include_names
+            && let Some(names) = ctx
+                .name_resolutions
+                .and_then(|names| names.get(&endpoint.ip))
+        {
+            texts.extend(names.iter().cloned());
+        }
+    }
+    texts.extend(packets(ctx).flat_map(|packet| packet.tags.iter().cloned()));
+    texts.extend(flow.tls.iter().filter_map(|tls| tls.server_name.clone()));
+    texts.extend(flow.dns.iter().map(|transaction| transaction.name.clone()));
+    texts
+}
+
+fn packets<'a>(ctx: &EvalContext<'a>) -> std::slice::Iter<'a, Packet> {
+    ctx.packet
+        .map(std::slice::from_ref)
+        .unwrap_or(&ctx.flow.packets)
+        .iter()
+}
+
+fn encapsulations<'a>(ctx: &EvalContext<'a>) -> impl Iterator<Item = &'a Encapsulation> {
+    packets(ctx).flat_map(|packet| packet.encapsulation.iter())
+}
+
+fn select<T>(side: Side, src: T, dst: T) -> Vec<T> {
+    match side {
+        Side::Either => vec![src, dst],
+        Side::Source => vec![src],
+        Side::Destination => vec![dst],
+    }
+}
+
+fn relative(timestamp: f64, ctx: &EvalContext) -> f64 {
+    ctx.timestamp_origin
+        .map(|origin| timestamp - origin)
+        .unwrap_or(timestamp)
+}
+
+fn counter(count: usize) -> Vec<FieldValue> {
+    vec![FieldValue::Integer(count as u64)]
+}
@@ -0,0 +1,143 @@
+use super::FilterError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Contains,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    Comma,
+    And,
+    Or,
+    Not,
+    In,
+    Compare(CompareOp),
+    /// Quoted string with escapes resolved.
+    Str(String),
+    /// Field names, numbers, addresses, host names and other unquoted literals.
+    Word(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    /// Character offset of the token in the query.
+    pub position: usize,
+}
+
+pub fn tokenize(query: &str) -> Result<Vec<Token>, FilterError> {
+    let chars: Vec<char> = query.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let position = i;
+        let next = chars.get(i + 1).copied();
+
+        let (kind, len) = match c {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => (TokenKind::LParen, 1),
+            ')' => (TokenKind::RParen, 1),
+            '{' => (TokenKind::LBrace, 1),
+            '}' => (TokenKind::RBrace, 1),
+            ',' => (TokenKind::Comma, 1),
+            '&' if next == Some('&') => (TokenKind::And, 2),
+            '|' if next == Some('|') => (TokenKind::Or, 2),
+            '=' if next == Some('=') => (TokenKind::Compare(CompareOp::Eq), 2),
+            '!' if next == Some('=') => (TokenKind::Compare(CompareOp::Ne), 2),
+            '!' => (TokenKind::Not, 1),
+            '>' if next == Some('=') => (TokenKind::Compare(CompareOp::Ge), 2),
+            '>' => (TokenKind::Compare(CompareOp::Gt), 1),
+            '<' if next == Some('=') => (TokenKind::Compare(CompareOp::Le), 2),
+            '<' => (TokenKind::Compare(CompareOp::Lt), 1),
+            '"' => {
+                let (value, len) = lex_string(&chars[i..], position)?;
+                (TokenKind::Str(value), len)
+            }
+            c if is_word_char(c) => {
+                let len = chars[i..].iter().take_while(|c| is_word_char(**c)).count();
+                let word: String = chars[i..i + len].iter().collect();
+                (keyword(&word).unwrap_or(TokenKind::Word(word)), len)
+            }
+            other => {
+                return Err(FilterError::new(
+                    position,
+                    format!("unexpected character '{other}'"),
+                ));
+            }
+        };
+
+        tokens.push(Token { kind, position });
+        i += len;
+    }
+
+    Ok(tokens)
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '.' | ':' | '/' | '-' | '_')
+}
+
+fn keyword(word: &str) -> Option<TokenKind> {
+    let kind = match word.to_ascii_lowercase().as_str() {
+        "and" => TokenKind::And,
+        "or" => TokenKind::Or,
+        "not" => TokenKind::Not,
+        "in" => TokenKind::In,
+        "eq" => TokenKind::Compare(CompareOp::Eq),
+        "ne" => TokenKind::Compare(CompareOp::Ne),
+        "gt" => TokenKind::Compare(CompareOp::Gt),
+        "ge" => TokenKind::Compare(CompareOp::Ge),
+        "lt" => TokenKind::Compare(CompareOp::Lt),
+        "le" => TokenKind::Compare(CompareOp::Le),
+        "contains" => TokenKind::Compare(CompareOp::Contains),
+        _ => return None,
+    };
+    Some(kind)
+}
+
+/// Reads a double-quoted string starting at `chars[0]`, returning the unescaped value
+/// and the number of characters consumed.
+fn lex_string(chars: &[char], position: usize) -> Result<(String, usize), FilterError> {
+    let mut value = String::new();
+    let mut i = 1;
+
+    while let Some(&c) = chars.get(i) {
+        match c {
+            '"' => return Ok((value, i + 1)),
+            '\\' => {
+                let escaped = chars.get(i + 1).copied().ok_or_else(|| {
+                    FilterError::new(position + i, "unterminated escape sequence")
+                })?;
+                value.push(match escaped {
+                    'n' => '\n',
+                    't' => '\t',
+                    'r' => '\r',
+                    other => other,
+                });
+                i += 2;
+            }
+            c => {
+                value.push(c);
+                i += 1;
+            }
+        }
+    }
+
+    Err(FilterError::new(position, "unterminated string"))
+}
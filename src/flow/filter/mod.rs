@@ -0,0 +1,220 @@
+mod expr;
+mod fields;
+mod lexer;
+
+#[cfg(test)]
+mod tests;
+
+use super::{Conversation, Endpoint, Flow, IPAddress, Packet, Protocol};
+use expr::Expr;
+use fields::EvalContext;
+pub use fields::FIELD_NAMES;
+use std::collections::HashMap;
+use std::fmt;
+
+/// A filter that failed to compile, with the character offset the problem was found at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterError {
+    position: usize,
+    message: String,
+}
+
+impl FilterError {
+    pub fn new(position: usize, message: impl Into<String>) -> Self {
+        Self {
+            position,
+            message: message.into(),
+        }
+    }
+
+    /// Zero-based character offset into the query.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at column {}", self.message, self.position + 1)
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+/// A compiled display filter such as `ip.addr == 10.0.0.0/8 && tcp.port in {80 443}`.
+/// An empty query compiles to a filter that matches everything.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DisplayFilter {
+    expr: Option<Expr>,
+}
+
+impl DisplayFilter {
+    pub fn compile(query: &str) -> Result<Self, FilterError> {
+        Ok(Self {
+            expr: expr::parse(query)?,
+        })
+    }
+
+    pub fn is_match_all(&self) -> bool {
+        self.expr.is_none()
+    }
+}
+
+/// A display filter bound to the presentation settings it is evaluated with: the
+/// timestamp origin for relative times, the name resolutions used by host names and
+/// the capture file names `frame.source` compares.
+#[derive(Debug, Clone)]
+pub struct FlowFilter<'a> {
+    filter: Result<DisplayFilter, FilterError>,
+    timestamp_origin: Option<f64>,
+    prefer_names: bool,
+    name_resolutions: Option<&'a HashMap<IPAddress, Vec<String>>>,
+    sources: &'a [String],
+}
+
+impl<'a> FlowFilter<'a> {
+    pub fn new(
+        query: impl AsRef<str>,
+        timestamp_origin: Option<f64>,
+        prefer_names: bool,
+        name_resolutions: Option<&'a HashMap<IPAddress, Vec<String>>>,
+    ) -> Self {
+        Self {
+            filter: DisplayFilter::compile(query.as_ref()),
+            timestamp_origin,
+            prefer_names,
+            name_resolutions,
+            sources: &[],
+        }
+    }
+
+    /// Like [`FlowFilter::new`], for a filter that was compiled beforehand.
+    pub fn compiled(
+        filter: DisplayFilter,
+        timestamp_origin: Option<f64>,
+        prefer_names: bool,
+        name_resolutions: Option<&'a HashMap<IPAddress, Vec<String>>>,
+    ) -> Self {
+        Self {
+            filter: Ok(filter),
+            timestamp_origin,
+            prefer_names,
+            name_resolutions,
+            sources: &[],
+        }
+    }
+
+    /// Names the capture files packets were read from (`ParseState::sources`), so
+    /// `frame.source` can match them.
+    pub fn with_sources(mut self, sources: &'a [String]) -> Self {
+        self.sources = sources;
+        self
+    }
+
+    /// Why the query didn't compile. An invalid filter matches nothing.
+    pub fn error(&self) -> Option<&FilterError> {
+        self.filter.as_ref().err()
+    }
+
+    pub fn matches_flow(&self, flow: &Flow) -> bool {
+        self.evaluate(flow, None)
+    }
+
+    /// Evaluates packet fields (`frame.len`, `tag`, addresses, ports) against `packet`
+    /// alone; flow fields still describe the whole flow.
+    pub fn matches_packet(&self, flow: &Flow, packet: &Packet) -> bool {
+        self.evaluate(flow, Some(packet))
+    }
+
+    pub fn is_match_all(&self) -> bool {
+        self.filter
+            .as_ref()
+            .is_ok_and(|filter| filter.is_match_all())
+    }
+
+    pub fn timestamp_origin(&self) -> Option<f64> {
+        self.timestamp_origin
+    }
+
+    fn evaluate(&self, flow: &Flow, packet: Option<&Packet>) -> bool {
+        let expr = match &self.filter {
+            Ok(DisplayFilter { expr: Some(expr) }) => expr,
+            Ok(DisplayFilter { expr: None }) => return true,
+            Err(_) => return false,
+        };
+        let ctx = EvalContext {
+            flow,
+            packet,
+            timestamp_origin: self.timestamp_origin,
+            name_resolutions: self.name_resolutions,
+            sources: self.sources,
+        };
+        expr.eval(&ctx, self.prefer_names)
+    }
+}
+
+pub struct FlowFormatter;
+
+impl FlowFormatter {
+    pub fn timestamp(timestamp: f64, origin: Option<f64>) -> String {
+        let relative = origin.map(|start| timestamp - start).unwrap_or(timestamp);
+        format!("{:.6}", relative)
+    }
+
+    pub fn ip_address(
+        ip: &IPAddress,
+        prefer_names: bool,
+        name_resolutions: Option<&HashMap<IPAddress, Vec<String>>>,
+    ) -> String {
+        if prefer_names
+            && let Some(first) = name_resolutions
+                .and_then(|m| m.get(ip))
+                .and_then(|names| names.first())
+        {
+            return first.clone();
+        }
+        ip.to_string()
+    }
+
+    pub fn endpoint(
+        endpoint: &Endpoint,
+        prefer_names: bool,
+        name_resolutions: Option<&HashMap<IPAddress, Vec<String>>>,
+    ) -> String {
+        let ip = Self::ip_address(&endpoint.ip, prefer_names, name_resolutions);
+        format!("{}:{}", ip, endpoint.port)
+    }
+
+    pub fn protocol(protocol: &Protocol) -> String {
+        match *protocol {
+            Protocol::TCP => "TCP".to_string(),
+            Protocol::UDP => "UDP".to_string(),
+            Protocol::ARP => "ARP".to_string(),
+            Protocol::ICMP => "ICMP".to_string(),
+            Protocol::IGMP => "IGMP".to_string(),
+            Protocol::ESP => "ESP".to_string(),
+            Protocol::AH => "AH".to_string(),
+            Protocol::ICMPV6 => "ICMPv6".to_string(),
+            Protocol::OSPF => "OSPF".to_string(),
+            Protocol::Other(n) => format!("Proto-{}", n),
+        }
+    }
+
+    /// Protocol of `flow` along with what tells it apart when it has no ports, e.g.
+    /// `ICMP (echo ID 7)`.
+    pub fn flow_protocol(flow: &Flow) -> String {
+        let protocol = Self::protocol(&flow.protocol);
+        match flow.conversation {
+            Conversation::Addresses => protocol,
+            conversation => format!("{protocol} ({conversation})"),
+        }
+    }
+
+    pub fn port(port: u16) -> String {
+        port.to_string()
+    }
+}
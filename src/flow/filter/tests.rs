@@ -0,0 +1,289 @@
+use super::{DisplayFilter, FlowFilter};
+use crate::flow::*;
+use std::collections::HashMap;
+
+fn packet(length: u16, tags: &[&str]) -> Packet {
+    Packet {
+        timestamp: 5.0,
+        src_ip: IPAddress::V4([10, 1, 2, 3]),
+        dst_ip: IPAddress::V4([192, 168, 0, 9]),
+        src_port: Some(51000),
+        dst_port: Some(443),
+        length,
+        data: Default::default(),
+        tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        ..Default::default()
+    }
+}
+
+fn sample_flow() -> Flow {
+    Flow {
+        timestamp: 5.0,
+        protocol: Protocol::TCP,
+        source: Endpoint::new(IPAddress::V4([10, 1, 2, 3]), 51000),
+        destination: Endpoint::new(IPAddress::V4([192, 168, 0, 9]), 443),
+        packets: vec![packet(60, &[]), packet(1500, &["RST", "TCP Dup ACK"])],
+        ..Default::default()
+    }
+}
+
+fn matches(query: &str) -> bool {
+    let filter = FlowFilter::new(query, Some(2.0), false, None);
+    assert!(filter.error().is_none(), "{query}: {:?}", filter.error());
+    filter.matches_flow(&sample_flow())
+}
+
+fn error_position(query: &str) -> usize {
+    DisplayFilter::compile(query).expect_err(query).position()
+}
+
+#[test]
+fn matches_addresses_and_networks() {
+    assert!(matches("ip.addr == 10.0.0.0/8"));
+    assert!(matches("ip.src == 10.1.2.3 && ip.dst == 192.168.0.0/16"));
+    assert!(!matches("ip.dst == 10.0.0.0/8"));
+    assert!(!matches("ipv6.addr == ::/0"));
+    assert!(matches("ip.addr != 172.16.0.0/12"));
+    assert!(!matches("ip.addr != 10.1.2.3"));
+}
+
+#[test]
+fn matches_sets_and_ranges() {
+    assert!(matches("tcp.port in {80 443}"));
+    assert!(matches("tcp.dstport in {80, 400..500}"));
+    assert!(!matches("tcp.port in {80 8080}"));
+    assert!(!matches("udp.port in {443}"));
+}
+
+#[test]
+fn integer_suffixes_scale_values() {
+    assert!(matches("flow.bytes > 1k"));
+    assert!(!matches("flow.bytes > 1M"));
+    assert!(matches("flow.bytes < 0x1000 && flow.packets == 2"));
+    assert!(matches("frame.len >= 1Ki"));
+}
+
+#[test]
+fn text_fields_support_contains() {
+    assert!(matches("tag contains \"RST\""));
+    assert!(matches("tag == \"TCP Dup ACK\""));
+    assert!(!matches("tag contains \"FIN\""));
+}
+
+#[test]
+fn boolean_operators_follow_precedence() {
+    // `and` binds tighter than `or`.
+    assert!(matches("udp || tcp && tcp.port == 443"));
+    assert!(!matches("(udp || tcp) && tcp.port == 80"));
+    assert!(matches("not udp and !(ip.addr == 1.1.1.1)"));
+    assert!(matches("flow.start == 3 or frame.time_relative > 100"));
+}
+
+#[test]
+fn bare_port_does_not_match_substrings() {
+    assert!(matches("443"));
+    assert!(!matches("44"));
+    assert!(!matches("4430"));
+}
+
+#[test]
+fn packet_fields_narrow_to_one_packet() {
+    let flow = sample_flow();
+    let filter = FlowFilter::new("frame.len > 100", None, false, None);
+    assert!(filter.matches_packet(&flow, &flow.packets[1]));
+    assert!(!filter.matches_packet(&flow, &flow.packets[0]));
+}
+
+#[test]
+fn source_fields_match_the_capture_file() {
+    let sources = ["host-a.pcapng".to_string(), "host-b.pcapng".to_string()];
+    let mut flow = sample_flow();
+    flow.packets[1].source = 1;
+    flow.packets[1].interface = 2;
+
+    let filter = FlowFilter::new("frame.source == \"host-b.pcapng\"", None, false, None)
+        .with_sources(&sources);
+    assert!(filter.matches_flow(&flow));
+    assert!(!filter.matches_packet(&flow, &flow.packets[0]));
+    let filter = FlowFilter::new(
+        "frame.source contains \"host-\" && frame.interface_id == 2",
+        None,
+        false,
+        None,
+    )
+    .with_sources(&sources);
+    assert!(filter.matches_packet(&flow, &flow.packets[1]));
+    let filter = FlowFilter::new("frame.source == \"host-c.pcapng\"", None, false, None)
+        .with_sources(&sources);
+    assert!(!filter.matches_flow(&flow));
+}
+
+#[test]
+fn host_names_match_resolved_addresses() {
+    let mut names = HashMap::new();
+    names.insert(
+        IPAddress::V4([192, 168, 0, 9]),
+        vec!["example.com".to_string()],
+    );
+    let filter = FlowFilter::new("ip.dst == example.com", None, false, Some(&names));
+    assert!(filter.matches_flow(&sample_flow()));
+    let filter = FlowFilter::new("ip.src == example.com", None, false, Some(&names));
+    assert!(!filter.matches_flow(&sample_flow()));
+}
+
+#[test]
+fn errors_report_positions() {
+    assert_eq!(error_position("tcp.prot == 80"), 0);
+    assert_eq!(error_position("tcp.port == eighty"), 12);
+    assert_eq!(error_position("(tcp"), 0);
+    assert_eq!(error_position("tcp &&"), 6);
+    assert_eq!(error_position("tcp.port contains \"8\""), 9);
+    assert_eq!(error_position("tcp.port in {80 9x}"), 16);
+    assert_eq!(error_position("tcp udp"), 4);
+    assert_eq!(error_position("tag == \"open"), 7);
+}
+
+#[test]
+fn invalid_filter_matches_nothing() {
+    let filter = FlowFilter::new("ip.addr ==", None, false, None);
+    let error = filter.error().expect("incomplete comparison");
+    assert_eq!(error.to_string(), "unexpected end of filter at column 11");
+    assert!(!filter.matches_flow(&sample_flow()));
+    assert!(!filter.is_match_all());
+}
+
+#[test]
+fn tls_fields_match_handshake_metadata() {
+    let flow = Flow {
+        tls: Some(tls::TlsInfo {
+            version: Some(0x0304),
+            server_name: Some("example.com".to_string()),
+            offered_alpn: vec!["h2".to_string(), "http/1.1".to_string()],
+            alpn: Some("h2".to_string()),
+            offered_cipher_suites: vec![0x1301, 0x1302],
+            cipher_suite: Some(0x1301),
+            certificates: vec![tls::CertificateInfo {
+                subject: "CN=example.com".to_string(),
+                issuer: "CN=Example CA".to_string(),
+                subject_alt_names: vec!["www.example.com".to_string()],
+                ..Default::default()
+            }],
+            ja3: Some("e7d705a3286e19ea42f587b344ee6865".to_string()),
+            ja4: Some("t13d1516h2_8daaf6152771_e5627efa2ab1".to_string()),
+            ja3s: Some("f4febc55ea12b31ae17cfb7e614afda8".to_string()),
+        }),
+        ..sample_flow()
+    };
+    let matches = |query: &str| {
+        let filter = FlowFilter::new(query, None, false, None);
+        assert!(filter.error().is_none(), "{query}: {:?}", filter.error());
+        filter.matches_flow(&flow)
+    };
+
+    assert!(matches("tls && tls.version == \"TLS 1.3\""));
+    assert!(matches("tls.sni == \"example.com\""));
+    assert!(matches("tls.alpn == \"http/1.1\""));
+    assert!(matches("tls.cipher == \"TLS_AES_128_GCM_SHA256\""));
+    assert!(matches("tls.offered_cipher contains \"AES_256\""));
+    assert!(matches("tls.cert.issuer contains \"Example CA\""));
+    assert!(matches("tls.cert.san == \"www.example.com\""));
+    assert!(!matches("tls.cert.subject contains \"other.org\""));
+    assert!(matches("example.com"));
+    assert!(matches("tls.ja3 == \"e7d705a3286e19ea42f587b344ee6865\""));
+    assert!(matches(
+        "tls.ja4 contains \"t13d\" && tls.ja3s contains \"f4febc\""
+    ));
+
+    assert!(!FlowFilter::new("tls", None, false, None).matches_flow(&sample_flow()));
+    assert!(!FlowFilter::new("tls.sni", None, false, None).matches_flow(&sample_flow()));
+}
+
+#[test]
+fn dns_fields_match_transactions() {
+    let flow = Flow {
+        dns: vec![dns::DnsTransaction {
+            id: 0x1234,
+            name: "missing.example.com".to_string(),
+            query_type: 28,
+            query_timestamp: Some(1.0),
+            response: Some(dns::DnsResponse {
+                rcode: 3,
+                answers: Vec::new(),
+                timestamp: 1.025,
+            }),
+        }],
+        ..sample_flow()
+    };
+    let matches = |query: &str| {
+        let filter = FlowFilter::new(query, None, false, None);
+        assert!(filter.error().is_none(), "{query}: {:?}", filter.error());
+        filter.matches_flow(&flow)
+    };
+
+    assert!(matches("dns && dns.qtype == AAAA"));
+    assert!(matches("dns.qname contains \"example\""));
+    assert!(matches("dns.rcode == NXDOMAIN"));
+    assert!(matches("dns.rcode == 3"));
+    assert!(!matches("dns.rcode == NOERROR"));
+    assert!(matches("dns.latency > 0.02 && dns.latency < 0.03"));
+    assert!(!matches("dns.answer"));
+    assert!(matches("missing.example"));
+
+    assert!(!FlowFilter::new("dns", None, false, None).matches_flow(&sample_flow()));
+}
+
+#[test]
+fn encapsulation_fields_match_tunnels_and_tags() {
+    let mut flow = sample_flow();
+    flow.packets[1].encapsulation = vec![
+        tunnel::Encapsulation::Vlan(100),
+        tunnel::Encapsulation::Tunnel {
+            kind: tunnel::TunnelKind::Vxlan { vni: 5001 },
+            src: IPAddress::V4([172, 16, 0, 1]),
+            dst: IPAddress::V4([172, 16, 0, 2]),
+        },
+    ];
+    let matches = |query: &str| {
+        let filter = FlowFilter::new(query, None, false, None);
+        assert!(filter.error().is_none(), "{query}: {:?}", filter.error());
+        filter.matches_flow(&flow)
+    };
+
+    assert!(matches("vlan && vlan.id == 100"));
+    assert!(matches("vxlan.vni == 5001 && tunnel"));
+    assert!(matches("tunnel.src == 172.16.0.0/24 && ip.src == 10.1.2.3"));
+    assert!(!matches("tunnel.dst == 10.0.0.0/8"));
+    assert!(!matches("gre || geneve || mpls || ipip"));
+    assert!(!matches("vlan.id == 200"));
+
+    let filter = FlowFilter::new("vxlan", None, false, None);
+    assert!(filter.matches_packet(&flow, &flow.packets[1]));
+    assert!(!filter.matches_packet(&flow, &flow.packets[0]));
+}
+
+#[test]
+fn portless_protocols_match_by_name() {
+    let ping = Flow {
+        protocol: Protocol::ICMP,
+        source: Endpoint::new(IPAddress::V4([10, 1, 2, 3]), 0),
+        destination: Endpoint::new(IPAddress::V4([192, 168, 0, 9]), 0),
+        conversation: Conversation::Echo(7),
+        ..sample_flow()
+    };
+    let arp = Flow {
+        protocol: Protocol::ARP,
+        ..ping.clone()
+    };
+    let matches = |flow: &Flow, query: &str| {
+        let filter = FlowFilter::new(query, None, false, None);
+        assert!(filter.error().is_none(), "{query}: {:?}", filter.error());
+        filter.matches_flow(flow)
+    };
+
+    assert!(matches(&ping, "icmp && ip && ip.proto == 1"));
+    assert!(!matches(&ping, "tcp || icmpv6 || tcp.port"));
+    assert!(!matches(&ping, "0"));
+    assert!(matches(&ping, "ICMP"));
+    assert!(matches(&arp, "arp && ip.addr == 10.1.2.3"));
+    assert!(!matches(&arp, "ip || ip.proto"));
+}
@@ -0,0 +1,51 @@
+/// An HTTP/1.x request and the response paired with it. Either side may be missing
+/// when the capture only holds the other one.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct HttpTransaction {
+    pub request: Option<HttpRequest>,
+    pub response: Option<HttpResponse>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct HttpRequest {
+    pub method: String,
+    pub uri: String,
+    /// e.g. `HTTP/1.1`.
+    pub version: String,
+    pub host: Option<String>,
+    pub content_type: Option<String>,
+    /// Length of the body after removing chunked transfer encoding.
+    pub body_length: usize,
+    /// Capture time of the packet completing the request headers.
+    pub timestamp: f64,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct HttpResponse {
+    pub version: String,
+    pub status: u16,
+    pub reason: String,
+    pub content_type: Option<String>,
+    /// Length of the body after removing chunked transfer encoding.
+    pub body_length: usize,
+    /// Capture time of the packet completing the response headers.
+    pub timestamp: f64,
+}
+
+impl HttpTransaction {
+    /// Seconds from the request headers to the response headers.
+    pub fn response_time(&self) -> Option<f64> {
+        let request = self.request.as_ref()?;
+        let response = self.response.as_ref()?;
+        Some(response.timestamp - request.timestamp)
+    }
+
+    /// When the transaction started, i.e. its request or else its response.
+    pub fn timestamp(&self) -> f64 {
+        self.request
+            .as_ref()
+            .map(|request| request.timestamp)
+            .or_else(|| self.response.as_ref().map(|response| response.timestamp))
+            .unwrap_or_default()
+    }
+}
@@ -1,7 +1,12 @@
 use std::cmp::Ordering;
 use std::fmt;
 
+pub mod dns;
 pub mod filter;
+pub mod http;
+pub mod storage;
+pub mod tls;
+pub mod tunnel;
 
 #[cfg(test)]
 mod tests;
@@ -16,7 +21,25 @@ pub enum IPAddress {
 pub enum Protocol {
     TCP,
     UDP,
+    /// Any other IP protocol, by number.
     Other(u8),
+    /// ARP, which isn't carried in IP; its flows are between the sender and target
+    /// protocol addresses.
+    ARP,
+}
+
+/// What tells apart flows between the same two addresses when there are no ports.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Conversation {
+    /// Nothing beyond addresses and ports, as for TCP, UDP and ARP.
+    #[default]
+    Addresses,
+    /// ICMP or ICMPv6 echo requests and replies, by identifier.
+    Echo(u16),
+    /// Other ICMP or ICMPv6 messages, by type and code.
+    Message { icmp_type: u8, code: u8 },
+    /// An IPsec security association (ESP or AH), by SPI. Each direction has its own.
+    SecurityAssociation(u32),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -37,7 +60,40 @@ pub struct Flow {
     pub protocol: Protocol,
     pub source: Endpoint,
     pub destination: Endpoint,
+    /// Set for protocols without ports; the endpoints' ports are 0 then.
+    pub conversation: Conversation,
     pub packets: Vec<Packet>,
+    pub analysis: TcpAnalysis,
+    /// Handshake metadata, for flows that carry TLS.
+    pub tls: Option<tls::TlsInfo>,
+    /// HTTP/1.x requests and responses, in the order the requests were sent.
+    pub http: Vec<http::HttpTransaction>,
+    /// DNS queries and their responses, in the order the queries were sent.
+    pub dns: Vec<dns::DnsTransaction>,
+}
+
+/// Findings of the TCP expert analysis, attached to packets as tags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TcpAnalysisFlag {
+    Retransmission,
+    FastRetransmission,
+    DuplicateAck,
+    OutOfOrder,
+    ZeroWindow,
+    WindowFull,
+    KeepAlive,
+}
+
+/// Per-flow counts of [`TcpAnalysisFlag`]s.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TcpAnalysis {
+    pub retransmissions: usize,
+    pub fast_retransmissions: usize,
+    pub duplicate_acks: usize,
+    pub out_of_order: usize,
+    pub zero_windows: usize,
+    pub window_full: usize,
+    pub keep_alives: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -48,8 +104,21 @@ pub struct Packet {
     pub src_port: Option<u16>,
     pub dst_port: Option<u16>,
     pub length: u16,
-    pub data: Vec<u8>,
+    /// Captured bytes, read from the capture file on demand.
+    pub data: storage::PacketData,
     pub tags: Vec<String>,
+    /// Index into the capture's interface list (`ParseState::interfaces`).
+    pub interface: usize,
+    /// Index of the capture file the packet was read from (`ParseState::sources`).
+    pub source: usize,
+    /// Timestamp as recorded in the capture, in units of the interface's resolution,
+    /// so exports reproduce it exactly.
+    pub timestamp_ticks: u64,
+    /// Length of the packet on the wire, which exceeds `data.len()` when truncated by the snaplen.
+    pub original_length: u32,
+    /// Layers the packet was carried in, outermost first. Its addresses and ports are
+    /// those of the innermost headers.
+    pub encapsulation: Vec<tunnel::Encapsulation>,
 }
 
 impl Default for Flow {
@@ -65,7 +134,32 @@ impl Default for Flow {
                 ip: IPAddress::V4([0, 0, 0, 0]),
                 port: 0,
             },
+            conversation: Conversation::Addresses,
             packets: Vec::new(),
+            analysis: TcpAnalysis::default(),
+            tls: None,
+            http: Vec::new(),
+            dns: Vec::new(),
+        }
+    }
+}
+
+impl Default for Packet {
+    fn default() -> Self {
+        Packet {
+            timestamp: 0.0,
+            src_ip: IPAddress::V4([0, 0, 0, 0]),
+            dst_ip: IPAddress::V4([0, 0, 0, 0]),
+            src_port: None,
+            dst_port: None,
+            length: 0,
+            data: storage::PacketData::default(),
+            tags: Vec::new(),
+            interface: 0,
+            source: 0,
+            timestamp_ticks: 0,
+            original_length: 0,
+            encapsulation: Vec::new(),
         }
     }
 }
@@ -81,6 +175,15 @@ impl fmt::Display for Flow {
 }
 
 impl Flow {
+    pub fn key(&self) -> FlowKey {
+        FlowKey::new(
+            self.source,
+            self.destination,
+            self.protocol,
+            self.conversation,
+        )
+    }
+
     /// Returns the total number of bytes observed across all packets in this flow.
     pub fn total_bytes(&self) -> usize {
         self.packets
@@ -90,17 +193,104 @@ impl Flow {
     }
 }
 
+impl TcpAnalysisFlag {
+    /// Tag text, matching Wireshark's expert info wording.
+    pub fn label(self) -> &'static str {
+        match self {
+            TcpAnalysisFlag::Retransmission => "TCP Retransmission",
+            TcpAnalysisFlag::FastRetransmission => "TCP Fast Retransmission",
+            TcpAnalysisFlag::DuplicateAck => "TCP Dup ACK",
+            TcpAnalysisFlag::OutOfOrder => "TCP Out-Of-Order",
+            TcpAnalysisFlag::ZeroWindow => "TCP ZeroWindow",
+            TcpAnalysisFlag::WindowFull => "TCP Window Full",
+            TcpAnalysisFlag::KeepAlive => "TCP Keep-Alive",
+        }
+    }
+}
+
+impl TcpAnalysis {
+    pub fn record(&mut self, flag: TcpAnalysisFlag) {
+        let counter = match flag {
+            TcpAnalysisFlag::Retransmission => &mut self.retransmissions,
+            TcpAnalysisFlag::FastRetransmission => &mut self.fast_retransmissions,
+            TcpAnalysisFlag::DuplicateAck => &mut self.duplicate_acks,
+            TcpAnalysisFlag::OutOfOrder => &mut self.out_of_order,
+            TcpAnalysisFlag::ZeroWindow => &mut self.zero_windows,
+            TcpAnalysisFlag::WindowFull => &mut self.window_full,
+            TcpAnalysisFlag::KeepAlive => &mut self.keep_alives,
+        };
+        *counter += 1;
+    }
+
+    /// Number of findings that point at a problem. Keep-alives are routine and left out,
+    /// so this is the value used to rank flows by health.
+    pub fn issues(&self) -> usize {
+        self.retransmissions
+            + self.fast_retransmissions
+            + self.duplicate_acks
+            + self.out_of_order
+            + self.zero_windows
+            + self.window_full
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct FlowKey {
     pub endpoints: FlowEndpoints,
     pub protocol: Protocol,
+    pub conversation: Conversation,
 }
 
 impl FlowKey {
     pub fn from_endpoints(source: Endpoint, destination: Endpoint, protocol: Protocol) -> Self {
+        Self::new(source, destination, protocol, Conversation::Addresses)
+    }
+
+    pub fn new(
+        source: Endpoint,
+        destination: Endpoint,
+        protocol: Protocol,
+        conversation: Conversation,
+    ) -> Self {
         Self {
             endpoints: FlowEndpoints::new(source, destination),
             protocol,
+            conversation,
+        }
+    }
+}
+
+impl Protocol {
+    pub const ICMP: Protocol = Protocol::Other(1);
+    pub const IGMP: Protocol = Protocol::Other(2);
+    pub const ESP: Protocol = Protocol::Other(50);
+    pub const AH: Protocol = Protocol::Other(51);
+    pub const ICMPV6: Protocol = Protocol::Other(58);
+    pub const OSPF: Protocol = Protocol::Other(89);
+
+    /// IP protocol number; `None` for ARP.
+    pub fn number(self) -> Option<u8> {
+        match self {
+            Protocol::TCP => Some(6),
+            Protocol::UDP => Some(17),
+            Protocol::Other(number) => Some(number),
+            Protocol::ARP => None,
+        }
+    }
+
+    /// Whether flows of the protocol are told apart by ports.
+    pub fn has_ports(self) -> bool {
+        matches!(self, Protocol::TCP | Protocol::UDP)
+    }
+}
+
+impl fmt::Display for Conversation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Conversation::Addresses => Ok(()),
+            Conversation::Echo(id) => write!(f, "echo ID {id}"),
+            Conversation::Message { icmp_type, code } => write!(f, "type {icmp_type} code {code}"),
+            Conversation::SecurityAssociation(spi) => write!(f, "SPI 0x{spi:08x}"),
         }
     }
 }
@@ -0,0 +1,112 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// A capture file kept open so packet bytes can be read back on demand.
+#[derive(Debug)]
+pub struct CaptureFile {
+    path: PathBuf,
+    file: File,
+}
+
+impl CaptureFile {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: File::open(path)?,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads `length` bytes at `offset`. Reads don't share a cursor, so any thread
+    /// holding the file can call this.
+    pub fn read_at(&self, offset: u64, length: usize) -> io::Result<Vec<u8>> {
+        let mut buf = vec![0; length];
+        read_exact_at(&self.file, &mut buf, offset)?;
+        Ok(buf)
+    }
+}
+
+#[cfg(unix)]
+fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buf.is_empty() {
+        match file.seek_read(buf, offset) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(read) => {
+                buf = &mut buf[read..];
+                offset += read as u64;
+            }
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(())
+}
+
+/// Captured bytes of a packet. Packets indexed from a capture file only remember
+/// where their bytes are, so loading a capture doesn't hold its contents in memory.
+#[derive(Debug, Clone)]
+pub enum PacketData {
+    /// Bytes held in memory, for packets that don't come from a file.
+    Memory(Arc<[u8]>),
+    /// `length` bytes at `offset` of `file`.
+    File {
+        file: Arc<CaptureFile>,
+        offset: u64,
+        length: u32,
+    },
+}
+
+impl PacketData {
+    pub fn len(&self) -> usize {
+        match self {
+            PacketData::Memory(bytes) => bytes.len(),
+            PacketData::File { length, .. } => *length as usize,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The bytes, read from the capture file when they aren't in memory.
+    pub fn load(&self) -> io::Result<Cow<'_, [u8]>> {
+        match self {
+            PacketData::Memory(bytes) => Ok(Cow::Borrowed(bytes)),
+            PacketData::File {
+                file,
+                offset,
+                length,
+            } => file.read_at(*offset, *length as usize).map(Cow::Owned),
+        }
+    }
+}
+
+impl Default for PacketData {
+    fn default() -> Self {
+        PacketData::Memory(Arc::from([]))
+    }
+}
+
+impl From<Vec<u8>> for PacketData {
+    fn from(bytes: Vec<u8>) -> Self {
+        PacketData::Memory(bytes.into())
+    }
+}
+
+impl From<&[u8]> for PacketData {
+    fn from(bytes: &[u8]) -> Self {
+        PacketData::Memory(bytes.into())
+    }
+}
@@ -8,6 +8,7 @@ fn sample_flow() -> Flow {
         source: Endpoint::new(IPAddress::V4([10, 0, 0, 1]), 12345),
         destination: Endpoint::new(IPAddress::V4([10, 0, 0, 2]), 80),
         packets: vec![],
+        ..Default::default()
     }
 }
 
@@ -21,8 +22,9 @@ fn total_bytes_sums_packet_lengths() {
             src_port: Some(10),
             dst_port: Some(20),
             length: 64,
-            data: vec![],
+            data: Default::default(),
             tags: vec![],
+            ..Default::default()
         },
         Packet {
             timestamp: 0.1,
@@ -31,8 +33,9 @@ fn total_bytes_sums_packet_lengths() {
             src_port: Some(10),
             dst_port: Some(20),
             length: 128,
-            data: vec![],
+            data: Default::default(),
             tags: vec![],
+            ..Default::default()
         },
     ];
 
@@ -42,6 +45,7 @@ fn total_bytes_sums_packet_lengths() {
         source: Endpoint::new(IPAddress::V4([10, 0, 0, 1]), 10),
         destination: Endpoint::new(IPAddress::V4([10, 0, 0, 2]), 20),
         packets,
+        ..Default::default()
     };
 
     assert_eq!(flow.total_bytes(), 64 + 128);
@@ -77,8 +81,21 @@ fn matches_ipv6_and_other_protocol() {
         source: Endpoint::new(IPAddress::V6([0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]), 443),
         destination: Endpoint::new(IPAddress::V6([0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]), 8443),
         packets: vec![],
+        ..Default::default()
     };
 
     assert!(FlowFilter::new("fe80:0:0:0:0:0:0:1", None, false, None).matches_flow(&flow));
     assert!(FlowFilter::new("proto-99", None, false, None).matches_flow(&flow));
 }
+
+#[test]
+fn tcp_analysis_issues_leave_out_keep_alives() {
+    let mut analysis = TcpAnalysis::default();
+    analysis.record(TcpAnalysisFlag::Retransmission);
+    analysis.record(TcpAnalysisFlag::DuplicateAck);
+    analysis.record(TcpAnalysisFlag::KeepAlive);
+
+    assert_eq!(analysis.retransmissions, 1);
+    assert_eq!(analysis.keep_alives, 1);
+    assert_eq!(analysis.issues(), 2);
+}
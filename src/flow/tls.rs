@@ -0,0 +1,64 @@
+use tls_parser::TlsCipherSuite;
+
+/// TLS handshake metadata of a flow, taken from its hellos and certificates.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TlsInfo {
+    /// Version negotiated by the Server Hello, e.g. `0x0304` for TLS 1.3.
+    pub version: Option<u16>,
+    /// Host name of the client's Server Name Indication.
+    pub server_name: Option<String>,
+    /// Protocols offered in the client's ALPN extension.
+    pub offered_alpn: Vec<String>,
+    /// Protocol the server selected through ALPN.
+    pub alpn: Option<String>,
+    pub offered_cipher_suites: Vec<u16>,
+    pub cipher_suite: Option<u16>,
+    /// Certificate chain sent by the server, leaf first.
+    pub certificates: Vec<CertificateInfo>,
+    /// Client fingerprints: the JA3 MD5 and the JA4 string.
+    pub ja3: Option<String>,
+    pub ja4: Option<String>,
+    /// Server fingerprint, the JA3S MD5.
+    pub ja3s: Option<String>,
+}
+
+/// The parts of an X.509 certificate worth showing and filtering on.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CertificateInfo {
+    pub subject: String,
+    pub issuer: String,
+    /// DNS names, addresses, e-mail addresses and URIs of the Subject Alternative Name
+    /// extension.
+    pub subject_alt_names: Vec<String>,
+    pub not_before: String,
+    pub not_after: String,
+}
+
+impl TlsInfo {
+    pub fn version_name(&self) -> Option<String> {
+        self.version.map(version_name)
+    }
+
+    pub fn cipher_suite_name(&self) -> Option<String> {
+        self.cipher_suite.map(cipher_suite_name)
+    }
+}
+
+/// Display name of a protocol version, e.g. `TLS 1.2`.
+pub fn version_name(version: u16) -> String {
+    match version {
+        0x0300 => "SSL 3.0".to_owned(),
+        0x0301 => "TLS 1.0".to_owned(),
+        0x0302 => "TLS 1.1".to_owned(),
+        0x0303 => "TLS 1.2".to_owned(),
+        0x0304 => "TLS 1.3".to_owned(),
+        other => format!("0x{other:04x}"),
+    }
+}
+
+/// IANA name of a cipher suite, e.g. `TLS_AES_128_GCM_SHA256`, or its hex id when unknown.
+pub fn cipher_suite_name(id: u16) -> String {
+    TlsCipherSuite::from_id(id)
+        .map(|suite| suite.name.to_owned())
+        .unwrap_or_else(|| format!("0x{id:04x}"))
+}
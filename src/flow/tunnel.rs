@@ -0,0 +1,55 @@
+use super::IPAddress;
+use std::fmt;
+
+/// A layer a packet was carried in before reaching the headers its flow is built from,
+/// such as a VLAN tag or a VXLAN tunnel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Encapsulation {
+    /// An 802.1Q or 802.1ad tag, with its VLAN ID.
+    Vlan(u16),
+    /// An MPLS label stack entry, with its label.
+    Mpls(u32),
+    /// A tunnel between two IP endpoints.
+    Tunnel {
+        kind: TunnelKind,
+        src: IPAddress,
+        dst: IPAddress,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TunnelKind {
+    /// IPv4 or IPv6 carried directly in IP (protocol 4 or 41).
+    IpInIp,
+    Gre {
+        key: Option<u32>,
+    },
+    Vxlan {
+        vni: u32,
+    },
+    Geneve {
+        vni: u32,
+    },
+}
+
+impl fmt::Display for Encapsulation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Encapsulation::Vlan(id) => write!(f, "VLAN {id}"),
+            Encapsulation::Mpls(label) => write!(f, "MPLS {label}"),
+            Encapsulation::Tunnel { kind, src, dst } => write!(f, "{kind} {src} → {dst}"),
+        }
+    }
+}
+
+impl fmt::Display for TunnelKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TunnelKind::IpInIp => write!(f, "IP-in-IP"),
+            TunnelKind::Gre { key: None } => write!(f, "GRE"),
+            TunnelKind::Gre { key: Some(key) } => write!(f, "GRE key {key}"),
+            TunnelKind::Vxlan { vni } => write!(f, "VXLAN {vni}"),
+            TunnelKind::Geneve { vni } => write!(f, "Geneve {vni}"),
+        }
+    }
+}
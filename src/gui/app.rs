@@ -1,23 +1,40 @@
-use crate::flow::filter::FlowFilter;
+use crate::export::export_flows;
+use crate::flow::filter::{FilterError, FlowFilter};
 use crate::flow::*;
 use crate::gui::assets::Assets;
 use crate::gui::components::{
-    FlowTable, PacketBytesView, PacketTable, ProtocolCategory, SearchBar, SettingsMenu, Toolbar,
-    histogram_from_flows, render_histogram,
+    DnsRow, DnsTable, FileMenu, FlowTable, FollowLine, FollowStreamView, HttpRow, HttpTable,
+    PacketBytesView, PacketTable, ProtocolCategory, ProtocolTreeView, SearchBar, SettingsMenu,
+    TlsDetailsView, Toolbar, histogram_from_flows, render_histogram,
 };
 use crate::gui::fonts;
 use crate::gui::layout::{BottomSplit, Layout};
 use crate::gui::theme::{ThemeMode, apply_theme};
-use crate::loader::{FlowLoadController, FlowLoadStatus};
+use crate::layers::dissector::DissectorRegistry;
+use crate::layers::tree::ProtocolTree;
+use crate::loader::recent::RecentFiles;
+use crate::loader::{FlowLoadController, FlowLoadStatus, display_paths};
+use crate::parser::decoder::dissect_packet;
+use crate::parser::follow::{FollowDirection, FollowFormat, FollowedStream, follow_stream};
+use crate::parser::pipeline::{FlowBatch, merge_flows};
+use crate::parser::state::CaptureInterface;
 use gpui::AsyncApp;
+use gpui::prelude::FluentBuilder;
 use gpui::*;
+use gpui_component::button::{Button, ButtonGroup};
 use gpui_component::input::InputEvent;
 use gpui_component::progress::Progress;
 use gpui_component::resizable::ResizableState;
+use gpui_component::spinner::Spinner;
 use gpui_component::table::TableEvent;
-use gpui_component::{ActiveTheme, Icon, IconName, Root, StyledExt};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use gpui_component::{ActiveTheme, Icon, IconName, Root, Selectable, Sizable, StyledExt};
+use pcap_parser::Linktype;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
 use tracing::{debug, info, trace, warn};
 
 struct FlowStore {
@@ -25,6 +42,8 @@ struct FlowStore {
     start_timestamp: Option<f64>,
     selected_flow: Option<FlowKey>,
     name_resolutions: HashMap<IPAddress, Vec<String>>,
+    interfaces: Vec<CaptureInterface>,
+    sources: Vec<String>,
 }
 
 impl FlowStore {
@@ -34,6 +53,8 @@ impl FlowStore {
             start_timestamp: None,
             selected_flow: None,
             name_resolutions: HashMap::new(),
+            interfaces: Vec::new(),
+            sources: Vec::new(),
         }
     }
 
@@ -42,6 +63,8 @@ impl FlowStore {
         flows: HashMap<FlowKey, Flow>,
         start_timestamp: Option<f64>,
         name_resolutions: HashMap<IPAddress, Vec<String>>,
+        interfaces: Vec<CaptureInterface>,
+        sources: Vec<String>,
     ) {
         let min_ts = flows
             .values()
@@ -51,27 +74,47 @@ impl FlowStore {
         let effective_start =
             start_timestamp.or_else(|| (min_ts != f64::INFINITY).then_some(min_ts));
 
-        if self.start_timestamp.is_none() {
-            self.start_timestamp = effective_start;
-        }
-
+        self.start_timestamp = effective_start.or(self.start_timestamp);
         self.flows = flows;
         self.name_resolutions = name_resolutions;
+        self.interfaces = interfaces;
+        self.sources = sources;
         info!(flow_count = self.flows.len(), "Flow store updated");
     }
 
-    fn filtered_flows(&self, search_text: &str, prefer_names: bool) -> Vec<(FlowKey, Flow)> {
+    /// Adds flows published while the capture is still loading.
+    fn merge_batch(&mut self, batch: FlowBatch) {
+        merge_flows(&mut self.flows, batch.flows);
+        if self.start_timestamp.is_none() {
+            self.start_timestamp = batch.start_timestamp;
+        }
+        self.name_resolutions = batch.name_resolutions;
+        self.interfaces = batch.interfaces;
+        self.sources = batch.sources;
+        trace!(flow_count = self.flows.len(), "Flow store extended");
+    }
+
+    /// Flows matching the display filter in `search_text`. A filter that doesn't compile
+    /// matches nothing and is returned alongside so the search bar can point at it.
+    fn filtered_flows(
+        &self,
+        search_text: &str,
+        prefer_names: bool,
+    ) -> (Vec<(FlowKey, Flow)>, Option<FilterError>) {
         let filter = FlowFilter::new(
             search_text,
             self.start_timestamp,
             prefer_names,
             Some(&self.name_resolutions),
-        );
-        self.flows
+        )
+        .with_sources(&self.sources);
+        let flows = self
+            .flows
             .iter()
             .filter(|(_, flow)| filter.matches_flow(flow))
             .map(|(k, v)| (*k, v.clone()))
-            .collect()
+            .collect();
+        (flows, filter.error().cloned())
     }
 
     fn select_flow(&mut self, flow_key: FlowKey) {
@@ -99,6 +142,14 @@ impl FlowStore {
         &self.name_resolutions
     }
 
+    fn interfaces(&self) -> &[CaptureInterface] {
+        &self.interfaces
+    }
+
+    fn sources(&self) -> &[String] {
+        &self.sources
+    }
+
     fn total_flows(&self) -> usize {
         self.flows.len()
     }
@@ -111,9 +162,14 @@ struct LoaderState {
 }
 
 impl LoaderState {
-    fn new(path: PathBuf) -> Self {
+    fn new(paths: Vec<PathBuf>, registry: Arc<DissectorRegistry>, follow: bool) -> Self {
+        let controller = if follow {
+            FlowLoadController::following(paths, registry)
+        } else {
+            FlowLoadController::with_registry(paths, registry)
+        };
         Self {
-            controller: FlowLoadController::new(path),
+            controller,
             progress: Some(0.0),
             error: None,
         }
@@ -122,7 +178,7 @@ impl LoaderState {
     fn poll(&mut self) -> FlowLoadStatus {
         let status = self.controller.poll();
         match &status {
-            FlowLoadStatus::Loading { progress } => {
+            FlowLoadStatus::Loading { progress, .. } => {
                 self.progress = Some(*progress);
             }
             FlowLoadStatus::Ready { .. } | FlowLoadStatus::Idle => {
@@ -136,6 +192,44 @@ impl LoaderState {
         status
     }
 
+    /// Aborts the load in progress, if any, and starts loading `paths`.
+    fn open(&mut self, paths: Vec<PathBuf>) {
+        self.controller.open(paths);
+        self.progress = Some(0.0);
+        self.error = None;
+    }
+
+    fn reload(&mut self) {
+        self.controller.reload();
+        self.progress = Some(0.0);
+        self.error = None;
+    }
+
+    fn cancel(&mut self) {
+        self.controller.cancel();
+        self.progress = None;
+    }
+
+    fn stop_following(&mut self) {
+        self.controller.stop_following();
+    }
+
+    fn is_loading(&self) -> bool {
+        self.controller.is_loading()
+    }
+
+    fn is_live(&self) -> bool {
+        self.controller.is_live()
+    }
+
+    fn is_following(&self) -> bool {
+        self.controller.is_following()
+    }
+
+    fn can_reload(&self) -> bool {
+        self.controller.can_reload()
+    }
+
     fn progress(&self) -> Option<f32> {
         self.progress
     }
@@ -148,7 +242,8 @@ impl LoaderState {
 struct FlowView {
     search_bar: SearchBar,
     table: FlowTable,
-    last_flow_keys: Vec<FlowKey>,
+    /// Keys and packet counts of the rows shown, which grow while a capture loads.
+    last_flow_rows: Vec<(FlowKey, usize)>,
     last_selected: Option<FlowKey>,
     last_start_timestamp: Option<f64>,
     last_prefer_names: bool,
@@ -193,7 +288,7 @@ impl FlowView {
         Self {
             search_bar,
             table,
-            last_flow_keys: Vec::new(),
+            last_flow_rows: Vec::new(),
             last_selected: None,
             last_start_timestamp: None,
             last_prefer_names: true,
@@ -204,8 +299,10 @@ impl FlowView {
         self.search_bar.entity().read(cx).value().to_string()
     }
 
-    fn search_bar(&self) -> SearchBar {
-        self.search_bar.clone()
+    fn search_bar(&self, error: Option<&FilterError>) -> SearchBar {
+        self.search_bar
+            .clone()
+            .error(error.map(ToString::to_string))
     }
 
     fn table(&self) -> FlowTable {
@@ -221,9 +318,12 @@ impl FlowView {
         prefer_names: bool,
         cx: &mut App,
     ) {
-        let new_keys: Vec<FlowKey> = flows.iter().map(|(key, _)| *key).collect();
+        let new_rows: Vec<(FlowKey, usize)> = flows
+            .iter()
+            .map(|(key, flow)| (*key, flow.packets.len()))
+            .collect();
         let prefer_changed = self.last_prefer_names != prefer_names;
-        if self.last_flow_keys == new_keys
+        if self.last_flow_rows == new_rows
             && self.last_selected == selected
             && self.last_start_timestamp == start_timestamp
             && !prefer_changed
@@ -245,7 +345,7 @@ impl FlowView {
             delegate.set_prefer_names(prefer_names);
             table.refresh(cx);
         });
-        self.last_flow_keys = new_keys;
+        self.last_flow_rows = new_rows;
         self.last_selected = selected;
         self.last_start_timestamp = start_timestamp;
         self.last_prefer_names = prefer_names;
@@ -264,11 +364,200 @@ impl FlowView {
     }
 }
 
+/// What the main area lists.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum MainMode {
+    #[default]
+    Flows,
+    HttpTransactions,
+    DnsLog,
+}
+
+/// HTTP transactions of the flows matching the display filter.
+struct HttpView {
+    table: HttpTable,
+    last_rows: Vec<(FlowKey, usize)>,
+    last_start_timestamp: Option<f64>,
+    last_prefer_names: bool,
+}
+
+impl HttpView {
+    fn new(window: &mut Window, cx: &mut Context<WirecrabApp>) -> Self {
+        let table = HttpTable::create(window, cx);
+
+        cx.subscribe_in(
+            table.entity(),
+            window,
+            |app, table_state, event, _window, cx| {
+                if let TableEvent::SelectRow(row_ix) = event {
+                    let state = table_state.read(cx);
+                    if let Some(row) = state.delegate().rows.get(*row_ix) {
+                        debug!(row = *row_ix, flow = ?row.flow_key, "HTTP transaction selected");
+                        app.on_flow_selected(row.flow_key);
+                        cx.notify();
+                    } else {
+                        warn!(row = *row_ix, "HTTP row selection out of bounds");
+                    }
+                }
+            },
+        )
+        .detach();
+
+        Self {
+            table,
+            last_rows: Vec::new(),
+            last_start_timestamp: None,
+            last_prefer_names: true,
+        }
+    }
+
+    fn table(&self) -> HttpTable {
+        self.table.clone()
+    }
+
+    fn update_table(
+        &mut self,
+        flows: &[(FlowKey, Flow)],
+        start_timestamp: Option<f64>,
+        name_resolutions: &HashMap<IPAddress, Vec<String>>,
+        prefer_names: bool,
+        cx: &mut App,
+    ) {
+        let rows_key: Vec<(FlowKey, usize)> = flows
+            .iter()
+            .filter(|(_, flow)| !flow.http.is_empty())
+            .map(|(key, flow)| (*key, flow.http.len()))
+            .collect();
+        if self.last_rows == rows_key
+            && self.last_start_timestamp == start_timestamp
+            && self.last_prefer_names == prefer_names
+        {
+            return;
+        }
+        let rows = HttpRow::from_flows(flows);
+        trace!(rows = rows.len(), "Refreshing HTTP transactions table");
+        self.table.update(cx, move |table, cx| {
+            let delegate = table.delegate_mut();
+            delegate.start_timestamp = start_timestamp;
+            delegate.name_resolutions = name_resolutions.clone();
+            delegate.prefer_names = prefer_names;
+            delegate.set_rows(rows);
+            table.refresh(cx);
+        });
+        self.last_rows = rows_key;
+        self.last_start_timestamp = start_timestamp;
+        self.last_prefer_names = prefer_names;
+    }
+}
+
+/// DNS transactions of the flows matching the display filter.
+struct DnsView {
+    table: DnsTable,
+    last_rows: Vec<(FlowKey, usize)>,
+    last_start_timestamp: Option<f64>,
+    last_prefer_names: bool,
+}
+
+impl DnsView {
+    fn new(window: &mut Window, cx: &mut Context<WirecrabApp>) -> Self {
+        let table = DnsTable::create(window, cx);
+
+        cx.subscribe_in(
+            table.entity(),
+            window,
+            |app, table_state, event, _window, cx| {
+                if let TableEvent::SelectRow(row_ix) = event {
+                    let state = table_state.read(cx);
+                    if let Some(row) = state.delegate().rows.get(*row_ix) {
+                        debug!(row = *row_ix, flow = ?row.flow_key, "DNS transaction selected");
+                        app.on_flow_selected(row.flow_key);
+                        cx.notify();
+                    } else {
+                        warn!(row = *row_ix, "DNS row selection out of bounds");
+                    }
+                }
+            },
+        )
+        .detach();
+
+        Self {
+            table,
+            last_rows: Vec::new(),
+            last_start_timestamp: None,
+            last_prefer_names: true,
+        }
+    }
+
+    fn table(&self) -> DnsTable {
+        self.table.clone()
+    }
+
+    fn update_table(
+        &mut self,
+        flows: &[(FlowKey, Flow)],
+        start_timestamp: Option<f64>,
+        name_resolutions: &HashMap<IPAddress, Vec<String>>,
+        prefer_names: bool,
+        cx: &mut App,
+    ) {
+        let rows_key: Vec<(FlowKey, usize)> = flows
+            .iter()
+            .filter(|(_, flow)| !flow.dns.is_empty())
+            .map(|(key, flow)| (*key, flow.dns.len()))
+            .collect();
+        if self.last_rows == rows_key
+            && self.last_start_timestamp == start_timestamp
+            && self.last_prefer_names == prefer_names
+        {
+            return;
+        }
+        let rows = DnsRow::from_flows(flows);
+        trace!(rows = rows.len(), "Refreshing DNS log");
+        self.table.update(cx, move |table, cx| {
+            let delegate = table.delegate_mut();
+            delegate.start_timestamp = start_timestamp;
+            delegate.name_resolutions = name_resolutions.clone();
+            delegate.prefer_names = prefer_names;
+            delegate.set_rows(rows);
+            table.refresh(cx);
+        });
+        self.last_rows = rows_key;
+        self.last_start_timestamp = start_timestamp;
+        self.last_prefer_names = prefer_names;
+    }
+}
+
+/// What the bottom pane shows for the selected flow.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum DetailMode {
+    #[default]
+    Packets,
+    FollowStream,
+}
+
+/// Reassembled payload of the flow shown in Follow Stream mode, with its rendered lines.
+struct FollowState {
+    flow_key: FlowKey,
+    packet_count: usize,
+    stream: FollowedStream,
+    lines: Rc<Vec<FollowLine>>,
+    list_state: ListState,
+}
+
 struct DetailPane {
     packet_table: Option<PacketTable>,
     split_state: Entity<ResizableState>,
     selected_packet: Option<Packet>,
+    /// Bytes of `selected_packet`, read from the capture file when it was selected.
+    selected_bytes: Vec<u8>,
     packet_bytes_list_state: Option<ListState>,
+    protocol_tree: Option<Rc<ProtocolTree>>,
+    /// Open tree nodes, kept across packets so the same layers stay expanded.
+    expanded_fields: HashSet<Vec<usize>>,
+    selected_field: Option<Vec<usize>>,
+    mode: DetailMode,
+    follow: Option<FollowState>,
+    follow_format: FollowFormat,
     last_flow_key: Option<FlowKey>,
     last_packet_count: usize,
     last_start_timestamp: Option<f64>,
@@ -281,7 +570,14 @@ impl DetailPane {
             packet_table: None,
             split_state: cx.new(|_| ResizableState::default()),
             selected_packet: None,
+            selected_bytes: Vec::new(),
             packet_bytes_list_state: None,
+            protocol_tree: None,
+            expanded_fields: HashSet::new(),
+            selected_field: None,
+            mode: DetailMode::default(),
+            follow: None,
+            follow_format: FollowFormat::default(),
             last_flow_key: None,
             last_packet_count: 0,
             last_start_timestamp: None,
@@ -298,7 +594,7 @@ impl DetailPane {
         prefer_names: bool,
         name_resolutions: &HashMap<IPAddress, Vec<String>>,
     ) {
-        let flow_key = FlowKey::from_endpoints(flow.source, flow.destination, flow.protocol);
+        let flow_key = flow.key();
         let packet_count = flow.packets.len();
         let needs_update = self.packet_table.is_none()
             || self.last_flow_key != Some(flow_key)
@@ -335,7 +631,7 @@ impl DetailPane {
             Self::subscribe_to_selection(&packet_table, window, cx);
             self.packet_table = Some(packet_table);
             self.split_state = cx.new(|_| ResizableState::default());
-            self.selected_packet = None;
+            self.set_selected_packet(None);
         }
 
         self.last_flow_key = Some(flow_key);
@@ -344,6 +640,13 @@ impl DetailPane {
         self.last_prefer_names = prefer_names;
     }
 
+    /// Passes the capture file names to the packet table, once there is one.
+    fn set_sources(&mut self, sources: &[String], cx: &mut Context<WirecrabApp>) {
+        if let Some(table) = &mut self.packet_table {
+            table.set_sources(sources, cx);
+        }
+    }
+
     fn subscribe_to_selection(
         packet_table: &PacketTable,
         window: &mut Window,
@@ -380,26 +683,116 @@ impl DetailPane {
     fn selected_packet_bytes(&self) -> Option<&[u8]> {
         self.selected_packet
             .as_ref()
-            .map(|packet| packet.data.as_slice())
+            .map(|_| self.selected_bytes.as_slice())
     }
 
     fn set_selected_packet(&mut self, packet: Option<Packet>) {
         self.selected_packet = packet;
-        if let Some(packet) = &self.selected_packet {
-            self.packet_bytes_list_state = Some(PacketBytesView::create_list_state(&packet.data));
+        self.selected_field = None;
+        self.protocol_tree = None;
+        self.selected_bytes = match &self.selected_packet {
+            Some(packet) => packet
+                .data
+                .load()
+                .map(Cow::into_owned)
+                .unwrap_or_else(|err| {
+                    warn!(error = %err, "Failed to read selected packet from the capture file");
+                    Vec::new()
+                }),
+            None => Vec::new(),
+        };
+        if self.selected_packet.is_some() {
+            self.packet_bytes_list_state =
+                Some(PacketBytesView::create_list_state(&self.selected_bytes));
         } else {
             self.packet_bytes_list_state = None;
         }
     }
 
+    /// Builds the protocol tree of the selected packet.
+    fn dissect_selected(&mut self, interfaces: &[CaptureInterface], registry: &DissectorRegistry) {
+        let Some(packet) = &self.selected_packet else {
+            return;
+        };
+        let linktype = interfaces
+            .get(packet.interface)
+            .map_or(Linktype::ETHERNET, |interface| interface.linktype);
+        self.protocol_tree = match dissect_packet(&self.selected_bytes, linktype, registry) {
+            Ok(context) => Some(Rc::new(context.tree)),
+            Err(err) => {
+                debug!(error = %err, "Failed to dissect selected packet");
+                None
+            }
+        };
+    }
+
+    fn toggle_field(&mut self, path: &[usize]) {
+        if !self.expanded_fields.remove(path) {
+            self.expanded_fields.insert(path.to_vec());
+        }
+    }
+
+    /// Selects a tree node and scrolls the byte grid to its first byte.
+    fn select_field(&mut self, path: &[usize]) {
+        self.selected_field = Some(path.to_vec());
+        if let (Some(range), Some(list_state)) =
+            (self.selected_field_range(), &self.packet_bytes_list_state)
+        {
+            list_state.scroll_to_reveal_item(PacketBytesView::row_of(range.start));
+        }
+    }
+
+    fn selected_field_range(&self) -> Option<Range<usize>> {
+        let path = self.selected_field.as_ref()?;
+        let node = self.protocol_tree.as_ref()?.node(path)?;
+        Some(node.range.clone())
+    }
+
+    /// Reassembles `flow` for Follow Stream mode unless it is already up to date.
+    fn ensure_follow(&mut self, flow: &Flow, interfaces: &[CaptureInterface]) {
+        let flow_key = flow.key();
+        let packet_count = flow.packets.len();
+        if self.follow.as_ref().is_some_and(|follow| {
+            follow.flow_key == flow_key && follow.packet_count == packet_count
+        }) {
+            return;
+        }
+
+        trace!(packet_count, "Following flow stream");
+        let stream = follow_stream(flow, interfaces);
+        let lines = FollowStreamView::lines(&stream, self.follow_format);
+        self.follow = Some(FollowState {
+            flow_key,
+            packet_count,
+            list_state: FollowStreamView::create_list_state(&lines),
+            lines: Rc::new(lines),
+            stream,
+        });
+    }
+
+    fn set_follow_format(&mut self, format: FollowFormat) {
+        self.follow_format = format;
+        if let Some(follow) = &mut self.follow {
+            let lines = FollowStreamView::lines(&follow.stream, format);
+            follow.list_state = FollowStreamView::create_list_state(&lines);
+            follow.lines = Rc::new(lines);
+        }
+    }
+
+    fn followed_payload(&self, direction: FollowDirection) -> Option<Vec<u8>> {
+        self.follow
+            .as_ref()
+            .map(|follow| follow.stream.payload(direction))
+    }
+
     fn has_content(&self) -> bool {
         self.packet_table.is_some()
     }
 
     fn close(&mut self, cx: &mut Context<WirecrabApp>) {
         self.packet_table = None;
-        self.selected_packet = None;
-        self.packet_bytes_list_state = None;
+        self.set_selected_packet(None);
+        self.follow = None;
         self.split_state = cx.new(|_| ResizableState::default());
         trace!("Detail pane closed");
         self.last_flow_key = None;
@@ -409,24 +802,68 @@ impl DetailPane {
 }
 
 pub struct WirecrabApp {
-    path: String,
+    /// The capture files shown, merged into one timeline when there are several.
+    paths: Vec<PathBuf>,
     loader: LoaderState,
     flows: FlowStore,
     flow_view: FlowView,
+    http_view: HttpView,
+    dns_view: DnsView,
+    main_mode: MainMode,
     detail_pane: DetailPane,
     main_split_state: Entity<ResizableState>,
     histogram_collapsed: bool,
     prefer_names: bool,
     theme_mode: ThemeMode,
+    /// Outcome of the last export, shown under the file name.
+    export_message: Option<String>,
+    /// Dissectors and decode-as rules used for loading and the protocol tree.
+    registry: Arc<DissectorRegistry>,
+    recent: RecentFiles,
+    /// Polls the loader until it finishes; replacing it stops the previous poll.
+    _loader_poll: Task<()>,
 }
 
 impl WirecrabApp {
-    fn new(path: PathBuf, window: &mut Window, cx: &mut Context<Self>) -> Self {
-        let loader = LoaderState::new(path.clone());
+    fn new(
+        paths: Vec<PathBuf>,
+        registry: Arc<DissectorRegistry>,
+        follow: bool,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) -> Self {
+        let loader = LoaderState::new(paths.clone(), registry.clone(), follow);
         let flow_view = FlowView::new(window, cx);
+        let http_view = HttpView::new(window, cx);
+        let dns_view = DnsView::new(window, cx);
         let detail_pane = DetailPane::new(cx);
         let main_split_state = cx.new(|_| ResizableState::default());
+        let mut recent = RecentFiles::load_default();
+        for path in &paths {
+            recent.add(path);
+        }
+
+        Self {
+            paths,
+            loader,
+            flows: FlowStore::new(),
+            flow_view,
+            http_view,
+            dns_view,
+            main_mode: MainMode::default(),
+            detail_pane,
+            main_split_state,
+            histogram_collapsed: false,
+            prefer_names: true,
+            theme_mode: ThemeMode::Dark,
+            export_message: None,
+            registry,
+            recent,
+            _loader_poll: Self::poll_loader(cx),
+        }
+    }
 
+    fn poll_loader(cx: &mut Context<Self>) -> Task<()> {
         cx.spawn(|view: gpui::WeakEntity<WirecrabApp>, cx: &mut AsyncApp| {
             let mut cx = cx.clone();
             async move {
@@ -444,27 +881,89 @@ impl WirecrabApp {
                         _ => break,
                     }
                 }
-                Ok::<(), anyhow::Error>(())
             }
         })
-        .detach();
+    }
 
-        Self {
-            path: path.to_string_lossy().to_string(),
-            loader,
-            flows: FlowStore::new(),
-            flow_view,
-            detail_pane,
-            main_split_state,
-            histogram_collapsed: false,
-            prefer_names: true,
-            theme_mode: ThemeMode::Dark,
+    /// Replaces the capture shown with `paths`, merged when there are several, aborting
+    /// the load in progress.
+    fn open_files(&mut self, paths: Vec<PathBuf>, cx: &mut Context<Self>) {
+        info!(paths = ?paths, "Opening capture files");
+        self.loader.open(paths.clone());
+        self.reset_capture(cx);
+        for path in &paths {
+            self.recent.add(path);
+        }
+        self.paths = paths;
+    }
+
+    /// The capture files as named in titles.
+    fn title(&self) -> String {
+        display_paths(&self.paths)
+    }
+
+    fn reload(&mut self, cx: &mut Context<Self>) {
+        if !self.loader.can_reload() {
+            self.export_message = Some("Standard input can't be reloaded".into());
+            cx.notify();
+            return;
+        }
+        info!(paths = ?self.paths, "Reloading capture file");
+        self.loader.reload();
+        self.reset_capture(cx);
+    }
+
+    /// Cancels the load, or finishes it with the packets read so far when following.
+    fn cancel_loading(&mut self, cx: &mut Context<Self>) {
+        if self.loader.is_following() {
+            self.loader.stop_following();
+            self.export_message = Some("Stopped following".into());
+        } else {
+            self.loader.cancel();
+            self.export_message = Some("Loading cancelled".into());
         }
+        cx.notify();
+    }
+
+    /// Clears what was shown of the previous load and starts polling the new one.
+    fn reset_capture(&mut self, cx: &mut Context<Self>) {
+        self.flows = FlowStore::new();
+        self.detail_pane.close(cx);
+        self.export_message = None;
+        self._loader_poll = Self::poll_loader(cx);
+        cx.notify();
+    }
+
+    /// Asks for capture files to open in place of the current ones.
+    fn prompt_open(&mut self, cx: &mut Context<Self>) {
+        let paths = cx.prompt_for_paths(PathPromptOptions {
+            files: true,
+            directories: false,
+            multiple: true,
+            prompt: Some("Open".into()),
+        });
+        cx.spawn(|view: gpui::WeakEntity<WirecrabApp>, cx: &mut AsyncApp| {
+            let mut cx = cx.clone();
+            async move {
+                let Ok(Ok(Some(paths))) = paths.await else {
+                    debug!("Open cancelled");
+                    return Ok::<(), anyhow::Error>(());
+                };
+                if paths.is_empty() {
+                    return Ok(());
+                }
+                view.update(&mut cx, |app: &mut WirecrabApp, cx| app.open_files(paths, cx))
+            }
+        })
+        .detach();
     }
 
     fn check_loader(&mut self, cx: &mut Context<Self>) -> bool {
         match self.loader.poll() {
-            FlowLoadStatus::Loading { .. } => {
+            FlowLoadStatus::Loading { batch, .. } => {
+                if let Some(batch) = batch {
+                    self.flows.merge_batch(batch);
+                }
                 cx.notify();
                 true
             }
@@ -472,9 +971,17 @@ impl WirecrabApp {
                 flows,
                 start_timestamp,
                 name_resolutions,
+                interfaces,
+                sources,
             } => {
                 info!(flow_count = flows.len(), "Loader ready with parsed flows");
-                self.flows.ingest(flows, start_timestamp, name_resolutions);
+                self.flows.ingest(
+                    flows,
+                    start_timestamp,
+                    name_resolutions,
+                    interfaces,
+                    sources,
+                );
                 cx.notify();
                 false
             }
@@ -487,6 +994,115 @@ impl WirecrabApp {
         }
     }
 
+    /// Asks for a destination and writes the flows matching the current filter to a pcapng.
+    fn export_filtered_view(&mut self, cx: &mut Context<Self>) {
+        let query = self.flow_view.query(cx);
+        let (flows, filter_error) = self.flows.filtered_flows(&query, self.prefer_names);
+        if let Some(error) = filter_error {
+            self.export_message = Some(format!("Cannot export: {error}"));
+            cx.notify();
+            return;
+        }
+        if flows.is_empty() {
+            self.export_message = Some("Nothing to export: no flows match the filter".into());
+            cx.notify();
+            return;
+        }
+
+        let capture = self.paths.first().map_or(Path::new(""), PathBuf::as_path);
+        let directory = capture.parent().map(Path::to_path_buf).unwrap_or_default();
+        let stem = capture
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| "capture".into());
+        let suggested_name = format!("{stem}-filtered.pcapng");
+        let destination = cx.prompt_for_new_path(&directory, Some(&suggested_name));
+        let interfaces = self.flows.interfaces().to_vec();
+        let name_resolutions = self.flows.name_resolutions().clone();
+
+        cx.spawn(|view: gpui::WeakEntity<WirecrabApp>, cx: &mut AsyncApp| {
+            let mut cx = cx.clone();
+            async move {
+                let Ok(Ok(Some(path))) = destination.await else {
+                    debug!("Export cancelled");
+                    return Ok::<(), anyhow::Error>(());
+                };
+                let target = path.clone();
+                let result = cx
+                    .background_executor()
+                    .spawn(async move {
+                        export_flows(
+                            &target,
+                            flows.iter().map(|(_, flow)| flow),
+                            &interfaces,
+                            &name_resolutions,
+                        )
+                    })
+                    .await;
+                view.update(&mut cx, |app: &mut WirecrabApp, cx| {
+                    app.export_message = Some(match result {
+                        Ok(summary) => {
+                            format!("Exported {} packets to {}", summary.packets, path.display())
+                        }
+                        Err(error) => {
+                            warn!(error = ?error, "Export failed");
+                            format!("Export failed: {error:#}")
+                        }
+                    });
+                    cx.notify();
+                })
+            }
+        })
+        .detach();
+    }
+
+    /// Asks for a destination and writes one direction of the followed stream to it.
+    fn save_followed_stream(&mut self, direction: FollowDirection, cx: &mut Context<Self>) {
+        let Some(payload) = self.detail_pane.followed_payload(direction) else {
+            return;
+        };
+
+        let capture = self.paths.first().map_or(Path::new(""), PathBuf::as_path);
+        let directory = capture.parent().map(Path::to_path_buf).unwrap_or_default();
+        let stem = capture
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| "capture".into());
+        let side = match direction {
+            FollowDirection::ClientToServer => "client",
+            FollowDirection::ServerToClient => "server",
+        };
+        let suggested_name = format!("{stem}-{side}.bin");
+        let destination = cx.prompt_for_new_path(&directory, Some(&suggested_name));
+
+        cx.spawn(|view: gpui::WeakEntity<WirecrabApp>, cx: &mut AsyncApp| {
+            let mut cx = cx.clone();
+            async move {
+                let Ok(Ok(Some(path))) = destination.await else {
+                    debug!("Saving followed stream cancelled");
+                    return Ok::<(), anyhow::Error>(());
+                };
+                let target = path.clone();
+                let length = payload.len();
+                let result = cx
+                    .background_executor()
+                    .spawn(async move { std::fs::write(&target, payload) })
+                    .await;
+                view.update(&mut cx, |app: &mut WirecrabApp, cx| {
+                    app.export_message = Some(match result {
+                        Ok(()) => format!("Saved {length} bytes to {}", path.display()),
+                        Err(error) => {
+                            warn!(error = ?error, "Saving followed stream failed");
+                            format!("Save failed: {error}")
+                        }
+                    });
+                    cx.notify();
+                })
+            }
+        })
+        .detach();
+    }
+
     fn on_flow_selected(&mut self, flow_key: FlowKey) {
         debug!(flow = ?flow_key, "Flow selected");
         self.flows.select_flow(flow_key);
@@ -504,6 +1120,8 @@ impl WirecrabApp {
             debug!("Packet selection cleared");
         }
         self.detail_pane.set_selected_packet(packet);
+        self.detail_pane
+            .dissect_selected(self.flows.interfaces(), &self.registry);
     }
 
     fn close_details(&mut self, cx: &mut Context<Self>) {
@@ -513,9 +1131,43 @@ impl WirecrabApp {
     }
 
     fn render_loader_status_bar(&self, cx: &mut Context<Self>) -> Option<AnyElement> {
+        if self.loader.is_live() && self.loader.progress().is_some() {
+            let headline = if self.loader.is_following() {
+                format!("Following {}", self.title())
+            } else {
+                format!("Reading {} live", self.title())
+            };
+            let status = div()
+                .id("loader_status_stream")
+                .bg(cx.theme().colors.secondary)
+                .border_t_1()
+                .border_color(cx.theme().colors.border)
+                .px_3()
+                .py_2()
+                .flex()
+                .items_center()
+                .gap_3()
+                .child(Spinner::new())
+                .child(
+                    div()
+                        .flex()
+                        .flex_col()
+                        .gap_1()
+                        .child(div().text_sm().font_bold().child(headline))
+                        .child(
+                            div()
+                                .text_xs()
+                                .text_color(cx.theme().colors.muted_foreground)
+                                .child("Flows are updated as packets arrive."),
+                        ),
+                );
+
+            return Some(status.into_any_element());
+        }
+
         if let Some(progress) = self.loader.progress() {
             let progress_percent = (progress * 100.0).clamp(0.0, 100.0);
-            let headline = format!("Loading {}", self.path);
+            let headline = format!("Loading {}", self.title());
 
             let status = div()
                 .id("loader_status_progress")
@@ -538,7 +1190,9 @@ impl WirecrabApp {
                             div()
                                 .text_xs()
                                 .text_color(cx.theme().colors.muted_foreground)
-                                .child("Parsing flows. Large captures can take a minute."),
+                                .child(
+                                    "Flows appear as they are parsed and can already be filtered.",
+                                ),
                         ),
                 )
                 .child(
@@ -562,7 +1216,7 @@ impl WirecrabApp {
         }
 
         if let Some(error) = self.loader.error() {
-            let message = format!("Wirecrab could not open {}.", self.path);
+            let message = format!("Wirecrab could not open {}.", self.title());
 
             let status = div()
                 .id("loader_status_error")
@@ -601,7 +1255,7 @@ impl Render for WirecrabApp {
         let loader_status = self.render_loader_status_bar(cx);
 
         let query = self.flow_view.query(cx);
-        let flows_vec = self.flows.filtered_flows(&query, self.prefer_names);
+        let (flows_vec, filter_error) = self.flows.filtered_flows(&query, self.prefer_names);
         let selected_flow = self.flows.selected_flow();
         let start_timestamp = self.flows.start_timestamp();
 
@@ -610,6 +1264,23 @@ impl Render for WirecrabApp {
 
         let name_resolutions = self.flows.name_resolutions().clone();
 
+        match self.main_mode {
+            MainMode::Flows => {}
+            MainMode::HttpTransactions => self.http_view.update_table(
+                &flows_vec,
+                start_timestamp,
+                &name_resolutions,
+                self.prefer_names,
+                cx,
+            ),
+            MainMode::DnsLog => self.dns_view.update_table(
+                &flows_vec,
+                start_timestamp,
+                &name_resolutions,
+                self.prefer_names,
+                cx,
+            ),
+        }
         self.flow_view.update_table(
             flows_vec,
             selected_flow,
@@ -630,30 +1301,50 @@ impl Render for WirecrabApp {
                 self.prefer_names,
                 &name_resolutions,
             );
+            self.detail_pane.set_sources(self.flows.sources(), cx);
+            if self.detail_pane.mode == DetailMode::FollowStream {
+                self.detail_pane
+                    .ensure_follow(flow, self.flows.interfaces());
+            }
         } else if self.detail_pane.has_content() {
             self.detail_pane.close(cx);
         }
 
         let toolbar = {
             let flow_count = self.flows.total_flows();
-            let file_info = div()
-                .flex()
-                .items_center()
-                .gap_2()
-                .child(Icon::new(IconName::FolderOpen))
-                .child(
-                    div()
-                        .flex()
-                        .flex_col()
-                        .gap_0()
-                        .child(div().text_sm().child(self.path.clone()))
-                        .child(
-                            div()
-                                .text_xs()
-                                .text_color(cx.theme().colors.muted_foreground)
-                                .child(format!("{flow_count} flows")),
-                        ),
-                );
+            let file_menu = FileMenu::new(
+                self.recent.paths().to_vec(),
+                self.loader.is_loading(),
+                cx.listener(|app: &mut WirecrabApp, &_event: &(), _window, cx| {
+                    app.prompt_open(cx);
+                }),
+                cx.listener(|app: &mut WirecrabApp, path: &PathBuf, _window, cx| {
+                    app.open_files(vec![path.clone()], cx);
+                }),
+                cx.listener(|app: &mut WirecrabApp, &_event: &(), _window, cx| {
+                    app.reload(cx);
+                }),
+                cx.listener(|app: &mut WirecrabApp, &_event: &(), _window, cx| {
+                    app.cancel_loading(cx);
+                }),
+            )
+            .following(self.loader.is_following());
+            let file_info = div().flex().items_center().gap_2().child(file_menu).child(
+                div()
+                    .flex()
+                    .flex_col()
+                    .gap_0()
+                    .child(div().text_sm().child(self.title()))
+                    .child(
+                        div()
+                            .text_xs()
+                            .text_color(cx.theme().colors.muted_foreground)
+                            .child(match &self.export_message {
+                                Some(message) => format!("{flow_count} flows · {message}"),
+                                None => format!("{flow_count} flows"),
+                            }),
+                    ),
+            );
 
             let toggle_resolve_names =
                 cx.listener(|app: &mut WirecrabApp, &_event: &(), _window, cx| {
@@ -672,16 +1363,22 @@ impl Render for WirecrabApp {
                 on_theme_change_listener(&mode, window, cx);
             };
 
+            let export_listener =
+                cx.listener(|app: &mut WirecrabApp, &_event: &(), _window, cx| {
+                    app.export_filtered_view(cx);
+                });
+
             let settings_menu = SettingsMenu::new(
                 self.prefer_names,
                 toggle_resolve_names,
                 self.theme_mode,
                 on_theme_change,
-            );
+            )
+            .on_export(export_listener);
 
             Toolbar::new()
                 .left(file_info)
-                .center(self.flow_view.search_bar())
+                .center(self.flow_view.search_bar(filter_error.as_ref()))
                 .right(settings_menu)
         };
 
@@ -707,12 +1404,49 @@ impl Render for WirecrabApp {
             cx,
         );
 
-        let main_content = div().flex().flex_col().size_full().child(histogram).child(
-            div()
-                .flex_1()
-                .overflow_hidden()
-                .child(self.flow_view.table()),
-        );
+        let main_mode = self.main_mode;
+        let main_mode_toggle = ButtonGroup::new("main_mode")
+            .xsmall()
+            .outline()
+            .child(
+                Button::new("main_mode_flows")
+                    .label("Flows")
+                    .selected(main_mode == MainMode::Flows),
+            )
+            .child(
+                Button::new("main_mode_http")
+                    .label("HTTP Transactions")
+                    .selected(main_mode == MainMode::HttpTransactions),
+            )
+            .child(
+                Button::new("main_mode_dns")
+                    .label("DNS")
+                    .selected(main_mode == MainMode::DnsLog),
+            )
+            .on_click(
+                cx.listener(|app: &mut WirecrabApp, clicked: &Vec<usize>, _, cx| {
+                    app.main_mode = if clicked.contains(&2) {
+                        MainMode::DnsLog
+                    } else if clicked.contains(&1) {
+                        MainMode::HttpTransactions
+                    } else {
+                        MainMode::Flows
+                    };
+                    cx.notify();
+                }),
+            );
+        let main_table = match main_mode {
+            MainMode::Flows => self.flow_view.table().into_any_element(),
+            MainMode::HttpTransactions => self.http_view.table().into_any_element(),
+            MainMode::DnsLog => self.dns_view.table().into_any_element(),
+        };
+        let main_content = div()
+            .flex()
+            .flex_col()
+            .size_full()
+            .child(histogram)
+            .child(div().flex().px_2().py_1().child(main_mode_toggle))
+            .child(div().flex_1().overflow_hidden().child(main_table));
 
         let mut layout = Layout::new(self.main_split_state.clone())
             .header(toolbar)
@@ -721,39 +1455,137 @@ impl Render for WirecrabApp {
         if let (Some(flow), Some(packet_table)) =
             (current_flow.as_ref(), self.detail_pane.packet_table())
         {
-            let header_content = PacketTable::pane_header(flow, cx);
+            let mode = self.detail_pane.mode;
+            let mode_toggle = ButtonGroup::new("detail_mode")
+                .xsmall()
+                .outline()
+                .child(
+                    Button::new("detail_mode_packets")
+                        .label("Packets")
+                        .selected(mode == DetailMode::Packets),
+                )
+                .child(
+                    Button::new("detail_mode_follow")
+                        .label("Follow Stream")
+                        .selected(mode == DetailMode::FollowStream),
+                )
+                .on_click(
+                    cx.listener(|app: &mut WirecrabApp, clicked: &Vec<usize>, _, cx| {
+                        app.detail_pane.mode = if clicked.contains(&1) {
+                            DetailMode::FollowStream
+                        } else {
+                            DetailMode::Packets
+                        };
+                        cx.notify();
+                    }),
+                );
+            let header_content = div()
+                .flex()
+                .items_center()
+                .gap_2()
+                .child(div().flex_grow().child(PacketTable::pane_header(flow, cx)))
+                .child(mode_toggle);
             let close_handler = cx.listener(|app: &mut WirecrabApp, &_event: &(), _window, cx| {
                 app.close_details(cx);
                 cx.notify();
             });
 
-            let bytes_view = PacketBytesView::new(
-                self.detail_pane.packet_bytes_list_state.clone(),
-                self.detail_pane.selected_packet_bytes().map(|b| b.to_vec()),
-            );
-
-            let split = BottomSplit::new(
-                "packet_detail_split",
-                self.detail_pane.split_state(),
-                packet_table,
-                bytes_view,
-            )
-            .left_size(px(420.0))
-            .left_range(px(280.0)..Pixels::MAX)
-            .right_range(px(240.0)..Pixels::MAX);
-
-            layout = layout.bottom_closable_split(header_content, split, close_handler);
+            match (mode, &self.detail_pane.follow) {
+                (DetailMode::FollowStream, Some(follow)) => {
+                    let on_format = cx.listener(
+                        |app: &mut WirecrabApp, format: &FollowFormat, _window, cx| {
+                            app.detail_pane.set_follow_format(*format);
+                            cx.notify();
+                        },
+                    );
+                    let on_save = cx.listener(
+                        |app: &mut WirecrabApp, direction: &FollowDirection, _window, cx| {
+                            app.save_followed_stream(*direction, cx);
+                        },
+                    );
+                    let follow_view = FollowStreamView::new(
+                        follow.list_state.clone(),
+                        follow.lines.clone(),
+                        self.detail_pane.follow_format,
+                        FollowStreamView::summary(&follow.stream),
+                        move |format, window, cx| on_format(&format, window, cx),
+                        move |direction, window, cx| on_save(&direction, window, cx),
+                    );
+                    layout = layout.bottom_closable(header_content, follow_view, close_handler);
+                }
+                _ => {
+                    let bytes_view = PacketBytesView::new(
+                        self.detail_pane.packet_bytes_list_state.clone(),
+                        self.detail_pane.selected_packet_bytes().map(|b| b.to_vec()),
+                    )
+                    .highlight(self.detail_pane.selected_field_range());
+                    let on_toggle =
+                        cx.listener(|app: &mut WirecrabApp, path: &Vec<usize>, _window, cx| {
+                            app.detail_pane.toggle_field(path);
+                            cx.notify();
+                        });
+                    let on_select =
+                        cx.listener(|app: &mut WirecrabApp, path: &Vec<usize>, _window, cx| {
+                            app.detail_pane.select_field(path);
+                            cx.notify();
+                        });
+                    let tree_view = ProtocolTreeView::new(
+                        self.detail_pane.protocol_tree.clone(),
+                        self.detail_pane.expanded_fields.clone(),
+                        self.detail_pane.selected_field.clone(),
+                        move |path, window, cx| on_toggle(&path.to_vec(), window, cx),
+                        move |path, window, cx| on_select(&path.to_vec(), window, cx),
+                    );
+                    let packet_details = div()
+                        .flex()
+                        .flex_col()
+                        .size_full()
+                        .when_some(flow.tls.clone(), |details, tls| {
+                            details.child(div().flex_1().min_h_0().child(TlsDetailsView::new(tls)))
+                        })
+                        .child(div().flex_1().min_h_0().child(tree_view))
+                        .child(div().flex_1().min_h_0().child(bytes_view));
+
+                    let split = BottomSplit::new(
+                        "packet_detail_split",
+                        self.detail_pane.split_state(),
+                        packet_table,
+                        packet_details,
+                    )
+                    .left_size(px(420.0))
+                    .left_range(px(280.0)..Pixels::MAX)
+                    .right_range(px(240.0)..Pixels::MAX);
+
+                    layout = layout.bottom_closable_split(header_content, split, close_handler);
+                }
+            }
         }
 
         if let Some(status) = loader_status {
             layout = layout.status_bar(status);
         }
 
-        div().size_full().child(layout)
+        div()
+            .size_full()
+            .drag_over::<ExternalPaths>(|style, _, _, cx| style.bg(cx.theme().colors.drop_target))
+            .on_drop(cx.listener(
+                |app: &mut WirecrabApp, paths: &ExternalPaths, _window, cx| {
+                    if !paths.paths().is_empty() {
+                        app.open_files(paths.paths().to_vec(), cx);
+                    }
+                },
+            ))
+            .child(layout)
     }
 }
 
-pub fn run_ui(path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+/// Opens the window showing `paths`, merged into one timeline when there are several,
+/// and followed as it grows when `follow` is set and there is one.
+pub fn run_ui(
+    paths: Vec<PathBuf>,
+    registry: Arc<DissectorRegistry>,
+    follow: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
     let app = Application::new().with_assets(Assets);
     info!("Launching GPUI application");
 
@@ -774,7 +1606,8 @@ pub fn run_ui(path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
             ..Default::default()
         };
         cx.open_window(win_opts, move |window, cx| {
-            let app = cx.new(|cx| WirecrabApp::new(path.clone(), window, cx));
+            let app =
+                cx.new(|cx| WirecrabApp::new(paths.clone(), registry.clone(), follow, window, cx));
 
             cx.new(move |cx| Root::new(app, window, cx))
         })
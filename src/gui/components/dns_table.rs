@@ -0,0 +1,229 @@
+use crate::flow::dns::DnsTransaction;
+use crate::flow::filter::FlowFormatter;
+use crate::flow::*;
+use gpui::*;
+use gpui_component::table::{Column, ColumnSort, Table, TableDelegate, TableState};
+use gpui_component::{ActiveTheme, Sizable};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// One row of the DNS log: a query and its response, with the flow carrying them.
+#[derive(Debug, Clone)]
+pub struct DnsRow {
+    pub flow_key: FlowKey,
+    pub source: Endpoint,
+    pub destination: Endpoint,
+    pub transaction: DnsTransaction,
+}
+
+impl DnsRow {
+    /// Rows for every DNS transaction of `flows`.
+    pub fn from_flows<'a>(flows: impl IntoIterator<Item = &'a (FlowKey, Flow)>) -> Vec<Self> {
+        flows
+            .into_iter()
+            .flat_map(|(key, flow)| {
+                flow.dns.iter().map(move |transaction| DnsRow {
+                    flow_key: *key,
+                    source: flow.source,
+                    destination: flow.destination,
+                    transaction: transaction.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Per-capture log of DNS queries, their answers and resolution latency. Selecting a
+/// row selects the flow carrying the query.
+#[derive(IntoElement, Clone)]
+pub struct DnsTable {
+    state: Entity<TableState<DnsTableDelegate>>,
+}
+
+impl DnsTable {
+    pub fn create<Owner>(window: &mut Window, cx: &mut Context<Owner>) -> Self {
+        let state = cx.new(|cx| TableState::new(DnsTableDelegate::new(), window, cx));
+        Self { state }
+    }
+
+    pub fn update<F, R>(&self, cx: &mut App, f: F) -> R
+    where
+        F: FnOnce(
+            &mut TableState<DnsTableDelegate>,
+            &mut Context<TableState<DnsTableDelegate>>,
+        ) -> R,
+    {
+        self.state.update(cx, f)
+    }
+
+    pub fn entity(&self) -> &Entity<TableState<DnsTableDelegate>> {
+        &self.state
+    }
+}
+
+impl RenderOnce for DnsTable {
+    fn render(self, _window: &mut Window, cx: &mut App) -> impl IntoElement {
+        let table = Table::new(&self.state).bordered(false).xsmall();
+
+        div()
+            .size_full()
+            .overflow_hidden()
+            .rounded_none()
+            .border_1()
+            .border_color(cx.theme().colors.border)
+            .child(table)
+    }
+}
+
+pub struct DnsTableDelegate {
+    pub rows: Vec<DnsRow>,
+    pub columns: Vec<Column>,
+    pub active_sort: Option<(usize, ColumnSort)>,
+    pub start_timestamp: Option<f64>,
+    pub prefer_names: bool,
+    pub name_resolutions: HashMap<IPAddress, Vec<String>>,
+}
+
+impl DnsTableDelegate {
+    fn new() -> Self {
+        Self {
+            rows: Vec::new(),
+            columns: vec![
+                Column::new("timestamp", "Timestamp").width(110.).sortable(),
+                Column::new("source", "Client").width(170.).sortable(),
+                Column::new("destination", "Server").width(170.).sortable(),
+                Column::new("id", "ID").width(70.).sortable(),
+                Column::new("name", "Query").width(240.).sortable(),
+                Column::new("type", "Type").width(70.).sortable(),
+                Column::new("rcode", "Response").width(100.).sortable(),
+                Column::new("answers", "Answers").width(360.).sortable(),
+                Column::new("latency", "Latency").width(110.).sortable(),
+            ],
+            active_sort: Some((0, ColumnSort::Ascending)),
+            start_timestamp: None,
+            prefer_names: false,
+            name_resolutions: HashMap::new(),
+        }
+    }
+
+    pub fn set_rows(&mut self, rows: Vec<DnsRow>) {
+        self.rows = rows;
+        if let Some((col_ix, sort)) = self.active_sort {
+            self.sort_data(col_ix, sort);
+        }
+    }
+
+    fn display_endpoint(&self, endpoint: &Endpoint) -> String {
+        FlowFormatter::endpoint(endpoint, self.prefer_names, Some(&self.name_resolutions))
+    }
+
+    /// Text of `row` in the column `key`.
+    fn cell(&self, row: &DnsRow, key: &str) -> String {
+        let transaction = &row.transaction;
+        match key {
+            "timestamp" => format!(
+                "{:.6}",
+                transaction.timestamp() - self.start_timestamp.unwrap_or(0.0)
+            ),
+            "source" => self.display_endpoint(&row.source),
+            "destination" => self.display_endpoint(&row.destination),
+            "id" => format!("0x{:04x}", transaction.id),
+            "name" => transaction.name.clone(),
+            "type" => transaction.query_type_name(),
+            "rcode" => transaction.rcode_name().unwrap_or_default(),
+            "answers" => transaction
+                .response
+                .iter()
+                .flat_map(|response| response.answers.iter())
+                .map(|record| record.data.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+            "latency" => transaction
+                .latency()
+                .map(|latency| format!("{latency:.6}"))
+                .unwrap_or_default(),
+            _ => String::new(),
+        }
+    }
+
+    fn compare(&self, a: &DnsRow, b: &DnsRow, key: &str) -> Ordering {
+        let number = |row: &DnsRow| -> Option<f64> {
+            let transaction = &row.transaction;
+            match key {
+                "timestamp" => Some(transaction.timestamp()),
+                "id" => Some(f64::from(transaction.id)),
+                "latency" => transaction.latency(),
+                _ => None,
+            }
+        };
+        match key {
+            "timestamp" | "id" | "latency" => {
+                let (a, b) = (number(a), number(b));
+                a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+            }
+            _ => self.cell(a, key).cmp(&self.cell(b, key)),
+        }
+    }
+
+    fn sort_data(&mut self, col_ix: usize, sort: ColumnSort) {
+        let key = self.columns[col_ix].key.clone();
+        let mut rows = std::mem::take(&mut self.rows);
+        match sort {
+            ColumnSort::Ascending => rows.sort_by(|a, b| self.compare(a, b, &key)),
+            ColumnSort::Descending => rows.sort_by(|a, b| self.compare(b, a, &key)),
+            ColumnSort::Default => {}
+        }
+        self.rows = rows;
+    }
+}
+
+impl TableDelegate for DnsTableDelegate {
+    fn columns_count(&self, _cx: &App) -> usize {
+        self.columns.len()
+    }
+
+    fn rows_count(&self, _cx: &App) -> usize {
+        self.rows.len()
+    }
+
+    fn column(&self, col_ix: usize, _cx: &App) -> &Column {
+        &self.columns[col_ix]
+    }
+
+    fn render_td(
+        &mut self,
+        row_ix: usize,
+        col_ix: usize,
+        _window: &mut Window,
+        _cx: &mut Context<TableState<Self>>,
+    ) -> impl IntoElement {
+        div().child(self.cell(&self.rows[row_ix], &self.columns[col_ix].key))
+    }
+
+    fn render_tr(
+        &mut self,
+        row_ix: usize,
+        _window: &mut Window,
+        _cx: &mut Context<TableState<Self>>,
+    ) -> Stateful<Div> {
+        div().id(row_ix)
+    }
+
+    fn perform_sort(
+        &mut self,
+        col_ix: usize,
+        sort: ColumnSort,
+        _window: &mut Window,
+        _cx: &mut Context<TableState<Self>>,
+    ) {
+        self.active_sort = Some((col_ix, sort));
+        for (i, col) in self.columns.iter_mut().enumerate() {
+            if i == col_ix {
+                col.sort = Some(sort);
+            } else {
+                col.sort = Some(ColumnSort::Default);
+            }
+        }
+        self.sort_data(col_ix, sort);
+    }
+}
@@ -0,0 +1,104 @@
+use gpui::*;
+use gpui_component::button::{Button, ButtonVariants};
+use gpui_component::menu::{DropdownMenu, PopupMenu, PopupMenuItem};
+use gpui_component::{Icon, IconName};
+use std::path::PathBuf;
+use std::rc::Rc;
+
+type ActionHandler = Rc<dyn Fn(&(), &mut Window, &mut App)>;
+type OpenHandler = Rc<dyn Fn(&PathBuf, &mut Window, &mut App)>;
+
+/// Dropdown for switching capture files: open another one, pick a recent one, reload
+/// the current one or stop loading it.
+#[derive(IntoElement, Clone)]
+pub struct FileMenu {
+    recent: Vec<PathBuf>,
+    loading: bool,
+    following: bool,
+    on_open: ActionHandler,
+    on_open_recent: OpenHandler,
+    on_reload: ActionHandler,
+    on_cancel: ActionHandler,
+}
+
+impl FileMenu {
+    pub fn new(
+        recent: Vec<PathBuf>,
+        loading: bool,
+        on_open: impl Fn(&(), &mut Window, &mut App) + 'static,
+        on_open_recent: impl Fn(&PathBuf, &mut Window, &mut App) + 'static,
+        on_reload: impl Fn(&(), &mut Window, &mut App) + 'static,
+        on_cancel: impl Fn(&(), &mut Window, &mut App) + 'static,
+    ) -> Self {
+        Self {
+            recent,
+            loading,
+            following: false,
+            on_open: Rc::new(on_open),
+            on_open_recent: Rc::new(on_open_recent),
+            on_reload: Rc::new(on_reload),
+            on_cancel: Rc::new(on_cancel),
+        }
+    }
+
+    /// Offers "Stop Following" in place of "Cancel Loading", for a file followed as it
+    /// grows.
+    pub fn following(mut self, following: bool) -> Self {
+        self.following = following;
+        self
+    }
+}
+
+impl RenderOnce for FileMenu {
+    fn render(self, _window: &mut Window, _cx: &mut App) -> impl IntoElement {
+        let FileMenu {
+            recent,
+            loading,
+            following,
+            on_open,
+            on_open_recent,
+            on_reload,
+            on_cancel,
+        } = self;
+
+        Button::new("file_menu_button")
+            .icon(Icon::new(IconName::FolderOpen))
+            .ghost()
+            .compact()
+            .dropdown_menu_with_anchor(Corner::TopLeft, move |menu: PopupMenu, _window, _cx| {
+                let mut menu = menu.item(PopupMenuItem::new("Open…").on_click({
+                    let handler = on_open.clone();
+                    move |_event, window, cx| handler(&(), window, cx)
+                }));
+                if !recent.is_empty() {
+                    menu = menu.separator().label("Recent");
+                }
+                for path in &recent {
+                    let handler = on_open_recent.clone();
+                    let target = path.clone();
+                    menu = menu.item(
+                        PopupMenuItem::new(path.display().to_string())
+                            .on_click(move |_event, window, cx| handler(&target, window, cx)),
+                    );
+                }
+                menu.separator()
+                    .item(PopupMenuItem::new("Reload").on_click({
+                        let handler = on_reload.clone();
+                        move |_event, window, cx| handler(&(), window, cx)
+                    }))
+                    .item(
+                        PopupMenuItem::new(if following {
+                            "Stop Following"
+                        } else {
+                            "Cancel Loading"
+                        })
+                        .disabled(!loading)
+                        .on_click({
+                            let handler = on_cancel.clone();
+                            move |_event, window, cx| handler(&(), window, cx)
+                        }),
+                    )
+            })
+            .into_any_element()
+    }
+}
@@ -3,6 +3,7 @@ use crate::flow::filter::FlowFormatter;
 use gpui::*;
 use gpui_component::table::{Column, ColumnSort, Table, TableDelegate, TableState};
 use gpui_component::{ActiveTheme, Sizable};
+use std::cmp::Reverse;
 use std::ops::Range;
 use std::collections::HashMap;
 
@@ -85,7 +86,7 @@ impl FlowTableDelegate {
             selected_flow,
             columns: vec![
                 Column::new("timestamp", "Timestamp").width(110.).sortable(),
-                Column::new("protocol", "Protocol").width(100.).sortable(),
+                Column::new("protocol", "Protocol").width(160.).sortable(),
                 Column::new("source", "Source").width(170.).sortable(),
                 Column::new("source_port", "Src Port")
                     .width(100.)
@@ -98,6 +99,10 @@ impl FlowTableDelegate {
                     .sortable(),
                 Column::new("packets", "Packets").width(100.).sortable(),
                 Column::new("bytes", "Bytes").width(120.).sortable(),
+                Column::new("issues", "Issues").width(80.).sortable(),
+                Column::new("ja3", "JA3").width(250.).sortable(),
+                Column::new("ja3s", "JA3S").width(250.).sortable(),
+                Column::new("ja4", "JA4").width(290.).sortable(),
             ],
             active_sort: Some((0, ColumnSort::Ascending)),
             start_timestamp,
@@ -125,7 +130,15 @@ impl FlowTableDelegate {
         self.prefer_names = prefer_names;
     }
 
-    fn display_endpoint(&self, endpoint: &Endpoint) -> String {
+    /// An endpoint of `flow`, without a port when its protocol has none.
+    fn display_endpoint(&self, flow: &Flow, endpoint: &Endpoint) -> String {
+        if !flow.protocol.has_ports() {
+            return FlowFormatter::ip_address(
+                &endpoint.ip,
+                self.prefer_names,
+                Some(&self.name_resolutions),
+            );
+        }
         FlowFormatter::endpoint(
             endpoint,
             self.prefer_names,
@@ -147,10 +160,10 @@ impl FlowTableDelegate {
             },
             "protocol" => match sort {
                 ColumnSort::Ascending => self.flows.sort_by(|a, b| {
-                    format!("{:?}", a.1.protocol).cmp(&format!("{:?}", b.1.protocol))
+                    FlowFormatter::flow_protocol(&a.1).cmp(&FlowFormatter::flow_protocol(&b.1))
                 }),
                 ColumnSort::Descending => self.flows.sort_by(|a, b| {
-                    format!("{:?}", b.1.protocol).cmp(&format!("{:?}", a.1.protocol))
+                    FlowFormatter::flow_protocol(&b.1).cmp(&FlowFormatter::flow_protocol(&a.1))
                 }),
                 ColumnSort::Default => {}
             },
@@ -182,10 +195,10 @@ impl FlowTableDelegate {
             "source_port" => match sort {
                 ColumnSort::Ascending => self
                     .flows
-                    .sort_by(|a, b| a.1.source.port.cmp(&b.1.source.port)),
+                    .sort_by_key(|a| a.1.source.port),
                 ColumnSort::Descending => self
                     .flows
-                    .sort_by(|a, b| b.1.source.port.cmp(&a.1.source.port)),
+                    .sort_by_key(|a| Reverse(a.1.source.port)),
                 ColumnSort::Default => {}
             },
             "destination" => match sort {
@@ -228,30 +241,51 @@ impl FlowTableDelegate {
             "destination_port" => match sort {
                 ColumnSort::Ascending => self
                     .flows
-                    .sort_by(|a, b| a.1.destination.port.cmp(&b.1.destination.port)),
+                    .sort_by_key(|a| a.1.destination.port),
                 ColumnSort::Descending => self
                     .flows
-                    .sort_by(|a, b| b.1.destination.port.cmp(&a.1.destination.port)),
+                    .sort_by_key(|a| Reverse(a.1.destination.port)),
                 ColumnSort::Default => {}
             },
             "packets" => match sort {
                 ColumnSort::Ascending => self
                     .flows
-                    .sort_by(|a, b| a.1.packets.len().cmp(&b.1.packets.len())),
+                    .sort_by_key(|a| a.1.packets.len()),
                 ColumnSort::Descending => self
                     .flows
-                    .sort_by(|a, b| b.1.packets.len().cmp(&a.1.packets.len())),
+                    .sort_by_key(|a| Reverse(a.1.packets.len())),
                 ColumnSort::Default => {}
             },
             "bytes" => match sort {
                 ColumnSort::Ascending => self
                     .flows
-                    .sort_by(|a, b| a.1.total_bytes().cmp(&b.1.total_bytes())),
+                    .sort_by_key(|a| a.1.total_bytes()),
+                ColumnSort::Descending => self
+                    .flows
+                    .sort_by_key(|a| Reverse(a.1.total_bytes())),
+                ColumnSort::Default => {}
+            },
+            "issues" => match sort {
+                ColumnSort::Ascending => self
+                    .flows
+                    .sort_by_key(|a| a.1.analysis.issues()),
                 ColumnSort::Descending => self
                     .flows
-                    .sort_by(|a, b| b.1.total_bytes().cmp(&a.1.total_bytes())),
+                    .sort_by_key(|a| Reverse(a.1.analysis.issues())),
                 ColumnSort::Default => {}
             },
+            "ja3" | "ja3s" | "ja4" => {
+                let key = col.key.clone();
+                match sort {
+                    ColumnSort::Ascending => self
+                        .flows
+                        .sort_by(|a, b| fingerprint(&a.1, &key).cmp(&fingerprint(&b.1, &key))),
+                    ColumnSort::Descending => self
+                        .flows
+                        .sort_by(|a, b| fingerprint(&b.1, &key).cmp(&fingerprint(&a.1, &key))),
+                    ColumnSort::Default => {}
+                }
+            }
             _ => {}
         }
     }
@@ -281,6 +315,17 @@ impl FlowTableDelegate {
     }
 }
 
+/// TLS fingerprint shown in the `ja3`, `ja3s` or `ja4` column.
+fn fingerprint<'a>(flow: &'a Flow, column: &str) -> Option<&'a str> {
+    let tls = flow.tls.as_ref()?;
+    match column {
+        "ja3" => tls.ja3.as_deref(),
+        "ja3s" => tls.ja3s.as_deref(),
+        "ja4" => tls.ja4.as_deref(),
+        _ => None,
+    }
+}
+
 impl TableDelegate for FlowTableDelegate {
     fn columns_count(&self, _cx: &App) -> usize {
         self.columns.len()
@@ -312,13 +357,15 @@ impl TableDelegate for FlowTableDelegate {
                     format!("{:.6}", flow.timestamp)
                 }
             }
-            "protocol" => format!("{:?}", flow.protocol),
-            "source" => self.display_endpoint(&flow.source),
-            "source_port" => flow.source.port.to_string(),
-            "destination" => self.display_endpoint(&flow.destination),
-            "destination_port" => flow.destination.port.to_string(),
+            "protocol" => FlowFormatter::flow_protocol(flow),
+            "source" => self.display_endpoint(flow, &flow.source),
+            "source_port" if flow.protocol.has_ports() => flow.source.port.to_string(),
+            "destination" => self.display_endpoint(flow, &flow.destination),
+            "destination_port" if flow.protocol.has_ports() => flow.destination.port.to_string(),
             "packets" => flow.packets.len().to_string(),
             "bytes" => flow.total_bytes().to_string(),
+            "issues" => flow.analysis.issues().to_string(),
+            "ja3" | "ja3s" | "ja4" => fingerprint(flow, &col.key).unwrap_or_default().to_owned(),
             _ => String::new(),
         };
 
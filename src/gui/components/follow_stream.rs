@@ -0,0 +1,192 @@
+use crate::gui::fonts::JETBRAINS_MONO_FAMILY;
+use crate::parser::follow::{FollowDirection, FollowFormat, FollowedStream};
+use gpui::*;
+use gpui_component::button::{Button, ButtonGroup};
+use gpui_component::{ActiveTheme, Selectable, Sizable};
+use std::rc::Rc;
+
+const LINE_HEIGHT: f32 = 18.0;
+
+type FormatHandler = Rc<dyn Fn(FollowFormat, &mut Window, &mut App)>;
+type SaveHandler = Rc<dyn Fn(FollowDirection, &mut Window, &mut App)>;
+
+/// One rendered line of a followed stream.
+#[derive(Debug, Clone)]
+pub struct FollowLine {
+    pub direction: FollowDirection,
+    pub text: SharedString,
+}
+
+/// Wireshark-style "Follow Stream" pane: the reassembled payload of both directions,
+/// client data in red and server data in blue.
+#[derive(IntoElement)]
+pub struct FollowStreamView {
+    list_state: ListState,
+    lines: Rc<Vec<FollowLine>>,
+    format: FollowFormat,
+    summary: SharedString,
+    on_format: FormatHandler,
+    on_save: SaveHandler,
+}
+
+impl FollowStreamView {
+    pub fn new(
+        list_state: ListState,
+        lines: Rc<Vec<FollowLine>>,
+        format: FollowFormat,
+        summary: impl Into<SharedString>,
+        on_format: impl Fn(FollowFormat, &mut Window, &mut App) + 'static,
+        on_save: impl Fn(FollowDirection, &mut Window, &mut App) + 'static,
+    ) -> Self {
+        Self {
+            list_state,
+            lines,
+            format,
+            summary: summary.into(),
+            on_format: Rc::new(on_format),
+            on_save: Rc::new(on_save),
+        }
+    }
+
+    /// Splits the rendered stream into lines so long streams can be virtualized.
+    pub fn lines(stream: &FollowedStream, format: FollowFormat) -> Vec<FollowLine> {
+        stream
+            .render(format)
+            .into_iter()
+            .flat_map(|(direction, text)| {
+                text.split('\n')
+                    .map(|line| FollowLine {
+                        direction,
+                        text: line.to_string().into(),
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    pub fn create_list_state(lines: &[FollowLine]) -> ListState {
+        ListState::new(lines.len(), ListAlignment::Top, px(LINE_HEIGHT))
+    }
+
+    /// One-line description of the stream for the pane toolbar.
+    pub fn summary(stream: &FollowedStream) -> String {
+        let mut summary = format!(
+            "{} bytes {} · {} bytes {}",
+            stream.byte_count(FollowDirection::ClientToServer),
+            FollowDirection::ClientToServer.label(),
+            stream.byte_count(FollowDirection::ServerToClient),
+            FollowDirection::ServerToClient.label(),
+        );
+        if stream.missing_bytes > 0 {
+            summary.push_str(&format!(" · {} bytes missing", stream.missing_bytes));
+        }
+        summary
+    }
+
+    fn render_toolbar(&self, cx: &mut App) -> Div {
+        let on_format = self.on_format.clone();
+        let formats = ButtonGroup::new("follow_stream_format")
+            .xsmall()
+            .outline()
+            .children(FollowFormat::ALL.iter().map(|format| {
+                Button::new(SharedString::from(format!("follow_format_{format:?}")))
+                    .label(format.label())
+                    .selected(*format == self.format)
+            }))
+            .on_click(move |clicked: &Vec<usize>, window, cx| {
+                if let Some(format) = clicked.first().and_then(|ix| FollowFormat::ALL.get(*ix)) {
+                    on_format(*format, window, cx);
+                }
+            });
+
+        let save_button = |id: &'static str, direction: FollowDirection| {
+            let on_save = self.on_save.clone();
+            Button::new(id)
+                .xsmall()
+                .outline()
+                .label(format!("Save {}…", direction.label()))
+                .on_click(move |_event, window, cx| on_save(direction, window, cx))
+        };
+
+        div()
+            .flex()
+            .flex_row()
+            .flex_shrink_0()
+            .items_center()
+            .gap_2()
+            .px_3()
+            .py_1()
+            .border_b_1()
+            .border_color(cx.theme().colors.border)
+            .child(formats)
+            .child(
+                div()
+                    .flex_grow()
+                    .text_xs()
+                    .text_color(cx.theme().colors.muted_foreground)
+                    .child(self.summary.clone()),
+            )
+            .child(save_button(
+                "follow_save_client",
+                FollowDirection::ClientToServer,
+            ))
+            .child(save_button(
+                "follow_save_server",
+                FollowDirection::ServerToClient,
+            ))
+    }
+}
+
+impl RenderOnce for FollowStreamView {
+    fn render(self, _window: &mut Window, cx: &mut App) -> impl IntoElement {
+        let toolbar = self.render_toolbar(cx);
+        let base = div()
+            .flex()
+            .flex_col()
+            .size_full()
+            .bg(cx.theme().colors.background)
+            .border_1()
+            .border_color(cx.theme().colors.border)
+            .child(toolbar);
+
+        if self.lines.is_empty() {
+            return base.child(
+                div()
+                    .flex()
+                    .items_center()
+                    .justify_center()
+                    .size_full()
+                    .text_sm()
+                    .text_color(cx.theme().colors.muted_foreground)
+                    .child("This flow carries no application payload."),
+            );
+        }
+
+        let client_color = cx.theme().colors.red;
+        let server_color = cx.theme().colors.blue;
+        let lines = self.lines;
+        base.child(
+            div()
+                .font_family(JETBRAINS_MONO_FAMILY)
+                .text_sm()
+                .flex_1()
+                .size_full()
+                .px_3()
+                .child(
+                    list(self.list_state, move |ix, _window, _cx| {
+                        let line = &lines[ix];
+                        let color = match line.direction {
+                            FollowDirection::ClientToServer => client_color,
+                            FollowDirection::ServerToClient => server_color,
+                        };
+                        div()
+                            .min_h(px(LINE_HEIGHT))
+                            .text_color(color)
+                            .child(line.text.clone())
+                            .into_any_element()
+                    })
+                    .size_full(),
+                ),
+        )
+    }
+}
@@ -181,7 +181,7 @@ impl ProtocolCategory {
         match self {
             ProtocolCategory::Tcp => "tcp",
             ProtocolCategory::Udp => "udp",
-            ProtocolCategory::Other => "other",
+            ProtocolCategory::Other => "!tcp && !udp",
         }
     }
 
@@ -238,7 +238,7 @@ pub fn compute_histogram(
             match proto {
                 Protocol::TCP => tcp += 1.0,
                 Protocol::UDP => udp += 1.0,
-                Protocol::Other(_) => other += 1.0,
+                Protocol::Other(_) | Protocol::ARP => other += 1.0,
             }
         }
         return vec![HistogramBucket {
@@ -271,7 +271,7 @@ pub fn compute_histogram(
         match proto {
             Protocol::TCP => buckets[bucket_idx].tcp += 1.0,
             Protocol::UDP => buckets[bucket_idx].udp += 1.0,
-            Protocol::Other(_) => buckets[bucket_idx].other += 1.0,
+            Protocol::Other(_) | Protocol::ARP => buckets[bucket_idx].other += 1.0,
         }
     }
 
@@ -0,0 +1,250 @@
+use crate::flow::filter::FlowFormatter;
+use crate::flow::http::HttpTransaction;
+use crate::flow::*;
+use gpui::*;
+use gpui_component::table::{Column, ColumnSort, Table, TableDelegate, TableState};
+use gpui_component::{ActiveTheme, Sizable};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// One row of the HTTP transactions table: a transaction and the flow carrying it.
+#[derive(Debug, Clone)]
+pub struct HttpRow {
+    pub flow_key: FlowKey,
+    pub source: Endpoint,
+    pub destination: Endpoint,
+    pub transaction: HttpTransaction,
+}
+
+impl HttpRow {
+    /// Rows for every transaction of `flows`.
+    pub fn from_flows<'a>(flows: impl IntoIterator<Item = &'a (FlowKey, Flow)>) -> Vec<Self> {
+        flows
+            .into_iter()
+            .flat_map(|(key, flow)| {
+                flow.http.iter().map(move |transaction| HttpRow {
+                    flow_key: *key,
+                    source: flow.source,
+                    destination: flow.destination,
+                    transaction: transaction.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Per-capture table of HTTP requests and their responses. Selecting a row selects
+/// the flow carrying the transaction.
+#[derive(IntoElement, Clone)]
+pub struct HttpTable {
+    state: Entity<TableState<HttpTableDelegate>>,
+}
+
+impl HttpTable {
+    pub fn create<Owner>(window: &mut Window, cx: &mut Context<Owner>) -> Self {
+        let state = cx.new(|cx| TableState::new(HttpTableDelegate::new(), window, cx));
+        Self { state }
+    }
+
+    pub fn update<F, R>(&self, cx: &mut App, f: F) -> R
+    where
+        F: FnOnce(
+            &mut TableState<HttpTableDelegate>,
+            &mut Context<TableState<HttpTableDelegate>>,
+        ) -> R,
+    {
+        self.state.update(cx, f)
+    }
+
+    pub fn entity(&self) -> &Entity<TableState<HttpTableDelegate>> {
+        &self.state
+    }
+}
+
+impl RenderOnce for HttpTable {
+    fn render(self, _window: &mut Window, cx: &mut App) -> impl IntoElement {
+        let table = Table::new(&self.state).bordered(false).xsmall();
+
+        div()
+            .size_full()
+            .overflow_hidden()
+            .rounded_none()
+            .border_1()
+            .border_color(cx.theme().colors.border)
+            .child(table)
+    }
+}
+
+pub struct HttpTableDelegate {
+    pub rows: Vec<HttpRow>,
+    pub columns: Vec<Column>,
+    pub active_sort: Option<(usize, ColumnSort)>,
+    pub start_timestamp: Option<f64>,
+    pub prefer_names: bool,
+    pub name_resolutions: HashMap<IPAddress, Vec<String>>,
+}
+
+impl HttpTableDelegate {
+    fn new() -> Self {
+        Self {
+            rows: Vec::new(),
+            columns: vec![
+                Column::new("timestamp", "Timestamp").width(110.).sortable(),
+                Column::new("source", "Source").width(170.).sortable(),
+                Column::new("destination", "Destination")
+                    .width(170.)
+                    .sortable(),
+                Column::new("method", "Method").width(80.).sortable(),
+                Column::new("host", "Host").width(180.).sortable(),
+                Column::new("uri", "URI").width(300.).sortable(),
+                Column::new("status", "Status").width(80.).sortable(),
+                Column::new("content_type", "Content Type")
+                    .width(180.)
+                    .sortable(),
+                Column::new("request_bytes", "Request Body")
+                    .width(110.)
+                    .sortable(),
+                Column::new("response_bytes", "Response Body")
+                    .width(110.)
+                    .sortable(),
+                Column::new("response_time", "Response Time")
+                    .width(110.)
+                    .sortable(),
+            ],
+            active_sort: Some((0, ColumnSort::Ascending)),
+            start_timestamp: None,
+            prefer_names: false,
+            name_resolutions: HashMap::new(),
+        }
+    }
+
+    pub fn set_rows(&mut self, rows: Vec<HttpRow>) {
+        self.rows = rows;
+        if let Some((col_ix, sort)) = self.active_sort {
+            self.sort_data(col_ix, sort);
+        }
+    }
+
+    fn display_endpoint(&self, endpoint: &Endpoint) -> String {
+        FlowFormatter::endpoint(endpoint, self.prefer_names, Some(&self.name_resolutions))
+    }
+
+    /// Text of `row` in the column `key`.
+    fn cell(&self, row: &HttpRow, key: &str) -> String {
+        let request = row.transaction.request.as_ref();
+        let response = row.transaction.response.as_ref();
+        match key {
+            "timestamp" => {
+                let timestamp = row.transaction.timestamp();
+                format!("{:.6}", timestamp - self.start_timestamp.unwrap_or(0.0))
+            }
+            "source" => self.display_endpoint(&row.source),
+            "destination" => self.display_endpoint(&row.destination),
+            "method" => request.map(|r| r.method.clone()).unwrap_or_default(),
+            "host" => request.and_then(|r| r.host.clone()).unwrap_or_default(),
+            "uri" => request.map(|r| r.uri.clone()).unwrap_or_default(),
+            "status" => response
+                .map(|r| format!("{} {}", r.status, r.reason))
+                .unwrap_or_default(),
+            "content_type" => response
+                .and_then(|r| r.content_type.clone())
+                .unwrap_or_default(),
+            "request_bytes" => request
+                .map(|r| r.body_length.to_string())
+                .unwrap_or_default(),
+            "response_bytes" => response
+                .map(|r| r.body_length.to_string())
+                .unwrap_or_default(),
+            "response_time" => row
+                .transaction
+                .response_time()
+                .map(|time| format!("{time:.6}"))
+                .unwrap_or_default(),
+            _ => String::new(),
+        }
+    }
+
+    fn compare(&self, a: &HttpRow, b: &HttpRow, key: &str) -> Ordering {
+        let number = |row: &HttpRow| -> Option<f64> {
+            let request = row.transaction.request.as_ref();
+            let response = row.transaction.response.as_ref();
+            match key {
+                "timestamp" => Some(row.transaction.timestamp()),
+                "status" => response.map(|r| f64::from(r.status)),
+                "request_bytes" => request.map(|r| r.body_length as f64),
+                "response_bytes" => response.map(|r| r.body_length as f64),
+                "response_time" => row.transaction.response_time(),
+                _ => None,
+            }
+        };
+        match key {
+            "timestamp" | "status" | "request_bytes" | "response_bytes" | "response_time" => {
+                let (a, b) = (number(a), number(b));
+                a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+            }
+            _ => self.cell(a, key).cmp(&self.cell(b, key)),
+        }
+    }
+
+    fn sort_data(&mut self, col_ix: usize, sort: ColumnSort) {
+        let key = self.columns[col_ix].key.clone();
+        let mut rows = std::mem::take(&mut self.rows);
+        match sort {
+            ColumnSort::Ascending => rows.sort_by(|a, b| self.compare(a, b, &key)),
+            ColumnSort::Descending => rows.sort_by(|a, b| self.compare(b, a, &key)),
+            ColumnSort::Default => {}
+        }
+        self.rows = rows;
+    }
+}
+
+impl TableDelegate for HttpTableDelegate {
+    fn columns_count(&self, _cx: &App) -> usize {
+        self.columns.len()
+    }
+
+    fn rows_count(&self, _cx: &App) -> usize {
+        self.rows.len()
+    }
+
+    fn column(&self, col_ix: usize, _cx: &App) -> &Column {
+        &self.columns[col_ix]
+    }
+
+    fn render_td(
+        &mut self,
+        row_ix: usize,
+        col_ix: usize,
+        _window: &mut Window,
+        _cx: &mut Context<TableState<Self>>,
+    ) -> impl IntoElement {
+        div().child(self.cell(&self.rows[row_ix], &self.columns[col_ix].key))
+    }
+
+    fn render_tr(
+        &mut self,
+        row_ix: usize,
+        _window: &mut Window,
+        _cx: &mut Context<TableState<Self>>,
+    ) -> Stateful<Div> {
+        div().id(row_ix)
+    }
+
+    fn perform_sort(
+        &mut self,
+        col_ix: usize,
+        sort: ColumnSort,
+        _window: &mut Window,
+        _cx: &mut Context<TableState<Self>>,
+    ) {
+        self.active_sort = Some((col_ix, sort));
+        for (i, col) in self.columns.iter_mut().enumerate() {
+            if i == col_ix {
+                col.sort = Some(sort);
+            } else {
+                col.sort = Some(ColumnSort::Default);
+            }
+        }
+        self.sort_data(col_ix, sort);
+    }
+}
@@ -1,15 +1,27 @@
+mod dns_table;
+mod file_menu;
 mod flow_table;
+mod follow_stream;
 mod histogram;
+mod http_table;
 mod packet_bytes;
 mod packet_table;
+mod protocol_tree;
 mod search_bar;
 mod settings_menu;
+mod tls_details;
 mod toolbar;
 
+pub use dns_table::{DnsRow, DnsTable};
+pub use file_menu::FileMenu;
 pub use flow_table::FlowTable;
+pub use follow_stream::{FollowLine, FollowStreamView};
 pub use histogram::{ProtocolCategory, histogram_from_flows, render_histogram};
+pub use http_table::{HttpRow, HttpTable};
 pub use packet_bytes::PacketBytesView;
 pub use packet_table::PacketTable;
+pub use protocol_tree::ProtocolTreeView;
 pub use search_bar::SearchBar;
 pub use settings_menu::SettingsMenu;
+pub use tls_details::TlsDetailsView;
 pub use toolbar::Toolbar;
@@ -1,6 +1,7 @@
 use crate::gui::fonts::JETBRAINS_MONO_FAMILY;
 use gpui::*;
 use gpui_component::ActiveTheme;
+use std::ops::Range;
 
 const BYTES_PER_ROW: usize = 16;
 
@@ -14,11 +15,27 @@ const ASCII_WIDTH: f32 = 140.0;
 pub struct PacketBytesView {
     list_state: Option<ListState>,
     bytes: Option<Vec<u8>>,
+    highlight: Option<Range<usize>>,
 }
 
 impl PacketBytesView {
     pub fn new(list_state: Option<ListState>, bytes: Option<Vec<u8>>) -> Self {
-        Self { list_state, bytes }
+        Self {
+            list_state,
+            bytes,
+            highlight: None,
+        }
+    }
+
+    /// Highlights the bytes of the selected protocol tree field.
+    pub fn highlight(mut self, range: Option<Range<usize>>) -> Self {
+        self.highlight = range.filter(|range| !range.is_empty());
+        self
+    }
+
+    /// Row of the grid showing the byte at `offset`.
+    pub fn row_of(offset: usize) -> usize {
+        offset / BYTES_PER_ROW
     }
 
     pub fn create_list_state(bytes: &[u8]) -> ListState {
@@ -55,7 +72,18 @@ impl PacketBytesView {
             .child(div().w(px(ASCII_WIDTH)).child("ASCII"))
     }
 
-    fn render_row(offset: usize, chunk: &[u8]) -> Div {
+    /// Column of byte `idx` of a row within the hex text, counting the gap after the
+    /// eighth byte.
+    fn hex_column(idx: usize) -> usize {
+        idx * 3 + usize::from(idx >= BYTES_PER_ROW / 2)
+    }
+
+    fn render_row(
+        offset: usize,
+        chunk: &[u8],
+        highlight: Option<Range<usize>>,
+        color: Hsla,
+    ) -> Div {
         let mut hex_part = String::new();
         for idx in 0..BYTES_PER_ROW {
             if idx == BYTES_PER_ROW / 2 {
@@ -70,6 +98,21 @@ impl PacketBytesView {
 
         let ascii_part: String = chunk.iter().map(|b| Self::printable_ascii(*b)).collect();
 
+        // Part of the highlighted range on this row, as indices into `chunk`.
+        let selected = highlight
+            .map(|range| range.start.max(offset)..range.end.min(offset + chunk.len()))
+            .filter(|range| range.start < range.end)
+            .map(|range| range.start - offset..range.end - offset);
+        let style = HighlightStyle {
+            background_color: Some(color),
+            ..Default::default()
+        };
+        let hex_highlights = selected.clone().map(|range| {
+            let start = Self::hex_column(range.start);
+            (start..Self::hex_column(range.end - 1) + 2, style)
+        });
+        let ascii_highlights = selected.map(|range| (range, style));
+
         div()
             .flex()
             .flex_row()
@@ -78,8 +121,16 @@ impl PacketBytesView {
             .py_px()
             .gap_2()
             .child(div().w(px(OFFSET_WIDTH)).child(format!("{offset:06X}")))
-            .child(div().w(px(HEX_WIDTH)).child(hex_part))
-            .child(div().w(px(ASCII_WIDTH)).child(ascii_part))
+            .child(
+                div()
+                    .w(px(HEX_WIDTH))
+                    .child(StyledText::new(hex_part).with_highlights(hex_highlights)),
+            )
+            .child(
+                div()
+                    .w(px(ASCII_WIDTH))
+                    .child(StyledText::new(ascii_part).with_highlights(ascii_highlights)),
+            )
     }
 }
 
@@ -100,6 +151,8 @@ impl RenderOnce for PacketBytesView {
 
         match (self.list_state, self.bytes) {
             (Some(list_state), Some(bytes)) => {
+                let highlight = self.highlight;
+                let highlight_color = cx.theme().colors.selection;
                 base.child(Self::render_header(cx)).child(
                     div()
                         .font_family(JETBRAINS_MONO_FAMILY)
@@ -113,7 +166,7 @@ impl RenderOnce for PacketBytesView {
                                 let end = (start + BYTES_PER_ROW).min(bytes.len());
                                 let chunk = &bytes[start..end];
                                 tracing::trace!("Rendering row {}", ix);
-                                Self::render_row(start, chunk)
+                                Self::render_row(start, chunk, highlight.clone(), highlight_color)
                                     .h(px(20.0))
                                     .into_any_element()
                             })
@@ -4,6 +4,7 @@ use gpui::*;
 use gpui_component::table::{Column, ColumnSort, Table, TableDelegate, TableState};
 use gpui_component::tag::Tag;
 use gpui_component::{ActiveTheme, ColorName, Sizable, StyledExt, h_flex};
+use std::cmp::Reverse;
 use std::ops::Range;
 use std::collections::HashMap;
 
@@ -15,6 +16,7 @@ pub struct PacketTable {
     last_start_timestamp: Option<f64>,
     name_resolutions: HashMap<IPAddress, Vec<String>>,
     prefer_names: bool,
+    sources: Vec<String>,
 }
 
 impl PacketTable {
@@ -35,7 +37,7 @@ impl PacketTable {
                 prefer_names,
                 name_resolutions.clone(),
             );
-        let flow_key = FlowKey::from_endpoints(flow.source, flow.destination, flow.protocol);
+        let flow_key = flow.key();
         Self {
             state,
             flow_key: Some(flow_key),
@@ -43,9 +45,23 @@ impl PacketTable {
             last_start_timestamp: start_timestamp,
             name_resolutions,
             prefer_names,
+            sources: Vec::new(),
         }
     }
 
+    /// Names the capture files packets were read from, shown in the Source column.
+    pub fn set_sources(&mut self, sources: &[String], cx: &mut App) {
+        if self.sources == sources {
+            return;
+        }
+        self.sources = sources.to_vec();
+        let sources = self.sources.clone();
+        self.state.update(cx, move |table, cx| {
+            table.delegate_mut().set_sources(sources);
+            table.refresh(cx);
+        });
+    }
+
     pub fn update(
         &mut self,
         flow: &Flow,
@@ -54,7 +70,7 @@ impl PacketTable {
         name_resolutions: HashMap<IPAddress, Vec<String>>,
         cx: &mut App,
     ) {
-        let flow_key = FlowKey::from_endpoints(flow.source, flow.destination, flow.protocol);
+        let flow_key = flow.key();
         let packet_count = flow.packets.len();
         let needs_refresh = self.flow_key != Some(flow_key)
             || self.packet_count != packet_count
@@ -142,6 +158,8 @@ pub struct PacketTableDelegate {
     pub start_timestamp: Option<f64>,
     pub prefer_names: bool,
     pub name_resolutions: HashMap<IPAddress, Vec<String>>,
+    /// Names of the capture files, indexed by `Packet::source`.
+    pub sources: Vec<String>,
 }
 
 impl PacketTableDelegate {
@@ -160,12 +178,14 @@ impl PacketTableDelegate {
                 make_packet_col("dst_ip", "Dest IP", 150.),
                 make_packet_col("dst_port", "Dst Port", 100.),
                 make_packet_col("size", "Size", 100.),
+                make_packet_col("source", "Source", 150.),
                 make_packet_col("details", "Details", 300.),
             ],
             active_sort: Some((0, ColumnSort::Ascending)),
             start_timestamp,
             prefer_names,
             name_resolutions,
+            sources: Vec::new(),
         }
     }
 
@@ -188,6 +208,17 @@ impl PacketTableDelegate {
         self.prefer_names = prefer_names;
     }
 
+    pub fn set_sources(&mut self, sources: Vec<String>) {
+        self.sources = sources;
+        if let Some((col_ix, sort)) = self.active_sort {
+            self.sort_data(col_ix, sort);
+        }
+    }
+
+    fn source_name(&self, packet: &Packet) -> &str {
+        self.sources.get(packet.source).map_or("", String::as_str)
+    }
+
     fn display_ip(&self, ip: &IPAddress) -> String {
         FlowFormatter::ip_address(ip, self.prefer_names, Some(&self.name_resolutions))
     }
@@ -256,20 +287,29 @@ impl PacketTableDelegate {
                 ColumnSort::Default => {}
             },
             "src_port" => match sort {
-                ColumnSort::Ascending => self.packets.sort_by(|a, b| a.src_port.cmp(&b.src_port)),
-                ColumnSort::Descending => self.packets.sort_by(|a, b| b.src_port.cmp(&a.src_port)),
+                ColumnSort::Ascending => self.packets.sort_by_key(|a| a.src_port),
+                ColumnSort::Descending => self.packets.sort_by_key(|a| Reverse(a.src_port)),
                 ColumnSort::Default => {}
             },
             "dst_port" => match sort {
-                ColumnSort::Ascending => self.packets.sort_by(|a, b| a.dst_port.cmp(&b.dst_port)),
-                ColumnSort::Descending => self.packets.sort_by(|a, b| b.dst_port.cmp(&a.dst_port)),
+                ColumnSort::Ascending => self.packets.sort_by_key(|a| a.dst_port),
+                ColumnSort::Descending => self.packets.sort_by_key(|a| Reverse(a.dst_port)),
                 ColumnSort::Default => {}
             },
             "size" => match sort {
-                ColumnSort::Ascending => self.packets.sort_by(|a, b| a.length.cmp(&b.length)),
-                ColumnSort::Descending => self.packets.sort_by(|a, b| b.length.cmp(&a.length)),
+                ColumnSort::Ascending => self.packets.sort_by_key(|a| a.length),
+                ColumnSort::Descending => self.packets.sort_by_key(|a| Reverse(a.length)),
                 ColumnSort::Default => {}
             },
+            "source" => {
+                let sources = &self.sources;
+                let name = |packet: &Packet| sources.get(packet.source).cloned();
+                match sort {
+                    ColumnSort::Ascending => self.packets.sort_by_key(name),
+                    ColumnSort::Descending => self.packets.sort_by_key(|a| Reverse(name(a))),
+                    ColumnSort::Default => {}
+                }
+            }
             _ => {}
         }
     }
@@ -354,6 +394,7 @@ impl TableDelegate for PacketTableDelegate {
                 .map(|p| p.to_string())
                 .unwrap_or_else(|| "-".to_string()),
             "size" => packet.length.to_string(),
+            "source" => self.source_name(packet).to_string(),
             _ => String::new(),
         };
 
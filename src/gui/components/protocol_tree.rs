@@ -0,0 +1,138 @@
+use crate::gui::fonts::JETBRAINS_MONO_FAMILY;
+use crate::layers::tree::ProtocolTree;
+use gpui::prelude::FluentBuilder;
+use gpui::*;
+use gpui_component::{ActiveTheme, Icon, IconName};
+use std::collections::HashSet;
+use std::rc::Rc;
+
+const ROW_HEIGHT: f32 = 20.0;
+const INDENT: f32 = 16.0;
+
+type PathHandler = Rc<dyn Fn(&[usize], &mut Window, &mut App)>;
+
+/// Expandable protocol tree of the selected packet. Clicking a row selects it so its
+/// bytes can be highlighted; clicking the chevron opens or closes it.
+#[derive(IntoElement)]
+pub struct ProtocolTreeView {
+    tree: Option<Rc<ProtocolTree>>,
+    expanded: HashSet<Vec<usize>>,
+    selected: Option<Vec<usize>>,
+    on_toggle: PathHandler,
+    on_select: PathHandler,
+}
+
+impl ProtocolTreeView {
+    pub fn new(
+        tree: Option<Rc<ProtocolTree>>,
+        expanded: HashSet<Vec<usize>>,
+        selected: Option<Vec<usize>>,
+        on_toggle: impl Fn(&[usize], &mut Window, &mut App) + 'static,
+        on_select: impl Fn(&[usize], &mut Window, &mut App) + 'static,
+    ) -> Self {
+        Self {
+            tree,
+            expanded,
+            selected,
+            on_toggle: Rc::new(on_toggle),
+            on_select: Rc::new(on_select),
+        }
+    }
+
+    fn render_rows(&self, tree: &ProtocolTree, cx: &mut App) -> Vec<AnyElement> {
+        let selection = cx.theme().colors.selection;
+        let hover = cx.theme().colors.list_hover;
+        tree.visible_rows(&self.expanded)
+            .into_iter()
+            .map(|row| {
+                let key = row
+                    .path
+                    .iter()
+                    .map(|ix| ix.to_string())
+                    .collect::<Vec<_>>()
+                    .join("_");
+                let chevron = if row.node.children.is_empty() {
+                    div().w(px(INDENT)).into_any_element()
+                } else {
+                    let on_toggle = self.on_toggle.clone();
+                    let path = row.path.clone();
+                    let icon = if row.expanded {
+                        IconName::ChevronDown
+                    } else {
+                        IconName::ChevronRight
+                    };
+                    div()
+                        .id(SharedString::from(format!("tree_toggle_{key}")))
+                        .w(px(INDENT))
+                        .child(Icon::new(icon).size(px(14.0)))
+                        .on_click(move |_event, window, cx| {
+                            cx.stop_propagation();
+                            on_toggle(&path, window, cx);
+                        })
+                        .into_any_element()
+                };
+
+                let on_select = self.on_select.clone();
+                let path = row.path.clone();
+                let is_selected = self.selected.as_ref() == Some(&row.path);
+                div()
+                    .id(SharedString::from(format!("tree_row_{key}")))
+                    .flex()
+                    .flex_row()
+                    .items_center()
+                    .h(px(ROW_HEIGHT))
+                    .pl(px(8.0 + row.depth as f32 * INDENT))
+                    .pr_3()
+                    .when(is_selected, |row| row.bg(selection))
+                    .hover(move |style| style.bg(hover))
+                    .child(chevron)
+                    .child(
+                        div()
+                            .whitespace_nowrap()
+                            .when(row.depth == 0, |label| {
+                                label.font_weight(FontWeight::MEDIUM)
+                            })
+                            .child(row.node.label()),
+                    )
+                    .on_click(move |_event, window, cx| on_select(&path, window, cx))
+                    .into_any_element()
+            })
+            .collect()
+    }
+}
+
+impl RenderOnce for ProtocolTreeView {
+    fn render(self, _window: &mut Window, cx: &mut App) -> impl IntoElement {
+        let base = div()
+            .flex()
+            .flex_col()
+            .size_full()
+            .bg(cx.theme().colors.background)
+            .border_1()
+            .border_color(cx.theme().colors.border);
+
+        match self.tree.clone().filter(|tree| !tree.is_empty()) {
+            Some(tree) => base.child(
+                div()
+                    .id("protocol_tree_rows")
+                    .flex_1()
+                    .overflow_y_scroll()
+                    .font_family(JETBRAINS_MONO_FAMILY)
+                    .text_sm()
+                    .text_color(cx.theme().colors.foreground)
+                    .py_1()
+                    .children(self.render_rows(&tree, cx)),
+            ),
+            None => base.child(
+                div()
+                    .flex()
+                    .items_center()
+                    .justify_center()
+                    .size_full()
+                    .text_sm()
+                    .text_color(cx.theme().colors.muted_foreground)
+                    .child("Select a packet to see its protocol layers"),
+            ),
+        }
+    }
+}
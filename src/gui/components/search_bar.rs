@@ -1,35 +1,60 @@
+use gpui::prelude::FluentBuilder as _;
 use gpui::*;
 use gpui_component::{
-    Icon, IconName,
+    ActiveTheme, Icon, IconName,
     input::{Input, InputState},
 };
 
 #[derive(IntoElement, Clone)]
 pub struct SearchBar {
     input_state: Entity<InputState>,
+    error: Option<SharedString>,
 }
 
 impl SearchBar {
-    const PLACEHOLDER: &'static str = "Search by IP or protocol...";
+    const PLACEHOLDER: &'static str = "Filter, e.g. ip.addr == 10.0.0.0/8 && tcp.port in {80 443}";
 
     pub fn create<Owner>(window: &mut Window, cx: &mut Context<Owner>) -> Self {
         let placeholder = SharedString::from(Self::PLACEHOLDER);
         let input_state =
             cx.new(move |cx| InputState::new(window, cx).placeholder(placeholder.clone()));
-        Self { input_state }
+        Self {
+            input_state,
+            error: None,
+        }
     }
 
     pub fn entity(&self) -> &Entity<InputState> {
         &self.input_state
     }
+
+    /// Shows a filter compile error below the input.
+    pub fn error(mut self, error: Option<impl Into<SharedString>>) -> Self {
+        self.error = error.map(Into::into);
+        self
+    }
 }
 
 impl RenderOnce for SearchBar {
-    fn render(self, _window: &mut Window, _cx: &mut App) -> impl IntoElement {
-        div().flex().flex_1().items_center().child(
-            Input::new(&self.input_state)
-                .prefix(Icon::new(IconName::Search))
-                .cleanable(true),
-        )
+    fn render(self, _window: &mut Window, cx: &mut App) -> impl IntoElement {
+        div()
+            .flex()
+            .flex_col()
+            .flex_1()
+            .child(
+                div().flex().items_center().child(
+                    Input::new(&self.input_state)
+                        .prefix(Icon::new(IconName::Search))
+                        .cleanable(true),
+                ),
+            )
+            .when_some(self.error, |this, error| {
+                this.child(
+                    div()
+                        .text_xs()
+                        .text_color(cx.theme().colors.danger)
+                        .child(error),
+                )
+            })
     }
 }
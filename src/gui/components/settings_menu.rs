@@ -1,4 +1,5 @@
 use crate::gui::theme::ThemeMode;
+use gpui::prelude::FluentBuilder as _;
 use gpui::*;
 use gpui_component::button::{Button, ButtonVariants};
 use gpui_component::menu::{DropdownMenu, PopupMenu, PopupMenuItem};
@@ -15,6 +16,7 @@ pub struct SettingsMenu {
     on_toggle_names: ToggleHandler,
     theme_mode: ThemeMode,
     on_theme_change: ThemeHandler,
+    on_export: Option<ToggleHandler>,
 }
 
 impl SettingsMenu {
@@ -29,8 +31,15 @@ impl SettingsMenu {
             on_toggle_names: Rc::new(on_toggle_names),
             theme_mode,
             on_theme_change: Rc::new(on_theme_change),
+            on_export: None,
         }
     }
+
+    /// Adds an "Export Filtered View…" entry that invokes `on_export`.
+    pub fn on_export(mut self, on_export: impl Fn(&(), &mut Window, &mut App) + 'static) -> Self {
+        self.on_export = Some(Rc::new(on_export));
+        self
+    }
 }
 
 impl RenderOnce for SettingsMenu {
@@ -39,6 +48,7 @@ impl RenderOnce for SettingsMenu {
         let on_toggle_names = self.on_toggle_names;
         let theme_mode = self.theme_mode;
         let on_theme_change = self.on_theme_change;
+        let on_export = self.on_export;
 
         Button::new("settings_menu_button")
             .icon(Icon::new(IconName::Settings))
@@ -73,6 +83,15 @@ impl RenderOnce for SettingsMenu {
                                 }
                             }),
                     )
+                    .when_some(on_export.clone(), |menu, handler| {
+                        menu.separator().label("File").item(
+                            PopupMenuItem::new("Export Filtered View…").on_click(
+                                move |_event, window, cx| {
+                                    handler(&(), window, cx);
+                                },
+                            ),
+                        )
+                    })
             })
             .into_any_element()
     }
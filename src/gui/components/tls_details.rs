@@ -0,0 +1,98 @@
+use crate::flow::tls::{TlsInfo, cipher_suite_name};
+use crate::gui::fonts::JETBRAINS_MONO_FAMILY;
+use gpui::*;
+use gpui_component::ActiveTheme;
+
+/// TLS section of the packet details: what the handshake of the selected flow
+/// negotiated and the certificates the server presented.
+#[derive(IntoElement)]
+pub struct TlsDetailsView {
+    tls: TlsInfo,
+}
+
+impl TlsDetailsView {
+    pub fn new(tls: TlsInfo) -> Self {
+        Self { tls }
+    }
+
+    /// Label and value rows, leaving out what the handshake didn't reveal.
+    fn rows(&self) -> Vec<(String, String)> {
+        let tls = &self.tls;
+        let mut rows = Vec::new();
+        let mut push = |label: &str, value: Option<String>| {
+            if let Some(value) = value.filter(|value| !value.is_empty()) {
+                rows.push((label.to_owned(), value));
+            }
+        };
+        push("Version", tls.version_name());
+        push("Server Name", tls.server_name.clone());
+        push("ALPN", tls.alpn.clone());
+        push("Offered ALPN", Some(tls.offered_alpn.join(", ")));
+        push("Cipher Suite", tls.cipher_suite_name());
+        push(
+            "Offered Cipher Suites",
+            Some(
+                tls.offered_cipher_suites
+                    .iter()
+                    .map(|id| cipher_suite_name(*id))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ),
+        );
+        push("JA3", tls.ja3.clone());
+        push("JA3S", tls.ja3s.clone());
+        push("JA4", tls.ja4.clone());
+        for (ix, cert) in tls.certificates.iter().enumerate() {
+            push(
+                &format!("Certificate {}", ix + 1),
+                Some(cert.subject.clone()),
+            );
+            push("  Issuer", Some(cert.issuer.clone()));
+            push("  Alt Names", Some(cert.subject_alt_names.join(", ")));
+            push(
+                "  Validity",
+                Some(format!("{} – {}", cert.not_before, cert.not_after)),
+            );
+        }
+        rows
+    }
+}
+
+impl RenderOnce for TlsDetailsView {
+    fn render(self, _window: &mut Window, cx: &mut App) -> impl IntoElement {
+        let muted = cx.theme().colors.muted_foreground;
+        div()
+            .id("tls_details")
+            .flex()
+            .flex_col()
+            .size_full()
+            .overflow_y_scroll()
+            .bg(cx.theme().colors.background)
+            .border_1()
+            .border_color(cx.theme().colors.border)
+            .px_3()
+            .py_1()
+            .text_sm()
+            .text_color(cx.theme().colors.foreground)
+            .child(
+                div()
+                    .font_weight(FontWeight::MEDIUM)
+                    .child("Transport Layer Security"),
+            )
+            .children(self.rows().into_iter().map(|(label, value)| {
+                div()
+                    .flex()
+                    .flex_row()
+                    .gap_2()
+                    .child(
+                        div()
+                            .w(px(160.0))
+                            .flex_shrink_0()
+                            .whitespace_nowrap()
+                            .text_color(muted)
+                            .child(label),
+                    )
+                    .child(div().font_family(JETBRAINS_MONO_FAMILY).child(value))
+            }))
+    }
+}
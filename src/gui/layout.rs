@@ -22,7 +22,7 @@ pub struct Layout {
 
 struct ClosableBottomPane {
     header: AnyElement,
-    content: BottomSplit,
+    content: AnyElement,
     on_close: CloseHandler,
 }
 
@@ -94,15 +94,26 @@ impl Layout {
     }
 
     pub fn bottom_closable_split(
-        mut self,
+        self,
         header: impl IntoElement,
         split: BottomSplit,
         on_close: impl for<'event, 'window, 'app> Fn(&'event (), &'window mut Window, &'app mut App)
         + 'static,
+    ) -> Self {
+        self.bottom_closable(header, render_split(split), on_close)
+    }
+
+    /// Like [`Layout::bottom_closable_split`], with a single element filling the pane.
+    pub fn bottom_closable(
+        mut self,
+        header: impl IntoElement,
+        content: impl IntoElement,
+        on_close: impl for<'event, 'window, 'app> Fn(&'event (), &'window mut Window, &'app mut App)
+        + 'static,
     ) -> Self {
         self.bottom = Some(ClosableBottomPane {
             header: header.into_any_element(),
-            content: split,
+            content: content.into_any_element(),
             on_close: Box::new(on_close),
         });
         self
@@ -132,7 +143,6 @@ impl RenderOnce for Layout {
                 on_close,
             } = bottom;
 
-            let pane_body = render_split(content);
             let bottom_content = div()
                 .flex()
                 .flex_col()
@@ -159,7 +169,7 @@ impl RenderOnce for Layout {
                                 }),
                         ),
                 )
-                .child(pane_body)
+                .child(content)
                 .into_any_element();
 
             v_resizable("main_split")
@@ -0,0 +1,333 @@
+use crate::flow::Protocol;
+use crate::layers::PacketContext;
+use crate::layers::tls_decrypt::KeyLog;
+use crate::layers::tree::ProtocolNode;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+use tracing::trace;
+
+/// Application payload of a packet as handed to a [`Dissector`].
+#[derive(Debug, Clone, Copy)]
+pub struct Payload<'a> {
+    pub transport: Protocol,
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub data: &'a [u8],
+}
+
+/// How a dissector is picked for a payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Registration {
+    /// Traffic to or from a well-known port.
+    Port(Protocol, u16),
+    /// Any payload of the transport that [`Dissector::heuristic`] accepts. Heuristics
+    /// are tried in registration order once no port matched.
+    Heuristic(Protocol),
+    /// Payload handed over by the named dissector, tried with [`Dissector::heuristic`].
+    Parent(&'static str),
+}
+
+/// Decoder for an application protocol. Implement it and add it to a
+/// [`DissectorRegistry`] to decode protocols the crate doesn't know about.
+pub trait Dissector: Send + Sync {
+    /// Unique lowercase name, as used by decode-as rules (e.g. `dns`).
+    fn name(&self) -> &'static str;
+
+    fn registrations(&self) -> Vec<Registration>;
+
+    /// Whether `data` looks like this protocol. Only consulted for
+    /// [`Registration::Heuristic`] and [`Registration::Parent`].
+    fn heuristic(&self, _data: &[u8]) -> bool {
+        false
+    }
+
+    /// Decodes `payload` into `context`. Returns the part of `payload.data` carrying an
+    /// encapsulated protocol, which is offered to the dissectors registered on this one.
+    fn dissect(&self, payload: &Payload<'_>, context: &mut PacketContext) -> Option<Range<usize>>;
+
+    /// Protocol tree nodes for `payload`, which starts at byte `offset` of the frame.
+    fn tree(&self, _payload: &Payload<'_>, _offset: usize) -> Vec<ProtocolNode> {
+        Vec::new()
+    }
+}
+
+/// Forces traffic on a port to a dissector, like Wireshark's "Decode As…". Written as
+/// `tcp.port==8443,tls`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodeAs {
+    pub transport: Protocol,
+    pub port: u16,
+    pub dissector: String,
+}
+
+impl FromStr for DecodeAs {
+    type Err = String;
+
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        let invalid =
+            || format!("Invalid decode-as rule '{rule}', expected e.g. tcp.port==8443,tls");
+        let (selector, dissector) = rule.split_once(',').ok_or_else(invalid)?;
+        let (field, port) = selector.split_once("==").ok_or_else(invalid)?;
+        let transport = match field.trim() {
+            "tcp.port" => Protocol::TCP,
+            "udp.port" => Protocol::UDP,
+            _ => return Err(invalid()),
+        };
+        let port = port.trim().parse().map_err(|_| invalid())?;
+        let dissector = dissector.trim().to_ascii_lowercase();
+        if dissector.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self {
+            transport,
+            port,
+            dissector,
+        })
+    }
+}
+
+impl fmt::Display for DecodeAs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let transport = match self.transport {
+            Protocol::TCP => "tcp",
+            Protocol::UDP => "udp",
+            Protocol::Other(_) => "ip",
+            Protocol::ARP => "arp",
+        };
+        write!(f, "{transport}.port=={},{}", self.port, self.dissector)
+    }
+}
+
+/// Dissectors known to the parser, and the rules choosing one for each payload.
+pub struct DissectorRegistry {
+    dissectors: Vec<Box<dyn Dissector>>,
+    ports: HashMap<(Protocol, u16), usize>,
+    heuristics: Vec<(Protocol, usize)>,
+    children: HashMap<&'static str, Vec<usize>>,
+    overrides: HashMap<(Protocol, u16), usize>,
+    tls_keylog: KeyLog,
+}
+
+impl Default for DissectorRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DissectorRegistry {
+    /// A registry with the built-in dissectors.
+    pub fn new() -> Self {
+        let mut registry = Self::empty();
+        registry.register(crate::parser::dns::DnsDissector);
+        registry.register(crate::layers::tls::TlsDissector);
+        registry.register(crate::parser::http::HttpDissector);
+        registry
+    }
+
+    /// The built-in dissectors with `rules` applied.
+    pub fn with_rules(rules: &[DecodeAs]) -> Result<Self, String> {
+        let mut registry = Self::new();
+        for rule in rules {
+            registry.decode_as(rule)?;
+        }
+        Ok(registry)
+    }
+
+    pub fn empty() -> Self {
+        Self {
+            dissectors: Vec::new(),
+            ports: HashMap::new(),
+            heuristics: Vec::new(),
+            children: HashMap::new(),
+            overrides: HashMap::new(),
+            tls_keylog: KeyLog::default(),
+        }
+    }
+
+    /// Adds `dissector`. A port already claimed by an earlier dissector is taken over.
+    pub fn register(&mut self, dissector: impl Dissector + 'static) {
+        let ix = self.dissectors.len();
+        for registration in dissector.registrations() {
+            match registration {
+                Registration::Port(transport, port) => {
+                    self.ports.insert((transport, port), ix);
+                }
+                Registration::Heuristic(transport) => self.heuristics.push((transport, ix)),
+                Registration::Parent(parent) => self.children.entry(parent).or_default().push(ix),
+            }
+        }
+        self.dissectors.push(Box::new(dissector));
+    }
+
+    /// Applies a decode-as rule. Fails when no dissector has the rule's name.
+    pub fn decode_as(&mut self, rule: &DecodeAs) -> Result<(), String> {
+        let ix = self
+            .dissectors
+            .iter()
+            .position(|dissector| dissector.name() == rule.dissector)
+            .ok_or_else(|| {
+                format!(
+                    "Unknown dissector '{}'; available: {}",
+                    rule.dissector,
+                    self.names().join(", ")
+                )
+            })?;
+        self.overrides.insert((rule.transport, rule.port), ix);
+        Ok(())
+    }
+
+    /// Secrets for decrypting TLS, e.g. from `--tls-keylog`. Captures can add their own.
+    pub fn set_tls_keylog(&mut self, keylog: KeyLog) {
+        self.tls_keylog = keylog;
+    }
+
+    pub fn tls_keylog(&self) -> &KeyLog {
+        &self.tls_keylog
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.dissectors
+            .iter()
+            .map(|dissector| dissector.name())
+            .collect()
+    }
+
+    /// Name of the dissector a decode-as rule forces on traffic between these ports.
+    pub fn forced(
+        &self,
+        transport: Protocol,
+        src_port: u16,
+        dst_port: u16,
+    ) -> Option<&'static str> {
+        [dst_port, src_port]
+            .iter()
+            .find_map(|port| self.overrides.get(&(transport, *port)))
+            .map(|ix| self.dissectors[*ix].name())
+    }
+
+    /// Name of the dissector that a decode-as rule, or else a port registration, assigns
+    /// to traffic between these ports. Heuristics are not consulted.
+    pub fn assigned(
+        &self,
+        transport: Protocol,
+        src_port: u16,
+        dst_port: u16,
+    ) -> Option<&'static str> {
+        self.forced(transport, src_port, dst_port).or_else(|| {
+            [dst_port, src_port]
+                .iter()
+                .find_map(|port| self.ports.get(&(transport, *port)))
+                .map(|ix| self.dissectors[*ix].name())
+        })
+    }
+
+    /// Picks the dissector for `payload`: decode-as rules first, then ports (destination
+    /// before source), then heuristics.
+    pub fn resolve(&self, payload: &Payload<'_>) -> Option<&dyn Dissector> {
+        let by_port = |table: &HashMap<(Protocol, u16), usize>| {
+            [payload.dst_port, payload.src_port]
+                .iter()
+                .find_map(|port| table.get(&(payload.transport, *port)).copied())
+        };
+        by_port(&self.overrides)
+            .or_else(|| by_port(&self.ports))
+            .or_else(|| {
+                self.heuristics
+                    .iter()
+                    .filter(|(transport, _)| *transport == payload.transport)
+                    .map(|(_, ix)| *ix)
+                    .find(|ix| self.dissectors[*ix].heuristic(payload.data))
+            })
+            .map(|ix| self.dissectors[ix].as_ref())
+    }
+
+    /// Runs the dissector chain on the TCP or UDP payload of `context`, which is part
+    /// of `packet`, the bytes the context was decoded from.
+    pub fn dissect(&self, context: &mut PacketContext, packet: &[u8]) {
+        let payload = match context.protocol {
+            Some(Protocol::TCP) => context
+                .tcp_segment
+                .as_ref()
+                .map(|segment| segment.payload.clone()),
+            Some(Protocol::UDP) => context.udp_payload.clone(),
+            _ => None,
+        };
+        if let Some(payload) = payload.and_then(|range| packet.get(range)) {
+            self.run(None, payload, context);
+        }
+    }
+
+    /// Offers `data`, which the `parent` dissector decoded from the payload of
+    /// `context` (e.g. decrypted TLS), to the dissectors registered on `parent`.
+    pub fn dissect_child(&self, parent: &str, data: &[u8], context: &mut PacketContext) {
+        self.run(Some(parent), data, context);
+    }
+
+    fn run(&self, parent: Option<&str>, data: &[u8], context: &mut PacketContext) {
+        let (Some(transport), Some(src_port), Some(dst_port)) =
+            (context.protocol, context.src_port, context.dst_port)
+        else {
+            return;
+        };
+        if data.is_empty() {
+            return;
+        }
+        let mut payload = Payload {
+            transport,
+            src_port,
+            dst_port,
+            data,
+        };
+        let mut dissector = match parent {
+            Some(parent) => self.child(parent, data),
+            None => self.resolve(&payload),
+        };
+        while let Some(current) = dissector {
+            trace!(
+                dissector = current.name(),
+                len = payload.data.len(),
+                "Dissecting payload"
+            );
+            let Some(handoff) = current.dissect(&payload, context) else {
+                return;
+            };
+            let Some(inner) = payload.data.get(handoff) else {
+                return;
+            };
+            payload.data = inner;
+            dissector = self.child(current.name(), inner);
+        }
+    }
+
+    /// Tree nodes for `payload` from the dissector chain, or an empty list when no
+    /// dissector claims it. `offset` is the payload's position in the frame.
+    pub fn tree(&self, payload: &Payload<'_>, offset: usize) -> Vec<ProtocolNode> {
+        let mut nodes = Vec::new();
+        let mut payload = *payload;
+        let mut offset = offset;
+        let mut dissector = self.resolve(&payload);
+        while let Some(current) = dissector {
+            nodes.extend(current.tree(&payload, offset));
+            let handoff = current.dissect(&payload, &mut PacketContext::default());
+            let Some((start, inner)) =
+                handoff.and_then(|range| Some((range.start, payload.data.get(range)?)))
+            else {
+                break;
+            };
+            payload.data = inner;
+            offset += start;
+            dissector = self.child(current.name(), inner);
+        }
+        nodes
+    }
+
+    fn child(&self, parent: &str, data: &[u8]) -> Option<&dyn Dissector> {
+        self.children
+            .get(parent)?
+            .iter()
+            .map(|ix| self.dissectors[*ix].as_ref())
+            .find(|dissector| dissector.heuristic(data))
+    }
+}
@@ -1,6 +1,15 @@
-use crate::flow::{IPAddress, Protocol};
+use crate::flow::dns::DnsMessage;
+use crate::flow::tunnel::Encapsulation;
+use crate::flow::{Conversation, FlowKey, IPAddress, Protocol, TcpAnalysisFlag};
+use std::ops::Range;
+use tree::ProtocolTree;
 
+pub mod dissector;
 pub mod tls;
+pub mod tls_decrypt;
+pub mod tls_fingerprint;
+pub mod tls_handshake;
+pub mod tree;
 
 #[derive(Default, Debug, Clone)]
 pub struct PacketContext {
@@ -9,10 +18,56 @@ pub struct PacketContext {
     pub src_port: Option<u16>,
     pub dst_port: Option<u16>,
     pub protocol: Option<Protocol>,
+    /// Tells apart flows of protocols without ports, such as ICMP echo exchanges.
+    pub conversation: Conversation,
+    /// For ICMP and ICMPv6 errors, the TCP or UDP flow of the packet they quote.
+    pub quoted_flow: Option<FlowKey>,
     pub is_syn: bool,
     pub is_ack: bool,
     pub tags: Vec<String>,
-    pub udp_payload: Option<Vec<u8>>,
+    /// Position of the UDP payload in the bytes the packet was decoded from.
+    pub udp_payload: Option<Range<usize>>,
+    pub tcp_segment: Option<TcpSegment>,
+    /// Set when the packet carries part of a fragmented IP datagram, whose transport
+    /// header and payload are only decoded once all parts are in.
+    pub ip_fragment: Option<IpFragment>,
+    pub tcp_analysis: Vec<TcpAnalysisFlag>,
+    /// Layers the packet was carried in, outermost first.
+    pub encapsulation: Vec<Encapsulation>,
+    /// Addresses resolved by the packet, such as DNS answers.
+    pub name_resolutions: Vec<(IPAddress, String)>,
+    /// DNS messages carried by the packet.
+    pub dns_messages: Vec<DnsMessage>,
+    /// Only built by [`crate::parser::decoder::dissect_packet`].
+    pub tree: ProtocolTree,
+}
+
+/// Sequencing details and payload of a TCP segment, consumed by stream reassembly.
+#[derive(Default, Debug, Clone)]
+pub struct TcpSegment {
+    pub seq: u32,
+    pub ack: u32,
+    pub window: u16,
+    /// Window scale shift from the options of a SYN segment.
+    pub window_scale: Option<u8>,
+    pub syn: bool,
+    pub fin: bool,
+    pub rst: bool,
+    /// Position of the payload in the bytes the packet was decoded from.
+    pub payload: Range<usize>,
+}
+
+/// Part of a fragmented IPv4 or IPv6 datagram, consumed by defragmentation.
+#[derive(Default, Debug, Clone)]
+pub struct IpFragment {
+    /// IPv4 identification or IPv6 fragment header identification.
+    pub id: u32,
+    /// IP protocol number of the datagram's payload.
+    pub protocol: u8,
+    /// Position of `payload` within the datagram's payload, in bytes.
+    pub offset: usize,
+    pub more_fragments: bool,
+    pub payload: Vec<u8>,
 }
 
 // Context populated while decoding packets; shared by decoders.
@@ -1,4 +1,12 @@
+use crate::flow::Protocol;
 use crate::layers::PacketContext;
+use crate::layers::dissector::{Dissector, DissectorRegistry, Payload, Registration};
+use crate::layers::tls_decrypt::{KeyLog, TlsSession};
+use crate::layers::tls_handshake;
+use crate::layers::tree::ProtocolNode;
+use crate::parser::reassembly::StreamDirection;
+use crate::parser::tcp::looks_like_tls;
+use std::ops::Range;
 use tls_parser::{TlsMessage, TlsMessageHandshake, TlsRecordType, TlsVersion, parse_tls_plaintext};
 use tracing::warn;
 
@@ -11,32 +19,456 @@ enum ContentType {
     Unknown(u8),
 }
 
-pub struct TlsParser;
+/// Largest record length allowed by RFC 8446 (2^14 plus AEAD expansion).
+const MAX_RECORD_LEN: usize = 16_384 + 2_048;
+const RECORD_HEADER_LEN: usize = 5;
+/// Bytes searched for a record boundary before a direction is treated as non-TLS.
+const MAX_RESYNC_SCAN: usize = 64 * 1024;
+
+/// Tags the TLS records of reassembled streams, decrypting those whose session secrets
+/// are in its key log.
+#[derive(Debug, Default, Clone)]
+pub struct TlsParser {
+    keylog: KeyLog,
+}
+
+/// TLS over TCP, on port 443 or found heuristically. Records are tagged from the
+/// reassembled stream by [`TlsParser::parse_stream`]; per packet this only supplies the
+/// protocol tree. Forcing another dissector onto a port turns stream parsing off.
+/// Decrypted application data is offered to the dissectors registered on this one.
+pub struct TlsDissector;
+
+impl TlsDissector {
+    pub const NAME: &'static str = "tls";
+}
+
+impl Dissector for TlsDissector {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn registrations(&self) -> Vec<Registration> {
+        vec![
+            Registration::Port(Protocol::TCP, 443),
+            Registration::Heuristic(Protocol::TCP),
+        ]
+    }
+
+    fn heuristic(&self, data: &[u8]) -> bool {
+        looks_like_tls(data)
+    }
+
+    fn dissect(
+        &self,
+        _payload: &Payload<'_>,
+        _context: &mut PacketContext,
+    ) -> Option<Range<usize>> {
+        None
+    }
+
+    fn tree(&self, payload: &Payload<'_>, offset: usize) -> Vec<ProtocolNode> {
+        dissect_records(payload.data, offset)
+    }
+}
+
+/// Progress of [`TlsParser::parse_stream`] through one direction of a reassembled stream.
+#[derive(Debug, Default, Clone)]
+pub struct TlsStreamState {
+    consumed: usize,
+    synced: bool,
+    started: bool,
+    /// After a gap any record type may follow, not just a handshake.
+    after_gap: bool,
+    scanned: usize,
+    abandoned: bool,
+}
+
+impl TlsStreamState {
+    /// Drops record alignment because bytes before `position` are incomplete.
+    pub fn resync_at(&mut self, position: usize) {
+        self.consumed = self.consumed.max(position);
+        self.synced = false;
+        self.after_gap = true;
+        self.scanned = 0;
+        self.abandoned = false;
+    }
+
+    /// Position in the stream of the first byte still to be parsed, or `usize::MAX`
+    /// once nothing more will be.
+    pub fn keep_from(&self) -> usize {
+        if self.abandoned {
+            usize::MAX
+        } else {
+            self.consumed
+        }
+    }
+
+    /// Shifts the position after the first `len` bytes of the stream were released.
+    pub fn released(&mut self, len: usize) {
+        self.consumed = self.consumed.saturating_sub(len);
+    }
+}
 
 impl TlsParser {
-    pub fn parse(&self, data: &[u8], context: &mut PacketContext) {
-        let mut input = data;
+    pub fn new(keylog: KeyLog) -> Self {
+        Self { keylog }
+    }
+
+    /// Adds NSS key log lines, such as those of a pcapng Decryption Secrets Block.
+    /// Returns the number of secrets added.
+    pub fn add_secrets(&mut self, keylog: &str) -> usize {
+        self.keylog.add_lines(keylog)
+    }
+
+    /// Parses every complete TLS record in `stream` that has not been seen yet, tagging
+    /// `context`. `stream` is the reassembled payload sent in `direction` of the
+    /// connection described by `session`; records split across segments are picked up
+    /// once their last byte arrives.
+    pub fn parse_stream(
+        &self,
+        stream: &[u8],
+        state: &mut TlsStreamState,
+        session: &mut TlsSession,
+        direction: StreamDirection,
+        registry: &DissectorRegistry,
+        context: &mut PacketContext,
+    ) {
+        while !state.abandoned && state.consumed < stream.len() {
+            let input = &stream[state.consumed..];
+            if !state.synced && !find_record_start(input, state) {
+                return;
+            }
+
+            let input = &stream[state.consumed..];
+            let Some(header) = input.get(..RECORD_HEADER_LEN) else {
+                return;
+            };
+            if !plausible_header(header, true) {
+                // Lost alignment (e.g. a non-TLS protocol or garbage); look for the next record.
+                state.synced = false;
+                state.consumed += 1;
+                continue;
+            }
+
+            let record_len =
+                RECORD_HEADER_LEN + u16::from_be_bytes([header[3], header[4]]) as usize;
+            let Some(record) = input.get(..record_len) else {
+                return; // wait for the rest of the record
+            };
+            self.parse_record(record, session, direction, registry, context);
+            state.consumed += record_len;
+        }
+    }
+
+    fn parse_record(
+        &self,
+        record: &[u8],
+        session: &mut TlsSession,
+        direction: StreamDirection,
+        registry: &DissectorRegistry,
+        context: &mut PacketContext,
+    ) {
+        if record[0] != u8::from(TlsRecordType::ChangeCipherSpec)
+            && let Some((content_type, plaintext)) = session.decrypt(direction, record)
+        {
+            self.parse_decrypted(
+                content_type,
+                &plaintext,
+                direction,
+                session,
+                registry,
+                context,
+            );
+            return;
+        }
+
+        match parse_tls_plaintext(record) {
+            Ok((_, record)) => {
+                let content_type = map_record_type(record.hdr.record_type);
+                let version = tls_version_from_parser(record.hdr.version);
+
+                for msg in &record.msg {
+                    self.observe_handshake(msg, session, direction);
+                    handle_message(content_type, &version, msg, context);
+                }
+                if content_type == ContentType::ChangeCipherSpec {
+                    session.change_cipher_spec(direction, &self.keylog);
+                }
+            }
+            Err(_) => {
+                // Fragmented or encrypted handshake messages don't parse on their own.
+                if record[0] == u8::from(TlsRecordType::Handshake) {
+                    let version = tls_version_from_parser(TlsVersion(u16::from_be_bytes([
+                        record[1], record[2],
+                    ])));
+                    context.tags.push(format!("Handshake ({})", version));
+                }
+            }
+        }
+    }
 
-        while !input.is_empty() {
-            match parse_tls_plaintext(input) {
-                Ok((remaining, record)) => {
-                    let content_type = map_record_type(record.hdr.record_type);
-                    let version = tls_version_from_parser(record.hdr.version);
+    /// Feeds the randoms, negotiated parameters and certificates of plaintext handshake
+    /// messages to `session`.
+    fn observe_handshake(
+        &self,
+        msg: &TlsMessage,
+        session: &mut TlsSession,
+        direction: StreamDirection,
+    ) {
+        let TlsMessage::Handshake(handshake) = msg else {
+            return;
+        };
+        match handshake {
+            TlsMessageHandshake::ClientHello(hello) => {
+                tls_handshake::client_hello(session.info_mut(), hello);
+                if let Some(random) = hello_random(hello.rand_time, hello.rand_data) {
+                    session.client_hello(direction, random);
+                }
+            }
+            TlsMessageHandshake::ServerHello(hello) => {
+                let tls13 = tls_handshake::server_hello(session.info_mut(), hello);
+                if let Some(random) = hello_random(hello.rand_time, hello.rand_data) {
+                    session.server_hello(random, hello.cipher.0, tls13, &self.keylog);
+                }
+            }
+            TlsMessageHandshake::Certificate(certificate) if !session.sent_by_client(direction) => {
+                tls_handshake::certificates(
+                    session.info_mut(),
+                    certificate.cert_chain.iter().map(|cert| cert.data),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    /// Tags a decrypted record and hands application data to the dissectors behind TLS.
+    fn parse_decrypted(
+        &self,
+        content_type: u8,
+        plaintext: &[u8],
+        direction: StreamDirection,
+        session: &mut TlsSession,
+        registry: &DissectorRegistry,
+        context: &mut PacketContext,
+    ) {
+        let version = tls_version_from_parser(session.version());
+        match map_record_type(TlsRecordType(content_type)) {
+            ContentType::Handshake => {
+                let from_client = session.sent_by_client(direction);
+                tls_handshake::decrypted_handshake(session.info_mut(), plaintext, from_client);
+                for handshake_type in handshake_types(plaintext) {
+                    context.tags.push(format!(
+                        "{} ({})",
+                        handshake_type_name(handshake_type),
+                        version
+                    ));
+                    session.handshake_message(direction, handshake_type, &self.keylog);
+                }
+            }
+            ContentType::ApplicationData => {
+                context
+                    .tags
+                    .push(format!("Decrypted Application Data ({})", version));
+                if !plaintext.is_empty() {
+                    registry.dissect_child(TlsDissector::NAME, plaintext, context);
+                }
+            }
+            ContentType::Alert => context.tags.push(format!("Alert ({})", version)),
+            ContentType::ChangeCipherSpec | ContentType::Unknown(_) => {}
+        }
+    }
+}
+
+fn hello_random(rand_time: u32, rand_data: &[u8]) -> Option<[u8; 32]> {
+    let mut random = [0; 32];
+    random[..4].copy_from_slice(&rand_time.to_be_bytes());
+    random[4..].copy_from_slice(rand_data.get(..28)?);
+    Some(random)
+}
 
-                    for msg in &record.msg {
-                        handle_message(content_type, &version, msg, context);
-                    }
+/// Types of the handshake messages in `body`, in order.
+fn handshake_types(body: &[u8]) -> Vec<u8> {
+    let mut types = Vec::new();
+    let mut pos = 0;
+    while pos + 4 <= body.len() {
+        types.push(body[pos]);
+        pos += 4 + u32::from_be_bytes([0, body[pos + 1], body[pos + 2], body[pos + 3]]) as usize;
+    }
+    types
+}
+
+/// Protocol tree nodes for the TLS records at the start of `payload`, which begins at
+/// byte `offset` of the frame. A record continuing in a later segment ends at the
+/// payload's end.
+pub fn dissect_records(payload: &[u8], offset: usize) -> Vec<ProtocolNode> {
+    let mut nodes = Vec::new();
+    let mut pos = 0;
+    while pos + RECORD_HEADER_LEN <= payload.len()
+        && plausible_header(&payload[pos..pos + RECORD_HEADER_LEN], true)
+    {
+        let record_len = u16::from_be_bytes([payload[pos + 3], payload[pos + 4]]) as usize;
+        let end = (pos + RECORD_HEADER_LEN + record_len).min(payload.len());
+        let complete = end == pos + RECORD_HEADER_LEN + record_len;
+        let start = offset + pos;
+        let version = version_name(u16::from_be_bytes([payload[pos + 1], payload[pos + 2]]));
 
-                    if matches!(content_type, ContentType::Unknown(_)) {
-                        break;
-                    }
+        let mut record = ProtocolNode::new("Transport Layer Security", start..offset + end);
+        record
+            .field(
+                "Content Type",
+                content_type_name(payload[pos]),
+                start..start + 1,
+            )
+            .field("Version", version.clone(), start + 1..start + 3)
+            .field("Length", record_len.to_string(), start + 3..start + 5);
 
-                    input = remaining;
+        let body = &payload[pos + RECORD_HEADER_LEN..end];
+        let body_offset = start + RECORD_HEADER_LEN;
+        let mut summary = content_type_name(payload[pos]);
+        if payload[pos] == u8::from(TlsRecordType::Handshake) {
+            let plaintext = complete && parse_tls_plaintext(&payload[pos..end]).is_ok();
+            if plaintext {
+                let messages = handshake_nodes(body, body_offset);
+                summary = messages
+                    .iter()
+                    .filter_map(|message| message.value.clone())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                for message in messages {
+                    record.child(message);
                 }
-                Err(_) => break, // incomplete or invalid; stop at current packet boundary
+            } else if complete {
+                summary = "Encrypted Handshake Message".to_owned();
             }
         }
+        if !body.is_empty() && payload[pos] != u8::from(TlsRecordType::Handshake) {
+            record.field(
+                "Data",
+                format!("{} bytes", body.len()),
+                body_offset..body_offset + body.len(),
+            );
+        }
+        if !complete {
+            summary.push_str(" [continues in a later segment]");
+        }
+        record.value = Some(format!("{summary} ({version})"));
+        nodes.push(record);
+        pos = end;
     }
+    nodes
+}
+
+fn handshake_nodes(body: &[u8], offset: usize) -> Vec<ProtocolNode> {
+    let mut nodes = Vec::new();
+    let mut pos = 0;
+    while pos + 4 <= body.len() {
+        let len = u32::from_be_bytes([0, body[pos + 1], body[pos + 2], body[pos + 3]]) as usize;
+        let end = (pos + 4 + len).min(body.len());
+        let start = offset + pos;
+        let name = handshake_type_name(body[pos]);
+        let mut node =
+            ProtocolNode::new("Handshake Protocol", start..offset + end).with_value(name.clone());
+        node.field("Handshake Type", name, start..start + 1).field(
+            "Length",
+            len.to_string(),
+            start + 1..start + 4,
+        );
+        nodes.push(node);
+        pos = end;
+    }
+    nodes
+}
+
+fn content_type_name(content_type: u8) -> String {
+    match content_type {
+        20 => "Change Cipher Spec".to_owned(),
+        21 => "Alert".to_owned(),
+        22 => "Handshake".to_owned(),
+        23 => "Application Data".to_owned(),
+        other => format!("Unknown ({other})"),
+    }
+}
+
+fn version_name(version: u16) -> String {
+    let name = match version {
+        0x0300 => "SSL 3.0",
+        0x0301 => "TLS 1.0",
+        0x0302 => "TLS 1.1",
+        0x0303 => "TLS 1.2",
+        0x0304 => "TLS 1.3",
+        _ => return format!("0x{version:04x}"),
+    };
+    format!("{name} (0x{version:04x})")
+}
+
+fn handshake_type_name(handshake_type: u8) -> String {
+    match handshake_type {
+        0 => "Hello Request".to_owned(),
+        1 => "Client Hello".to_owned(),
+        2 => "Server Hello".to_owned(),
+        4 => "New Session Ticket".to_owned(),
+        5 => "End Of Early Data".to_owned(),
+        8 => "Encrypted Extensions".to_owned(),
+        11 => "Certificate".to_owned(),
+        12 => "Server Key Exchange".to_owned(),
+        13 => "Certificate Request".to_owned(),
+        14 => "Server Hello Done".to_owned(),
+        15 => "Certificate Verify".to_owned(),
+        16 => "Client Key Exchange".to_owned(),
+        20 => "Finished".to_owned(),
+        24 => "Key Update".to_owned(),
+        other => format!("Unknown ({other})"),
+    }
+}
+
+/// Aligns `state` on the next plausible record header. The first bytes of a direction
+/// may start with any record type; otherwise only a handshake record is accepted, so a
+/// stray `0x17 0x03` inside non-TLS payload doesn't start parsing.
+fn find_record_start(input: &[u8], state: &mut TlsStreamState) -> bool {
+    if !state.started {
+        if input.len() < RECORD_HEADER_LEN {
+            return false;
+        }
+        state.started = true;
+        if plausible_header(&input[..RECORD_HEADER_LEN], true) {
+            state.synced = true;
+            return true;
+        }
+    }
+
+    let candidates = input.windows(RECORD_HEADER_LEN);
+    match candidates
+        .clone()
+        .position(|header| plausible_header(header, state.after_gap))
+    {
+        Some(position) => {
+            state.consumed += position;
+            state.synced = true;
+            state.after_gap = false;
+            state.scanned = 0;
+            true
+        }
+        None => {
+            // Keep the last few bytes: a header may straddle the next segment.
+            let searched = candidates.len();
+            state.consumed += searched;
+            state.scanned += searched;
+            if state.scanned > MAX_RESYNC_SCAN {
+                state.abandoned = true;
+            }
+            false
+        }
+    }
+}
+
+fn plausible_header(header: &[u8], any_content_type: bool) -> bool {
+    let content_type_ok = if any_content_type {
+        (20..=23).contains(&header[0])
+    } else {
+        header[0] == u8::from(TlsRecordType::Handshake)
+    };
+    let len = u16::from_be_bytes([header[3], header[4]]) as usize;
+    content_type_ok && header[1] == 3 && header[2] <= 4 && len > 0 && len <= MAX_RECORD_LEN
 }
 
 fn handle_message(
@@ -0,0 +1,526 @@
+use crate::flow::tls::TlsInfo;
+use crate::parser::reassembly::StreamDirection;
+use aes_gcm::aead::{Aead as _, KeyInit, Payload};
+use aes_gcm::{Aes128Gcm, Aes256Gcm, Nonce};
+use anyhow::{Context, Result};
+use chacha20poly1305::ChaCha20Poly1305;
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use sha2::{Sha256, Sha384};
+use std::collections::HashMap;
+use std::path::Path;
+use tls_parser::TlsVersion;
+use tracing::{debug, info};
+
+const RANDOM_LEN: usize = 32;
+const RECORD_HEADER_LEN: usize = 5;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+/// Explicit nonce carried by each TLS 1.2 AES-GCM record.
+const EXPLICIT_NONCE_LEN: usize = 8;
+const HANDSHAKE_FINISHED: u8 = 20;
+const HANDSHAKE_KEY_UPDATE: u8 = 24;
+
+/// Secrets logged for one session.
+#[derive(Debug, Default, Clone)]
+struct SessionSecrets {
+    /// TLS 1.2 and earlier.
+    master_secret: Option<Vec<u8>>,
+    client_handshake: Option<Vec<u8>>,
+    server_handshake: Option<Vec<u8>>,
+    client_traffic: Option<Vec<u8>>,
+    server_traffic: Option<Vec<u8>>,
+}
+
+/// TLS secrets in the NSS key log format written by `SSLKEYLOGFILE`, keyed by the
+/// client random of each session.
+#[derive(Debug, Default, Clone)]
+pub struct KeyLog {
+    sessions: HashMap<[u8; RANDOM_LEN], SessionSecrets>,
+}
+
+type SecretSlot = fn(&mut SessionSecrets) -> &mut Option<Vec<u8>>;
+
+impl KeyLog {
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read TLS key log {}", path.display()))?;
+        let mut keylog = Self::default();
+        let secrets = keylog.add_lines(&text);
+        info!(?path, secrets, "Loaded TLS key log");
+        Ok(keylog)
+    }
+
+    /// Adds the `<label> <client random> <secret>` lines of `text`, skipping comments,
+    /// labels we have no use for and malformed lines. Returns the number of secrets added.
+    pub fn add_lines(&mut self, text: &str) -> usize {
+        text.lines().filter(|line| self.add_line(line)).count()
+    }
+
+    fn add_line(&mut self, line: &str) -> bool {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [label, random, secret] = fields[..] else {
+            return false;
+        };
+        let slot: SecretSlot = match label {
+            "CLIENT_RANDOM" => |secrets| &mut secrets.master_secret,
+            "CLIENT_HANDSHAKE_TRAFFIC_SECRET" => |secrets| &mut secrets.client_handshake,
+            "SERVER_HANDSHAKE_TRAFFIC_SECRET" => |secrets| &mut secrets.server_handshake,
+            "CLIENT_TRAFFIC_SECRET_0" => |secrets| &mut secrets.client_traffic,
+            "SERVER_TRAFFIC_SECRET_0" => |secrets| &mut secrets.server_traffic,
+            _ => return false,
+        };
+        let (Some(random), Some(secret)) = (decode_hex(random), decode_hex(secret)) else {
+            return false;
+        };
+        let Ok(random) = <[u8; RANDOM_LEN]>::try_from(random) else {
+            return false;
+        };
+        *slot(self.sessions.entry(random).or_default()) = Some(secret);
+        true
+    }
+
+    fn secrets(&self, client_random: &[u8; RANDOM_LEN]) -> Option<&SessionSecrets> {
+        self.sessions.get(client_random)
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AeadAlgorithm {
+    Aes128Gcm,
+    Aes256Gcm,
+    ChaCha20Poly1305,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HashAlgorithm {
+    Sha256,
+    Sha384,
+}
+
+impl HashAlgorithm {
+    fn len(self) -> usize {
+        match self {
+            HashAlgorithm::Sha256 => 32,
+            HashAlgorithm::Sha384 => 48,
+        }
+    }
+}
+
+/// The AEAD cipher suites we can decrypt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct CipherSuite {
+    aead: AeadAlgorithm,
+    hash: HashAlgorithm,
+}
+
+impl CipherSuite {
+    fn from_id(id: u16) -> Option<Self> {
+        let (aead, hash) = match id {
+            // TLS_AES_128_GCM_SHA256 and the TLS 1.2 *_WITH_AES_128_GCM_SHA256 suites.
+            0x1301 | 0x009c | 0x009e | 0xc02b | 0xc02f => {
+                (AeadAlgorithm::Aes128Gcm, HashAlgorithm::Sha256)
+            }
+            0x1302 | 0x009d | 0x009f | 0xc02c | 0xc030 => {
+                (AeadAlgorithm::Aes256Gcm, HashAlgorithm::Sha384)
+            }
+            0x1303 | 0xcca8 | 0xcca9 | 0xccaa => {
+                (AeadAlgorithm::ChaCha20Poly1305, HashAlgorithm::Sha256)
+            }
+            _ => return None,
+        };
+        Some(Self { aead, hash })
+    }
+
+    fn key_len(self) -> usize {
+        match self.aead {
+            AeadAlgorithm::Aes128Gcm => 16,
+            AeadAlgorithm::Aes256Gcm | AeadAlgorithm::ChaCha20Poly1305 => 32,
+        }
+    }
+
+    /// Implicit part of the TLS 1.2 nonce. AES-GCM records carry the other 8 bytes.
+    fn tls12_iv_len(self) -> usize {
+        match self.aead {
+            AeadAlgorithm::Aes128Gcm | AeadAlgorithm::Aes256Gcm => 4,
+            AeadAlgorithm::ChaCha20Poly1305 => NONCE_LEN,
+        }
+    }
+}
+
+enum Cipher {
+    Aes128Gcm(Box<Aes128Gcm>),
+    Aes256Gcm(Box<Aes256Gcm>),
+    ChaCha20Poly1305(Box<ChaCha20Poly1305>),
+}
+
+impl Cipher {
+    fn new(aead: AeadAlgorithm, key: &[u8]) -> Option<Self> {
+        Some(match aead {
+            AeadAlgorithm::Aes128Gcm => {
+                Self::Aes128Gcm(Box::new(Aes128Gcm::new_from_slice(key).ok()?))
+            }
+            AeadAlgorithm::Aes256Gcm => {
+                Self::Aes256Gcm(Box::new(Aes256Gcm::new_from_slice(key).ok()?))
+            }
+            AeadAlgorithm::ChaCha20Poly1305 => {
+                Self::ChaCha20Poly1305(Box::new(ChaCha20Poly1305::new_from_slice(key).ok()?))
+            }
+        })
+    }
+
+    fn open(&self, nonce: &[u8; NONCE_LEN], ciphertext: &[u8], aad: &[u8]) -> Option<Vec<u8>> {
+        let nonce = Nonce::from_slice(nonce);
+        let payload = Payload {
+            msg: ciphertext,
+            aad,
+        };
+        match self {
+            Self::Aes128Gcm(cipher) => cipher.decrypt(nonce, payload),
+            Self::Aes256Gcm(cipher) => cipher.decrypt(nonce, payload),
+            Self::ChaCha20Poly1305(cipher) => cipher.decrypt(nonce, payload),
+        }
+        .ok()
+    }
+}
+
+/// Record protection for one direction of a session.
+struct DirectionKeys {
+    suite: CipherSuite,
+    cipher: Cipher,
+    iv: Vec<u8>,
+    seq: u64,
+    /// TLS 1.3 traffic secret the keys came from, needed to follow key updates.
+    tls13_secret: Option<Vec<u8>>,
+}
+
+impl DirectionKeys {
+    fn tls12(suite: CipherSuite, key: &[u8], iv: &[u8]) -> Option<Self> {
+        Some(Self {
+            suite,
+            cipher: Cipher::new(suite.aead, key)?,
+            iv: iv.to_vec(),
+            seq: 0,
+            tls13_secret: None,
+        })
+    }
+
+    fn tls13(cipher_suite: u16, secret: &[u8]) -> Option<Self> {
+        let suite = CipherSuite::from_id(cipher_suite)?;
+        let (key, iv) = tls13_traffic_keys(cipher_suite, secret)?;
+        Some(Self {
+            suite,
+            cipher: Cipher::new(suite.aead, &key)?,
+            iv,
+            seq: 0,
+            tls13_secret: Some(secret.to_vec()),
+        })
+    }
+
+    /// Decrypts `record`, header included, returning its real content type and the
+    /// plaintext. Every record counts towards the sequence number, even if it fails.
+    fn open(&mut self, record: &[u8]) -> Option<(u8, Vec<u8>)> {
+        let seq = self.seq;
+        self.seq += 1;
+        let (header, body) = record.split_at_checked(RECORD_HEADER_LEN)?;
+
+        if self.tls13_secret.is_some() {
+            let mut plaintext = self.cipher.open(&self.nonce(seq), body, header)?;
+            // TLSInnerPlaintext: content, the real content type, then zero padding.
+            let content_type_at = plaintext.iter().rposition(|byte| *byte != 0)?;
+            let content_type = plaintext[content_type_at];
+            plaintext.truncate(content_type_at);
+            return Some((content_type, plaintext));
+        }
+
+        let (nonce, ciphertext) = match self.suite.aead {
+            AeadAlgorithm::ChaCha20Poly1305 => (self.nonce(seq), body),
+            AeadAlgorithm::Aes128Gcm | AeadAlgorithm::Aes256Gcm => {
+                let (explicit, ciphertext) = body.split_at_checked(EXPLICIT_NONCE_LEN)?;
+                let mut nonce = [0; NONCE_LEN];
+                nonce[..self.iv.len()].copy_from_slice(&self.iv);
+                nonce[self.iv.len()..].copy_from_slice(explicit);
+                (nonce, ciphertext)
+            }
+        };
+        let plaintext_len = u16::try_from(ciphertext.len().checked_sub(TAG_LEN)?).ok()?;
+        let mut aad = seq.to_be_bytes().to_vec();
+        aad.extend_from_slice(&header[..3]);
+        aad.extend_from_slice(&plaintext_len.to_be_bytes());
+        let plaintext = self.cipher.open(&nonce, ciphertext, &aad)?;
+        Some((header[0], plaintext))
+    }
+
+    /// Per-record nonce of TLS 1.3 and ChaCha20-Poly1305: the IV XORed with the sequence number.
+    fn nonce(&self, seq: u64) -> [u8; NONCE_LEN] {
+        let mut nonce = [0; NONCE_LEN];
+        nonce.copy_from_slice(&self.iv);
+        for (byte, seq_byte) in nonce[NONCE_LEN - 8..].iter_mut().zip(seq.to_be_bytes()) {
+            *byte ^= seq_byte;
+        }
+        nonce
+    }
+}
+
+/// Handshake parameters of one TLS connection and the record keys derived from them
+/// once the key log has the session's secrets.
+#[derive(Default)]
+pub struct TlsSession {
+    /// Metadata for the flow, present once a hello has been seen.
+    info: Option<TlsInfo>,
+    /// Direction the Client Hello was sent in.
+    client: Option<StreamDirection>,
+    client_random: Option<[u8; RANDOM_LEN]>,
+    server_random: Option<[u8; RANDOM_LEN]>,
+    cipher_suite: Option<u16>,
+    tls13: bool,
+    client_keys: Option<DirectionKeys>,
+    server_keys: Option<DirectionKeys>,
+}
+
+impl TlsSession {
+    /// Version negotiated by the Server Hello.
+    pub fn version(&self) -> TlsVersion {
+        if self.tls13 {
+            TlsVersion::Tls13
+        } else {
+            TlsVersion::Tls12
+        }
+    }
+
+    /// Whether records sent in `direction` come from the client.
+    pub fn sent_by_client(&self, direction: StreamDirection) -> bool {
+        self.client == Some(direction)
+    }
+
+    pub fn info_mut(&mut self) -> &mut TlsInfo {
+        self.info.get_or_insert_default()
+    }
+
+    pub fn into_info(self) -> Option<TlsInfo> {
+        self.info
+    }
+
+    pub fn client_hello(&mut self, direction: StreamDirection, random: [u8; RANDOM_LEN]) {
+        self.client = Some(direction);
+        self.client_random = Some(random);
+    }
+
+    /// Records the server's choices. TLS 1.3 encrypts everything after the Server Hello,
+    /// so the handshake keys of both directions are installed right away.
+    pub fn server_hello(
+        &mut self,
+        random: [u8; RANDOM_LEN],
+        cipher_suite: u16,
+        tls13: bool,
+        keylog: &KeyLog,
+    ) {
+        self.server_random = Some(random);
+        self.cipher_suite = Some(cipher_suite);
+        self.tls13 = tls13;
+        if !tls13 {
+            return;
+        }
+        let Some(secrets) = self.secrets(keylog) else {
+            return;
+        };
+        let keys =
+            |secret: &Option<Vec<u8>>| DirectionKeys::tls13(cipher_suite, secret.as_deref()?);
+        self.client_keys = keys(&secrets.client_handshake);
+        self.server_keys = keys(&secrets.server_handshake);
+    }
+
+    /// A TLS 1.2 Change Cipher Spec: records sent in `direction` are encrypted from now on.
+    pub fn change_cipher_spec(&mut self, direction: StreamDirection, keylog: &KeyLog) {
+        if self.tls13 {
+            // Only sent for middlebox compatibility in TLS 1.3.
+            return;
+        }
+        *self.keys_mut(direction) = self.tls12_keys(direction, keylog);
+    }
+
+    /// Decrypts a record sent in `direction`, returning its content type and plaintext.
+    /// `None` until the direction's keys are known or when the record doesn't decrypt.
+    pub fn decrypt(&mut self, direction: StreamDirection, record: &[u8]) -> Option<(u8, Vec<u8>)> {
+        let keys = self.keys_mut(direction).as_mut()?;
+        let opened = keys.open(record);
+        if opened.is_none() {
+            debug!(
+                ?direction,
+                seq = keys.seq - 1,
+                "TLS record failed to decrypt"
+            );
+        }
+        opened
+    }
+
+    /// Follows the TLS 1.3 key changes that a decrypted handshake message sent in
+    /// `direction` announces.
+    pub fn handshake_message(
+        &mut self,
+        direction: StreamDirection,
+        handshake_type: u8,
+        keylog: &KeyLog,
+    ) {
+        let (true, Some(cipher_suite)) = (self.tls13, self.cipher_suite) else {
+            return;
+        };
+        let next = match handshake_type {
+            HANDSHAKE_FINISHED => {
+                let Some(secrets) = self.secrets(keylog) else {
+                    return;
+                };
+                let secret = if self.client == Some(direction) {
+                    &secrets.client_traffic
+                } else {
+                    &secrets.server_traffic
+                };
+                secret
+                    .as_deref()
+                    .and_then(|secret| DirectionKeys::tls13(cipher_suite, secret))
+            }
+            HANDSHAKE_KEY_UPDATE => {
+                let Some(keys) = self.keys_mut(direction).as_ref() else {
+                    return;
+                };
+                let hash = keys.suite.hash;
+                keys.tls13_secret
+                    .as_deref()
+                    .and_then(|secret| expand_label(hash, secret, "traffic upd", hash.len()))
+                    .and_then(|secret| DirectionKeys::tls13(cipher_suite, &secret))
+            }
+            _ => return,
+        };
+        *self.keys_mut(direction) = next;
+    }
+
+    fn tls12_keys(&self, direction: StreamDirection, keylog: &KeyLog) -> Option<DirectionKeys> {
+        let cipher_suite = self.cipher_suite?;
+        let suite = CipherSuite::from_id(cipher_suite)?;
+        let block = tls12_key_block(
+            cipher_suite,
+            self.secrets(keylog)?.master_secret.as_deref()?,
+            &self.client_random?,
+            &self.server_random?,
+        )?;
+        let (key_len, iv_len) = (suite.key_len(), suite.tls12_iv_len());
+        let (key, iv) = if self.client == Some(direction) {
+            (&block[..key_len], &block[2 * key_len..2 * key_len + iv_len])
+        } else {
+            (&block[key_len..2 * key_len], &block[2 * key_len + iv_len..])
+        };
+        DirectionKeys::tls12(suite, key, iv)
+    }
+
+    fn secrets<'a>(&self, keylog: &'a KeyLog) -> Option<&'a SessionSecrets> {
+        if self.cipher_suite.and_then(CipherSuite::from_id).is_none() {
+            debug!(
+                cipher_suite = ?self.cipher_suite,
+                "TLS cipher suite not supported for decryption"
+            );
+            return None;
+        }
+        keylog.secrets(self.client_random.as_ref()?)
+    }
+
+    fn keys_mut(&mut self, direction: StreamDirection) -> &mut Option<DirectionKeys> {
+        if self.client == Some(direction) {
+            &mut self.client_keys
+        } else {
+            &mut self.server_keys
+        }
+    }
+}
+
+/// Key and IV of a TLS 1.3 traffic secret (RFC 8446, section 7.3).
+pub(crate) fn tls13_traffic_keys(cipher_suite: u16, secret: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+    let suite = CipherSuite::from_id(cipher_suite)?;
+    Some((
+        expand_label(suite.hash, secret, "key", suite.key_len())?,
+        expand_label(suite.hash, secret, "iv", NONCE_LEN)?,
+    ))
+}
+
+/// TLS 1.2 key block: client and server write keys, then client and server write IVs
+/// (RFC 5246, section 6.3). AEAD suites have no MAC keys.
+pub(crate) fn tls12_key_block(
+    cipher_suite: u16,
+    master_secret: &[u8],
+    client_random: &[u8; RANDOM_LEN],
+    server_random: &[u8; RANDOM_LEN],
+) -> Option<Vec<u8>> {
+    let suite = CipherSuite::from_id(cipher_suite)?;
+    let mut seed = server_random.to_vec();
+    seed.extend_from_slice(client_random);
+    let len = 2 * (suite.key_len() + suite.tls12_iv_len());
+    Some(match suite.hash {
+        HashAlgorithm::Sha256 => {
+            p_hash::<Hmac<Sha256>>(master_secret, b"key expansion", &seed, len)
+        }
+        HashAlgorithm::Sha384 => {
+            p_hash::<Hmac<Sha384>>(master_secret, b"key expansion", &seed, len)
+        }
+    })
+}
+
+/// The TLS 1.2 PRF, `P_hash(secret, label + seed)`.
+pub(crate) fn p_hash<M: Mac + KeyInit + Clone>(
+    secret: &[u8],
+    label: &[u8],
+    seed: &[u8],
+    len: usize,
+) -> Vec<u8> {
+    let mac = <M as KeyInit>::new_from_slice(secret).expect("HMAC takes keys of any length");
+    let mut label_seed = label.to_vec();
+    label_seed.extend_from_slice(seed);
+    let mut a = label_seed.clone();
+    let mut out = Vec::with_capacity(len);
+    while out.len() < len {
+        a = mac
+            .clone()
+            .chain_update(&a)
+            .finalize()
+            .into_bytes()
+            .to_vec();
+        out.extend(
+            mac.clone()
+                .chain_update(&a)
+                .chain_update(&label_seed)
+                .finalize()
+                .into_bytes(),
+        );
+    }
+    out.truncate(len);
+    out
+}
+
+/// `HKDF-Expand-Label` with an empty context (RFC 8446, section 7.1).
+fn expand_label(hash: HashAlgorithm, secret: &[u8], label: &str, len: usize) -> Option<Vec<u8>> {
+    let label = format!("tls13 {label}");
+    let mut info = u16::try_from(len).ok()?.to_be_bytes().to_vec();
+    info.push(u8::try_from(label.len()).ok()?);
+    info.extend_from_slice(label.as_bytes());
+    info.push(0);
+
+    let mut out = vec![0; len];
+    match hash {
+        HashAlgorithm::Sha256 => Hkdf::<Sha256>::from_prk(secret)
+            .ok()?
+            .expand(&info, &mut out),
+        HashAlgorithm::Sha384 => Hkdf::<Sha384>::from_prk(secret)
+            .ok()?
+            .expand(&info, &mut out),
+    }
+    .ok()?;
+    Some(out)
+}
@@ -0,0 +1,189 @@
+use md5::{Digest, Md5};
+use sha2::Sha256;
+use tls_parser::{TlsClientHelloContents, TlsServerHelloContents};
+
+const EXT_SERVER_NAME: u16 = 0x0000;
+const EXT_SUPPORTED_GROUPS: u16 = 0x000a;
+const EXT_EC_POINT_FORMATS: u16 = 0x000b;
+const EXT_SIGNATURE_ALGORITHMS: u16 = 0x000d;
+const EXT_ALPN: u16 = 0x0010;
+const EXT_SUPPORTED_VERSIONS: u16 = 0x002b;
+/// JA4 hash of an empty list.
+const JA4_EMPTY_HASH: &str = "000000000000";
+
+/// JA3 of a Client Hello: MD5 of the version, cipher suites, extensions, supported
+/// groups and point formats, GREASE values left out.
+pub fn ja3(hello: &TlsClientHelloContents) -> String {
+    let extensions = extensions(hello.ext);
+    let groups = extension_data(&extensions, EXT_SUPPORTED_GROUPS)
+        .map(|data| u16_list(data.get(2..).unwrap_or_default()))
+        .unwrap_or_default();
+    let point_formats: Vec<u16> = extension_data(&extensions, EXT_EC_POINT_FORMATS)
+        .and_then(|data| data.get(1..))
+        .unwrap_or_default()
+        .iter()
+        .map(|format| *format as u16)
+        .collect();
+    let text = [
+        hello.version.0.to_string(),
+        decimal_list(hello.ciphers.iter().map(|cipher| cipher.0)),
+        decimal_list(extensions.iter().map(|(ext_type, _)| *ext_type)),
+        decimal_list(groups),
+        decimal_list(point_formats),
+    ]
+    .join(",");
+    md5_hex(&text)
+}
+
+/// JA3S of a Server Hello: MD5 of the version, chosen cipher suite and extensions.
+pub fn ja3s(hello: &TlsServerHelloContents) -> String {
+    let extensions = extensions(hello.ext);
+    let text = [
+        hello.version.0.to_string(),
+        hello.cipher.0.to_string(),
+        decimal_list(extensions.iter().map(|(ext_type, _)| *ext_type)),
+    ]
+    .join(",");
+    md5_hex(&text)
+}
+
+/// JA4 of a Client Hello sent over TCP, e.g. `t13d1516h2_8daaf6152771_e5627efa2ab1`.
+pub fn ja4(hello: &TlsClientHelloContents) -> String {
+    let extensions = extensions(hello.ext);
+    let ciphers: Vec<u16> = hello
+        .ciphers
+        .iter()
+        .map(|cipher| cipher.0)
+        .filter(|cipher| !is_grease(*cipher))
+        .collect();
+
+    let version = extension_data(&extensions, EXT_SUPPORTED_VERSIONS)
+        .and_then(|data| u16_list(data.get(1..)?).into_iter().max())
+        .unwrap_or(hello.version.0);
+    let version = match version {
+        0x0304 => "13",
+        0x0303 => "12",
+        0x0302 => "11",
+        0x0301 => "10",
+        0x0300 => "s3",
+        _ => "00",
+    };
+    let sni = if extension_data(&extensions, EXT_SERVER_NAME).is_some() {
+        'd'
+    } else {
+        'i'
+    };
+    let alpn = extension_data(&extensions, EXT_ALPN)
+        .and_then(|data| {
+            let len = *data.get(2)? as usize;
+            data.get(3..3 + len).filter(|protocol| !protocol.is_empty())
+        })
+        .map_or_else(|| "00".to_owned(), alpn_chars);
+
+    let mut sorted_ciphers = ciphers.clone();
+    sorted_ciphers.sort_unstable();
+    let mut sorted_extensions: Vec<u16> = extensions
+        .iter()
+        .map(|(ext_type, _)| *ext_type)
+        .filter(|ext_type| *ext_type != EXT_SERVER_NAME && *ext_type != EXT_ALPN)
+        .collect();
+    sorted_extensions.sort_unstable();
+    let signature_algorithms = extension_data(&extensions, EXT_SIGNATURE_ALGORITHMS)
+        .map(|data| u16_list(data.get(2..).unwrap_or_default()))
+        .unwrap_or_default();
+    let mut extension_text = hex_list(&sorted_extensions);
+    if !signature_algorithms.is_empty() {
+        extension_text.push('_');
+        extension_text.push_str(&hex_list(&signature_algorithms));
+    }
+
+    format!(
+        "t{version}{sni}{:02}{:02}{alpn}_{}_{}",
+        ciphers.len().min(99),
+        extensions.len().min(99),
+        ja4_hash(&hex_list(&sorted_ciphers), sorted_ciphers.is_empty()),
+        ja4_hash(&extension_text, sorted_extensions.is_empty()),
+    )
+}
+
+/// Type and data of each non-GREASE extension, in the order sent. Walked by hand so
+/// unknown extensions keep their place.
+fn extensions(block: Option<&[u8]>) -> Vec<(u16, &[u8])> {
+    let mut extensions = Vec::new();
+    let mut rest = block.unwrap_or_default();
+    while rest.len() >= 4 {
+        let ext_type = u16::from_be_bytes([rest[0], rest[1]]);
+        let len = u16::from_be_bytes([rest[2], rest[3]]) as usize;
+        let Some(data) = rest.get(4..4 + len) else {
+            break;
+        };
+        if !is_grease(ext_type) {
+            extensions.push((ext_type, data));
+        }
+        rest = &rest[4 + len..];
+    }
+    extensions
+}
+
+fn extension_data<'a>(extensions: &[(u16, &'a [u8])], ext_type: u16) -> Option<&'a [u8]> {
+    extensions
+        .iter()
+        .find(|(candidate, _)| *candidate == ext_type)
+        .map(|(_, data)| *data)
+}
+
+/// Big-endian `u16`s of `data`, GREASE values left out.
+fn u16_list(data: &[u8]) -> Vec<u16> {
+    data.chunks_exact(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .filter(|value| !is_grease(*value))
+        .collect()
+}
+
+/// GREASE values (RFC 8701) look like `0x?a?a` with both bytes equal.
+fn is_grease(value: u16) -> bool {
+    value & 0x0f0f == 0x0a0a && value >> 8 == value & 0xff
+}
+
+/// First and last character of the ALPN value, or the outer hex digits of its first
+/// and last bytes when those aren't alphanumeric.
+fn alpn_chars(protocol: &[u8]) -> String {
+    let (first, last) = (protocol[0], protocol[protocol.len() - 1]);
+    if first.is_ascii_alphanumeric() && last.is_ascii_alphanumeric() {
+        format!("{}{}", first as char, last as char)
+    } else {
+        format!("{:x}{:x}", first >> 4, last & 0x0f)
+    }
+}
+
+fn decimal_list(values: impl IntoIterator<Item = u16>) -> String {
+    values
+        .into_iter()
+        .filter(|value| !is_grease(*value))
+        .map(|value| value.to_string())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+fn hex_list(values: &[u16]) -> String {
+    values
+        .iter()
+        .map(|value| format!("{value:04x}"))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn ja4_hash(text: &str, empty: bool) -> String {
+    if empty {
+        return JA4_EMPTY_HASH.to_owned();
+    }
+    hex(&Sha256::digest(text.as_bytes()))[..12].to_owned()
+}
+
+fn md5_hex(text: &str) -> String {
+    hex(&Md5::digest(text.as_bytes()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
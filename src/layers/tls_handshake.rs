@@ -0,0 +1,165 @@
+use crate::flow::IPAddress;
+use crate::flow::tls::{CertificateInfo, TlsInfo};
+use crate::layers::tls_fingerprint;
+use tls_parser::{
+    SNIType, TlsClientHelloContents, TlsExtension, TlsServerHelloContents, TlsVersion,
+    parse_tls_client_hello_extensions, parse_tls_server_hello_extensions,
+};
+use tracing::debug;
+use x509_parser::extensions::GeneralName;
+use x509_parser::parse_x509_certificate;
+
+const HANDSHAKE_ENCRYPTED_EXTENSIONS: u8 = 8;
+const HANDSHAKE_CERTIFICATE: u8 = 11;
+
+/// Records the SNI, ALPN protocols and cipher suites a client offers, and its
+/// fingerprints.
+pub fn client_hello(info: &mut TlsInfo, hello: &TlsClientHelloContents) {
+    info.ja3 = Some(tls_fingerprint::ja3(hello));
+    info.ja4 = Some(tls_fingerprint::ja4(hello));
+    info.offered_cipher_suites = hello.ciphers.iter().map(|cipher| cipher.0).collect();
+    let extensions = hello
+        .ext
+        .and_then(|ext| parse_tls_client_hello_extensions(ext).ok())
+        .map(|(_, extensions)| extensions)
+        .unwrap_or_default();
+    for extension in extensions {
+        match extension {
+            TlsExtension::SNI(names) => {
+                info.server_name = names
+                    .iter()
+                    .find(|(name_type, _)| *name_type == SNIType::HostName)
+                    .map(|(_, name)| String::from_utf8_lossy(name).into_owned());
+            }
+            TlsExtension::ALPN(protocols) => info.offered_alpn = alpn_names(&protocols),
+            _ => {}
+        }
+    }
+}
+
+/// Records the server's choices and returns whether it negotiated TLS 1.3.
+pub fn server_hello(info: &mut TlsInfo, hello: &TlsServerHelloContents) -> bool {
+    info.ja3s = Some(tls_fingerprint::ja3s(hello));
+    info.version = Some(hello.version.0);
+    info.cipher_suite = Some(hello.cipher.0);
+    let extensions = hello
+        .ext
+        .and_then(|ext| parse_tls_server_hello_extensions(ext).ok())
+        .map(|(_, extensions)| extensions)
+        .unwrap_or_default();
+    for extension in extensions {
+        match extension {
+            TlsExtension::SupportedVersions(versions) => {
+                if let Some(version) = versions.first() {
+                    info.version = Some(version.0);
+                }
+            }
+            TlsExtension::ALPN(protocols) => info.alpn = alpn_names(&protocols).into_iter().next(),
+            _ => {}
+        }
+    }
+    info.version == Some(TlsVersion::Tls13.0)
+}
+
+/// Replaces the certificate chain with the DER certificates of a Certificate message.
+pub fn certificates<'a>(info: &mut TlsInfo, chain: impl IntoIterator<Item = &'a [u8]>) {
+    info.certificates = chain.into_iter().filter_map(certificate_info).collect();
+}
+
+/// Picks the metadata out of decrypted TLS 1.3 handshake messages: the ALPN choice in
+/// Encrypted Extensions and the certificate chain. Certificates sent by the client are
+/// ignored.
+pub fn decrypted_handshake(info: &mut TlsInfo, body: &[u8], from_client: bool) {
+    let mut pos = 0;
+    while pos + 4 <= body.len() {
+        let len = u32::from_be_bytes([0, body[pos + 1], body[pos + 2], body[pos + 3]]) as usize;
+        let Some(message) = body.get(pos + 4..pos + 4 + len) else {
+            return;
+        };
+        match body[pos] {
+            HANDSHAKE_ENCRYPTED_EXTENSIONS => {
+                let extensions = message
+                    .get(2..)
+                    .and_then(|ext| parse_tls_server_hello_extensions(ext).ok())
+                    .map(|(_, extensions)| extensions)
+                    .unwrap_or_default();
+                for extension in extensions {
+                    if let TlsExtension::ALPN(protocols) = extension {
+                        info.alpn = alpn_names(&protocols).into_iter().next();
+                    }
+                }
+            }
+            HANDSHAKE_CERTIFICATE if !from_client => {
+                certificates(info, tls13_certificate_list(message));
+            }
+            _ => {}
+        }
+        pos += 4 + len;
+    }
+}
+
+/// DER certificates of a TLS 1.3 Certificate message, whose entries each carry their
+/// own extensions (RFC 8446, section 4.4.2).
+fn tls13_certificate_list(message: &[u8]) -> Vec<&[u8]> {
+    let mut chain = Vec::new();
+    let Some(&context_len) = message.first() else {
+        return chain;
+    };
+    let mut pos = 1 + context_len as usize + 3;
+    while let Some(header) = message.get(pos..pos + 3) {
+        let cert_len = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+        let Some(cert) = message.get(pos + 3..pos + 3 + cert_len) else {
+            break;
+        };
+        chain.push(cert);
+        pos += 3 + cert_len;
+        let Some(ext_len) = message.get(pos..pos + 2) else {
+            break;
+        };
+        pos += 2 + u16::from_be_bytes([ext_len[0], ext_len[1]]) as usize;
+    }
+    chain
+}
+
+fn certificate_info(der: &[u8]) -> Option<CertificateInfo> {
+    let (_, cert) = parse_x509_certificate(der)
+        .inspect_err(|err| debug!(error = %err, "Failed to parse TLS certificate"))
+        .ok()?;
+    let subject_alt_names = match cert.subject_alternative_name() {
+        Ok(Some(san)) => san
+            .value
+            .general_names
+            .iter()
+            .filter_map(general_name)
+            .collect(),
+        _ => Vec::new(),
+    };
+    Some(CertificateInfo {
+        subject: cert.subject().to_string(),
+        issuer: cert.issuer().to_string(),
+        subject_alt_names,
+        not_before: cert.validity().not_before.to_string(),
+        not_after: cert.validity().not_after.to_string(),
+    })
+}
+
+fn general_name(name: &GeneralName) -> Option<String> {
+    match name {
+        GeneralName::DNSName(name) | GeneralName::RFC822Name(name) | GeneralName::URI(name) => {
+            Some((*name).to_owned())
+        }
+        GeneralName::IPAddress(bytes) => match bytes.len() {
+            4 => Some(IPAddress::V4((*bytes).try_into().ok()?).to_string()),
+            16 => Some(IPAddress::V6((*bytes).try_into().ok()?).to_string()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn alpn_names(protocols: &[&[u8]]) -> Vec<String> {
+    protocols
+        .iter()
+        .map(|protocol| String::from_utf8_lossy(protocol).into_owned())
+        .collect()
+}
@@ -0,0 +1,113 @@
+use std::collections::HashSet;
+use std::ops::Range;
+
+/// A protocol layer or one of its fields, with the bytes of the frame it was decoded from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtocolNode {
+    pub name: String,
+    pub value: Option<String>,
+    /// Byte range within the captured frame.
+    pub range: Range<usize>,
+    pub children: Vec<ProtocolNode>,
+}
+
+/// Per-packet dissection: one root node per protocol layer, outermost first.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ProtocolTree {
+    pub layers: Vec<ProtocolNode>,
+}
+
+/// A node of a [`ProtocolTree`] as shown in an expandable view. `path` holds the child
+/// indices leading to the node, starting with the layer index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeRow<'a> {
+    pub path: Vec<usize>,
+    pub depth: usize,
+    pub node: &'a ProtocolNode,
+    pub expanded: bool,
+}
+
+impl ProtocolNode {
+    pub fn new(name: impl Into<String>, range: Range<usize>) -> Self {
+        Self {
+            name: name.into(),
+            value: None,
+            range,
+            children: Vec::new(),
+        }
+    }
+
+    pub fn with_value(mut self, value: impl Into<String>) -> Self {
+        self.value = Some(value.into());
+        self
+    }
+
+    /// Adds a `name: value` field covering `range`.
+    pub fn field(
+        &mut self,
+        name: &str,
+        value: impl Into<String>,
+        range: Range<usize>,
+    ) -> &mut Self {
+        self.children
+            .push(ProtocolNode::new(name, range).with_value(value));
+        self
+    }
+
+    pub fn child(&mut self, node: ProtocolNode) -> &mut Self {
+        self.children.push(node);
+        self
+    }
+
+    /// `Name: value`, or just the name for nodes without a value.
+    pub fn label(&self) -> String {
+        match &self.value {
+            Some(value) => format!("{}: {}", self.name, value),
+            None => self.name.clone(),
+        }
+    }
+}
+
+impl ProtocolTree {
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    pub fn node(&self, path: &[usize]) -> Option<&ProtocolNode> {
+        let (first, rest) = path.split_first()?;
+        rest.iter()
+            .try_fold(self.layers.get(*first)?, |node, ix| node.children.get(*ix))
+    }
+
+    /// Rows to display when the nodes whose paths are in `expanded` are open.
+    pub fn visible_rows(&self, expanded: &HashSet<Vec<usize>>) -> Vec<TreeRow<'_>> {
+        let mut rows = Vec::new();
+        for (ix, layer) in self.layers.iter().enumerate() {
+            push_rows(layer, vec![ix], expanded, &mut rows);
+        }
+        rows
+    }
+}
+
+fn push_rows<'a>(
+    node: &'a ProtocolNode,
+    path: Vec<usize>,
+    expanded: &HashSet<Vec<usize>>,
+    rows: &mut Vec<TreeRow<'a>>,
+) {
+    let is_expanded = expanded.contains(&path);
+    rows.push(TreeRow {
+        depth: path.len() - 1,
+        path: path.clone(),
+        node,
+        expanded: is_expanded,
+    });
+    if !is_expanded {
+        return;
+    }
+    for (ix, child) in node.children.iter().enumerate() {
+        let mut child_path = path.clone();
+        child_path.push(ix);
+        push_rows(child, child_path, expanded, rows);
+    }
+}
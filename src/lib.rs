@@ -1,3 +1,5 @@
+pub mod cli;
+pub mod export;
 pub mod flow;
 pub mod gui;
 pub mod layers;
@@ -1,63 +1,170 @@
 use crate::flow::{Flow, FlowKey, IPAddress};
-use crate::parser::parse_pcap;
+use crate::layers::dissector::DissectorRegistry;
+use crate::parser::pipeline::{BatchSink, FlowBatch};
+use crate::parser::state::CaptureInterface;
+use crate::parser::{Cancelled, ParseOptions, STDIN_PATH, is_stream, parse_captures};
 use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{self, Receiver};
 use std::thread;
-use tracing::{error, info, trace};
+use std::time::Duration;
+use tracing::{error, info, trace, warn};
+
+pub mod recent;
 
 #[cfg(test)]
 mod tests;
 
+/// How often flows parsed so far are handed to the frontend.
+const BATCH_INTERVAL: Duration = Duration::from_millis(250);
+
 pub enum LoadStatus {
     Progress(f32),
-    Loaded(HashMap<FlowKey, Flow>, Option<f64>, HashMap<IPAddress, Vec<String>>),
+    /// Flows added or extended since the previous batch.
+    Partial(FlowBatch),
+    Loaded(
+        HashMap<FlowKey, Flow>,
+        Option<f64>,
+        HashMap<IPAddress, Vec<String>>,
+        Vec<CaptureInterface>,
+        Vec<String>,
+    ),
     Error(String),
 }
 
+/// Parses a capture, or several merged into one, on a background thread. Dropping the
+/// loader cancels the parse.
 pub struct Loader {
     rx: Receiver<LoadStatus>,
+    cancel: Arc<AtomicBool>,
+    /// Set while the parse waits for the capture to grow, see [`ParseOptions::follow`].
+    follow: Arc<AtomicBool>,
 }
 
 impl Loader {
-    pub fn new(path: PathBuf) -> Self {
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        Self::with_registry(paths, Arc::new(DissectorRegistry::default()))
+    }
+
+    pub fn with_registry(paths: Vec<PathBuf>, registry: Arc<DissectorRegistry>) -> Self {
+        Self::spawn(paths, registry, false)
+    }
+
+    /// Loads `paths` and keeps adding the packets written to a single file afterwards
+    /// until [`stop_following`](Self::stop_following) is called.
+    pub fn following(paths: Vec<PathBuf>, registry: Arc<DissectorRegistry>) -> Self {
+        Self::spawn(paths, registry, true)
+    }
+
+    fn spawn(paths: Vec<PathBuf>, registry: Arc<DissectorRegistry>, follow: bool) -> Self {
         let (tx, rx) = mpsc::channel();
-        let path_clone = path.clone();
-        info!(path = ?path_clone, "Spawning loader thread");
+        let cancel = Arc::new(AtomicBool::new(false));
+        // A stream ends when its writer is done, so there is nothing to follow. Merged
+        // files are read to their end as they are.
+        let follow = follow && matches!(paths.as_slice(), [path] if !is_stream(path));
+        let follow = Arc::new(AtomicBool::new(follow));
+        let path_clone = paths.clone();
+        info!(paths = ?path_clone, follow = follow.load(Ordering::Relaxed), "Spawning loader thread");
+        let cancel_flag = cancel.clone();
+        let follow_flag = follow.clone();
         thread::spawn(move || {
-            let result = parse_pcap(&path_clone, |progress| {
+            let batch_tx = tx.clone();
+            let mut send_batch = move |batch: FlowBatch| {
+                trace!(flows = batch.flows.len(), "Parser published flows");
+                let _ = batch_tx.send(LoadStatus::Partial(batch));
+            };
+            let sink = BatchSink {
+                interval: BATCH_INTERVAL,
+                send: &mut send_batch,
+            };
+            let options = ParseOptions {
+                sink: Some(sink),
+                cancel: Some(&cancel_flag),
+                follow: Some(&follow_flag),
+                ..ParseOptions::default()
+            };
+            let result = parse_captures(&path_clone, &registry, options, |progress| {
                 trace!(progress, "Parser progress update");
                 let _ = tx.send(LoadStatus::Progress(progress));
             });
 
             match result {
-                Ok((flows, start_ts, name_resolutions)) => {
-                    info!(path = ?path_clone, flows = flows.len(), "PCAP parsed; sending results");
-                    let _ = tx.send(LoadStatus::Loaded(flows, start_ts, name_resolutions));
+                Ok(state) => {
+                    info!(paths = ?path_clone, flows = state.flows.len(), "PCAP parsed; sending results");
+                    let _ = tx.send(LoadStatus::Loaded(
+                        state.flows,
+                        state.first_packet_ts,
+                        state.name_resolutions,
+                        state.interfaces,
+                        state.sources,
+                    ));
+                }
+                Err(e) if e.is::<Cancelled>() => {
+                    info!(paths = ?path_clone, "Loader thread stopped after cancellation");
                 }
                 Err(e) => {
-                    error!(path = ?path_clone, error = ?e, "Failed to parse PCAP");
+                    error!(paths = ?path_clone, error = ?e, "Failed to parse PCAP");
                     let _ = tx.send(LoadStatus::Error(e.to_string()));
                 }
             }
         });
 
-        Self { rx }
+        Self { rx, cancel, follow }
     }
 
     pub fn try_recv(&self) -> Option<LoadStatus> {
         self.rx.try_recv().ok()
     }
+
+    /// Asks the parse to stop. The thread exits shortly after without reporting back.
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_following(&self) -> bool {
+        self.follow.load(Ordering::Relaxed)
+    }
+
+    /// Finishes the parse at the current end of the capture, which then reports its
+    /// flows as usual.
+    pub fn stop_following(&self) {
+        self.follow.store(false, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Loader {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// How the captures at `paths` are named in titles: the path of a single one, or the
+/// first followed by how many more there are.
+pub fn display_paths(paths: &[PathBuf]) -> String {
+    match paths {
+        [] => String::new(),
+        [path] => path.display().to_string(),
+        [first, rest @ ..] => format!("{} and {} more", first.display(), rest.len()),
+    }
 }
 
 pub enum FlowLoadStatus {
     Loading {
         progress: f32,
+        /// Flows parsed since the last poll, to merge into those already shown. The
+        /// `Ready` flows replace them all.
+        batch: Option<FlowBatch>,
     },
     Ready {
         flows: HashMap<FlowKey, Flow>,
         start_timestamp: Option<f64>,
         name_resolutions: HashMap<IPAddress, Vec<String>>,
+        /// Interfaces of the capture, needed to export its packets.
+        interfaces: Vec<CaptureInterface>,
+        /// Names of the capture files, which `Packet::source` indexes.
+        sources: Vec<String>,
     },
     Error(String),
     Idle,
@@ -66,13 +173,103 @@ pub enum FlowLoadStatus {
 pub struct FlowLoadController {
     loader: Option<Loader>,
     last_progress: f32,
+    /// The files being or last loaded.
+    paths: Vec<PathBuf>,
+    /// Whether one of `paths` is a stream, which reports no progress.
+    streaming: bool,
+    /// Whether files are followed as they grow.
+    follow: bool,
+    registry: Arc<DissectorRegistry>,
 }
 
 impl FlowLoadController {
-    pub fn new(path: PathBuf) -> Self {
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        Self::with_registry(paths, Arc::new(DissectorRegistry::default()))
+    }
+
+    /// Loads `paths` decoding payload with `registry`, e.g. to honour decode-as rules.
+    pub fn with_registry(paths: Vec<PathBuf>, registry: Arc<DissectorRegistry>) -> Self {
+        Self::start(paths, registry, false)
+    }
+
+    /// Loads `paths` and follows a single file as it grows, as do the files opened
+    /// later.
+    pub fn following(paths: Vec<PathBuf>, registry: Arc<DissectorRegistry>) -> Self {
+        Self::start(paths, registry, true)
+    }
+
+    fn start(paths: Vec<PathBuf>, registry: Arc<DissectorRegistry>, follow: bool) -> Self {
         Self {
-            loader: Some(Loader::new(path)),
+            loader: Some(Loader::spawn(paths.clone(), registry.clone(), follow)),
             last_progress: 0.0,
+            streaming: paths.iter().any(|path| is_stream(path)),
+            follow,
+            paths,
+            registry,
+        }
+    }
+
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.loader.is_some()
+    }
+
+    /// Whether the capture is read as it is written: from standard input, a pipe, or a
+    /// followed file. Its progress is unknown then.
+    pub fn is_live(&self) -> bool {
+        self.loader.is_some() && (self.streaming || self.is_following())
+    }
+
+    pub fn is_following(&self) -> bool {
+        self.loader.as_ref().is_some_and(Loader::is_following)
+    }
+
+    /// Stops waiting for the followed file to grow. The load then completes with the
+    /// packets written so far.
+    pub fn stop_following(&mut self) {
+        if let Some(loader) = &self.loader {
+            loader.stop_following();
+            info!(paths = ?self.paths, "Stopped following capture");
+        }
+    }
+
+    /// Starts loading `paths` instead, aborting the load in progress.
+    pub fn open(&mut self, paths: Vec<PathBuf>) {
+        self.cancel();
+        info!(paths = ?paths, "Switching capture file");
+        self.loader = Some(Loader::spawn(
+            paths.clone(),
+            self.registry.clone(),
+            self.follow,
+        ));
+        self.last_progress = 0.0;
+        self.streaming = paths.iter().any(|path| is_stream(path));
+        self.paths = paths;
+    }
+
+    /// Whether [`reload`](Self::reload) can read the capture again, which standard
+    /// input can't.
+    pub fn can_reload(&self) -> bool {
+        self.paths.iter().all(|path| path.as_os_str() != STDIN_PATH)
+    }
+
+    /// Loads the current file again from the start. Does nothing for standard input.
+    pub fn reload(&mut self) {
+        if !self.can_reload() {
+            warn!("Standard input can't be reloaded");
+            return;
+        }
+        self.open(self.paths.clone());
+    }
+
+    /// Aborts the load in progress. Later polls return `Idle`.
+    pub fn cancel(&mut self) {
+        if let Some(loader) = self.loader.take() {
+            loader.cancel();
+            info!(paths = ?self.paths, "Load cancelled");
         }
     }
 
@@ -81,24 +278,32 @@ impl FlowLoadController {
             return FlowLoadStatus::Idle;
         }
 
-        let mut status = FlowLoadStatus::Loading {
-            progress: self.last_progress,
-        };
-
+        let mut batch: Option<FlowBatch> = None;
         while let Some(message) = self.loader.as_ref().and_then(|loader| loader.try_recv()) {
             match message {
                 LoadStatus::Progress(p) => {
                     self.last_progress = p;
                     trace!(progress = p, "Loader received progress update");
-                    status = FlowLoadStatus::Loading { progress: p };
                 }
-                LoadStatus::Loaded(flows, start_timestamp, name_resolutions) => {
+                LoadStatus::Partial(later) => match &mut batch {
+                    Some(batch) => batch.extend(later),
+                    None => batch = Some(later),
+                },
+                LoadStatus::Loaded(
+                    flows,
+                    start_timestamp,
+                    name_resolutions,
+                    interfaces,
+                    sources,
+                ) => {
                     self.loader = None;
                     info!(flows = flows.len(), "Loader completed successfully");
                     return FlowLoadStatus::Ready {
                         flows,
                         start_timestamp,
                         name_resolutions,
+                        interfaces,
+                        sources,
                     };
                 }
                 LoadStatus::Error(error) => {
@@ -109,6 +314,9 @@ impl FlowLoadController {
             }
         }
 
-        status
+        FlowLoadStatus::Loading {
+            progress: self.last_progress,
+            batch,
+        }
     }
 }
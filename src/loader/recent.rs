@@ -0,0 +1,88 @@
+use crate::parser::STDIN_PATH;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+/// Files listed at most.
+const LIMIT: usize = 10;
+
+/// Capture files opened lately, most recent first. The list is kept in a text file with
+/// one path per line so both frontends share it across sessions.
+#[derive(Debug, Clone, Default)]
+pub struct RecentFiles {
+    paths: Vec<PathBuf>,
+    /// Where the list is saved; `None` keeps it in memory only.
+    store: Option<PathBuf>,
+}
+
+impl RecentFiles {
+    /// The list saved in the user's state directory, or an in-memory one when there is none.
+    pub fn load_default() -> Self {
+        Self::load(default_store())
+    }
+
+    /// Reads the list from `store`, starting empty when it doesn't exist yet.
+    pub fn load(store: Option<PathBuf>) -> Self {
+        let paths = store
+            .as_deref()
+            .and_then(|store| std::fs::read_to_string(store).ok())
+            .map(|contents| {
+                contents
+                    .lines()
+                    .filter(|line| !line.is_empty())
+                    .map(PathBuf::from)
+                    .take(LIMIT)
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self { paths, store }
+    }
+
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// Moves `path` to the top of the list and saves it. Standard input isn't listed.
+    pub fn add(&mut self, path: &Path) {
+        if path.as_os_str() == STDIN_PATH {
+            return;
+        }
+        let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        self.paths.retain(|recent| *recent != path);
+        self.paths.insert(0, path);
+        self.paths.truncate(LIMIT);
+        self.save();
+    }
+
+    fn save(&self) {
+        let Some(store) = &self.store else {
+            return;
+        };
+        let mut contents = String::new();
+        for path in &self.paths {
+            contents.push_str(&path.to_string_lossy());
+            contents.push('\n');
+        }
+        let result = store
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|()| std::fs::write(store, contents));
+        match result {
+            Ok(()) => debug!(store = ?store, "Saved recent files"),
+            Err(error) => warn!(store = ?store, error = %error, "Failed to save recent files"),
+        }
+    }
+}
+
+/// `recent-files` under `$XDG_STATE_HOME/wirecrab`, `~/.local/state/wirecrab` or, on
+/// Windows, `%LOCALAPPDATA%\wirecrab`.
+fn default_store() -> Option<PathBuf> {
+    let env_dir = |name: &str| {
+        std::env::var_os(name)
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+    };
+    let base = env_dir("XDG_STATE_HOME")
+        .or_else(|| env_dir("HOME").map(|home| home.join(".local").join("state")))
+        .or_else(|| env_dir("LOCALAPPDATA"))?;
+    Some(base.join("wirecrab").join("recent-files"))
+}
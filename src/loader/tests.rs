@@ -1,5 +1,13 @@
+use super::recent::RecentFiles;
 use super::{FlowLoadController, FlowLoadStatus};
+use crate::layers::dissector::DissectorRegistry;
+use crate::parser::pipeline::merge_flows;
+use etherparse::PacketBuilder;
+use std::collections::HashMap;
 use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
 use std::thread::sleep;
 use std::time::Duration;
 
@@ -9,7 +17,7 @@ fn controller_reports_error_for_invalid_pcap() {
     // Create an empty file to force the parser to error.
     File::create(&tmp_path).expect("create temp pcap");
 
-    let mut controller = FlowLoadController::new(tmp_path.clone());
+    let mut controller = FlowLoadController::new(vec![tmp_path.clone()]);
 
     // Poll until the background thread reports an error or time out.
     let mut saw_error = false;
@@ -42,14 +50,14 @@ fn controller_loads_valid_pcap() {
     let path = std::path::PathBuf::from("testdata/valid_tcp_udp.pcapng");
     assert!(path.exists(), "expected testdata/valid_tcp_udp.pcapng to exist");
 
-    let mut controller = FlowLoadController::new(path);
+    let mut controller = FlowLoadController::new(vec![path]);
 
     let mut got_ready = false;
     let mut flows_seen = 0usize;
     let mut saw_timestamp = false;
     for _ in 0..200 {
         match controller.poll() {
-            FlowLoadStatus::Ready { flows, start_timestamp, name_resolutions } => {
+            FlowLoadStatus::Ready { flows, start_timestamp, name_resolutions, .. } => {
                 got_ready = true;
                 flows_seen = flows.len();
                 saw_timestamp = start_timestamp.is_some();
@@ -72,13 +80,13 @@ fn controller_reports_progress_before_completion() {
     let path = std::path::PathBuf::from("testdata/randpkt_mixed.pcapng");
     assert!(path.exists(), "expected testdata/randpkt_mixed.pcapng to exist");
 
-    let mut controller = FlowLoadController::new(path);
+    let mut controller = FlowLoadController::new(vec![path]);
 
     let mut saw_progress = false;
     let mut finished = false;
     for _ in 0..500 {
         match controller.poll() {
-            FlowLoadStatus::Loading { progress } => {
+            FlowLoadStatus::Loading { progress, .. } => {
                 saw_progress = true;
                 if progress >= 1.0 {
                     // allow completion soon after
@@ -100,3 +108,155 @@ fn controller_reports_progress_before_completion() {
     // After completion, subsequent polls should be idle.
     assert!(matches!(controller.poll(), FlowLoadStatus::Idle));
 }
+
+/// Writes a legacy pcap holding a single UDP packet.
+fn write_single_packet_pcap(name: &str) -> PathBuf {
+    let mut buf = Vec::new();
+    for word in [0xa1b2c3d4u32, 0x0004_0002, 0, 0, 65535, 1] {
+        buf.extend_from_slice(&word.to_le_bytes());
+    }
+    buf.extend(udp_record(5000));
+    let path = std::env::temp_dir().join(name);
+    std::fs::write(&path, buf).expect("write pcap");
+    path
+}
+
+/// A legacy pcap record of a UDP packet from `source_port`.
+fn udp_record(source_port: u16) -> Vec<u8> {
+    let builder = PacketBuilder::ethernet2([1, 2, 3, 4, 5, 6], [6, 5, 4, 3, 2, 1])
+        .ipv4([10, 0, 0, 1], [10, 0, 0, 2], 64)
+        .udp(source_port, 6000);
+    let mut packet = Vec::with_capacity(builder.size(4));
+    builder.write(&mut packet, b"ping").unwrap();
+
+    let mut buf = Vec::new();
+    for word in [
+        1_700_000_000u32,
+        0,
+        packet.len() as u32,
+        packet.len() as u32,
+    ] {
+        buf.extend_from_slice(&word.to_le_bytes());
+    }
+    buf.extend_from_slice(&packet);
+    buf
+}
+
+#[test]
+fn controller_cancel_stops_the_load() {
+    let path = write_single_packet_pcap("wirecrab_cancel_test.pcap");
+    let mut controller = FlowLoadController::new(vec![path.clone()]);
+    assert!(controller.is_loading());
+
+    controller.cancel();
+
+    assert!(!controller.is_loading());
+    assert!(matches!(controller.poll(), FlowLoadStatus::Idle));
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn controller_open_switches_to_another_file() {
+    let missing = std::env::temp_dir().join("wirecrab_missing_test.pcap");
+    let path = write_single_packet_pcap("wirecrab_switch_test.pcap");
+    let mut controller = FlowLoadController::new(vec![missing]);
+
+    controller.open(vec![path.clone()]);
+    assert_eq!(controller.paths(), std::slice::from_ref(&path));
+
+    let mut flows_seen = None;
+    for _ in 0..200 {
+        match controller.poll() {
+            FlowLoadStatus::Ready { flows, .. } => {
+                flows_seen = Some(flows.len());
+                break;
+            }
+            FlowLoadStatus::Loading { .. } => sleep(Duration::from_millis(10)),
+            FlowLoadStatus::Error(msg) => panic!("unexpected error: {msg}"),
+            FlowLoadStatus::Idle => {}
+        }
+    }
+    assert_eq!(flows_seen, Some(1), "the opened file should load");
+
+    controller.reload();
+    assert!(controller.is_loading());
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn recent_files_keep_the_latest_first_and_persist() {
+    let store = std::env::temp_dir()
+        .join("wirecrab_recent_test")
+        .join("recent-files");
+    let _ = std::fs::remove_file(&store);
+
+    let mut recent = RecentFiles::load(Some(store.clone()));
+    for name in ["/captures/a.pcap", "/captures/b.pcap", "/captures/a.pcap"] {
+        recent.add(std::path::Path::new(name));
+    }
+    for index in 0..12 {
+        recent.add(&PathBuf::from(format!("/captures/{index}.pcap")));
+    }
+    recent.add(std::path::Path::new("/captures/a.pcap"));
+
+    let reloaded = RecentFiles::load(Some(store.clone()));
+    assert_eq!(reloaded.paths(), recent.paths());
+    assert_eq!(recent.paths().len(), 10);
+    assert_eq!(recent.paths()[0], PathBuf::from("/captures/a.pcap"));
+    assert_eq!(recent.paths()[1], PathBuf::from("/captures/11.pcap"));
+    assert!(!recent.paths().contains(&PathBuf::from("/captures/b.pcap")));
+    let _ = std::fs::remove_file(store);
+}
+
+#[test]
+fn controller_follows_a_growing_file() {
+    let path = write_single_packet_pcap("wirecrab_follow_test.pcap");
+    let registry = Arc::new(DissectorRegistry::default());
+    let mut controller = FlowLoadController::following(vec![path.clone()], registry);
+    let mut flows = HashMap::new();
+    let mut wait_for_flows = |controller: &mut FlowLoadController, count: usize| {
+        for _ in 0..300 {
+            if let FlowLoadStatus::Loading {
+                batch: Some(batch), ..
+            } = controller.poll()
+            {
+                merge_flows(&mut flows, batch.flows);
+            }
+            if flows.len() >= count {
+                return true;
+            }
+            sleep(Duration::from_millis(10));
+        }
+        false
+    };
+
+    assert!(wait_for_flows(&mut controller, 1), "first packet not shown");
+    assert!(controller.is_following());
+    assert!(controller.is_live());
+
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .expect("open pcap");
+    file.write_all(&udp_record(5001)).expect("append packet");
+    assert!(
+        wait_for_flows(&mut controller, 2),
+        "appended packet not shown"
+    );
+
+    controller.stop_following();
+    let mut flows_seen = None;
+    for _ in 0..300 {
+        if let FlowLoadStatus::Ready { flows, .. } = controller.poll() {
+            flows_seen = Some(flows.len());
+            break;
+        }
+        sleep(Duration::from_millis(10));
+    }
+    assert_eq!(
+        flows_seen,
+        Some(2),
+        "load should finish once no longer following"
+    );
+    let _ = std::fs::remove_file(path);
+}
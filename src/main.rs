@@ -1,40 +1,148 @@
 use anyhow::Result;
-use clap::{Parser, ValueEnum};
+use clap::{Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
-use tracing::{info, warn};
+use std::process::ExitCode;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use wirecrab::cli::{self, OutputFormat};
 #[cfg(feature = "ui")]
 use wirecrab::gui;
+use wirecrab::layers::dissector::{DecodeAs, DissectorRegistry};
+use wirecrab::layers::tls_decrypt::KeyLog;
+use wirecrab::logging;
+use wirecrab::parser::expand_paths;
 #[cfg(feature = "tui")]
 use wirecrab::tui;
-use wirecrab::logging;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Args {
-    /// Path to the pcap file to parse
-    file_path: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Paths or glob patterns of the pcap files to parse, merged into one timeline when
+    /// there are several, or `-` to read a capture from standard input
+    file_paths: Vec<PathBuf>,
 
     /// Launch the Graphical User Interface
-    #[arg(long)]
+    #[arg(long, requires = "file_paths")]
     ui: bool,
 
     /// Launch the Terminal User Interface
-    #[arg(long)]
+    #[arg(long, requires = "file_paths")]
     tui: bool,
 
+    /// Keep adding packets as they are written to a single capture, e.g. by dumpcap
+    #[arg(long, requires = "file_paths")]
+    follow: bool,
+
+    /// Decode traffic on a port with the named dissector, e.g. `tcp.port==8443,tls`.
+    /// May be repeated.
+    #[arg(short = 'd', long = "decode-as", global = true, value_name = "RULE")]
+    decode_as: Vec<DecodeAs>,
+
+    /// NSS key log file (as written via SSLKEYLOGFILE) used to decrypt TLS
+    #[arg(long, global = true, value_name = "PATH")]
+    tls_keylog: Option<PathBuf>,
+
     /// Path to write log output when not logging to stdout
-    #[arg(long, default_value = "wirecrab.log")]
+    #[arg(long, global = true, default_value = "wirecrab.log")]
     log_file: PathBuf,
 
     /// Emit logs to stdout instead of the log file
-    #[arg(long)]
+    #[arg(long, global = true)]
     log_stdout: bool,
 
     /// Log verbosity (error, warn, info, debug, trace)
-    #[arg(long, default_value = "info", value_enum)]
+    #[arg(long, global = true, default_value = "info", value_enum)]
     log_level: LogLevel,
 }
 
+/// Headless commands for scripting. Results go to stdout, errors to stderr.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// List the flows in a capture
+    Flows {
+        /// Paths or glob patterns of the capture files, or `-` for standard input
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        /// Display filter selecting the flows to list
+        #[arg(short, long)]
+        filter: Option<String>,
+
+        /// Output format
+        #[arg(long, default_value = "table", value_enum)]
+        format: OutputFormat,
+    },
+    /// List the HTTP/1.x requests and responses in a capture
+    Http {
+        /// Paths or glob patterns of the capture files, or `-` for standard input
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        /// Display filter selecting the flows whose transactions to list
+        #[arg(short, long)]
+        filter: Option<String>,
+
+        /// Output format
+        #[arg(long, default_value = "table", value_enum)]
+        format: OutputFormat,
+    },
+    /// List the DNS queries, their responses and resolution latency in a capture
+    Dns {
+        /// Paths or glob patterns of the capture files, or `-` for standard input
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        /// Display filter selecting the flows whose queries to list
+        #[arg(short, long)]
+        filter: Option<String>,
+
+        /// Output format
+        #[arg(long, default_value = "table", value_enum)]
+        format: OutputFormat,
+    },
+    /// Print summary statistics for a capture
+    Stats {
+        /// Paths or glob patterns of the capture files, or `-` for standard input
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        /// Output format
+        #[arg(long, default_value = "table", value_enum)]
+        format: OutputFormat,
+    },
+    /// Write the packets of the matching flows to a new pcapng file
+    Export {
+        /// Paths or glob patterns of the capture files, or `-` for standard input
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        /// Display filter selecting the flows to export
+        #[arg(short, long)]
+        filter: Option<String>,
+
+        /// Path of the pcapng file to write
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+}
+
+impl Command {
+    /// The capture paths and patterns the command was given.
+    fn files(&self) -> &[PathBuf] {
+        match self {
+            Command::Flows { files, .. }
+            | Command::Http { files, .. }
+            | Command::Dns { files, .. }
+            | Command::Stats { files, .. }
+            | Command::Export { files, .. } => files,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, ValueEnum)]
 enum LogLevel {
     Error,
@@ -56,7 +164,7 @@ impl From<LogLevel> for tracing::level_filters::LevelFilter {
     }
 }
 
-fn main() -> Result<()> {
+fn main() -> Result<ExitCode> {
     let args = Args::parse();
 
     let log_level = tracing::level_filters::LevelFilter::from(args.log_level);
@@ -69,17 +177,50 @@ fn main() -> Result<()> {
         log_level = ?args.log_level,
         "Logger initialized"
     );
+
+    let registry = match build_registry(&args) {
+        Ok(registry) => Arc::new(registry),
+        Err(err) => {
+            error!(error = %err, "Invalid dissector options");
+            eprintln!("Error: {err:#}");
+            drop(log_guard);
+            return Ok(ExitCode::from(cli::EXIT_USAGE));
+        }
+    };
+
+    let expanded = match &args.command {
+        Some(command) => expand_paths(command.files()),
+        None => expand_paths(&args.file_paths),
+    };
+    let file_paths = match expanded {
+        Ok(paths) => paths,
+        Err(err) => {
+            error!(error = %err, "Invalid capture paths");
+            eprintln!("Error: {err:#}");
+            drop(log_guard);
+            return Ok(ExitCode::from(cli::EXIT_CAPTURE_ERROR));
+        }
+    };
+
+    if let Some(command) = args.command {
+        let exit_code = run_command(command, &file_paths, &registry);
+        info!(exit_code, "Shutting down Wirecrab");
+        drop(log_guard);
+        return Ok(ExitCode::from(exit_code));
+    }
+
     info!(
-        file = ?args.file_path,
+        files = ?file_paths,
         ui = args.ui,
         tui = args.tui,
+        follow = args.follow,
         "Starting Wirecrab"
     );
 
     if args.ui {
         #[cfg(feature = "ui")]
         {
-            gui::run_ui(args.file_path).map_err(|e| anyhow::anyhow!("{}", e))?;
+            gui::run_ui(file_paths, registry, args.follow).map_err(|e| anyhow::anyhow!("{}", e))?;
         }
         #[cfg(not(feature = "ui"))]
         {
@@ -88,7 +229,8 @@ fn main() -> Result<()> {
     } else if args.tui {
         #[cfg(feature = "tui")]
         {
-            tui::run_tui(args.file_path).map_err(|e| anyhow::anyhow!("{}", e))?;
+            tui::run_tui(file_paths, registry, args.follow)
+                .map_err(|e| anyhow::anyhow!("{}", e))?;
         }
         #[cfg(not(feature = "tui"))]
         {
@@ -100,5 +242,45 @@ fn main() -> Result<()> {
 
     info!("Shutting down Wirecrab");
     drop(log_guard);
-    Ok(())
+    Ok(ExitCode::SUCCESS)
+}
+
+/// The dissectors with the `--decode-as` rules and `--tls-keylog` secrets applied.
+fn build_registry(args: &Args) -> Result<DissectorRegistry> {
+    let mut registry =
+        DissectorRegistry::with_rules(&args.decode_as).map_err(anyhow::Error::msg)?;
+    if let Some(path) = &args.tls_keylog {
+        registry.set_tls_keylog(KeyLog::from_file(path)?);
+    }
+    Ok(registry)
+}
+
+/// Runs `command` on the captures at `files`, its file arguments with globs expanded.
+fn run_command(command: Command, files: &[PathBuf], registry: &DissectorRegistry) -> u8 {
+    info!(?command, "Running command");
+    let mut stdout = std::io::stdout().lock();
+    let result = match &command {
+        Command::Flows { filter, format, .. } => {
+            cli::run_flows(files, registry, filter.as_deref(), *format, &mut stdout)
+        }
+        Command::Http { filter, format, .. } => {
+            cli::run_http(files, registry, filter.as_deref(), *format, &mut stdout)
+        }
+        Command::Dns { filter, format, .. } => {
+            cli::run_dns(files, registry, filter.as_deref(), *format, &mut stdout)
+        }
+        Command::Stats { format, .. } => cli::run_stats(files, registry, *format, &mut stdout),
+        Command::Export { filter, output, .. } => {
+            cli::run_export(files, registry, filter.as_deref(), output, &mut stdout)
+        }
+    };
+
+    match result {
+        Ok(()) => cli::EXIT_SUCCESS,
+        Err(err) => {
+            error!(error = %err, "Command failed");
+            eprintln!("Error: {err}");
+            err.exit_code()
+        }
+    }
 }
@@ -1,51 +1,210 @@
 use crate::flow::{IPAddress, Protocol};
-use crate::layers::PacketContext;
-use crate::layers::tls::TlsParser;
-use crate::parser::tcp::{looks_like_tls, tag_tcp};
-use etherparse::{NetHeaders, PacketHeaders, TransportHeader};
+use crate::layers::dissector::DissectorRegistry;
+use crate::layers::{IpFragment, PacketContext, TcpSegment};
+use crate::parser::dissect::{build_tree, offset_in};
+use crate::parser::icmp::{ICMP_HEADER_LEN, tag_icmp, tag_ip_payload};
+use crate::parser::link::parse_link_headers;
+use crate::parser::tcp::tag_tcp;
+use crate::parser::tunnel::{Decapsulated, decapsulate};
+use etherparse::{
+    ArpPacket, EtherType, IpNumber, NetHeaders, PacketHeaders, PayloadSlice, TcpHeader,
+    TcpOptionElement, TransportHeader, UdpHeader,
+};
+use pcap_parser::Linktype;
+use std::ops::Range;
 use tracing::trace;
 
-pub fn decode_headers(packet: &[u8], tls_parser: &TlsParser) -> Result<PacketContext, String> {
-    let mut context = PacketContext::default();
+pub fn decode_headers(packet: &[u8]) -> Result<PacketContext, String> {
+    decode_link_headers(packet, Linktype::ETHERNET)
+}
 
-    let headers = PacketHeaders::from_ethernet_slice(packet).map_err(|err| {
-        trace!(error = ?err, "Failed to parse packet headers");
-        format!("header parse error: {err:?}")
+/// Decodes a packet captured on an interface with the given `linktype`. Payloads are
+/// recorded as positions in `packet` rather than copied.
+pub fn decode_link_headers(packet: &[u8], linktype: Linktype) -> Result<PacketContext, String> {
+    let headers = parse_link_headers(packet, linktype).inspect_err(|err| {
+        trace!(error = %err, ?linktype, "Failed to parse packet headers");
     })?;
 
-    if let Some(net) = &headers.net {
-        match net {
-            NetHeaders::Ipv4(ip, _) => {
-                context.src_ip = Some(IPAddress::V4(ip.source));
-                context.dst_ip = Some(IPAddress::V4(ip.destination));
+    Ok(decode_decapsulated(packet, decapsulate(headers)))
+}
+
+/// Like [`decode_link_headers`], but also builds the packet's protocol tree for display.
+pub fn dissect_packet(
+    packet: &[u8],
+    linktype: Linktype,
+    registry: &DissectorRegistry,
+) -> Result<PacketContext, String> {
+    let headers = parse_link_headers(packet, linktype)?;
+    let decapsulated = decapsulate(headers);
+    let tree = build_tree(packet, linktype, &decapsulated, registry);
+    let mut context = decode_decapsulated(packet, decapsulated);
+    context.tree = tree;
+    Ok(context)
+}
+
+/// Decodes the innermost headers of `packet`, keeping the layers they were carried in.
+fn decode_decapsulated(packet: &[u8], decapsulated: Decapsulated) -> PacketContext {
+    let (headers, encapsulation) = decapsulated.into_innermost();
+    let mut context = decode_parsed_headers(packet, headers);
+    context.encapsulation = encapsulation;
+    context
+}
+
+fn decode_parsed_headers(packet: &[u8], headers: PacketHeaders) -> PacketContext {
+    let mut context = PacketContext::default();
+
+    let fragment = match &headers.net {
+        Some(NetHeaders::Ipv4(ip, _)) => {
+            context.src_ip = Some(IPAddress::V4(ip.source));
+            context.dst_ip = Some(IPAddress::V4(ip.destination));
+            Some((
+                u32::from(ip.identification),
+                ip.fragment_offset,
+                ip.more_fragments,
+            ))
+        }
+        Some(NetHeaders::Ipv6(ip, extensions)) => {
+            context.src_ip = Some(IPAddress::V6(ip.source));
+            context.dst_ip = Some(IPAddress::V6(ip.destination));
+            extensions.fragment.as_ref().map(|fragment| {
+                (
+                    fragment.identification,
+                    fragment.fragment_offset,
+                    fragment.more_fragments,
+                )
+            })
+        }
+        Some(NetHeaders::Arp(arp)) => {
+            decode_arp(arp, &mut context);
+            None
+        }
+        None => None,
+    };
+
+    if let PayloadSlice::Ip(payload) = &headers.payload
+        && payload.fragmented
+        && let Some((id, offset, more_fragments)) = fragment
+    {
+        context.tags.push("IP Fragment".to_string());
+        context.ip_fragment = Some(IpFragment {
+            id,
+            protocol: payload.ip_number.0,
+            offset: usize::from(offset.value()) * 8,
+            more_fragments,
+            payload: payload.payload.to_vec(),
+        });
+        return context;
+    }
+
+    match (headers.transport, &headers.payload) {
+        (Some(transport), payload) => {
+            decode_transport_header(transport, packet, payload.slice(), &mut context)
+        }
+        (None, PayloadSlice::Ip(payload)) => {
+            tag_ip_payload(payload.ip_number.0, payload.payload, &mut context)
+        }
+        _ => {}
+    }
+
+    context
+}
+
+/// Takes an ARP packet's flow from its sender and target IPv4 addresses.
+fn decode_arp(arp: &ArpPacket, context: &mut PacketContext) {
+    let (Ok(sender), Ok(target)) = (
+        <[u8; 4]>::try_from(arp.sender_protocol_addr()),
+        <[u8; 4]>::try_from(arp.target_protocol_addr()),
+    ) else {
+        return;
+    };
+    if arp.proto_addr_type != EtherType::IPV4 {
+        return;
+    }
+    context.src_ip = Some(IPAddress::V4(sender));
+    context.dst_ip = Some(IPAddress::V4(target));
+    context.protocol = Some(Protocol::ARP);
+    let tag = match arp.operation.0 {
+        1 if sender == target => "Gratuitous ARP".to_string(),
+        1 => "ARP Request".to_string(),
+        2 => "ARP Reply".to_string(),
+        other => format!("ARP Opcode {other}"),
+    };
+    context.tags.push(tag);
+}
+
+/// Decodes the transport header and payload of a datagram put back together from its
+/// fragments. `protocol` is the IP protocol number the fragments carried; payloads are
+/// recorded as positions in `datagram`.
+pub fn decode_reassembled(protocol: u8, datagram: &[u8], context: &mut PacketContext) {
+    match IpNumber(protocol) {
+        IpNumber::TCP => {
+            if let Ok((tcp, payload)) = TcpHeader::from_slice(datagram) {
+                decode_transport_header(TransportHeader::Tcp(tcp), datagram, payload, context);
+            }
+        }
+        IpNumber::UDP => {
+            if let Ok((udp, payload)) = UdpHeader::from_slice(datagram) {
+                let length = usize::from(udp.length).saturating_sub(UdpHeader::LEN);
+                let payload = payload.get(..length).unwrap_or(payload);
+                decode_transport_header(TransportHeader::Udp(udp), datagram, payload, context);
             }
-            NetHeaders::Ipv6(ip, _) => {
-                context.src_ip = Some(IPAddress::V6(ip.source));
-                context.dst_ip = Some(IPAddress::V6(ip.destination));
+        }
+        IpNumber::ICMP | IpNumber::IPV6_ICMP => {
+            if let Some((header, payload)) = datagram.split_first_chunk::<ICMP_HEADER_LEN>() {
+                tag_icmp(protocol == IpNumber::IPV6_ICMP.0, header, payload, context);
             }
-            _ => {}
         }
+        _ => tag_ip_payload(protocol, datagram, context),
     }
+}
 
-    let payload = headers.payload.slice();
-
-    if let Some(transport) = headers.transport {
-        match transport {
-            TransportHeader::Tcp(tcp) => {
-                tag_tcp(&tcp, payload.len(), &mut context);
-                if looks_like_tls(payload) {
-                    tls_parser.parse(payload, &mut context);
-                }
+/// Decodes `transport`, the header in front of `payload`, which is part of `packet`.
+fn decode_transport_header(
+    transport: TransportHeader,
+    packet: &[u8],
+    payload: &[u8],
+    context: &mut PacketContext,
+) {
+    match transport {
+        TransportHeader::Tcp(tcp) => {
+            tag_tcp(&tcp, payload.len(), context);
+            context.tcp_segment = Some(TcpSegment {
+                seq: tcp.sequence_number,
+                ack: tcp.acknowledgment_number,
+                window: tcp.window_size,
+                window_scale: tcp.options_iterator().find_map(|option| match option {
+                    Ok(TcpOptionElement::WindowScale(shift)) => Some(shift),
+                    _ => None,
+                }),
+                syn: tcp.syn,
+                fin: tcp.fin,
+                rst: tcp.rst,
+                payload: range_in(packet, payload),
+            });
+        }
+        TransportHeader::Udp(udp) => {
+            context.src_port = Some(udp.source_port);
+            context.dst_port = Some(udp.destination_port);
+            context.protocol = Some(Protocol::UDP);
+            context.udp_payload = Some(range_in(packet, payload));
+        }
+        TransportHeader::Icmpv4(icmp) => {
+            let bytes = icmp.to_bytes();
+            if let Some(header) = bytes.first_chunk::<ICMP_HEADER_LEN>() {
+                tag_icmp(false, header, payload, context);
             }
-            TransportHeader::Udp(udp) => {
-                context.src_port = Some(udp.source_port);
-                context.dst_port = Some(udp.destination_port);
-                context.protocol = Some(Protocol::UDP);
-                context.udp_payload = Some(payload.to_vec());
+        }
+        TransportHeader::Icmpv6(icmp) => {
+            let bytes = icmp.to_bytes();
+            if let Some(header) = bytes.first_chunk::<ICMP_HEADER_LEN>() {
+                tag_icmp(true, header, payload, context);
             }
-            _ => {}
         }
     }
+}
 
-    Ok(context)
+/// Position of `payload`, a part of `packet`, within it.
+fn range_in(packet: &[u8], payload: &[u8]) -> Range<usize> {
+    let start = offset_in(packet, payload);
+    start..(start + payload.len()).min(packet.len())
 }
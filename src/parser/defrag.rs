@@ -0,0 +1,221 @@
+use crate::flow::IPAddress;
+use crate::layers::IpFragment;
+use std::collections::{HashMap, VecDeque};
+use std::ops::Range;
+
+/// Seconds of capture time a datagram waits for its missing fragments, as Linux's
+/// default `ipfrag_time`.
+pub const FRAGMENT_TIMEOUT: f64 = 30.0;
+
+/// Bytes held across incomplete datagrams before the oldest are given up on, as
+/// Linux's default `ipfrag_high_thresh`.
+const MAX_BUFFERED_BYTES: usize = 4 * 1024 * 1024;
+
+/// Largest datagram payload that fits the 16-bit IPv4 and IPv6 length fields.
+const MAX_DATAGRAM_LEN: usize = u16::MAX as usize;
+
+/// Identifies the fragments of one datagram.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FragmentKey {
+    pub src: IPAddress,
+    pub dst: IPAddress,
+    pub id: u32,
+    pub protocol: u8,
+}
+
+/// A datagram whose fragments are being collected, along with the packets carrying
+/// them.
+#[derive(Debug)]
+pub struct Datagram<T> {
+    pub key: FragmentKey,
+    first_seen: f64,
+    data: Vec<u8>,
+    /// Byte ranges of `data` received so far, sorted and never touching.
+    received: Vec<Range<usize>>,
+    /// Known once the last fragment arrived.
+    total_len: Option<usize>,
+    /// Packets carrying the fragments, in the order they arrived.
+    pub packets: Vec<T>,
+    /// Set when fragments overlapped, or disagreed on where the datagram ends.
+    pub overlapping: bool,
+}
+
+impl<T> Datagram<T> {
+    fn new(key: FragmentKey, first_seen: f64) -> Self {
+        Self {
+            key,
+            first_seen,
+            data: Vec::new(),
+            received: Vec::new(),
+            total_len: None,
+            packets: Vec::new(),
+            overlapping: false,
+        }
+    }
+
+    /// The datagram's payload from its start up to the first missing byte, which is all
+    /// of it once it is complete.
+    pub fn payload(&self) -> &[u8] {
+        match self.received.first() {
+            Some(range) if range.start == 0 => &self.data[range.clone()],
+            _ => &[],
+        }
+    }
+
+    fn is_complete(&self) -> bool {
+        match (self.total_len, self.received.as_slice()) {
+            (Some(len), [range]) => *range == (0..len),
+            _ => false,
+        }
+    }
+
+    /// Records where the datagram ends, dropping whatever earlier fragments put past it.
+    fn set_total_len(&mut self, len: usize) {
+        self.total_len = Some(len);
+        if self.data.len() > len {
+            self.overlapping = true;
+            self.data.truncate(len);
+            self.received.retain_mut(|range| {
+                range.end = range.end.min(len);
+                range.start < range.end
+            });
+        }
+    }
+
+    /// Copies the bytes of `fragment` that fill holes. Where fragments overlap, the
+    /// bytes that arrived first are kept.
+    fn insert(&mut self, fragment: &IpFragment) {
+        let start = fragment.offset;
+        let end = start + fragment.payload.len();
+        if !fragment.more_fragments {
+            match self.total_len {
+                Some(len) if len != end => self.overlapping = true,
+                _ => self.set_total_len(end),
+            }
+        }
+        let limit = self.total_len.unwrap_or(MAX_DATAGRAM_LEN);
+        if end > limit {
+            self.overlapping = true;
+        }
+        let end = end.min(limit);
+        if start >= end {
+            return;
+        }
+        if self.data.len() < end {
+            self.data.resize(end, 0);
+        }
+
+        let mut position = start;
+        let mut merged = start..end;
+        let mut kept = Vec::with_capacity(self.received.len() + 1);
+        for range in self.received.drain(..) {
+            if range.end < start || range.start > end {
+                kept.push(range);
+                continue;
+            }
+            if range.start < end && range.end > start {
+                self.overlapping = true;
+            }
+            if position < range.start {
+                self.data[position..range.start.min(end)].copy_from_slice(
+                    &fragment.payload[position - start..range.start.min(end) - start],
+                );
+            }
+            position = position.max(range.end);
+            merged = merged.start.min(range.start)..merged.end.max(range.end);
+        }
+        if position < end {
+            self.data[position..end]
+                .copy_from_slice(&fragment.payload[position - start..end - start]);
+        }
+        let at = kept.partition_point(|range| range.end < merged.start);
+        kept.insert(at, merged);
+        self.received = kept;
+    }
+}
+
+/// Puts fragmented IP datagrams back together, keyed by addresses, identification and
+/// protocol. Datagrams still missing fragments [`FRAGMENT_TIMEOUT`] seconds after
+/// their first one arrived are given up on, as are the oldest ones while more than
+/// [`MAX_BUFFERED_BYTES`] are held.
+#[derive(Debug)]
+pub struct IpReassembler<T> {
+    datagrams: HashMap<FragmentKey, Datagram<T>>,
+    /// Datagrams in the order they were started, for expiring them.
+    started: VecDeque<(f64, FragmentKey)>,
+    /// Payload bytes held by `datagrams`.
+    buffered: usize,
+}
+
+impl<T> Default for IpReassembler<T> {
+    fn default() -> Self {
+        Self {
+            datagrams: HashMap::new(),
+            started: VecDeque::new(),
+            buffered: 0,
+        }
+    }
+}
+
+impl<T> IpReassembler<T> {
+    /// Adds `fragment`, carried by `packet` captured at `timestamp`. Returns the
+    /// datagram once it is complete, with `packet` as its last packet. Beyond
+    /// [`MAX_BUFFERED_BYTES`], the next [`expire`](Self::expire) gives up on the oldest.
+    pub fn push(
+        &mut self,
+        key: FragmentKey,
+        fragment: &IpFragment,
+        timestamp: f64,
+        packet: T,
+    ) -> Option<Datagram<T>> {
+        let datagram = self.datagrams.entry(key).or_insert_with(|| {
+            self.started.push_back((timestamp, key));
+            Datagram::new(key, timestamp)
+        });
+        let held = datagram.data.len();
+        datagram.insert(fragment);
+        datagram.packets.push(packet);
+        self.buffered = self.buffered - held + datagram.data.len();
+        if !datagram.is_complete() {
+            return None;
+        }
+        self.remove(key)
+    }
+
+    /// Removes the datagrams given up on by `timestamp`, oldest first: those that timed
+    /// out, then as many of the oldest as keep the bytes held under
+    /// [`MAX_BUFFERED_BYTES`].
+    pub fn expire(&mut self, timestamp: f64) -> Vec<Datagram<T>> {
+        let mut expired = Vec::new();
+        while let Some(&(first_seen, key)) = self.started.front()
+            && (timestamp - first_seen > FRAGMENT_TIMEOUT || self.buffered > MAX_BUFFERED_BYTES)
+        {
+            self.started.pop_front();
+            expired.extend(self.remove_started(first_seen, key));
+        }
+        expired
+    }
+
+    /// Removes every incomplete datagram, oldest first, as at the end of a capture.
+    pub fn drain(&mut self) -> Vec<Datagram<T>> {
+        std::mem::take(&mut self.started)
+            .into_iter()
+            .filter_map(|(first_seen, key)| self.remove_started(first_seen, key))
+            .collect()
+    }
+
+    /// Removes the datagram under `key` if it is the one started at `first_seen`; the
+    /// key may have been completed since, or reused by a later datagram.
+    fn remove_started(&mut self, first_seen: f64, key: FragmentKey) -> Option<Datagram<T>> {
+        match self.datagrams.get(&key) {
+            Some(datagram) if datagram.first_seen == first_seen => self.remove(key),
+            _ => None,
+        }
+    }
+
+    fn remove(&mut self, key: FragmentKey) -> Option<Datagram<T>> {
+        let datagram = self.datagrams.remove(&key)?;
+        self.buffered -= datagram.data.len();
+        Some(datagram)
+    }
+}
@@ -0,0 +1,661 @@
+use crate::flow::{IPAddress, Protocol};
+use crate::layers::dissector::{DissectorRegistry, Payload};
+use crate::layers::tree::{ProtocolNode, ProtocolTree};
+use crate::parser::icmp;
+use crate::parser::tunnel::{Decapsulated, TunnelHeader};
+use etherparse::{LinkExtHeader, LinkHeader, NetHeaders, PacketHeaders, TransportHeader};
+use pcap_parser::Linktype;
+
+/// Builds the protocol tree of `packet` from its decoded headers, every level of a
+/// tunnelled packet in turn. Field ranges are offsets into `packet`, so a view can
+/// highlight the bytes behind each field. Application payload is left to the
+/// dissectors of `registry`.
+pub fn build_tree(
+    packet: &[u8],
+    linktype: Linktype,
+    decapsulated: &Decapsulated,
+    registry: &DissectorRegistry,
+) -> ProtocolTree {
+    let mut layers = vec![
+        ProtocolNode::new("Frame", 0..packet.len())
+            .with_value(format!("{} bytes captured", packet.len())),
+    ];
+
+    let mut offset = framing_layer(packet, linktype, &mut layers);
+    let mut levels = decapsulated.levels.iter();
+    let mut headers = levels.next().expect("a packet has at least one level");
+    for ((tunnel, bytes), inner) in decapsulated.tunnels.iter().zip(levels) {
+        header_layers(packet, offset, headers, &mut layers);
+        let start = offset_in(packet, bytes);
+        offset = tunnel_layer(packet, start, *tunnel, bytes.len(), &mut layers);
+        headers = inner;
+    }
+    offset = header_layers(packet, offset, headers, &mut layers);
+
+    let ports = match &headers.transport {
+        Some(TransportHeader::Tcp(tcp)) => {
+            Some((Protocol::TCP, tcp.source_port, tcp.destination_port))
+        }
+        Some(TransportHeader::Udp(udp)) => {
+            Some((Protocol::UDP, udp.source_port, udp.destination_port))
+        }
+        _ => None,
+    };
+    let payload = headers.payload.slice();
+    if !payload.is_empty() && offset + payload.len() <= packet.len() {
+        let nodes = ports.map_or_else(Vec::new, |(transport, src_port, dst_port)| {
+            let payload = Payload {
+                transport,
+                src_port,
+                dst_port,
+                data: payload,
+            };
+            registry.tree(&payload, offset)
+        });
+        if !nodes.is_empty() {
+            layers.extend(nodes);
+        } else {
+            layers.push(
+                ProtocolNode::new("Data", offset..offset + payload.len())
+                    .with_value(format!("{} bytes", payload.len())),
+            );
+        }
+    }
+
+    ProtocolTree { layers }
+}
+
+/// Adds the link, network and transport layers of one level of headers, which start at
+/// `offset`, and returns where they end.
+fn header_layers(
+    packet: &[u8],
+    mut offset: usize,
+    headers: &PacketHeaders,
+    layers: &mut Vec<ProtocolNode>,
+) -> usize {
+    if let Some(link) = &headers.link {
+        offset = link_layer(packet, offset, link, layers);
+    }
+    for ext in &headers.link_exts {
+        offset = link_ext_layer(packet, offset, ext, layers);
+    }
+    if let Some(net) = &headers.net {
+        offset = net_layer(packet, offset, net, layers);
+    }
+    if let Some(transport) = &headers.transport {
+        offset = transport_layer(packet, offset, transport, layers);
+    }
+    offset
+}
+
+/// Position of `bytes`, a part of `packet`, within it.
+pub fn offset_in(packet: &[u8], bytes: &[u8]) -> usize {
+    (bytes.as_ptr() as usize)
+        .saturating_sub(packet.as_ptr() as usize)
+        .min(packet.len())
+}
+
+/// Adds the `len` bytes long header of `tunnel` at `offset` and returns where it ends.
+fn tunnel_layer(
+    packet: &[u8],
+    offset: usize,
+    tunnel: TunnelHeader,
+    len: usize,
+    layers: &mut Vec<ProtocolNode>,
+) -> usize {
+    let o = offset;
+    let end = o + len;
+    match tunnel {
+        TunnelHeader::IpInIp => {}
+        TunnelHeader::Mpls => {
+            for entry in (o..end).step_by(4) {
+                let label = be32(packet, entry) >> 12;
+                let mut node = ProtocolNode::new("MultiProtocol Label Switching", entry..entry + 4)
+                    .with_value(format!("Label: {label}"));
+                node.field("Label", label.to_string(), entry..entry + 3)
+                    .field(
+                        "Traffic Class",
+                        ((packet[entry + 2] >> 1) & 0x07).to_string(),
+                        entry + 2..entry + 3,
+                    )
+                    .field(
+                        "Bottom of Stack",
+                        (packet[entry + 2] & 0x01).to_string(),
+                        entry + 2..entry + 3,
+                    )
+                    .field(
+                        "Time to Live",
+                        packet[entry + 3].to_string(),
+                        entry + 3..entry + 4,
+                    );
+                layers.push(node);
+            }
+        }
+        TunnelHeader::Gre => {
+            let flags = be16(packet, o);
+            let protocol = be16(packet, o + 2);
+            let mut node = ProtocolNode::new("Generic Routing Encapsulation", o..end)
+                .with_value(ether_type(protocol));
+            node.field("Flags and Version", format!("0x{flags:04x}"), o..o + 2)
+                .field("Protocol Type", ether_type(protocol), o + 2..o + 4);
+            let mut field = o + 4;
+            if flags & 0x8000 != 0 {
+                node.field(
+                    "Checksum",
+                    format!("0x{:04x}", be16(packet, field)),
+                    field..field + 2,
+                );
+                field += 4;
+            }
+            if flags & 0x2000 != 0 {
+                node.field("Key", be32(packet, field).to_string(), field..field + 4);
+                field += 4;
+            }
+            if flags & 0x1000 != 0 {
+                node.field(
+                    "Sequence Number",
+                    be32(packet, field).to_string(),
+                    field..field + 4,
+                );
+            }
+            layers.push(node);
+        }
+        TunnelHeader::Vxlan => {
+            let vni = be32(packet, o + 4) >> 8;
+            let mut node = ProtocolNode::new("Virtual eXtensible Local Area Network", o..end)
+                .with_value(format!("VNI: {vni}"));
+            node.field("Flags", format!("0x{:02x}", packet[o]), o..o + 1)
+                .field("VNI", vni.to_string(), o + 4..o + 7);
+            layers.push(node);
+        }
+        TunnelHeader::Geneve => {
+            let vni = be32(packet, o + 4) >> 8;
+            let options = usize::from(packet[o] & 0x3f) * 4;
+            let mut node =
+                ProtocolNode::new("Generic Network Virtualization Encapsulation", o..end)
+                    .with_value(format!("VNI: {vni}"));
+            node.field("Version", (packet[o] >> 6).to_string(), o..o + 1)
+                .field("Options Length", format!("{options} bytes"), o..o + 1)
+                .field(
+                    "Protocol Type",
+                    ether_type(be16(packet, o + 2)),
+                    o + 2..o + 4,
+                )
+                .field("VNI", vni.to_string(), o + 4..o + 7);
+            if options > 0 {
+                node.field("Options", format!("{options} bytes"), o + 8..end);
+            }
+            layers.push(node);
+        }
+    }
+    end
+}
+
+/// Pseudo-headers that `parse_link_headers` strips before handing the rest to etherparse.
+fn framing_layer(packet: &[u8], linktype: Linktype, layers: &mut Vec<ProtocolNode>) -> usize {
+    match linktype {
+        Linktype::LINUX_SLL if packet.len() >= 16 => {
+            let mut node = ProtocolNode::new("Linux cooked capture v1", 0..16);
+            node.field("Packet Type", be16(packet, 0).to_string(), 0..2)
+                .field("Link-layer Address Type", be16(packet, 2).to_string(), 2..4)
+                .field(
+                    "Link-layer Address Length",
+                    be16(packet, 4).to_string(),
+                    4..6,
+                )
+                .field("Source", hex_bytes(&packet[6..14]), 6..14)
+                .field("Protocol", ether_type(be16(packet, 14)), 14..16);
+            layers.push(node);
+            16
+        }
+        Linktype::LINUX_SLL2 if packet.len() >= 20 => {
+            let mut node = ProtocolNode::new("Linux cooked capture v2", 0..20);
+            node.field("Protocol", ether_type(be16(packet, 0)), 0..2)
+                .field("Interface Index", be32(packet, 4).to_string(), 4..8)
+                .field(
+                    "Link-layer Address Type",
+                    be16(packet, 8).to_string(),
+                    8..10,
+                )
+                .field("Packet Type", packet[10].to_string(), 10..11)
+                .field("Link-layer Address Length", packet[11].to_string(), 11..12)
+                .field("Source", hex_bytes(&packet[12..20]), 12..20);
+            layers.push(node);
+            20
+        }
+        Linktype::NULL | Linktype::LOOP if packet.len() >= 4 => {
+            let family = if linktype == Linktype::LOOP {
+                be32(packet, 0)
+            } else {
+                u32::from_le_bytes([packet[0], packet[1], packet[2], packet[3]])
+            };
+            let mut node = ProtocolNode::new("Null/Loopback", 0..4);
+            node.field("Family", family.to_string(), 0..4);
+            layers.push(node);
+            4
+        }
+        _ => 0,
+    }
+}
+
+fn link_layer(
+    packet: &[u8],
+    offset: usize,
+    link: &LinkHeader,
+    layers: &mut Vec<ProtocolNode>,
+) -> usize {
+    match link {
+        LinkHeader::Ethernet2(header) => {
+            let o = offset;
+            let mut node = ProtocolNode::new("Ethernet II", o..o + 14).with_value(format!(
+                "{} → {}",
+                mac(&header.source),
+                mac(&header.destination)
+            ));
+            node.field("Destination", mac(&header.destination), o..o + 6)
+                .field("Source", mac(&header.source), o + 6..o + 12)
+                .field("Type", ether_type(header.ether_type.0), o + 12..o + 14);
+            layers.push(node);
+            o + 14
+        }
+        LinkHeader::LinuxSll(header) => {
+            let end = offset + header.header_len();
+            layers.push(ProtocolNode::new("Linux cooked capture v1", offset..end));
+            end.min(packet.len())
+        }
+    }
+}
+
+fn link_ext_layer(
+    packet: &[u8],
+    offset: usize,
+    ext: &LinkExtHeader,
+    layers: &mut Vec<ProtocolNode>,
+) -> usize {
+    match ext {
+        LinkExtHeader::Vlan(header) => {
+            let o = offset;
+            let mut node = ProtocolNode::new("802.1Q Virtual LAN", o..o + 4)
+                .with_value(format!("ID: {}", header.vlan_id.value()));
+            node.field("Priority", header.pcp.value().to_string(), o..o + 1)
+                .field(
+                    "DEI",
+                    u8::from(header.drop_eligible_indicator).to_string(),
+                    o..o + 1,
+                )
+                .field("ID", header.vlan_id.value().to_string(), o..o + 2)
+                .field("Type", ether_type(header.ether_type.0), o + 2..o + 4);
+            layers.push(node);
+            o + 4
+        }
+        LinkExtHeader::Macsec(header) => {
+            let end = (offset + header.header_len()).min(packet.len());
+            layers.push(ProtocolNode::new("MACsec", offset..end));
+            end
+        }
+    }
+}
+
+fn net_layer(
+    packet: &[u8],
+    offset: usize,
+    net: &NetHeaders,
+    layers: &mut Vec<ProtocolNode>,
+) -> usize {
+    let o = offset;
+    match net {
+        NetHeaders::Ipv4(header, exts) => {
+            let header_len = header.header_len();
+            let end = o + header_len + exts.header_len();
+            let source = IPAddress::V4(header.source);
+            let destination = IPAddress::V4(header.destination);
+            let mut flags = Vec::new();
+            if header.dont_fragment {
+                flags.push("DF");
+            }
+            if header.more_fragments {
+                flags.push("MF");
+            }
+            let mut node = ProtocolNode::new("Internet Protocol Version 4", o..end)
+                .with_value(format!("{source} → {destination}"));
+            node.field("Version", "4", o..o + 1)
+                .field("Header Length", format!("{header_len} bytes"), o..o + 1)
+                .field(
+                    "Differentiated Services",
+                    format!("0x{:02x}", packet[o + 1]),
+                    o + 1..o + 2,
+                )
+                .field("Total Length", header.total_len.to_string(), o + 2..o + 4)
+                .field(
+                    "Identification",
+                    format!("0x{0:04x} ({0})", header.identification),
+                    o + 4..o + 6,
+                )
+                .field("Flags", flag_list(packet[o + 6] >> 5, &flags), o + 6..o + 7)
+                .field(
+                    "Fragment Offset",
+                    header.fragment_offset.value().to_string(),
+                    o + 6..o + 8,
+                )
+                .field(
+                    "Time to Live",
+                    header.time_to_live.to_string(),
+                    o + 8..o + 9,
+                )
+                .field("Protocol", ip_protocol(header.protocol.0), o + 9..o + 10)
+                .field(
+                    "Header Checksum",
+                    format!("0x{:04x}", header.header_checksum),
+                    o + 10..o + 12,
+                )
+                .field("Source Address", source.to_string(), o + 12..o + 16)
+                .field(
+                    "Destination Address",
+                    destination.to_string(),
+                    o + 16..o + 20,
+                );
+            if header_len > 20 {
+                node.field(
+                    "Options",
+                    format!("{} bytes", header_len - 20),
+                    o + 20..o + header_len,
+                );
+            }
+            if end > o + header_len {
+                node.field(
+                    "Extension Headers",
+                    format!("{} bytes", end - o - header_len),
+                    o + header_len..end,
+                );
+            }
+            layers.push(node);
+            end
+        }
+        NetHeaders::Ipv6(header, exts) => {
+            let end = o + 40 + exts.header_len();
+            let source = IPAddress::V6(header.source);
+            let destination = IPAddress::V6(header.destination);
+            let mut node = ProtocolNode::new("Internet Protocol Version 6", o..end)
+                .with_value(format!("{source} → {destination}"));
+            node.field("Version", "6", o..o + 1)
+                .field(
+                    "Traffic Class",
+                    format!("0x{:02x}", header.traffic_class),
+                    o..o + 2,
+                )
+                .field(
+                    "Flow Label",
+                    format!("0x{:05x}", header.flow_label.value()),
+                    o + 1..o + 4,
+                )
+                .field(
+                    "Payload Length",
+                    header.payload_length.to_string(),
+                    o + 4..o + 6,
+                )
+                .field(
+                    "Next Header",
+                    ip_protocol(header.next_header.0),
+                    o + 6..o + 7,
+                )
+                .field("Hop Limit", header.hop_limit.to_string(), o + 7..o + 8)
+                .field("Source Address", source.to_string(), o + 8..o + 24)
+                .field(
+                    "Destination Address",
+                    destination.to_string(),
+                    o + 24..o + 40,
+                );
+            if end > o + 40 {
+                node.field(
+                    "Extension Headers",
+                    format!("{} bytes", end - o - 40),
+                    o + 40..end,
+                );
+            }
+            layers.push(node);
+            end
+        }
+        NetHeaders::Arp(arp) => {
+            let end = o + arp.packet_len();
+            let hw = arp.hw_addr_size() as usize;
+            let proto = arp.protocol_addr_size() as usize;
+            let sender_hw = o + 8;
+            let sender_proto = sender_hw + hw;
+            let target_hw = sender_proto + proto;
+            let target_proto = target_hw + hw;
+            let operation = match arp.operation.0 {
+                1 => "request (1)".to_string(),
+                2 => "reply (2)".to_string(),
+                other => other.to_string(),
+            };
+            let mut node = ProtocolNode::new("Address Resolution Protocol", o..end)
+                .with_value(operation.clone());
+            node.field("Hardware Type", arp.hw_addr_type.0.to_string(), o..o + 2)
+                .field(
+                    "Protocol Type",
+                    ether_type(arp.proto_addr_type.0),
+                    o + 2..o + 4,
+                )
+                .field("Hardware Size", hw.to_string(), o + 4..o + 5)
+                .field("Protocol Size", proto.to_string(), o + 5..o + 6)
+                .field("Opcode", operation, o + 6..o + 8)
+                .field(
+                    "Sender Hardware Address",
+                    address(packet, sender_hw, hw),
+                    sender_hw..sender_proto,
+                )
+                .field(
+                    "Sender Protocol Address",
+                    address(packet, sender_proto, proto),
+                    sender_proto..target_hw,
+                )
+                .field(
+                    "Target Hardware Address",
+                    address(packet, target_hw, hw),
+                    target_hw..target_proto,
+                )
+                .field(
+                    "Target Protocol Address",
+                    address(packet, target_proto, proto),
+                    target_proto..end,
+                );
+            layers.push(node);
+            end
+        }
+    }
+}
+
+fn transport_layer(
+    packet: &[u8],
+    offset: usize,
+    transport: &TransportHeader,
+    layers: &mut Vec<ProtocolNode>,
+) -> usize {
+    let o = offset;
+    let end = o + transport.header_len();
+    match transport {
+        TransportHeader::Tcp(tcp) => {
+            let header_len = end - o;
+            let flags = [
+                (tcp.cwr, "CWR"),
+                (tcp.ece, "ECE"),
+                (tcp.urg, "URG"),
+                (tcp.ack, "ACK"),
+                (tcp.psh, "PSH"),
+                (tcp.rst, "RST"),
+                (tcp.syn, "SYN"),
+                (tcp.fin, "FIN"),
+            ];
+            let set: Vec<&str> = flags
+                .iter()
+                .filter(|(set, _)| *set)
+                .map(|(_, name)| *name)
+                .collect();
+            let raw_flags = be16(packet, o + 12) & 0x0fff;
+            let mut node =
+                ProtocolNode::new("Transmission Control Protocol", o..end).with_value(format!(
+                    "{} → {} [{}] Seq={}",
+                    tcp.source_port,
+                    tcp.destination_port,
+                    set.join(", "),
+                    tcp.sequence_number
+                ));
+            node.field("Source Port", tcp.source_port.to_string(), o..o + 2)
+                .field(
+                    "Destination Port",
+                    tcp.destination_port.to_string(),
+                    o + 2..o + 4,
+                )
+                .field(
+                    "Sequence Number",
+                    tcp.sequence_number.to_string(),
+                    o + 4..o + 8,
+                )
+                .field(
+                    "Acknowledgment Number",
+                    tcp.acknowledgment_number.to_string(),
+                    o + 8..o + 12,
+                )
+                .field(
+                    "Header Length",
+                    format!("{header_len} bytes"),
+                    o + 12..o + 13,
+                )
+                .field("Flags", flag_list(raw_flags, &set), o + 12..o + 14)
+                .field("Window", tcp.window_size.to_string(), o + 14..o + 16)
+                .field(
+                    "Checksum",
+                    format!("0x{:04x}", tcp.checksum),
+                    o + 16..o + 18,
+                )
+                .field(
+                    "Urgent Pointer",
+                    tcp.urgent_pointer.to_string(),
+                    o + 18..o + 20,
+                );
+            if header_len > 20 {
+                node.field("Options", format!("{} bytes", header_len - 20), o + 20..end);
+            }
+            layers.push(node);
+        }
+        TransportHeader::Udp(udp) => {
+            let mut node = ProtocolNode::new("User Datagram Protocol", o..end)
+                .with_value(format!("{} → {}", udp.source_port, udp.destination_port));
+            node.field("Source Port", udp.source_port.to_string(), o..o + 2)
+                .field(
+                    "Destination Port",
+                    udp.destination_port.to_string(),
+                    o + 2..o + 4,
+                )
+                .field("Length", udp.length.to_string(), o + 4..o + 6)
+                .field("Checksum", format!("0x{:04x}", udp.checksum), o + 6..o + 8);
+            layers.push(node);
+        }
+        TransportHeader::Icmpv4(_) | TransportHeader::Icmpv6(_) => {
+            let v6 = matches!(transport, TransportHeader::Icmpv6(_));
+            let name = if v6 {
+                "Internet Control Message Protocol v6"
+            } else {
+                "Internet Control Message Protocol"
+            };
+            let (icmp_type, code) = (packet[o], packet[o + 1]);
+            let mut node =
+                ProtocolNode::new(name, o..end).with_value(icmp::message_name(v6, icmp_type, code));
+            node.field("Type", icmp_type.to_string(), o..o + 1)
+                .field("Code", code.to_string(), o + 1..o + 2)
+                .field(
+                    "Checksum",
+                    format!("0x{:04x}", be16(packet, o + 2)),
+                    o + 2..o + 4,
+                );
+            if icmp::is_echo(v6, icmp_type) {
+                node.field("Identifier", be16(packet, o + 4).to_string(), o + 4..o + 6)
+                    .field(
+                        "Sequence Number",
+                        be16(packet, o + 6).to_string(),
+                        o + 6..o + 8,
+                    );
+            }
+            layers.push(node);
+        }
+    }
+    end
+}
+
+fn be16(packet: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([packet[offset], packet[offset + 1]])
+}
+
+fn be32(packet: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([
+        packet[offset],
+        packet[offset + 1],
+        packet[offset + 2],
+        packet[offset + 3],
+    ])
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+fn mac(bytes: &[u8; 6]) -> String {
+    hex_bytes(bytes)
+}
+
+/// Formats an ARP address: dotted quad for 4 bytes, colon-separated hex otherwise.
+fn address(packet: &[u8], offset: usize, len: usize) -> String {
+    let Some(bytes) = packet.get(offset..offset + len) else {
+        return String::new();
+    };
+    match <[u8; 4]>::try_from(bytes) {
+        Ok(octets) => IPAddress::V4(octets).to_string(),
+        Err(_) => hex_bytes(bytes),
+    }
+}
+
+fn flag_list(raw: impl std::fmt::LowerHex, set: &[&str]) -> String {
+    if set.is_empty() {
+        format!("0x{raw:03x}")
+    } else {
+        format!("0x{raw:03x} ({})", set.join(", "))
+    }
+}
+
+fn ether_type(value: u16) -> String {
+    let name = match value {
+        0x0800 => "IPv4",
+        0x0806 => "ARP",
+        0x6558 => "Transparent Ethernet Bridging",
+        0x86dd => "IPv6",
+        0x8100 => "802.1Q",
+        0x8847 => "MPLS",
+        0x8848 => "MPLS multicast",
+        0x88a8 => "802.1ad",
+        0x88e5 => "MACsec",
+        _ => return format!("0x{value:04x}"),
+    };
+    format!("{name} (0x{value:04x})")
+}
+
+fn ip_protocol(value: u8) -> String {
+    let name = match value {
+        1 => "ICMP",
+        2 => "IGMP",
+        4 => "IPIP",
+        6 => "TCP",
+        17 => "UDP",
+        41 => "IPv6",
+        47 => "GRE",
+        50 => "ESP",
+        51 => "AH",
+        58 => "ICMPv6",
+        132 => "SCTP",
+        _ => return value.to_string(),
+    };
+    format!("{name} ({value})")
+}
@@ -1,112 +1,466 @@
+use crate::flow::dns::{DnsMessage, DnsQuestion, DnsRecord, DnsResponse, DnsTransaction};
+use crate::flow::dns::{rcode_name, record_type_name};
 use crate::flow::{IPAddress, Protocol};
 use crate::layers::PacketContext;
+use crate::layers::dissector::{Dissector, Payload, Registration};
+use crate::layers::tree::ProtocolNode;
 use pcap_parser::pcapng::{NameRecordType, NameResolutionBlock};
 use std::collections::HashMap;
+use std::fmt::Write;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::ops::Range;
 use tracing::debug;
 
+const HEADER_LEN: usize = 12;
+
 pub fn handle_name_resolution(
     nrb: &NameResolutionBlock,
     name_resolutions: &mut HashMap<IPAddress, Vec<String>>,
 ) {
+    for (ip, name) in name_resolution_records(nrb) {
+        add_name_resolution(ip, name, name_resolutions);
+    }
+}
+
+/// Address and name pairs of a pcapng Name Resolution Block, in block order.
+pub fn name_resolution_records(nrb: &NameResolutionBlock) -> Vec<(IPAddress, String)> {
+    let mut records = Vec::new();
     for record in &nrb.nr {
-        match record.record_type {
-            NameRecordType::Ipv4 => {
-                if let Some((ip, name)) = parse_name_record_value(record.record_value, 4) {
-                    add_name_resolution(ip, name, name_resolutions);
-                }
+        let ip_len = match record.record_type {
+            NameRecordType::Ipv4 => 4,
+            NameRecordType::Ipv6 => 16,
+            _ => continue,
+        };
+        if let Some((ip, names)) = parse_name_record_value(record.record_value, ip_len) {
+            records.extend(names.into_iter().map(|name| (ip, name)));
+        }
+    }
+    records
+}
+
+/// DNS over UDP and TCP. Messages are collected in [`PacketContext::dns_messages`]
+/// and address records in responses become name resolutions. Messages over TCP are
+/// framed by [`DnsStreamState`] once the stream is reassembled.
+pub struct DnsDissector;
+
+impl DnsDissector {
+    pub const NAME: &'static str = "dns";
+}
+
+impl Dissector for DnsDissector {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn registrations(&self) -> Vec<Registration> {
+        vec![
+            Registration::Port(Protocol::UDP, 53),
+            Registration::Port(Protocol::TCP, 53),
+        ]
+    }
+
+    fn dissect(&self, payload: &Payload<'_>, context: &mut PacketContext) -> Option<Range<usize>> {
+        if payload.transport == Protocol::UDP
+            && let Some(message) = parse_message(payload.data)
+        {
+            push_message(message, context);
+        }
+        None
+    }
+
+    fn tree(&self, payload: &Payload<'_>, offset: usize) -> Vec<ProtocolNode> {
+        // Over TCP each message is preceded by its length.
+        let skip = match payload.transport {
+            Protocol::TCP => 2,
+            _ => 0,
+        };
+        let Some(data) = payload.data.get(skip..) else {
+            return Vec::new();
+        };
+        let Some((message, sections)) = parse(data) else {
+            return Vec::new();
+        };
+        let kind = if message.is_response {
+            "Response"
+        } else {
+            "Query"
+        };
+        let word = |at: usize| u16::from_be_bytes([data[at], data[at + 1]]);
+        let o = offset + skip;
+        let mut node = ProtocolNode::new("Domain Name System", offset..o + data.len())
+            .with_value(format!("{kind} 0x{:04x}", message.id));
+        if skip > 0 {
+            node.field("Length", word_at(payload.data, 0).to_string(), offset..o);
+        }
+        let flags = word(2);
+        let mut flags_value = format!("0x{flags:04x} ({kind})");
+        if message.is_response {
+            let _ = write!(flags_value, ", {}", rcode_name(message.rcode));
+        }
+        node.field("Transaction ID", format!("0x{:04x}", message.id), o..o + 2)
+            .field("Flags", flags_value, o + 2..o + 4)
+            .field("Questions", word(4).to_string(), o + 4..o + 6)
+            .field("Answer RRs", word(6).to_string(), o + 6..o + 8)
+            .field("Authority RRs", word(8).to_string(), o + 8..o + 10)
+            .field("Additional RRs", word(10).to_string(), o + 10..o + 12);
+        let mut ranges = sections.into_iter();
+        for question in &message.questions {
+            let Some(range) = ranges.next() else { break };
+            node.field(
+                "Query",
+                format!(
+                    "{} {}",
+                    question.name,
+                    record_type_name(question.record_type)
+                ),
+                o + range.start..o + range.end,
+            );
+        }
+        for (label, records) in [
+            ("Answer", &message.answers),
+            ("Authority", &message.authorities),
+            ("Additional", &message.additionals),
+        ] {
+            for record in records {
+                let Some(range) = ranges.next() else { break };
+                node.field(label, format_record(record), o + range.start..o + range.end);
             }
-            NameRecordType::Ipv6 => {
-                if let Some((ip, name)) = parse_name_record_value(record.record_value, 16) {
-                    add_name_resolution(ip, name, name_resolutions);
-                }
+        }
+        vec![node]
+    }
+}
+
+/// Framing of DNS messages in one direction of a TCP connection: each message is
+/// preceded by its two-byte length.
+#[derive(Debug, Default)]
+pub struct DnsStreamState {
+    consumed: usize,
+    abandoned: bool,
+}
+
+impl DnsStreamState {
+    /// Decodes the messages completed in `stream`, the reassembled payload of one
+    /// direction, into `context`.
+    pub fn parse_stream(&mut self, stream: &[u8], context: &mut PacketContext) {
+        while !self.abandoned && stream.len() >= self.consumed + 2 {
+            let start = self.consumed + 2;
+            let end = start + usize::from(word_at(stream, self.consumed));
+            let Some(frame) = stream.get(start..end) else {
+                return;
+            };
+            match parse_message(frame) {
+                Some(message) => push_message(message, context),
+                None => self.abandoned = true,
             }
-            _ => {}
+            self.consumed = end;
         }
     }
+
+    /// Stops parsing at a gap, since the length prefixes can't be found past it.
+    pub fn resync_at(&mut self, _position: usize) {
+        self.abandoned = true;
+    }
+
+    /// Position in the stream of the first byte still to be parsed, or `usize::MAX`
+    /// once nothing more will be.
+    pub fn keep_from(&self) -> usize {
+        if self.abandoned {
+            usize::MAX
+        } else {
+            self.consumed
+        }
+    }
+
+    /// Shifts the position after the first `len` bytes of the stream were released.
+    pub fn released(&mut self, len: usize) {
+        self.consumed = self.consumed.saturating_sub(len);
+    }
 }
 
+/// Adds `message`, captured at `timestamp`, to the transactions `log` of its flow. A
+/// query starts a transaction unless it retransmits one still waiting for its
+/// response; a response completes the latest such transaction with the same ID and
+/// question, or starts one of its own when the query wasn't captured.
+pub fn record_message(log: &mut Vec<DnsTransaction>, message: DnsMessage, timestamp: f64) {
+    let question = message.questions.first();
+    let name = question.map(|q| q.name.clone()).unwrap_or_default();
+    let query_type = question.map(|q| q.record_type).unwrap_or_default();
+    let open = log.iter_mut().rev().find(|transaction| {
+        transaction.response.is_none()
+            && transaction.query_timestamp.is_some()
+            && transaction.id == message.id
+            && (question.is_none()
+                || (transaction.query_type == query_type
+                    && transaction.name.eq_ignore_ascii_case(&name)))
+    });
+
+    if !message.is_response {
+        if open.is_none() {
+            log.push(DnsTransaction {
+                id: message.id,
+                name,
+                query_type,
+                query_timestamp: Some(timestamp),
+                response: None,
+            });
+        }
+        return;
+    }
+
+    let response = DnsResponse {
+        rcode: message.rcode,
+        answers: message.answers,
+        timestamp,
+    };
+    match open {
+        Some(transaction) => transaction.response = Some(response),
+        None => log.push(DnsTransaction {
+            id: message.id,
+            name,
+            query_type,
+            query_timestamp: None,
+            response: Some(response),
+        }),
+    }
+}
+
+/// Records the addresses answered by the DNS response carried in `context`, which was
+/// decoded from `packet`.
 pub fn handle_dns_response(
     context: &PacketContext,
+    packet: &[u8],
     name_resolutions: &mut HashMap<IPAddress, Vec<String>>,
 ) {
-    let payload = match (&context.protocol, &context.udp_payload) {
-        (Some(Protocol::UDP), Some(data)) => data.as_slice(),
-        _ => return,
+    let (Some(Protocol::UDP), Some(range)) = (&context.protocol, &context.udp_payload) else {
+        return;
     };
-
-    let from_dns_port = matches!(context.src_port, Some(53)) || matches!(context.dst_port, Some(53));
-    if !from_dns_port {
+    let Some(payload) = packet.get(range.clone()) else {
         return;
-    }
+    };
 
-    for (ip, name) in parse_dns_answers(payload) {
-        add_name_resolution(ip, name, name_resolutions);
+    if let Some(message) = parse_message(payload) {
+        for (ip, name) in address_answers(&message) {
+            add_name_resolution(ip, name, name_resolutions);
+        }
     }
 }
 
-fn parse_dns_answers(payload: &[u8]) -> Vec<(IPAddress, String)> {
-    if payload.len() < 12 {
-        return Vec::new();
-    }
+fn push_message(message: DnsMessage, context: &mut PacketContext) {
+    context.name_resolutions.extend(address_answers(&message));
+    context.dns_messages.push(message);
+}
 
-    let flags = u16::from_be_bytes([payload[2], payload[3]]);
-    let is_response = flags & 0x8000 != 0;
-    if !is_response {
+/// Addresses of the A and AAAA answers of a response, with the names they belong to.
+fn address_answers(message: &DnsMessage) -> Vec<(IPAddress, String)> {
+    if !message.is_response {
         return Vec::new();
     }
+    message
+        .answers
+        .iter()
+        .filter(|record| record.class == 1)
+        .filter_map(|record| {
+            let ip = match record.record_type {
+                1 => IPAddress::V4(record.data.parse::<Ipv4Addr>().ok()?.octets()),
+                28 => IPAddress::V6(record.data.parse::<Ipv6Addr>().ok()?.octets()),
+                _ => return None,
+            };
+            Some((ip, record.name.clone()))
+        })
+        .collect()
+}
 
-    let qdcount = u16::from_be_bytes([payload[4], payload[5]]) as usize;
-    let ancount = u16::from_be_bytes([payload[6], payload[7]]) as usize;
-
-    let mut offset = 12usize;
+/// Decodes a DNS message. Records following a malformed one are left out.
+pub fn parse_message(payload: &[u8]) -> Option<DnsMessage> {
+    parse(payload).map(|(message, _)| message)
+}
 
-    for _ in 0..qdcount {
-        if let Some((_, next)) = read_dns_name(payload, offset) {
-            offset = next;
-        } else {
-            return Vec::new();
-        }
+/// The message in `payload` and the byte range of each of its questions and records,
+/// in message order.
+fn parse(payload: &[u8]) -> Option<(DnsMessage, Vec<Range<usize>>)> {
+    if payload.len() < HEADER_LEN {
+        return None;
+    }
+    let flags = word_at(payload, 2);
+    let mut message = DnsMessage {
+        id: word_at(payload, 0),
+        is_response: flags & 0x8000 != 0,
+        opcode: ((flags >> 11) & 0x0f) as u8,
+        rcode: (flags & 0x0f) as u8,
+        ..DnsMessage::default()
+    };
+    let counts = [4, 6, 8, 10].map(|at| usize::from(word_at(payload, at)));
+    let mut ranges = Vec::new();
+    let mut offset = HEADER_LEN;
 
-        if offset + 4 > payload.len() {
-            return Vec::new();
+    for _ in 0..counts[0] {
+        let (name, next) = read_dns_name(payload, offset)?;
+        if next + 4 > payload.len() {
+            return None;
         }
-        offset += 4; // type + class
+        message.questions.push(DnsQuestion {
+            name,
+            record_type: word_at(payload, next),
+            class: word_at(payload, next + 2),
+        });
+        ranges.push(offset..next + 4);
+        offset = next + 4;
     }
 
-    let mut results = Vec::new();
-    for _ in 0..ancount {
-        let (name, next) = match read_dns_name(payload, offset) {
-            Some(n) => n,
-            None => break,
-        };
-        offset = next;
-
-        if offset + 10 > payload.len() {
-            break;
+    let sections = [
+        (counts[1], &mut message.answers),
+        (counts[2], &mut message.authorities),
+        (counts[3], &mut message.additionals),
+    ];
+    'sections: for (count, records) in sections {
+        for _ in 0..count {
+            let Some((record, next)) = read_record(payload, offset) else {
+                break 'sections;
+            };
+            records.push(record);
+            ranges.push(offset..next);
+            offset = next;
         }
+    }
 
-        let rtype = u16::from_be_bytes([payload[offset], payload[offset + 1]]);
-        let class = u16::from_be_bytes([payload[offset + 2], payload[offset + 3]]);
-        let rdlength = u16::from_be_bytes([payload[offset + 8], payload[offset + 9]]) as usize;
-        offset += 10;
+    Some((message, ranges))
+}
 
-        if offset + rdlength > payload.len() {
-            break;
-        }
+/// Reads the resource record at `start`, returning it and the offset following it.
+fn read_record(packet: &[u8], start: usize) -> Option<(DnsRecord, usize)> {
+    let (name, offset) = read_dns_name(packet, start)?;
+    if offset + 10 > packet.len() {
+        return None;
+    }
+    let record_type = word_at(packet, offset);
+    let class = word_at(packet, offset + 2);
+    let ttl = u32::from_be_bytes(packet[offset + 4..offset + 8].try_into().ok()?);
+    let rdlength = usize::from(word_at(packet, offset + 8));
+    let rdata_start = offset + 10;
+    let end = rdata_start + rdlength;
+    if end > packet.len() {
+        return None;
+    }
+    let data = format_rdata(packet, record_type, rdata_start..end)
+        .unwrap_or_else(|| hex(&packet[rdata_start..end]));
+    Some((
+        DnsRecord {
+            name,
+            record_type,
+            class,
+            ttl,
+            data,
+        },
+        end,
+    ))
+}
 
-        let rdata = &payload[offset..offset + rdlength];
-        if class == 1 {
-            match (rtype, rdlength) {
-                (1, 4) => results.push((IPAddress::V4(rdata.try_into().unwrap()), name.clone())),
-                (28, 16) => results.push((IPAddress::V6(rdata.try_into().unwrap()), name.clone())),
-                _ => {}
+/// RDATA in presentation format, or `None` when it doesn't match the layout of its
+/// type. Names inside RDATA may be compressed, so `packet` is the whole message.
+fn format_rdata(packet: &[u8], record_type: u16, range: Range<usize>) -> Option<String> {
+    let rdata = &packet[range.clone()];
+    let name_at = |at: usize| read_dns_name(packet, range.start + at);
+    let text = match record_type {
+        1 => Ipv4Addr::from(<[u8; 4]>::try_from(rdata).ok()?).to_string(),
+        28 => Ipv6Addr::from(<[u8; 16]>::try_from(rdata).ok()?).to_string(),
+        // NS, CNAME, PTR, DNAME
+        2 | 5 | 12 | 39 => name_at(0)?.0,
+        // MX
+        15 => format!("{} {}", word_at(rdata.get(..2)?, 0), name_at(2)?.0),
+        // TXT, SPF, HINFO
+        16 | 99 | 13 => character_strings(rdata)?.join(" "),
+        6 => {
+            let (mname, next) = name_at(0)?;
+            let (rname, next) = read_dns_name(packet, next)?;
+            let numbers = packet.get(next..next + 20)?;
+            let mut text = format!("{mname} {rname}");
+            for chunk in numbers.chunks(4) {
+                let _ = write!(text, " {}", u32::from_be_bytes(chunk.try_into().ok()?));
             }
+            text
+        }
+        // SRV
+        33 => {
+            let header = rdata.get(..6)?;
+            format!(
+                "{} {} {} {}",
+                word_at(header, 0),
+                word_at(header, 2),
+                word_at(header, 4),
+                name_at(6)?.0
+            )
         }
+        // SVCB, HTTPS: priority and target; parameters are left out.
+        64 | 65 => format!(
+            "{} {}",
+            word_at(rdata.get(..2)?, 0),
+            name_or_root(name_at(2)?.0)
+        ),
+        // DS
+        43 => {
+            let header = rdata.get(..4)?;
+            format!(
+                "{} {} {} {}",
+                word_at(header, 0),
+                header[2],
+                header[3],
+                hex(&rdata[4..])
+            )
+        }
+        // CAA
+        257 => {
+            let flags = *rdata.first()?;
+            let tag_len = usize::from(*rdata.get(1)?);
+            let tag = rdata.get(2..2 + tag_len)?;
+            let value = &rdata[2 + tag_len..];
+            format!(
+                "{flags} {} \"{}\"",
+                String::from_utf8_lossy(tag),
+                String::from_utf8_lossy(value)
+            )
+        }
+        _ => return None,
+    };
+    Some(text)
+}
+
+/// Quoted `<character-string>`s filling `rdata`.
+fn character_strings(rdata: &[u8]) -> Option<Vec<String>> {
+    let mut strings = Vec::new();
+    let mut offset = 0;
+    while offset < rdata.len() {
+        let len = usize::from(rdata[offset]);
+        let text = rdata.get(offset + 1..offset + 1 + len)?;
+        strings.push(format!("\"{}\"", String::from_utf8_lossy(text)));
+        offset += 1 + len;
+    }
+    Some(strings)
+}
 
-        offset += rdlength;
+fn name_or_root(name: String) -> String {
+    if name.is_empty() {
+        ".".to_owned()
+    } else {
+        name
     }
+}
+
+/// One line summary of a record, e.g. `example.com CNAME www.example.net`.
+pub fn format_record(record: &DnsRecord) -> String {
+    format!(
+        "{} {} {}",
+        name_or_root(record.name.clone()),
+        record_type_name(record.record_type),
+        record.data
+    )
+}
 
-    results
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn word_at(data: &[u8], at: usize) -> u16 {
+    u16::from_be_bytes([data[at], data[at + 1]])
 }
 
 fn read_dns_name(packet: &[u8], start: usize) -> Option<(String, usize)> {
@@ -163,19 +517,19 @@ fn read_dns_name(packet: &[u8], start: usize) -> Option<(String, usize)> {
     Some((labels.join("."), next_offset))
 }
 
-fn parse_name_record_value(record_value: &[u8], ip_len: usize) -> Option<(IPAddress, String)> {
+/// Splits a name record into its address and the zero-terminated names that follow it.
+fn parse_name_record_value(record_value: &[u8], ip_len: usize) -> Option<(IPAddress, Vec<String>)> {
     if record_value.len() < ip_len + 1 {
         return None;
     }
 
     let (ip_bytes, rest) = record_value.split_at(ip_len);
-    let name_bytes = rest.split(|b| *b == 0).next().unwrap_or(&[]);
-    if name_bytes.is_empty() {
-        return None;
-    }
-
-    let name = String::from_utf8_lossy(name_bytes).trim().to_string();
-    if name.is_empty() {
+    let names: Vec<String> = rest
+        .split(|b| *b == 0)
+        .map(|name| String::from_utf8_lossy(name).trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect();
+    if names.is_empty() {
         return None;
     }
 
@@ -185,10 +539,10 @@ fn parse_name_record_value(record_value: &[u8], ip_len: usize) -> Option<(IPAddr
         _ => return None,
     };
 
-    Some((ip, name))
+    Some((ip, names))
 }
 
-fn add_name_resolution(
+pub fn add_name_resolution(
     ip: IPAddress,
     name: String,
     name_resolutions: &mut HashMap<IPAddress, Vec<String>>,
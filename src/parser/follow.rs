@@ -0,0 +1,268 @@
+use crate::flow::{Endpoint, Flow, Protocol};
+use crate::parser::decoder::{decode_link_headers, decode_reassembled};
+use crate::parser::defrag::{FragmentKey, IpReassembler};
+use crate::parser::reassembly::TcpStream;
+use crate::parser::state::CaptureInterface;
+use pcap_parser::Linktype;
+use std::fmt::Write as _;
+
+const HEX_DUMP_WIDTH: usize = 16;
+const RAW_WIDTH: usize = 32;
+
+/// Which endpoint of a followed flow sent a chunk. The flow's `source` is treated as
+/// the client, as it sent the first packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FollowDirection {
+    ClientToServer,
+    ServerToClient,
+}
+
+/// How followed payload is turned into text.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FollowFormat {
+    /// Printable ASCII with line breaks kept and everything else shown as `.`.
+    #[default]
+    Ascii,
+    /// Offset, hex and ASCII columns, with offsets counted per direction.
+    HexDump,
+    /// Lossy UTF-8.
+    Utf8,
+    /// Plain hex digits.
+    Raw,
+}
+
+/// Contiguous application payload sent in one direction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamChunk {
+    pub direction: FollowDirection,
+    /// Timestamp of the packet that completed the chunk's first bytes.
+    pub timestamp: f64,
+    pub data: Vec<u8>,
+}
+
+/// Application payload of a flow in the order it was delivered, as shown by
+/// Wireshark's "Follow Stream".
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FollowedStream {
+    pub chunks: Vec<StreamChunk>,
+    /// Bytes never captured, summed over both directions of a TCP stream.
+    pub missing_bytes: u64,
+}
+
+impl FollowDirection {
+    fn of(flow: &Flow, sender: Endpoint) -> Self {
+        if sender == flow.source {
+            FollowDirection::ClientToServer
+        } else {
+            FollowDirection::ServerToClient
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            FollowDirection::ClientToServer => "client → server",
+            FollowDirection::ServerToClient => "server → client",
+        }
+    }
+}
+
+impl FollowFormat {
+    pub const ALL: [FollowFormat; 4] = [
+        FollowFormat::Ascii,
+        FollowFormat::HexDump,
+        FollowFormat::Utf8,
+        FollowFormat::Raw,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            FollowFormat::Ascii => "ASCII",
+            FollowFormat::HexDump => "Hex Dump",
+            FollowFormat::Utf8 => "UTF-8",
+            FollowFormat::Raw => "Raw",
+        }
+    }
+}
+
+impl FollowedStream {
+    /// All payload sent in `direction`, concatenated. This is what gets saved to a file.
+    pub fn payload(&self, direction: FollowDirection) -> Vec<u8> {
+        self.chunks
+            .iter()
+            .filter(|chunk| chunk.direction == direction)
+            .flat_map(|chunk| chunk.data.iter().copied())
+            .collect()
+    }
+
+    pub fn byte_count(&self, direction: FollowDirection) -> usize {
+        self.chunks
+            .iter()
+            .filter(|chunk| chunk.direction == direction)
+            .map(|chunk| chunk.data.len())
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Renders every chunk in `format`, one entry per chunk. Hex dump offsets continue
+    /// across the chunks of the same direction.
+    pub fn render(&self, format: FollowFormat) -> Vec<(FollowDirection, String)> {
+        let mut client_offset = 0;
+        let mut server_offset = 0;
+        self.chunks
+            .iter()
+            .map(|chunk| {
+                let offset = match chunk.direction {
+                    FollowDirection::ClientToServer => &mut client_offset,
+                    FollowDirection::ServerToClient => &mut server_offset,
+                };
+                let text = render_bytes(&chunk.data, format, *offset);
+                *offset += chunk.data.len();
+                (chunk.direction, text)
+            })
+            .collect()
+    }
+
+    /// Appends `data`, merging it into the previous chunk when the direction is unchanged.
+    fn push(&mut self, direction: FollowDirection, timestamp: f64, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+        match self.chunks.last_mut() {
+            Some(last) if last.direction == direction => last.data.extend_from_slice(data),
+            _ => self.chunks.push(StreamChunk {
+                direction,
+                timestamp,
+                data: data.to_vec(),
+            }),
+        }
+    }
+}
+
+/// Reassembles the payload of `flow`. TCP segments are put in sequence order per
+/// direction; UDP datagrams are taken as they are. Fragmented IP datagrams count once
+/// complete, at their last fragment. Other protocols have no stream.
+pub fn follow_stream(flow: &Flow, interfaces: &[CaptureInterface]) -> FollowedStream {
+    let mut followed = FollowedStream::default();
+    if !matches!(flow.protocol, Protocol::TCP | Protocol::UDP) {
+        return followed;
+    }
+
+    let mut client = TcpStream::default();
+    let mut server = TcpStream::default();
+    let mut fragments = IpReassembler::default();
+    for packet in &flow.packets {
+        let linktype = interfaces
+            .get(packet.interface)
+            .map_or(Linktype::ETHERNET, |interface| interface.linktype);
+        let Ok(data) = packet.data.load() else {
+            continue;
+        };
+        let Ok(mut context) = decode_link_headers(&data, linktype) else {
+            continue;
+        };
+        let mut reassembled = None;
+        if let Some(fragment) = context.ip_fragment.take()
+            && let (Some(src), Some(dst)) = (context.src_ip, context.dst_ip)
+        {
+            let key = FragmentKey {
+                src,
+                dst,
+                id: fragment.id,
+                protocol: fragment.protocol,
+            };
+            let Some(datagram) = fragments.push(key, &fragment, packet.timestamp, ()) else {
+                continue;
+            };
+            decode_reassembled(key.protocol, datagram.payload(), &mut context);
+            reassembled = Some(datagram);
+        }
+        // Payload positions point into the datagram once it was put back together.
+        let bytes = reassembled
+            .as_ref()
+            .map_or(&data[..], |datagram| datagram.payload());
+        let (Some(src_ip), Some(src_port)) = (context.src_ip, context.src_port) else {
+            continue;
+        };
+        let direction = FollowDirection::of(flow, Endpoint::new(src_ip, src_port));
+
+        match (flow.protocol, context.tcp_segment, context.udp_payload) {
+            (Protocol::TCP, Some(segment), _) => {
+                let stream = match direction {
+                    FollowDirection::ClientToServer => &mut client,
+                    FollowDirection::ServerToClient => &mut server,
+                };
+                let payload = bytes.get(segment.payload.clone()).unwrap_or_default();
+                let appended = stream.push(&segment, payload).appended;
+                followed.push(direction, packet.timestamp, &stream.data()[appended]);
+                stream.release(usize::MAX);
+            }
+            (Protocol::UDP, _, Some(range)) => {
+                let payload = bytes.get(range).unwrap_or_default();
+                followed.push(direction, packet.timestamp, payload);
+            }
+            _ => {}
+        }
+    }
+
+    // Data still waiting behind a hole is delivered after giving up on the hole.
+    let last_timestamp = flow.packets.last().map_or(flow.timestamp, |p| p.timestamp);
+    for (direction, stream) in [
+        (FollowDirection::ClientToServer, &mut client),
+        (FollowDirection::ServerToClient, &mut server),
+    ] {
+        let delivered = stream.data().len();
+        stream.flush_gaps();
+        followed.push(direction, last_timestamp, &stream.data()[delivered..]);
+        followed.missing_bytes += stream.gaps().iter().map(|gap| gap.missing).sum::<u64>();
+    }
+    followed
+}
+
+/// Formats `data` as text. `offset` is the position of `data[0]` within its direction
+/// and only affects the hex dump.
+pub fn render_bytes(data: &[u8], format: FollowFormat, offset: usize) -> String {
+    match format {
+        FollowFormat::Ascii => data
+            .iter()
+            .filter_map(|&byte| match byte {
+                b'\r' => None,
+                b'\n' | b'\t' | 0x20..=0x7e => Some(byte as char),
+                _ => Some('.'),
+            })
+            .collect(),
+        FollowFormat::Utf8 => String::from_utf8_lossy(data).replace("\r\n", "\n"),
+        FollowFormat::HexDump => {
+            let mut text = String::new();
+            for (row, chunk) in data.chunks(HEX_DUMP_WIDTH).enumerate() {
+                let _ = write!(text, "{:08X}  ", offset + row * HEX_DUMP_WIDTH);
+                for idx in 0..HEX_DUMP_WIDTH {
+                    if idx == HEX_DUMP_WIDTH / 2 {
+                        text.push(' ');
+                    }
+                    match chunk.get(idx) {
+                        Some(byte) => {
+                            let _ = write!(text, "{byte:02x} ");
+                        }
+                        None => text.push_str("   "),
+                    }
+                }
+                text.push(' ');
+                text.extend(chunk.iter().map(|&byte| match byte {
+                    0x20..=0x7e => byte as char,
+                    _ => '.',
+                }));
+                text.push('\n');
+            }
+            text.pop();
+            text
+        }
+        FollowFormat::Raw => data
+            .chunks(RAW_WIDTH)
+            .map(|line| line.iter().map(|byte| format!("{byte:02x}")).collect())
+            .collect::<Vec<String>>()
+            .join("\n"),
+    }
+}
@@ -0,0 +1,612 @@
+use crate::flow::Protocol;
+use crate::flow::http::{HttpRequest, HttpResponse, HttpTransaction};
+use crate::layers::PacketContext;
+use crate::layers::dissector::{Dissector, Payload, Registration};
+use crate::layers::tree::ProtocolNode;
+use crate::parser::reassembly::StreamDirection;
+use std::collections::VecDeque;
+use std::ops::Range;
+
+const METHODS: [&str; 9] = [
+    "GET", "HEAD", "POST", "PUT", "DELETE", "CONNECT", "OPTIONS", "TRACE", "PATCH",
+];
+/// Enough bytes to tell a start line from other data: the longest method plus a space.
+const MIN_SNIFF_LEN: usize = 8;
+/// Header blocks longer than this are taken for something else than HTTP.
+const MAX_HEAD_LEN: usize = 64 * 1024;
+/// Bytes searched for a start line after a gap before a direction is given up.
+const MAX_RESYNC_SCAN: usize = 64 * 1024;
+
+/// HTTP/1.0 and HTTP/1.1 over TCP, found from the request or status line. Messages are
+/// parsed from the reassembled stream by [`HttpConnection::parse_stream`]; per packet
+/// this only supplies the protocol tree. Use a decode-as rule to force it onto a port.
+pub struct HttpDissector;
+
+impl HttpDissector {
+    pub const NAME: &'static str = "http";
+}
+
+impl Dissector for HttpDissector {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn registrations(&self) -> Vec<Registration> {
+        vec![Registration::Heuristic(Protocol::TCP)]
+    }
+
+    fn heuristic(&self, data: &[u8]) -> bool {
+        looks_like_http(data)
+    }
+
+    fn dissect(
+        &self,
+        _payload: &Payload<'_>,
+        _context: &mut PacketContext,
+    ) -> Option<Range<usize>> {
+        None
+    }
+
+    fn tree(&self, payload: &Payload<'_>, offset: usize) -> Vec<ProtocolNode> {
+        let data = payload.data;
+        let o = offset;
+        let mut node = ProtocolNode::new("Hypertext Transfer Protocol", o..o + data.len());
+        let mut pos = 0;
+        for (ix, line) in lines(data).enumerate() {
+            let range = o + pos..o + pos + line.len();
+            pos += line.len();
+            let text = String::from_utf8_lossy(trim_line_end(line));
+            if ix == 0 {
+                if start_line(text.as_ref()).is_none() {
+                    return vec![node.with_value("Continuation")];
+                }
+                node = node.with_value(text.as_ref());
+                node.field("Start Line", text.as_ref(), range);
+            } else if text.is_empty() {
+                if data.len() > pos {
+                    node.field(
+                        "Body",
+                        format!("{} bytes", data.len() - pos),
+                        o + pos..o + data.len(),
+                    );
+                }
+                break;
+            } else {
+                let (name, value) = text.split_once(':').unwrap_or((text.as_ref(), ""));
+                node.field(name.trim(), value.trim(), range);
+            }
+        }
+        vec![node]
+    }
+}
+
+/// Whether `payload` starts like an HTTP/1.x request or response.
+pub fn looks_like_http(payload: &[u8]) -> bool {
+    payload.starts_with(b"HTTP/1.")
+        || METHODS.iter().any(|method| {
+            payload.len() > method.len()
+                && payload.starts_with(method.as_bytes())
+                && payload[method.len()] == b' '
+        })
+}
+
+/// HTTP parsing state of one TCP connection: both directions and the transactions
+/// paired so far.
+#[derive(Debug, Default, Clone)]
+pub struct HttpConnection {
+    forward: HttpStreamState,
+    reverse: HttpStreamState,
+    transactions: Vec<HttpTransaction>,
+    /// Transactions whose request awaits its final response, oldest first. Pipelined
+    /// responses arrive in request order, so each one answers the front.
+    pending: VecDeque<usize>,
+    /// The connection switched protocols (e.g. WebSocket) or became a CONNECT tunnel.
+    upgraded: bool,
+}
+
+/// Progress of [`HttpConnection::parse_stream`] through one direction.
+#[derive(Debug, Clone)]
+struct HttpStreamState {
+    consumed: usize,
+    /// Message whose body is still being read.
+    body: Option<Body>,
+    synced: bool,
+    scanned: usize,
+    abandoned: bool,
+}
+
+impl Default for HttpStreamState {
+    fn default() -> Self {
+        Self {
+            consumed: 0,
+            body: None,
+            synced: true,
+            scanned: 0,
+            abandoned: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Body {
+    transaction: usize,
+    is_request: bool,
+    /// Body bytes read so far, without the chunked framing.
+    length: usize,
+    framing: Framing,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Framing {
+    /// A body of known length, of which this many bytes are still to come.
+    Length(usize),
+    /// Chunked transfer encoding, in the part of the framing expected next.
+    Chunked(Chunk),
+    /// A response without length, ending when the connection closes.
+    UntilClose,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Chunk {
+    /// The line with the size of the next chunk.
+    Size,
+    /// Data of a chunk with this many bytes left, followed by a line end.
+    Data(usize),
+    /// The trailer fields after the last chunk, up to an empty line.
+    Trailer,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum StartLine {
+    Request {
+        method: String,
+        uri: String,
+        version: String,
+    },
+    Response {
+        version: String,
+        status: u16,
+        reason: String,
+    },
+}
+
+/// A parsed start line and header block, `len` bytes long including the empty line.
+struct Head {
+    start: StartLine,
+    headers: Vec<(String, String)>,
+    len: usize,
+}
+
+impl Head {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .rev()
+            .find(|(candidate, _)| candidate.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn is_chunked(&self) -> bool {
+        self.header("Transfer-Encoding").is_some_and(|encoding| {
+            encoding
+                .rsplit(',')
+                .next()
+                .is_some_and(|last| last.trim().eq_ignore_ascii_case("chunked"))
+        })
+    }
+
+    fn content_length(&self) -> Option<usize> {
+        self.header("Content-Length")?.trim().parse().ok()
+    }
+}
+
+impl HttpConnection {
+    /// Parses the messages in `stream` that have not been seen yet, tagging `context`
+    /// with the start line of each one whose headers it completes. `stream` is the
+    /// reassembled payload sent in `direction`, `timestamp` the capture time of the
+    /// packet that extended it.
+    pub fn parse_stream(
+        &mut self,
+        stream: &[u8],
+        direction: StreamDirection,
+        timestamp: f64,
+        context: &mut PacketContext,
+    ) {
+        loop {
+            let state = match direction {
+                StreamDirection::Forward => &mut self.forward,
+                StreamDirection::Reverse => &mut self.reverse,
+            };
+            if self.upgraded || state.abandoned {
+                return;
+            }
+
+            if let Some(body) = &mut state.body {
+                if !read_body(stream, &mut state.consumed, body) {
+                    return;
+                }
+                let transaction = &mut self.transactions[body.transaction];
+                if body.is_request {
+                    if let Some(request) = &mut transaction.request {
+                        request.body_length = body.length;
+                    }
+                } else if let Some(response) = &mut transaction.response {
+                    response.body_length = body.length;
+                }
+                state.body = None;
+                continue;
+            }
+
+            // Empty lines may precede a message.
+            while stream[state.consumed..].starts_with(b"\r\n") {
+                state.consumed += 2;
+            }
+            if !state.synced && !find_start_line(stream, state) {
+                return;
+            }
+            let input = &stream[state.consumed..];
+            if input.is_empty() {
+                return;
+            }
+            if input.len() >= MIN_SNIFF_LEN && !looks_like_http(input) {
+                state.abandoned = true;
+                return;
+            }
+            let head = match parse_head(input) {
+                Ok(Some(head)) => head,
+                Ok(None) => return,
+                Err(()) => {
+                    state.abandoned = true;
+                    return;
+                }
+            };
+            state.consumed += head.len;
+            if let Some(body) = self.record(head, timestamp, context) {
+                match direction {
+                    StreamDirection::Forward => self.forward.body = Some(body),
+                    StreamDirection::Reverse => self.reverse.body = Some(body),
+                }
+            }
+        }
+    }
+
+    /// Drops the message in progress in `direction` because bytes before `position`
+    /// are missing, and looks for the next start line from there.
+    pub fn resync_at(&mut self, direction: StreamDirection, position: usize) {
+        let state = match direction {
+            StreamDirection::Forward => &mut self.forward,
+            StreamDirection::Reverse => &mut self.reverse,
+        };
+        state.consumed = state.consumed.max(position);
+        state.body = None;
+        state.synced = false;
+        state.scanned = 0;
+        state.abandoned = false;
+    }
+
+    /// Position in the stream sent in `direction` of the first byte still to be parsed,
+    /// or `usize::MAX` once nothing more will be.
+    pub fn keep_from(&self, direction: StreamDirection) -> usize {
+        let state = match direction {
+            StreamDirection::Forward => &self.forward,
+            StreamDirection::Reverse => &self.reverse,
+        };
+        if self.upgraded || state.abandoned {
+            usize::MAX
+        } else {
+            state.consumed
+        }
+    }
+
+    /// Shifts the position in `direction` after the first `len` bytes of its stream
+    /// were released.
+    pub fn released(&mut self, direction: StreamDirection, len: usize) {
+        let state = match direction {
+            StreamDirection::Forward => &mut self.forward,
+            StreamDirection::Reverse => &mut self.reverse,
+        };
+        state.consumed = state.consumed.saturating_sub(len);
+    }
+
+    /// Ends the body of a response in `direction` delimited by the connection closing.
+    pub fn finish(&mut self, direction: StreamDirection) {
+        let state = match direction {
+            StreamDirection::Forward => &mut self.forward,
+            StreamDirection::Reverse => &mut self.reverse,
+        };
+        if let Some(body) = state.body.take()
+            && matches!(body.framing, Framing::UntilClose)
+            && let Some(response) = &mut self.transactions[body.transaction].response
+        {
+            response.body_length = body.length;
+        }
+    }
+
+    /// Starts `direction` over for a new connection on the same addresses and ports,
+    /// which has no requests outstanding.
+    pub fn restart(&mut self, direction: StreamDirection) {
+        let state = match direction {
+            StreamDirection::Forward => &mut self.forward,
+            StreamDirection::Reverse => &mut self.reverse,
+        };
+        *state = HttpStreamState::default();
+        self.pending.clear();
+        self.upgraded = false;
+    }
+
+    pub fn into_transactions(self) -> Vec<HttpTransaction> {
+        self.transactions
+    }
+
+    /// Adds the message with headers `head` to its transaction, returning the body
+    /// still to be read.
+    fn record(&mut self, head: Head, timestamp: f64, context: &mut PacketContext) -> Option<Body> {
+        let content_type = head.header("Content-Type").map(str::to_owned);
+        match &head.start {
+            StartLine::Request {
+                method,
+                uri,
+                version,
+            } => {
+                context.tags.push(format!("{method} {uri} {version}"));
+                let transaction = self.transactions.len();
+                self.transactions.push(HttpTransaction {
+                    request: Some(HttpRequest {
+                        method: method.clone(),
+                        uri: uri.clone(),
+                        version: version.clone(),
+                        host: head.header("Host").map(str::to_owned),
+                        content_type,
+                        body_length: 0,
+                        timestamp,
+                    }),
+                    response: None,
+                });
+                self.pending.push_back(transaction);
+                let framing = if head.is_chunked() {
+                    Framing::Chunked(Chunk::Size)
+                } else {
+                    Framing::Length(head.content_length().unwrap_or(0))
+                };
+                Some(Body {
+                    transaction,
+                    is_request: true,
+                    length: 0,
+                    framing,
+                })
+            }
+            StartLine::Response {
+                version,
+                status,
+                reason,
+            } => {
+                let tag = format!("{version} {status} {reason}");
+                context.tags.push(tag.trim_end().to_owned());
+                // Interim responses such as 100 Continue precede the final one.
+                if (100..200).contains(status) && *status != 101 {
+                    return None;
+                }
+                let method = self
+                    .pending
+                    .front()
+                    .and_then(|ix| self.transactions[*ix].request.as_ref())
+                    .map(|request| request.method.clone());
+                let transaction = self.pending.pop_front().unwrap_or_else(|| {
+                    self.transactions.push(HttpTransaction::default());
+                    self.transactions.len() - 1
+                });
+                self.transactions[transaction].response = Some(HttpResponse {
+                    version: version.clone(),
+                    status: *status,
+                    reason: reason.clone(),
+                    content_type,
+                    body_length: 0,
+                    timestamp,
+                });
+
+                let is_connect = method.as_deref() == Some("CONNECT");
+                if *status == 101 || (is_connect && (200..300).contains(status)) {
+                    self.upgraded = true;
+                    return None;
+                }
+                let framing = if method.as_deref() == Some("HEAD") || matches!(status, 204 | 304) {
+                    Framing::Length(0)
+                } else if head.is_chunked() {
+                    Framing::Chunked(Chunk::Size)
+                } else if let Some(length) = head.content_length() {
+                    Framing::Length(length)
+                } else {
+                    Framing::UntilClose
+                };
+                Some(Body {
+                    transaction,
+                    is_request: false,
+                    length: 0,
+                    framing,
+                })
+            }
+        }
+    }
+}
+
+/// Reads what has arrived of `body` in `stream`, moving `consumed` past it, so the
+/// body's bytes need not be kept. Returns whether all of it has been read.
+fn read_body(stream: &[u8], consumed: &mut usize, body: &mut Body) -> bool {
+    match &mut body.framing {
+        Framing::Length(left) => {
+            let read = (*left).min(stream.len() - *consumed);
+            *consumed += read;
+            *left -= read;
+            body.length += read;
+            *left == 0
+        }
+        Framing::Chunked(chunk) => loop {
+            let input = &stream[*consumed..];
+            match chunk {
+                Chunk::Size => {
+                    let Some(size_len) = line_len(input) else {
+                        return false;
+                    };
+                    let line = trim_line_end(&input[..size_len]);
+                    let size = std::str::from_utf8(line)
+                        .ok()
+                        .and_then(|line| {
+                            let size = line.split(';').next()?.trim();
+                            usize::from_str_radix(size, 16).ok()
+                        })
+                        // Not a chunk size: treat it as the last chunk.
+                        .unwrap_or(0);
+                    *consumed += size_len;
+                    *chunk = if size == 0 {
+                        Chunk::Trailer
+                    } else {
+                        Chunk::Data(size)
+                    };
+                }
+                Chunk::Data(0) => {
+                    let Some(end_len) = line_len_at(stream, *consumed) else {
+                        return false;
+                    };
+                    *consumed += end_len;
+                    *chunk = Chunk::Size;
+                }
+                Chunk::Data(left) => {
+                    if input.is_empty() {
+                        return false;
+                    }
+                    let read = (*left).min(input.len());
+                    *consumed += read;
+                    *left -= read;
+                    body.length += read;
+                }
+                Chunk::Trailer => {
+                    let Some(trailer_len) = line_len(input) else {
+                        return false;
+                    };
+                    *consumed += trailer_len;
+                    if trim_line_end(&input[..trailer_len]).is_empty() {
+                        return true;
+                    }
+                }
+            }
+        },
+        Framing::UntilClose => {
+            body.length += stream.len() - *consumed;
+            *consumed = stream.len();
+            false
+        }
+    }
+}
+
+/// Length of the CRLF (or bare LF) ending a chunk's data, once it has arrived.
+fn line_len_at(stream: &[u8], pos: usize) -> Option<usize> {
+    match stream.get(pos..)? {
+        [b'\r', b'\n', ..] => Some(2),
+        [b'\n', ..] => Some(1),
+        [b'\r'] | [] => None,
+        _ => Some(0),
+    }
+}
+
+/// Moves `state` to the next line of `stream` that starts like a message. Returns
+/// whether one was found; otherwise gives up after [`MAX_RESYNC_SCAN`] bytes.
+fn find_start_line(stream: &[u8], state: &mut HttpStreamState) -> bool {
+    let input = &stream[state.consumed..];
+    let candidate = std::iter::once(0)
+        .chain(newlines(input).map(|newline| newline + 1))
+        .find(|&at| looks_like_http(&input[at..]));
+    if let Some(at) = candidate {
+        state.consumed += at;
+        state.synced = true;
+        return true;
+    }
+    // Resume after the last complete line, which may still be followed by a start line.
+    let skipped = input
+        .iter()
+        .rposition(|byte| *byte == b'\n')
+        .map_or(0, |newline| newline + 1);
+    state.consumed += skipped;
+    state.scanned += skipped;
+    if state.scanned > MAX_RESYNC_SCAN {
+        state.abandoned = true;
+    }
+    false
+}
+
+fn newlines(input: &[u8]) -> impl Iterator<Item = usize> + '_ {
+    input
+        .iter()
+        .enumerate()
+        .filter(|(_, byte)| **byte == b'\n')
+        .map(|(ix, _)| ix)
+}
+
+/// Parses the start line and headers at the beginning of `input`. `Ok(None)` means the
+/// header block is incomplete, `Err` that `input` isn't an HTTP message.
+fn parse_head(input: &[u8]) -> Result<Option<Head>, ()> {
+    let mut start = None;
+    let mut headers = Vec::new();
+    let mut len = 0;
+    for line in lines(input) {
+        if line.last() != Some(&b'\n') {
+            break;
+        }
+        len += line.len();
+        let text = String::from_utf8_lossy(trim_line_end(line));
+        let Some(first) = &start else {
+            start = Some(start_line(&text).ok_or(())?);
+            continue;
+        };
+        if text.is_empty() {
+            return Ok(Some(Head {
+                start: first.clone(),
+                headers,
+                len,
+            }));
+        }
+        if let Some((name, value)) = text.split_once(':') {
+            headers.push((name.trim().to_owned(), value.trim().to_owned()));
+        }
+    }
+    if input.len() > MAX_HEAD_LEN {
+        return Err(());
+    }
+    Ok(None)
+}
+
+fn start_line(line: &str) -> Option<StartLine> {
+    let mut parts = line.splitn(3, ' ');
+    let first = parts.next()?;
+    let second = parts.next()?;
+    let third = parts.next().unwrap_or_default();
+    if first.starts_with("HTTP/1.") {
+        return Some(StartLine::Response {
+            version: first.to_owned(),
+            status: second
+                .parse()
+                .ok()
+                .filter(|status| (100..1000).contains(status))?,
+            reason: third.to_owned(),
+        });
+    }
+    let is_token = !first.is_empty() && first.bytes().all(|byte| byte.is_ascii_uppercase());
+    (is_token && third.starts_with("HTTP/1.")).then(|| StartLine::Request {
+        method: first.to_owned(),
+        uri: second.to_owned(),
+        version: third.to_owned(),
+    })
+}
+
+/// Lines of `data` including their line ending; the last one may be unterminated.
+fn lines(data: &[u8]) -> impl Iterator<Item = &[u8]> {
+    data.split_inclusive(|byte| *byte == b'\n')
+}
+
+fn line_len(data: &[u8]) -> Option<usize> {
+    data.iter().position(|byte| *byte == b'\n').map(|ix| ix + 1)
+}
+
+fn trim_line_end(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
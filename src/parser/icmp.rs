@@ -0,0 +1,158 @@
+use crate::flow::{Conversation, Endpoint, FlowKey, IPAddress, Protocol};
+use crate::layers::PacketContext;
+use etherparse::{IpNumber, LaxIpSlice};
+use std::net::IpAddr;
+
+/// Length of the ICMP and ICMPv6 header, including the type-specific word.
+pub const ICMP_HEADER_LEN: usize = 8;
+
+/// Sets the protocol, conversation and tag of an ICMP (`v6` false) or ICMPv6 message.
+/// `header` is its 8 byte header and `payload` what follows; for error messages that
+/// is the start of the offending packet, whose TCP or UDP flow becomes the context's
+/// `quoted_flow`.
+pub fn tag_icmp(
+    v6: bool,
+    header: &[u8; ICMP_HEADER_LEN],
+    payload: &[u8],
+    context: &mut PacketContext,
+) {
+    let (icmp_type, code) = (header[0], header[1]);
+    context.protocol = Some(if v6 { Protocol::ICMPV6 } else { Protocol::ICMP });
+    context.conversation = if is_echo(v6, icmp_type) {
+        Conversation::Echo(u16::from_be_bytes([header[4], header[5]]))
+    } else {
+        Conversation::Message { icmp_type, code }
+    };
+    let prefix = if v6 { "ICMPv6" } else { "ICMP" };
+    context
+        .tags
+        .push(format!("{prefix} {}", message_name(v6, icmp_type, code)));
+    if is_error(v6, icmp_type) {
+        context.quoted_flow = quoted_flow(payload);
+    }
+}
+
+/// Sets the protocol of an IP payload that isn't TCP, UDP or ICMP, with the SPI of ESP
+/// and AH packets as their conversation.
+pub fn tag_ip_payload(protocol: u8, payload: &[u8], context: &mut PacketContext) {
+    let protocol = Protocol::Other(protocol);
+    context.protocol = Some(protocol);
+    // The SPI leads the ESP header; AH puts it after its next header, length and
+    // reserved fields.
+    let spi = match protocol {
+        Protocol::ESP => payload.get(0..4),
+        Protocol::AH => payload.get(4..8),
+        _ => None,
+    };
+    if let Some(spi) = spi {
+        context.conversation =
+            Conversation::SecurityAssociation(u32::from_be_bytes([spi[0], spi[1], spi[2], spi[3]]));
+    }
+}
+
+/// Echo requests and replies, which share their identifier.
+pub fn is_echo(v6: bool, icmp_type: u8) -> bool {
+    if v6 {
+        matches!(icmp_type, 128 | 129)
+    } else {
+        matches!(icmp_type, 0 | 8)
+    }
+}
+
+/// Messages quoting the packet that caused them.
+fn is_error(v6: bool, icmp_type: u8) -> bool {
+    if v6 {
+        matches!(icmp_type, 1..=4)
+    } else {
+        matches!(icmp_type, 3 | 4 | 5 | 11 | 12)
+    }
+}
+
+/// Name of a message, with its code where the type has well-known ones, e.g.
+/// `Destination Unreachable (Port Unreachable)`.
+pub fn message_name(v6: bool, icmp_type: u8, code: u8) -> String {
+    let Some(name) = type_name(v6, icmp_type) else {
+        return format!("Type {icmp_type} Code {code}");
+    };
+    match code_name(v6, icmp_type, code) {
+        Some(code) => format!("{name} ({code})"),
+        None => name.to_string(),
+    }
+}
+
+fn type_name(v6: bool, icmp_type: u8) -> Option<&'static str> {
+    let name = match (v6, icmp_type) {
+        (false, 0) | (true, 129) => "Echo Reply",
+        (false, 8) | (true, 128) => "Echo Request",
+        (false, 3) | (true, 1) => "Destination Unreachable",
+        (false, 4) => "Source Quench",
+        (false, 5) => "Redirect",
+        (false, 9) => "Router Advertisement",
+        (false, 10) => "Router Solicitation",
+        (false, 11) | (true, 3) => "Time Exceeded",
+        (false, 12) | (true, 4) => "Parameter Problem",
+        (false, 13) => "Timestamp Request",
+        (false, 14) => "Timestamp Reply",
+        (true, 2) => "Packet Too Big",
+        (true, 130) => "Multicast Listener Query",
+        (true, 131) => "Multicast Listener Report",
+        (true, 132) => "Multicast Listener Done",
+        (true, 133) => "Router Solicitation",
+        (true, 134) => "Router Advertisement",
+        (true, 135) => "Neighbor Solicitation",
+        (true, 136) => "Neighbor Advertisement",
+        (true, 137) => "Redirect",
+        (true, 143) => "Multicast Listener Report v2",
+        _ => return None,
+    };
+    Some(name)
+}
+
+fn code_name(v6: bool, icmp_type: u8, code: u8) -> Option<&'static str> {
+    let name = match (v6, icmp_type, code) {
+        (false, 3, 0) => "Network Unreachable",
+        (false, 3, 1) => "Host Unreachable",
+        (false, 3, 2) => "Protocol Unreachable",
+        (false, 3, 3) | (true, 1, 4) => "Port Unreachable",
+        (false, 3, 4) => "Fragmentation Needed",
+        (false, 3, 9 | 10 | 13) | (true, 1, 1) => "Administratively Prohibited",
+        (true, 1, 0) => "No Route",
+        (true, 1, 3) => "Address Unreachable",
+        (false, 11, 0) | (true, 3, 0) => "TTL Exceeded",
+        (false, 11, 1) | (true, 3, 1) => "Reassembly Time Exceeded",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Flow of the TCP or UDP packet quoted by an ICMP error, from its IP header and the
+/// ports leading its transport header.
+fn quoted_flow(quoted: &[u8]) -> Option<FlowKey> {
+    let (ip, _) = LaxIpSlice::from_slice(quoted).ok()?;
+    let payload = ip.payload();
+    let protocol = match payload.ip_number {
+        IpNumber::TCP => Protocol::TCP,
+        IpNumber::UDP => Protocol::UDP,
+        _ => return None,
+    };
+    if payload.fragmented {
+        return None;
+    }
+    let ports = payload.payload.get(..4)?;
+    let source = Endpoint::new(
+        address(ip.source_addr()),
+        u16::from_be_bytes([ports[0], ports[1]]),
+    );
+    let destination = Endpoint::new(
+        address(ip.destination_addr()),
+        u16::from_be_bytes([ports[2], ports[3]]),
+    );
+    Some(FlowKey::from_endpoints(source, destination, protocol))
+}
+
+fn address(ip: IpAddr) -> IPAddress {
+    match ip {
+        IpAddr::V4(ip) => IPAddress::V4(ip.octets()),
+        IpAddr::V6(ip) => IPAddress::V6(ip.octets()),
+    }
+}
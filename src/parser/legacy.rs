@@ -0,0 +1,87 @@
+use std::io::{self, Read};
+
+/// Widths of the global header's fields: magic number, major and minor version, time
+/// zone, accuracy, snapshot length and linktype.
+const GLOBAL_HEADER: &[usize] = &[4, 2, 2, 4, 4, 4, 4];
+/// Widths of a record header's fields in the modified format: timestamp, captured and
+/// original length, interface index, protocol, packet type and padding.
+const RECORD_HEADER: &[usize] = &[4, 4, 4, 4, 4, 2, 1, 1];
+const MAX_HEADER_LEN: usize = 24;
+const CAPLEN_OFFSET: usize = 8;
+
+/// Reads a big-endian capture in the "modified" pcap format (magic `0xa1b2cd34`) as its
+/// little-endian form, the only byte order pcap-parser reads that format in. Header
+/// fields are swapped in place, so offsets into what is read are offsets into the file.
+pub struct ModifiedBigEndian<R> {
+    inner: R,
+    /// Field widths of the header being read; the global header comes first.
+    layout: &'static [usize],
+    header: [u8; MAX_HEADER_LEN],
+    /// Header bytes read so far, and how many of them were returned once swapped.
+    filled: usize,
+    returned: usize,
+    /// Packet bytes to pass through before the next record header.
+    data_left: usize,
+}
+
+impl<R> ModifiedBigEndian<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            layout: GLOBAL_HEADER,
+            header: [0; MAX_HEADER_LEN],
+            filled: 0,
+            returned: 0,
+            data_left: 0,
+        }
+    }
+
+    fn swap_header(&mut self) {
+        let mut start = 0;
+        for width in self.layout {
+            self.header[start..start + width].reverse();
+            start += width;
+        }
+    }
+}
+
+impl<R: Read> Read for ModifiedBigEndian<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.data_left > 0 {
+            let len = buf.len().min(self.data_left);
+            let read = self.inner.read(&mut buf[..len])?;
+            self.data_left -= read;
+            return Ok(read);
+        }
+
+        let header_len: usize = self.layout.iter().sum();
+        while self.filled < header_len {
+            let read = self.inner.read(&mut self.header[self.filled..header_len])?;
+            if read == 0 {
+                // The rest of the header isn't written yet, or the capture ends here.
+                return Ok(0);
+            }
+            self.filled += read;
+            if self.filled == header_len {
+                self.swap_header();
+            }
+        }
+        let len = buf.len().min(header_len - self.returned);
+        buf[..len].copy_from_slice(&self.header[self.returned..self.returned + len]);
+        self.returned += len;
+        if self.returned == header_len {
+            if self.layout == RECORD_HEADER {
+                let caplen = &self.header[CAPLEN_OFFSET..CAPLEN_OFFSET + 4];
+                self.data_left =
+                    u32::from_le_bytes([caplen[0], caplen[1], caplen[2], caplen[3]]) as usize;
+            }
+            self.layout = RECORD_HEADER;
+            self.filled = 0;
+            self.returned = 0;
+        }
+        Ok(len)
+    }
+}
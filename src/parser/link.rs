@@ -0,0 +1,89 @@
+use etherparse::{EtherType, PacketHeaders};
+use pcap_parser::Linktype;
+
+const LINUX_SLL_HEADER_LEN: usize = 16;
+const LINUX_SLL2_HEADER_LEN: usize = 20;
+const NULL_HEADER_LEN: usize = 4;
+
+// BSD address families used by DLT_NULL / DLT_LOOP. AF_INET6 differs per OS.
+const AF_INET: u32 = 2;
+const AF_INET6_LINUX: u32 = 10;
+const AF_INET6_NETBSD: u32 = 24;
+const AF_INET6_FREEBSD: u32 = 28;
+const AF_INET6_DARWIN: u32 = 30;
+
+/// Returns true when `linktype` can be handed to [`parse_link_headers`].
+pub fn is_supported(linktype: Linktype) -> bool {
+    matches!(
+        linktype,
+        Linktype::ETHERNET
+            | Linktype::LINUX_SLL
+            | Linktype::LINUX_SLL2
+            | Linktype::RAW
+            | Linktype::IPV4
+            | Linktype::IPV6
+            | Linktype::NULL
+            | Linktype::LOOP
+    )
+}
+
+/// Strips the link-layer framing for `linktype` and decodes the remaining headers.
+pub fn parse_link_headers(packet: &[u8], linktype: Linktype) -> Result<PacketHeaders<'_>, String> {
+    match linktype {
+        Linktype::ETHERNET => PacketHeaders::from_ethernet_slice(packet)
+            .map_err(|err| format!("header parse error: {err:?}")),
+        Linktype::LINUX_SLL => {
+            let header = packet
+                .get(..LINUX_SLL_HEADER_LEN)
+                .ok_or_else(|| "truncated Linux SLL header".to_string())?;
+            let protocol = u16::from_be_bytes([header[14], header[15]]);
+            from_ether_type(EtherType(protocol), &packet[LINUX_SLL_HEADER_LEN..])
+        }
+        Linktype::LINUX_SLL2 => {
+            let header = packet
+                .get(..LINUX_SLL2_HEADER_LEN)
+                .ok_or_else(|| "truncated Linux SLL2 header".to_string())?;
+            let protocol = u16::from_be_bytes([header[0], header[1]]);
+            from_ether_type(EtherType(protocol), &packet[LINUX_SLL2_HEADER_LEN..])
+        }
+        Linktype::RAW | Linktype::IPV4 | Linktype::IPV6 => PacketHeaders::from_ip_slice(packet)
+            .map_err(|err| format!("header parse error: {err:?}")),
+        Linktype::NULL | Linktype::LOOP => {
+            let header: [u8; NULL_HEADER_LEN] = packet
+                .get(..NULL_HEADER_LEN)
+                .and_then(|bytes| bytes.try_into().ok())
+                .ok_or_else(|| "truncated loopback header".to_string())?;
+            let family = loopback_family(header, linktype);
+            let ether_type = match family {
+                AF_INET => EtherType::IPV4,
+                AF_INET6_LINUX | AF_INET6_NETBSD | AF_INET6_FREEBSD | AF_INET6_DARWIN => {
+                    EtherType::IPV6
+                }
+                other => return Err(format!("unsupported loopback address family {other}")),
+            };
+            from_ether_type(ether_type, &packet[NULL_HEADER_LEN..])
+        }
+        other => Err(format!("unsupported linktype {other:?}")),
+    }
+}
+
+fn from_ether_type(ether_type: EtherType, payload: &[u8]) -> Result<PacketHeaders<'_>, String> {
+    PacketHeaders::from_ether_type(ether_type, payload)
+        .map_err(|err| format!("header parse error: {err:?}"))
+}
+
+/// DLT_LOOP stores the family in network byte order, DLT_NULL in the capturing
+/// host's byte order. Family values are small, so a value that does not fit in
+/// 16 bits was written by a host with the opposite endianness.
+fn loopback_family(header: [u8; NULL_HEADER_LEN], linktype: Linktype) -> u32 {
+    if linktype == Linktype::LOOP {
+        return u32::from_be_bytes(header);
+    }
+
+    let family = u32::from_le_bytes(header);
+    if family > 0xFFFF {
+        family.swap_bytes()
+    } else {
+        family
+    }
+}
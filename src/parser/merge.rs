@@ -0,0 +1,115 @@
+use super::pipeline::{CaptureEvent, CaptureItem, RawPacket};
+use anyhow::Result;
+use std::sync::mpsc::{self, Receiver};
+
+/// Items each capture may read ahead of the merge.
+const QUEUE_SIZE: usize = 1024;
+
+/// Reads several captures at once, each `read` on a thread of its own, and emits their
+/// items as those of a single capture with packets in timestamp order. Packets with
+/// the same timestamp keep the order of `readers`. Interfaces are renumbered in the
+/// order they are described across all captures, and packets record the position of
+/// their reader as [`CaptureRecord::source`](super::packets::CaptureRecord::source).
+/// Returns the first error of a reader, once all of them are done.
+pub fn merge<R>(readers: Vec<R>, emit: &mut dyn FnMut(CaptureItem)) -> Result<()>
+where
+    R: FnOnce(&mut dyn FnMut(CaptureItem)) -> Result<()> + Send,
+{
+    std::thread::scope(|scope| {
+        let mut threads = Vec::new();
+        let mut inputs: Vec<Input> = readers
+            .into_iter()
+            .map(|read| {
+                let (tx, rx) = mpsc::sync_channel(QUEUE_SIZE);
+                threads.push(scope.spawn(move || {
+                    // Only fails once the merge is gone, i.e. it panicked, which the
+                    // scope reports when it ends.
+                    read(&mut |item| {
+                        let _ = tx.send(item);
+                    })
+                }));
+                Input {
+                    items: rx,
+                    head: None,
+                    interfaces: Vec::new(),
+                }
+            })
+            .collect();
+
+        let mut interface_count = 0;
+        loop {
+            for (source, input) in inputs.iter_mut().enumerate() {
+                input.advance(source, &mut interface_count, emit);
+            }
+            let earliest = inputs
+                .iter_mut()
+                .filter(|input| input.head.is_some())
+                .min_by(|a, b| a.timestamp().total_cmp(&b.timestamp()));
+            let Some(input) = earliest else {
+                break;
+            };
+            if let Some(packet) = input.head.take() {
+                emit(CaptureItem::Packet(packet));
+            }
+        }
+        threads.into_iter().try_for_each(|thread| {
+            thread
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+        })
+    })
+}
+
+/// One of the merged captures.
+struct Input {
+    items: Receiver<CaptureItem>,
+    /// The capture's next packet, which waits for the earlier packets of the others.
+    head: Option<RawPacket>,
+    /// Merged index of each interface the capture described so far.
+    interfaces: Vec<usize>,
+}
+
+impl Input {
+    /// Receives the capture's items up to its next packet, emitting the events before
+    /// it right away. The head stays empty once the capture is done.
+    fn advance(
+        &mut self,
+        source: usize,
+        interface_count: &mut usize,
+        emit: &mut dyn FnMut(CaptureItem),
+    ) {
+        while self.head.is_none() {
+            let Ok(item) = self.items.recv() else {
+                return;
+            };
+            match item {
+                CaptureItem::Packet(mut packet) => {
+                    packet.record.source = source;
+                    packet.record.interface = self.interfaces[packet.record.interface];
+                    self.head = Some(packet);
+                }
+                CaptureItem::Event(CaptureEvent::Interface(interface)) => {
+                    self.interfaces.push(*interface_count);
+                    *interface_count += 1;
+                    emit(CaptureItem::Event(CaptureEvent::Interface(interface)));
+                }
+                CaptureItem::Event(CaptureEvent::Unsupported {
+                    interface,
+                    linktype,
+                }) => emit(CaptureItem::Event(CaptureEvent::Unsupported {
+                    interface: self.interfaces[interface],
+                    linktype,
+                })),
+                // One capture waiting for input says nothing about the others.
+                CaptureItem::Event(CaptureEvent::Waiting) => {}
+                CaptureItem::Event(event) => emit(CaptureItem::Event(event)),
+            }
+        }
+    }
+
+    fn timestamp(&self) -> f64 {
+        self.head
+            .as_ref()
+            .map_or(f64::INFINITY, |packet| packet.record.timestamp)
+    }
+}
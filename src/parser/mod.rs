@@ -1,11 +1,27 @@
 pub mod decoder;
+pub mod defrag;
+pub mod dissect;
 pub mod dns;
+pub mod follow;
+pub mod http;
+pub mod icmp;
+pub mod legacy;
+pub mod link;
+pub mod merge;
 pub mod packets;
+pub mod pipeline;
+pub mod reassembly;
 pub mod reader;
 pub mod state;
 pub mod tcp;
+pub mod tcp_analysis;
+pub mod tunnel;
 
 #[cfg(test)]
 mod tests;
 
-pub use reader::parse_pcap;
+pub use reader::{
+    Cancelled, ParseOptions, STDIN_PATH, expand_paths, is_stream, parse_capture,
+    parse_capture_incremental, parse_capture_with, parse_capture_with_workers, parse_captures,
+    parse_pcap, parse_stream,
+};
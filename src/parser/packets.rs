@@ -1,50 +1,90 @@
-use crate::flow::{Endpoint, Flow, FlowKey, IPAddress, Protocol};
+use super::dns;
+use crate::flow::storage::PacketData;
+use crate::flow::{Endpoint, Flow, FlowKey, IPAddress, Protocol, TcpAnalysis};
 use crate::layers::PacketContext;
 use std::collections::HashMap;
 
+/// Where a packet sits in the capture: its interface and timestamp as recorded.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CaptureRecord {
+    pub interface: usize,
+    /// Capture file the packet was read from, when several are merged.
+    pub source: usize,
+    pub timestamp: f64,
+    /// Raw timestamp in units of the interface's resolution.
+    pub timestamp_ticks: u64,
+    pub original_length: u32,
+}
+
+/// Adds the packet decoded into `context` to its flow and returns the flow's key, or
+/// `None` for packets that don't belong to a flow. `data` locates its captured bytes.
+/// ICMP errors join the TCP or UDP flow they quote when it is known.
 pub fn add_packet(
-    epb_packet_data: &[u8],
+    data: PacketData,
     context: PacketContext,
-    timestamp: f64,
+    record: CaptureRecord,
     flows: &mut HashMap<FlowKey, Flow>,
     packet_count: &mut usize,
-) {
-    if let Some((src_ip, dst_ip, src_port, dst_port, protocol)) = unpack_context(&context) {
-        let src_ep = Endpoint::new(src_ip, src_port);
-        let dst_ep = Endpoint::new(dst_ip, dst_port);
-        let key = FlowKey::from_endpoints(src_ep, dst_ep, protocol);
-        let packet_length = u16::try_from(epb_packet_data.len()).unwrap_or(u16::MAX);
+) -> Option<FlowKey> {
+    let timestamp = record.timestamp;
+    let (src_ip, dst_ip, protocol) = unpack_context(&context)?;
+    let (src_port, dst_port) = (context.src_port, context.dst_port);
+    let src_ep = Endpoint::new(src_ip, src_port.unwrap_or(0));
+    let dst_ep = Endpoint::new(dst_ip, dst_port.unwrap_or(0));
+    let key = match context.quoted_flow {
+        Some(quoted) if flows.contains_key(&quoted) => quoted,
+        _ => FlowKey::new(src_ep, dst_ep, protocol, context.conversation),
+    };
+    let packet_length = u16::try_from(data.len()).unwrap_or(u16::MAX);
 
-        let packet = crate::flow::Packet {
-            timestamp,
-            src_ip,
-            dst_ip,
-            src_port: Some(src_port),
-            dst_port: Some(dst_port),
-            length: packet_length,
-            data: epb_packet_data.to_vec(),
-            tags: context.tags,
-        };
+    let packet = crate::flow::Packet {
+        timestamp,
+        src_ip,
+        dst_ip,
+        src_port,
+        dst_port,
+        length: packet_length,
+        data,
+        tags: context.tags,
+        interface: record.interface,
+        source: record.source,
+        timestamp_ticks: record.timestamp_ticks,
+        original_length: record.original_length,
+        encapsulation: context.encapsulation,
+    };
 
-        let flow = flows.entry(key).or_insert_with(|| Flow {
-            timestamp,
-            protocol,
-            source: src_ep,
-            destination: dst_ep,
-            packets: Vec::new(),
-        });
+    let flow = flows.entry(key).or_insert_with(|| Flow {
+        timestamp,
+        protocol,
+        source: src_ep,
+        destination: dst_ep,
+        conversation: context.conversation,
+        packets: Vec::new(),
+        analysis: TcpAnalysis::default(),
+        tls: None,
+        http: Vec::new(),
+        dns: Vec::new(),
+    });
 
-        if protocol == Protocol::TCP && context.is_syn && !context.is_ack {
-            flow.source = src_ep;
-            flow.destination = dst_ep;
-        }
+    if protocol == Protocol::TCP && context.is_syn && !context.is_ack {
+        flow.source = src_ep;
+        flow.destination = dst_ep;
+    }
 
-        flow.packets.push(packet);
-        *packet_count += 1;
+    for flag in &context.tcp_analysis {
+        flow.analysis.record(*flag);
+    }
+    for message in context.dns_messages {
+        dns::record_message(&mut flow.dns, message, timestamp);
     }
+    flow.packets.push(packet);
+    *packet_count += 1;
+    Some(key)
 }
 
-fn unpack_context(context: &PacketContext) -> Option<(IPAddress, IPAddress, u16, u16, Protocol)> {
+/// Addresses and protocol of a packet that belongs to a flow: TCP and UDP packets need
+/// both ports, packets of other protocols have none.
+fn unpack_context(context: &PacketContext) -> Option<(IPAddress, IPAddress, Protocol)> {
     match (
         context.src_ip,
         context.dst_ip,
@@ -52,8 +92,11 @@ fn unpack_context(context: &PacketContext) -> Option<(IPAddress, IPAddress, u16,
         context.dst_port,
         context.protocol,
     ) {
-        (Some(src_ip), Some(dst_ip), Some(src_port), Some(dst_port), Some(protocol)) => {
-            Some((src_ip, dst_ip, src_port, dst_port, protocol))
+        (Some(src_ip), Some(dst_ip), Some(_), Some(_), Some(protocol)) => {
+            Some((src_ip, dst_ip, protocol))
+        }
+        (Some(src_ip), Some(dst_ip), None, None, Some(protocol)) if !protocol.has_ports() => {
+            Some((src_ip, dst_ip, protocol))
         }
         _ => None,
     }
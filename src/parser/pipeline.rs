@@ -0,0 +1,483 @@
+use super::decoder::{decode_link_headers, decode_reassembled};
+use super::defrag::{Datagram, FragmentKey};
+use super::packets::{self, CaptureRecord};
+use super::state::{self, CaptureInterface, HeldFragment, ParseState};
+use super::{dns, tcp};
+use crate::flow::storage::PacketData;
+use crate::flow::{Flow, FlowKey, IPAddress, Packet};
+use crate::layers::dissector::DissectorRegistry;
+use crate::layers::tls::TlsParser;
+use crate::layers::{IpFragment, PacketContext};
+use pcap_parser::Linktype;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::num::NonZeroUsize;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// Items handed from the reader to a decode worker at once.
+const BATCH_SIZE: usize = 256;
+
+/// What the reader extracts from a capture, in file order.
+pub enum CaptureItem {
+    Packet(RawPacket),
+    Event(CaptureEvent),
+}
+
+/// A capture item other than a packet, which the merge stage applies as it comes.
+pub enum CaptureEvent {
+    Interface(CaptureInterface),
+    /// A packet on an interface whose linktype can't be decoded.
+    Unsupported {
+        interface: usize,
+        linktype: Linktype,
+    },
+    NameResolutions(Vec<(IPAddress, String)>),
+    /// NSS key log lines from a pcapng Decryption Secrets Block.
+    TlsSecrets(String),
+    /// The reader is about to wait for more input, so what was merged so far should
+    /// be published now rather than once the next packet arrives.
+    Waiting,
+}
+
+/// A packet copied out of the reader's buffer, waiting to be decoded.
+pub struct RawPacket {
+    /// Shared with `stored` when the packet is kept in memory.
+    pub data: Arc<[u8]>,
+    pub linktype: Linktype,
+    pub record: CaptureRecord,
+    /// Where the packet's bytes stay available once `data` is dropped.
+    pub stored: PacketData,
+}
+
+/// A capture item after the parallel stage: packets are decoded and dissected.
+enum DecodedItem {
+    Packet {
+        /// `None` when the headers couldn't be decoded.
+        context: Option<Box<PacketContext>>,
+        /// The packet's bytes, kept only when its TCP payload is still to be reassembled.
+        data: Option<Arc<[u8]>>,
+        record: CaptureRecord,
+        stored: PacketData,
+    },
+    Event(CaptureEvent),
+}
+
+/// Flows added or extended since the previous batch, published while a capture is
+/// still being parsed. Each flow carries only the packets appended since then; its
+/// other fields are current, except TLS and HTTP which are filled in at the end.
+#[derive(Debug, Clone, Default)]
+pub struct FlowBatch {
+    pub flows: HashMap<FlowKey, Flow>,
+    pub start_timestamp: Option<f64>,
+    pub name_resolutions: HashMap<IPAddress, Vec<String>>,
+    pub interfaces: Vec<CaptureInterface>,
+    pub sources: Vec<String>,
+}
+
+impl FlowBatch {
+    /// Folds `later`, the batch published after this one, into this one.
+    pub fn extend(&mut self, later: FlowBatch) {
+        merge_flows(&mut self.flows, later.flows);
+        self.start_timestamp = later.start_timestamp;
+        self.name_resolutions = later.name_resolutions;
+        self.interfaces = later.interfaces;
+        self.sources = later.sources;
+    }
+}
+
+/// Applies the flows of a [`FlowBatch`] to `flows`, appending the new packets of the
+/// flows already there.
+pub fn merge_flows(flows: &mut HashMap<FlowKey, Flow>, updates: HashMap<FlowKey, Flow>) {
+    for (key, mut update) in updates {
+        match flows.entry(key) {
+            Entry::Occupied(mut entry) => {
+                let flow = entry.get_mut();
+                let mut packets = std::mem::take(&mut flow.packets);
+                packets.append(&mut update.packets);
+                *flow = Flow { packets, ..update };
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(update);
+            }
+        }
+    }
+}
+
+/// Receives [`FlowBatch`]es from the merge stage, at most once per `interval`.
+pub struct BatchSink<'a> {
+    pub interval: Duration,
+    pub send: &'a mut (dyn FnMut(FlowBatch) + Send),
+}
+
+/// Decode threads to use by default: one per available core.
+pub fn default_workers() -> usize {
+    std::thread::available_parallelism().map_or(1, NonZeroUsize::get)
+}
+
+/// Builds the parse state from the items `read` emits. Header decoding and
+/// dissection, which depend on nothing but the packet, run on `workers` threads;
+/// everything stateful (reassembly, TLS, aggregation) is applied afterwards in
+/// capture order, so the result doesn't depend on the number of workers. With a
+/// single worker everything runs on the calling thread. Flows are published to `sink`
+/// as they grow. `sources` names the capture files `read` reads from, which packets
+/// refer to by [`CaptureRecord::source`].
+pub fn run<R>(
+    registry: &DissectorRegistry,
+    workers: usize,
+    sink: Option<BatchSink<'_>>,
+    sources: Vec<String>,
+    read: R,
+) -> ParseState
+where
+    R: FnOnce(&mut dyn FnMut(CaptureItem)),
+{
+    let mut merger = Merger::new(registry, sink, sources);
+    if workers <= 1 {
+        read(&mut |item| merger.apply(decode(item, registry)));
+        return merger.finish();
+    }
+
+    let (batch_tx, batch_rx) = mpsc::sync_channel::<(usize, Vec<CaptureItem>)>(workers * 2);
+    let (decoded_tx, decoded_rx) = mpsc::sync_channel::<(usize, Vec<DecodedItem>)>(workers * 2);
+    let batch_rx = Mutex::new(batch_rx);
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            let batch_rx = &batch_rx;
+            let decoded_tx = decoded_tx.clone();
+            scope.spawn(move || {
+                loop {
+                    let next = batch_rx.lock().expect("batch queue poisoned").recv();
+                    let Ok((index, items)) = next else {
+                        break;
+                    };
+                    let decoded = items
+                        .into_iter()
+                        .map(|item| decode(item, registry))
+                        .collect();
+                    if decoded_tx.send((index, decoded)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(decoded_tx);
+
+        // Batches finish out of order; hold them back until their predecessors are in.
+        let merge = scope.spawn(move || {
+            let mut pending = BTreeMap::new();
+            let mut next = 0;
+            for (index, batch) in decoded_rx {
+                pending.insert(index, batch);
+                while let Some(batch) = pending.remove(&next) {
+                    for item in batch {
+                        merger.apply(item);
+                    }
+                    next += 1;
+                }
+            }
+            merger.finish()
+        });
+
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        let mut index = 0;
+        let mut send = |batch: Vec<CaptureItem>| {
+            // Only fails once the workers are gone, i.e. one of them panicked, which
+            // the scope reports when it ends.
+            let _ = batch_tx.send((index, batch));
+            index += 1;
+        };
+        read(&mut |item| {
+            // Packets read before a wait shouldn't sit in a half-full batch meanwhile.
+            let waiting = matches!(item, CaptureItem::Event(CaptureEvent::Waiting));
+            batch.push(item);
+            if batch.len() == BATCH_SIZE || waiting {
+                send(std::mem::replace(
+                    &mut batch,
+                    Vec::with_capacity(BATCH_SIZE),
+                ));
+            }
+        });
+        if !batch.is_empty() {
+            send(batch);
+        }
+        drop(batch_tx);
+        merge.join().expect("merge stage panicked")
+    })
+}
+
+/// The parallel stage: decodes the headers of a packet and runs its dissectors.
+fn decode(item: CaptureItem, registry: &DissectorRegistry) -> DecodedItem {
+    let packet = match item {
+        CaptureItem::Packet(packet) => packet,
+        CaptureItem::Event(event) => return DecodedItem::Event(event),
+    };
+    let context = decode_link_headers(&packet.data, packet.linktype)
+        .ok()
+        .map(|mut context| {
+            registry.dissect(&mut context, &packet.data);
+            Box::new(context)
+        });
+    let data = context
+        .as_ref()
+        .and_then(|context| context.tcp_segment.as_ref())
+        .filter(|segment| !segment.payload.is_empty())
+        .map(|_| packet.data);
+    DecodedItem::Packet {
+        context,
+        data,
+        record: packet.record,
+        stored: packet.stored,
+    }
+}
+
+/// The sequential stage, applying decoded items to the parse state in capture order.
+struct Merger<'a, 's> {
+    registry: &'a DissectorRegistry,
+    tls_parser: TlsParser,
+    state: ParseState,
+    sink: Option<BatchSink<'s>>,
+    last_batch: Instant,
+    /// Flows that gained packets since the last batch.
+    updated: HashSet<FlowKey>,
+    /// Packets of each flow already published.
+    published: HashMap<FlowKey, usize>,
+}
+
+impl<'a, 's> Merger<'a, 's> {
+    fn new(
+        registry: &'a DissectorRegistry,
+        sink: Option<BatchSink<'s>>,
+        sources: Vec<String>,
+    ) -> Self {
+        Self {
+            registry,
+            tls_parser: TlsParser::new(registry.tls_keylog().clone()),
+            state: ParseState {
+                sources,
+                ..ParseState::default()
+            },
+            sink,
+            last_batch: Instant::now(),
+            updated: HashSet::new(),
+            published: HashMap::new(),
+        }
+    }
+
+    fn apply(&mut self, item: DecodedItem) {
+        let state = &mut self.state;
+        match item {
+            DecodedItem::Packet {
+                context,
+                data,
+                record,
+                stored,
+            } => {
+                state::update_first_timestamp(&mut state.first_packet_ts, record.timestamp);
+                for datagram in state.ip_fragments.expire(record.timestamp) {
+                    self.file_incomplete(datagram);
+                }
+                let Some(context) = context else {
+                    return;
+                };
+                let mut packet = HeldFragment {
+                    context,
+                    record,
+                    stored,
+                };
+                match packet.context.ip_fragment.take() {
+                    Some(fragment) => self.file_fragment(&fragment, packet),
+                    None => self.file_packet(packet, data.as_deref().unwrap_or_default()),
+                }
+            }
+            DecodedItem::Event(CaptureEvent::Interface(interface)) => {
+                state.interfaces.push(interface);
+            }
+            DecodedItem::Event(CaptureEvent::Unsupported {
+                interface,
+                linktype,
+            }) => state.record_unsupported_linktype(interface, linktype),
+            DecodedItem::Event(CaptureEvent::NameResolutions(records)) => {
+                for (ip, name) in records {
+                    dns::add_name_resolution(ip, name, &mut state.name_resolutions);
+                }
+            }
+            DecodedItem::Event(CaptureEvent::TlsSecrets(keylog)) => {
+                let secrets = self.tls_parser.add_secrets(&keylog);
+                debug!(secrets, "Added TLS secrets from DecryptionSecrets block");
+            }
+            DecodedItem::Event(CaptureEvent::Waiting) => {
+                if !self.updated.is_empty() {
+                    self.publish();
+                }
+            }
+        }
+    }
+
+    /// Runs the stateful stages on a decoded packet and adds it to its flow. `bytes`
+    /// holds what the payload positions in the packet's context point into.
+    fn file_packet(&mut self, packet: HeldFragment, bytes: &[u8]) {
+        let HeldFragment {
+            mut context,
+            record,
+            stored,
+        } = packet;
+        let state = &mut self.state;
+        tcp::handle_tcp_segment(
+            &mut context,
+            bytes,
+            record.timestamp,
+            &self.tls_parser,
+            self.registry,
+            state,
+        );
+        for (ip, name) in context.name_resolutions.drain(..) {
+            dns::add_name_resolution(ip, name, &mut state.name_resolutions);
+        }
+        let key = packets::add_packet(
+            stored,
+            *context,
+            record,
+            &mut state.flows,
+            &mut state.packet_count,
+        );
+        if let Some(key) = key
+            && self.sink.is_some()
+        {
+            self.updated.insert(key);
+            self.publish_if_due();
+        }
+    }
+
+    /// Holds a packet carrying an IP fragment until its datagram is complete. The
+    /// datagram is then decoded and dissected as the payload of its last packet, and
+    /// every fragment is added to the datagram's flow.
+    fn file_fragment(&mut self, fragment: &IpFragment, packet: HeldFragment) {
+        let (Some(src), Some(dst)) = (packet.context.src_ip, packet.context.dst_ip) else {
+            return;
+        };
+        let key = FragmentKey {
+            src,
+            dst,
+            id: fragment.id,
+            protocol: fragment.protocol,
+        };
+        let timestamp = packet.record.timestamp;
+        let Some(mut datagram) = self
+            .state
+            .ip_fragments
+            .push(key, fragment, timestamp, packet)
+        else {
+            return;
+        };
+        let Some(mut last) = datagram.packets.pop() else {
+            return;
+        };
+        decode_reassembled(key.protocol, datagram.payload(), &mut last.context);
+        self.registry.dissect(&mut last.context, datagram.payload());
+        last.context.tags.push("IP Reassembled".to_string());
+        if datagram.overlapping {
+            last.context.tags.push("IP Fragment Overlap".to_string());
+        }
+        for mut packet in std::mem::take(&mut datagram.packets) {
+            attribute_fragment(&mut packet.context, &last.context);
+            self.file_packet(packet, &[]);
+        }
+        self.file_packet(last, datagram.payload());
+    }
+
+    /// Adds the packets of a datagram that never completed to its flow, as far as the
+    /// fragments received reveal its transport header. Without it they have no flow.
+    fn file_incomplete(&mut self, datagram: Datagram<HeldFragment>) {
+        let mut transport = PacketContext::default();
+        decode_reassembled(datagram.key.protocol, datagram.payload(), &mut transport);
+        if transport.protocol.is_none() {
+            debug!(
+                packets = datagram.packets.len(),
+                "Dropping fragments of an incomplete datagram without transport header"
+            );
+            return;
+        }
+        for mut packet in datagram.packets {
+            attribute_fragment(&mut packet.context, &transport);
+            packet
+                .context
+                .tags
+                .push("IP Reassembly Timeout".to_string());
+            self.file_packet(packet, &[]);
+        }
+    }
+
+    /// Files the fragments still waiting for the rest of their datagram, once the
+    /// capture ended.
+    fn finish(mut self) -> ParseState {
+        for datagram in self.state.ip_fragments.drain() {
+            self.file_incomplete(datagram);
+        }
+        self.state
+    }
+
+    /// Sends the flows updated since the last batch once the sink's interval has passed.
+    fn publish_if_due(&mut self) {
+        if self
+            .sink
+            .as_ref()
+            .is_some_and(|sink| self.last_batch.elapsed() >= sink.interval)
+        {
+            self.publish();
+        }
+    }
+
+    /// Sends the flows updated since the last batch.
+    fn publish(&mut self) {
+        let Some(sink) = &mut self.sink else {
+            return;
+        };
+        self.last_batch = Instant::now();
+        let flows = self
+            .updated
+            .drain()
+            .filter_map(|key| {
+                let flow = self.state.flows.get(&key)?;
+                let published = self.published.entry(key).or_default();
+                let packets = flow.packets[*published..].to_vec();
+                *published = flow.packets.len();
+                Some((key, flow_with_packets(flow, packets)))
+            })
+            .collect();
+        (sink.send)(FlowBatch {
+            flows,
+            start_timestamp: self.state.first_packet_ts,
+            name_resolutions: self.state.name_resolutions.clone(),
+            interfaces: self.state.interfaces.clone(),
+            sources: self.state.sources.clone(),
+        });
+    }
+}
+
+/// Gives a fragment the flow of the datagram `transport` decoded.
+fn attribute_fragment(fragment: &mut PacketContext, transport: &PacketContext) {
+    fragment.src_port = transport.src_port;
+    fragment.dst_port = transport.dst_port;
+    fragment.protocol = transport.protocol;
+    fragment.conversation = transport.conversation;
+    fragment.quoted_flow = transport.quoted_flow;
+}
+
+/// `flow` with `packets` in place of its own, without cloning those.
+fn flow_with_packets(flow: &Flow, packets: Vec<Packet>) -> Flow {
+    Flow {
+        timestamp: flow.timestamp,
+        protocol: flow.protocol,
+        source: flow.source,
+        destination: flow.destination,
+        conversation: flow.conversation,
+        packets,
+        analysis: flow.analysis,
+        tls: flow.tls.clone(),
+        http: flow.http.clone(),
+        dns: flow.dns.clone(),
+    }
+}
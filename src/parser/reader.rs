@@ -1,17 +1,71 @@
-use super::decoder::decode_headers;
-use super::{dns, packets, state};
+use super::legacy::ModifiedBigEndian;
+use super::packets::CaptureRecord;
+use super::pipeline::{self, BatchSink, CaptureEvent, CaptureItem, RawPacket};
+use super::reassembly::StreamDirection;
+use super::state::CaptureInterface;
+use super::{dns, link, merge, state};
+use crate::flow::storage::{CaptureFile, PacketData};
 use crate::flow::{Flow, FlowKey, IPAddress};
-use crate::layers::tls::TlsParser;
-use anyhow::{Context, Result};
-use pcap_parser::pcapng::EnhancedPacketBlock;
+use crate::layers::dissector::DissectorRegistry;
+use anyhow::{Context, Result, anyhow};
+use pcap_parser::pcapng::{EnhancedPacketBlock, SecretsType};
 use pcap_parser::traits::{PcapNGPacketBlock, PcapReaderIterator};
 use pcap_parser::*;
 use std::collections::HashMap;
+use std::fmt;
 use std::fs::File;
-use std::time::Instant;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 use tracing::{debug, error, info, warn};
 
+const PCAPNG_MAGIC: [u8; 4] = [0x0a, 0x0d, 0x0d, 0x0a];
+/// Bytes from the start of an Enhanced Packet Block to its packet data.
+const EPB_DATA_OFFSET: u64 = 28;
+
+/// How long a followed capture waits at its end before looking for more packets.
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Capture path standing for standard input, e.g. `tcpdump -U -w - | wirecrab --tui -`.
+pub const STDIN_PATH: &str = "-";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CaptureFormat {
+    PcapNg,
+    Legacy,
+    /// The big-endian "modified" format, which pcap-parser only reads little-endian.
+    LegacyModifiedBigEndian,
+}
+
+/// Where the packets of a capture keep their bytes.
+enum Storage {
+    /// Offsets into the capture file, which is `size` bytes long.
+    File {
+        capture: Arc<CaptureFile>,
+        size: u64,
+    },
+    /// Copies, for streams that can't be read back.
+    Memory,
+}
+
+impl Storage {
+    /// The bytes `data` of a packet starting at `offset` in the capture.
+    fn packet_data(&self, offset: u64, data: &Arc<[u8]>) -> PacketData {
+        match self {
+            Storage::File { capture, .. } => PacketData::File {
+                file: capture.clone(),
+                offset,
+                length: data.len() as u32,
+            },
+            Storage::Memory => PacketData::Memory(data.clone()),
+        }
+    }
+}
+
 struct InterfaceDescription {
+    index: usize,
     linktype: Linktype,
     ts_resolution: u8,
     ts_offset: i64,
@@ -23,29 +77,424 @@ type ParseResult = (
     HashMap<IPAddress, Vec<String>>,
 );
 
-pub fn parse_pcap<F>(file_path: &std::path::Path, on_progress: F) -> Result<ParseResult>
+pub fn parse_pcap<F>(file_path: &Path, on_progress: F) -> Result<ParseResult>
+where
+    F: Fn(f32),
+{
+    let state = parse_capture(file_path, on_progress)?;
+    Ok((state.flows, state.first_packet_ts, state.name_resolutions))
+}
+
+/// Parses a capture and returns the full parse state, including bookkeeping such as
+/// packets skipped because of unsupported link types.
+pub fn parse_capture<F>(file_path: &Path, on_progress: F) -> Result<state::ParseState>
+where
+    F: Fn(f32),
+{
+    parse_capture_with(file_path, &DissectorRegistry::default(), on_progress)
+}
+
+/// Like [`parse_capture`], decoding application payload with the dissectors and
+/// decode-as rules of `registry`.
+pub fn parse_capture_with<F>(
+    file_path: &Path,
+    registry: &DissectorRegistry,
+    on_progress: F,
+) -> Result<state::ParseState>
+where
+    F: Fn(f32),
+{
+    parse_capture_with_workers(
+        file_path,
+        registry,
+        pipeline::default_workers(),
+        on_progress,
+    )
+}
+
+/// Like [`parse_capture_with`], decoding packets on `workers` threads. The result is
+/// the same for any number of workers.
+pub fn parse_capture_with_workers<F>(
+    file_path: &Path,
+    registry: &DissectorRegistry,
+    workers: usize,
+    on_progress: F,
+) -> Result<state::ParseState>
+where
+    F: Fn(f32),
+{
+    let options = ParseOptions {
+        workers,
+        ..ParseOptions::default()
+    };
+    parse_capture_incremental(file_path, registry, options, on_progress)
+}
+
+/// How [`parse_capture_incremental`] runs.
+pub struct ParseOptions<'a> {
+    /// Threads decoding packets. The result is the same for any number.
+    pub workers: usize,
+    /// Receives flows as they grow, so they can be shown before the parse is done.
+    pub sink: Option<BatchSink<'a>>,
+    /// Stops the parse once set, which then fails with [`Cancelled`].
+    pub cancel: Option<&'a AtomicBool>,
+    /// While set, the end of the capture is waited on for more packets to be written
+    /// instead of finishing the parse. Clearing it finishes at the current end.
+    pub follow: Option<&'a AtomicBool>,
+}
+
+impl Default for ParseOptions<'_> {
+    fn default() -> Self {
+        Self {
+            workers: pipeline::default_workers(),
+            sink: None,
+            cancel: None,
+            follow: None,
+        }
+    }
+}
+
+/// Error of a parse stopped through [`ParseOptions::cancel`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Parse cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Whether the capture at `path` is read as a stream: standard input, a FIFO or
+/// anything else that isn't a regular file.
+pub fn is_stream(path: &Path) -> bool {
+    path.as_os_str() == STDIN_PATH
+        || std::fs::metadata(path).is_ok_and(|metadata| !metadata.is_file())
+}
+
+/// Like [`parse_capture_with`], with the worker count, live flow publishing and
+/// cancellation of `options`. Streams (see [`is_stream`]) are parsed as by
+/// [`parse_stream`] and report no progress.
+pub fn parse_capture_incremental<F>(
+    file_path: &Path,
+    registry: &DissectorRegistry,
+    options: ParseOptions<'_>,
+    on_progress: F,
+) -> Result<state::ParseState>
+where
+    F: Fn(f32),
+{
+    parse_captures(&[file_path], registry, options, on_progress)
+}
+
+/// Parses several captures as one, e.g. captures of an incident taken on different
+/// hosts or the files of a ring buffer, merging their packets in timestamp order.
+/// Packets record the capture they come from as an index into
+/// [`ParseState::sources`](state::ParseState::sources). Only a single capture is
+/// followed as it grows; with several, [`ParseOptions::follow`] is ignored.
+pub fn parse_captures<P, F>(
+    file_paths: &[P],
+    registry: &DissectorRegistry,
+    options: ParseOptions<'_>,
+    on_progress: F,
+) -> Result<state::ParseState>
+where
+    P: AsRef<Path>,
+    F: Fn(f32),
+{
+    let captures = file_paths
+        .iter()
+        .map(|path| open_capture(path.as_ref()))
+        .collect::<Result<Vec<_>>>()?;
+    parse_opened(
+        captures,
+        source_names(file_paths),
+        registry,
+        options,
+        on_progress,
+    )
+}
+
+/// Parses a capture from `input` as its bytes arrive, e.g. from a pipe. Packets are
+/// merged one by one rather than decoded in parallel batches, so flows published to
+/// the sink of `options` are never behind the input, and keep a copy of their bytes.
+pub fn parse_stream<R>(
+    input: R,
+    registry: &DissectorRegistry,
+    options: ParseOptions<'_>,
+) -> Result<state::ParseState>
+where
+    R: Read + Send + 'static,
+{
+    let capture = open_input("stream", Box::new(input), Storage::Memory)?;
+    parse_opened(
+        vec![capture],
+        vec!["stream".to_string()],
+        registry,
+        options,
+        |_| {},
+    )
+}
+
+/// Expands the glob patterns among `patterns`, e.g. `ring-*.pcapng`, into the paths
+/// they match in alphabetical order. Paths of existing files are kept as they are even
+/// if they look like patterns, as is anything that doesn't; opening those reports
+/// whether they exist.
+pub fn expand_paths(patterns: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for pattern in patterns {
+        let text = pattern.to_string_lossy();
+        if pattern.exists() || !text.contains(['*', '?', '[']) {
+            paths.push(pattern.clone());
+            continue;
+        }
+        let matches = glob::glob(&text)
+            .with_context(|| format!("Invalid pattern {text}"))?
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("Failed to expand {text}"))?;
+        if matches.is_empty() {
+            anyhow::bail!("No capture files match {text}");
+        }
+        paths.extend(matches);
+    }
+    Ok(paths)
+}
+
+/// Names of the captures at `paths` for the source column: their file names, or their
+/// whole paths where file names repeat.
+fn source_names<P: AsRef<Path>>(paths: &[P]) -> Vec<String> {
+    let file_name = |path: &Path| {
+        if path.as_os_str() == STDIN_PATH {
+            return "stdin".to_string();
+        }
+        path.file_name().map_or_else(
+            || path.display().to_string(),
+            |name| name.to_string_lossy().to_string(),
+        )
+    };
+    let names: Vec<String> = paths.iter().map(|path| file_name(path.as_ref())).collect();
+    names
+        .iter()
+        .zip(paths)
+        .map(|(name, path)| {
+            if names.iter().filter(|other| *other == name).count() > 1 {
+                path.as_ref().display().to_string()
+            } else {
+                name.clone()
+            }
+        })
+        .collect()
+}
+
+/// A capture ready to have its blocks read.
+struct OpenCapture {
+    reader: Box<dyn PcapReaderIterator + Send>,
+    storage: Storage,
+}
+
+impl OpenCapture {
+    /// Bytes in the capture, unknown for streams.
+    fn size(&self) -> Option<u64> {
+        match &self.storage {
+            Storage::File { size, .. } => Some(*size),
+            Storage::Memory => None,
+        }
+    }
+}
+
+/// Opens the capture at `file_path`, reading standard input for [`STDIN_PATH`].
+fn open_capture(file_path: &Path) -> Result<OpenCapture> {
+    let source = file_path.display().to_string();
+    if file_path.as_os_str() == STDIN_PATH {
+        return open_input(&source, Box::new(std::io::stdin()), Storage::Memory);
+    }
+    let open_error = || format!("Failed to open {source}");
+    let file = File::open(file_path).with_context(open_error)?;
+    if is_stream(file_path) {
+        return open_input(&source, Box::new(file), Storage::Memory);
+    }
+    let size = file.metadata()?.len();
+    // Packets keep the offset of their bytes in this handle instead of a copy.
+    let capture = Arc::new(CaptureFile::open(file_path).with_context(open_error)?);
+    open_input(&source, Box::new(file), Storage::File { capture, size })
+}
+
+fn open_input(source: &str, input: Box<dyn Read + Send>, storage: Storage) -> Result<OpenCapture> {
+    let mut input = BufReader::new(input);
+    let format = detect_format(&mut input)?;
+    let reader: Box<dyn PcapReaderIterator + Send> = match format {
+        CaptureFormat::PcapNg => Box::new(
+            PcapNGReader::new(65536, input)
+                .map_err(|e| anyhow::anyhow!(e))
+                .context("Failed to create reader")?,
+        ),
+        CaptureFormat::Legacy => Box::new(
+            LegacyPcapReader::new(65536, input)
+                .map_err(|e| anyhow::anyhow!(e))
+                .context("Failed to create reader")?,
+        ),
+        CaptureFormat::LegacyModifiedBigEndian => Box::new(
+            LegacyPcapReader::new(65536, ModifiedBigEndian::new(input))
+                .map_err(|e| anyhow::anyhow!(e))
+                .context("Failed to create reader")?,
+        ),
+    };
+    let capture = OpenCapture { reader, storage };
+    info!(path = %source, size_bytes = ?capture.size(), ?format, "Opened capture");
+    Ok(capture)
+}
+
+fn parse_opened<F>(
+    captures: Vec<OpenCapture>,
+    sources: Vec<String>,
+    registry: &DissectorRegistry,
+    options: ParseOptions<'_>,
+    on_progress: F,
+) -> Result<state::ParseState>
 where
     F: Fn(f32),
 {
-    let file = File::open(file_path).context("Failed to open file")?;
-    let file_size = file.metadata()?.len();
-    info!(path = ?file_path, size_bytes = file_size, "Starting PCAP parse");
-    let mut reader = PcapNGReader::new(65536, file)
-        .map_err(|e| anyhow::anyhow!(e))
-        .context("Failed to create reader")?;
-    let mut state = state::ParseState::default();
+    let ParseOptions {
+        workers,
+        sink,
+        cancel,
+        follow,
+    } = options;
+    let size_bytes: Option<u64> = captures.iter().map(OpenCapture::size).sum();
+    // Streams are merged packet by packet, see `parse_stream`.
+    let workers = if size_bytes.is_some() { workers } else { 1 };
+    info!(?sources, ?size_bytes, workers, "Starting PCAP parse");
+    let report = |bytes: usize| {
+        if let Some(size) = size_bytes {
+            on_progress(bytes as f32 / size as f32);
+        }
+    };
+    let start_time = Instant::now();
+    let mut read = Ok(());
+    let mut state = pipeline::run(registry, workers, sink, sources, |emit| {
+        read = match <[OpenCapture; 1]>::try_from(captures) {
+            Ok([capture]) => read_blocks(
+                capture.reader,
+                &capture.storage,
+                cancel,
+                follow,
+                report,
+                emit,
+            ),
+            Err(captures) => read_merged(captures, cancel, report, emit),
+        };
+    });
+    if cancel.is_some_and(|cancel| cancel.load(Ordering::Relaxed)) {
+        info!(sources = ?state.sources, "PCAP parse cancelled");
+        return Err(Cancelled.into());
+    }
+    read?;
+    for (key, session) in std::mem::take(&mut state.tls_sessions) {
+        if let Some(flow) = state.flows.get_mut(&key) {
+            flow.tls = session.into_info();
+        }
+    }
+    for (key, mut connection) in std::mem::take(&mut state.http_connections) {
+        for direction in [StreamDirection::Forward, StreamDirection::Reverse] {
+            connection.finish(direction);
+        }
+        if let Some(flow) = state.flows.get_mut(&key) {
+            flow.http = connection.into_transactions();
+        }
+    }
+    for (interface, skipped) in &state.skipped_interfaces {
+        warn!(
+            interface,
+            linktype = ?skipped.linktype,
+            packets = skipped.packets,
+            "Skipped packets with unsupported linktype"
+        );
+    }
+    let elapsed = start_time.elapsed();
+    info!(
+        sources = ?state.sources,
+        packets = state.packet_count,
+        flows = state.flows.len(),
+        skipped = state.skipped_packet_count(),
+        elapsed_ms = elapsed.as_millis(),
+        "Completed PCAP parse"
+    );
+    Ok(state)
+}
+
+/// Reads `captures` on threads of their own and emits their items merged into one
+/// capture, see [`merge::merge`]. Progress counts the bytes read from all of them.
+fn read_merged<F>(
+    captures: Vec<OpenCapture>,
+    cancel: Option<&AtomicBool>,
+    on_progress: F,
+    emit: &mut dyn FnMut(CaptureItem),
+) -> Result<()>
+where
+    F: Fn(usize),
+{
+    let bytes_read: Vec<AtomicUsize> = captures.iter().map(|_| AtomicUsize::new(0)).collect();
+    let readers = captures
+        .into_iter()
+        .zip(&bytes_read)
+        .map(|(capture, bytes_read)| {
+            move |emit: &mut dyn FnMut(CaptureItem)| {
+                let on_progress = |bytes| bytes_read.store(bytes, Ordering::Relaxed);
+                read_blocks(
+                    capture.reader,
+                    &capture.storage,
+                    cancel,
+                    None,
+                    on_progress,
+                    emit,
+                )
+            }
+        })
+        .collect();
+    let mut last_progress_update = 0;
+    merge::merge(readers, &mut |item| {
+        let total = bytes_read
+            .iter()
+            .map(|bytes| bytes.load(Ordering::Relaxed))
+            .sum::<usize>();
+        if total - last_progress_update > 1_000 {
+            on_progress(total);
+            last_progress_update = total;
+        }
+        emit(item);
+    })
+}
+
+/// Reads the blocks of a capture and emits its packets, and the blocks that affect how
+/// later packets are parsed, in file order. `on_progress` receives the bytes read so
+/// far. Malformed data ends the capture early; failing to read the input is an error.
+fn read_blocks<F>(
+    mut reader: Box<dyn PcapReaderIterator + Send>,
+    storage: &Storage,
+    cancel: Option<&AtomicBool>,
+    follow: Option<&AtomicBool>,
+    on_progress: F,
+    emit: &mut dyn FnMut(CaptureItem),
+) -> Result<()>
+where
+    F: Fn(usize),
+{
     let mut interfaces: Vec<InterfaceDescription> = Vec::new();
+    let mut next_interface_index = 0;
     let mut bytes_read = 0;
     let mut last_progress_update = 0;
-    let start_time = Instant::now();
-    let tls_parser = TlsParser;
 
     loop {
+        if cancel.is_some_and(|cancel| cancel.load(Ordering::Relaxed)) {
+            break;
+        }
         match reader.next() {
             Ok((offset, block)) => {
+                let block_start = bytes_read as u64;
                 bytes_read += offset;
                 if bytes_read - last_progress_update > 1_000 {
-                    on_progress(bytes_read as f32 / file_size as f32);
+                    on_progress(bytes_read);
                     last_progress_update = bytes_read;
                 }
                 match block {
@@ -54,13 +503,29 @@ where
                         interfaces.clear();
                     }
                     PcapBlockOwned::NG(Block::InterfaceDescription(idb)) => {
+                        emit(CaptureItem::Event(CaptureEvent::Interface(
+                            CaptureInterface {
+                                linktype: idb.linktype,
+                                snaplen: idb.snaplen,
+                                ts_resolution: idb.if_tsresol,
+                                ts_offset: idb.if_tsoffset,
+                                name: idb.if_name().and_then(Result::ok).map(str::to_string),
+                                description: idb
+                                    .if_description()
+                                    .and_then(Result::ok)
+                                    .map(str::to_string),
+                            },
+                        )));
                         interfaces.push(InterfaceDescription {
+                            index: next_interface_index,
                             linktype: idb.linktype,
                             ts_resolution: idb.if_tsresol,
                             ts_offset: idb.if_tsoffset,
                         });
+                        next_interface_index += 1;
                         debug!(
                             if_id = interfaces.len() - 1,
+                            linktype = ?idb.linktype,
                             "Registered interface description"
                         );
                     }
@@ -73,15 +538,17 @@ where
                             );
                         } else {
                             let interface = &interfaces[if_id];
-                            if interface.linktype == pcap_parser::Linktype::ETHERNET {
-                                let epb_packet_data = epb.packet_data();
-                                handle_enhanced_packet(
-                                    epb,
-                                    interface,
-                                    &tls_parser,
-                                    epb_packet_data,
-                                    &mut state,
-                                );
+                            if link::is_supported(interface.linktype) {
+                                let data = Arc::from(epb.packet_data());
+                                emit(CaptureItem::Packet(RawPacket {
+                                    linktype: interface.linktype,
+                                    record: enhanced_packet_record(epb, interface),
+                                    stored: storage
+                                        .packet_data(block_start + EPB_DATA_OFFSET, &data),
+                                    data,
+                                }));
+                            } else {
+                                emit(unsupported(interface));
                             }
                         }
                     }
@@ -89,13 +556,21 @@ where
                         debug!("Unsupported block type: SimplePacket")
                     }
                     PcapBlockOwned::NG(Block::NameResolution(nrb)) => {
-                        dns::handle_name_resolution(&nrb, &mut state.name_resolutions);
+                        emit(CaptureItem::Event(CaptureEvent::NameResolutions(
+                            dns::name_resolution_records(&nrb),
+                        )));
                     }
                     PcapBlockOwned::NG(Block::InterfaceStatistics(_)) => {
                         debug!("Unsupported block type: InterfaceStatistics")
                     }
-                    PcapBlockOwned::NG(Block::DecryptionSecrets(_)) => {
-                        debug!("Unsupported block type: DecryptionSecrets")
+                    PcapBlockOwned::NG(Block::DecryptionSecrets(dsb)) => {
+                        if dsb.secrets_type == SecretsType::TlsKeyLog {
+                            emit(CaptureItem::Event(CaptureEvent::TlsSecrets(
+                                String::from_utf8_lossy(dsb.data).into_owned(),
+                            )));
+                        } else {
+                            debug!(secrets_type = ?dsb.secrets_type, "Unsupported secrets type");
+                        }
                     }
                     PcapBlockOwned::NG(Block::Custom(_)) => {
                         debug!("Unsupported block type: Custom")
@@ -109,31 +584,114 @@ where
                     PcapBlockOwned::NG(Block::ProcessInformation(_)) => {
                         debug!("Unsupported block type: ProcessInformation")
                     }
-                    PcapBlockOwned::Legacy(_legacy_pcap_block) => {
-                        debug!("Unsupported block type: Legacy")
-                    }
-                    PcapBlockOwned::LegacyHeader(_pcap_header) => {
-                        debug!("Unsupported block type: LegacyHeader")
+                    PcapBlockOwned::LegacyHeader(header) => {
+                        // Legacy captures have a single, implicit interface described by the
+                        // global header.
+                        interfaces.clear();
+                        let ts_resolution = if header.is_nanosecond_precision() {
+                            9
+                        } else {
+                            6
+                        };
+                        emit(CaptureItem::Event(CaptureEvent::Interface(
+                            CaptureInterface {
+                                linktype: header.network,
+                                snaplen: header.snaplen,
+                                ts_resolution,
+                                ts_offset: 0,
+                                name: None,
+                                description: None,
+                            },
+                        )));
+                        interfaces.push(InterfaceDescription {
+                            index: next_interface_index,
+                            linktype: header.network,
+                            ts_resolution,
+                            ts_offset: 0,
+                        });
+                        next_interface_index += 1;
+                        debug!(
+                            linktype = ?header.network,
+                            nanosecond = header.is_nanosecond_precision(),
+                            big_endian = header.is_bigendian(),
+                            "Registered legacy PCAP header"
+                        );
                     }
+                    PcapBlockOwned::Legacy(ref legacy) => match interfaces.first() {
+                        Some(interface) => {
+                            if link::is_supported(interface.linktype) {
+                                let data = Arc::from(legacy.data);
+                                emit(CaptureItem::Packet(RawPacket {
+                                    linktype: interface.linktype,
+                                    record: legacy_record(legacy, interface),
+                                    // Records of the modified format have a longer header.
+                                    stored: storage.packet_data(
+                                        block_start + (offset - legacy.data.len()) as u64,
+                                        &data,
+                                    ),
+                                    data,
+                                }));
+                            } else {
+                                emit(unsupported(interface));
+                            }
+                        }
+                        None => warn!("Legacy packet before PCAP header; skipping packet"),
+                    },
                 }
                 reader.consume(offset);
             }
+            // A block cut short is still being written.
+            Err(PcapError::Eof | PcapError::UnexpectedEof)
+                if follow.is_some_and(|follow| follow.load(Ordering::Relaxed)) =>
+            {
+                emit(CaptureItem::Event(CaptureEvent::Waiting));
+                std::thread::sleep(FOLLOW_POLL_INTERVAL);
+                if let Err(error) = reader.refill() {
+                    error!(error = ?error, "Failed to read the followed capture; stopping parse");
+                    return Err(anyhow!("Failed to read the capture: {error:?}"));
+                }
+            }
             Err(PcapError::Eof) => break,
             Err(PcapError::Incomplete(_)) => {
-                reader.refill().expect("Failed to refill reader");
+                if matches!(storage, Storage::Memory) {
+                    // The next read may block until more packets arrive.
+                    emit(CaptureItem::Event(CaptureEvent::Waiting));
+                }
+                if let Err(error) = reader.refill() {
+                    error!(error = ?error, "Failed to read the capture; stopping parse");
+                    return Err(anyhow!("Failed to read the capture: {error:?}"));
+                }
+            }
+            Err(e) => {
+                error!(error = ?e, "Error while reading packet data; stopping parse");
+                break;
             }
-            Err(e) => error!(error = ?e, "Error while reading packet data"),
         }
     }
-    let elapsed = start_time.elapsed();
-    info!(
-        path = ?file_path,
-        packets = state.packet_count,
-        flows = state.flows.len(),
-        elapsed_ms = elapsed.as_millis(),
-        "Completed PCAP parse"
-    );
-    Ok((state.flows, state.first_packet_ts, state.name_resolutions))
+    Ok(())
+}
+
+fn detect_format<R: BufRead>(input: &mut R) -> Result<CaptureFormat> {
+    let header = input.fill_buf().context("Failed to read capture header")?;
+    let magic: [u8; 4] = header
+        .get(..4)
+        .and_then(|bytes| bytes.try_into().ok())
+        .context("Failed to read capture header: file is too short")?;
+
+    if magic == PCAPNG_MAGIC {
+        return Ok(CaptureFormat::PcapNg);
+    }
+
+    // Microsecond, nanosecond and "modified" magic numbers, in either byte order.
+    match u32::from_le_bytes(magic) {
+        0xa1b2_c3d4 | 0xd4c3_b2a1 | 0xa1b2_3c4d | 0x4d3c_b2a1 | 0xa1b2_cd34 => {
+            Ok(CaptureFormat::Legacy)
+        }
+        0x34cd_b2a1 => Ok(CaptureFormat::LegacyModifiedBigEndian),
+        other => Err(anyhow::anyhow!(
+            "Failed to detect capture format: unrecognized magic number {other:#010x}"
+        )),
+    }
 }
 
 fn calculate_ts_unit(resolution: u8) -> u64 {
@@ -152,24 +710,33 @@ fn parse_timestamp(
     epb.decode_ts_f64(interface.ts_offset as u64, unit)
 }
 
-fn handle_enhanced_packet(
+fn legacy_record(block: &LegacyPcapBlock, interface: &InterfaceDescription) -> CaptureRecord {
+    let unit = calculate_ts_unit(interface.ts_resolution);
+    CaptureRecord {
+        interface: interface.index,
+        source: 0,
+        timestamp: block.ts_sec as f64 + block.ts_usec as f64 / unit as f64,
+        timestamp_ticks: u64::from(block.ts_sec) * unit + u64::from(block.ts_usec),
+        original_length: block.origlen,
+    }
+}
+
+fn enhanced_packet_record(
     epb: &EnhancedPacketBlock,
     interface: &InterfaceDescription,
-    tls_parser: &TlsParser,
-    epb_packet_data: &[u8],
-    state: &mut state::ParseState,
-) {
-    let timestamp = parse_timestamp(epb, interface);
-    state::update_first_timestamp(&mut state.first_packet_ts, timestamp);
-
-    if let Ok(context) = decode_headers(epb_packet_data, tls_parser) {
-        dns::handle_dns_response(&context, &mut state.name_resolutions);
-        packets::add_packet(
-            epb_packet_data,
-            context,
-            timestamp,
-            &mut state.flows,
-            &mut state.packet_count,
-        );
+) -> CaptureRecord {
+    CaptureRecord {
+        interface: interface.index,
+        source: 0,
+        timestamp: parse_timestamp(epb, interface),
+        timestamp_ticks: (u64::from(epb.ts_high) << 32) | u64::from(epb.ts_low),
+        original_length: epb.origlen,
     }
 }
+
+fn unsupported(interface: &InterfaceDescription) -> CaptureItem {
+    CaptureItem::Event(CaptureEvent::Unsupported {
+        interface: interface.index,
+        linktype: interface.linktype,
+    })
+}
@@ -0,0 +1,338 @@
+use crate::flow::{Endpoint, FlowKey};
+use crate::layers::TcpSegment;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+
+/// Out-of-order bytes buffered per direction before the missing range is given up on.
+const MAX_PENDING_BYTES: usize = 4 * 1024 * 1024;
+/// Largest TCP window (RFC 7323). A segment further than this from the stream end
+/// belongs to another connection on the same addresses and ports.
+const MAX_WINDOW: i64 = 1 << 30;
+
+/// Which side of a [`FlowKey`] sent a segment. `Forward` is data sent by
+/// `FlowKey::endpoints.first`, `Reverse` data sent by `endpoints.second`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StreamDirection {
+    Forward,
+    Reverse,
+}
+
+impl StreamDirection {
+    pub fn from_sender(key: &FlowKey, sender: Endpoint) -> Self {
+        if sender == key.endpoints.first {
+            StreamDirection::Forward
+        } else {
+            StreamDirection::Reverse
+        }
+    }
+}
+
+/// Identifies one direction of a TCP conversation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StreamId {
+    pub key: FlowKey,
+    pub direction: StreamDirection,
+}
+
+/// How a segment related to the bytes already seen in its direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentKind {
+    /// No payload (pure ACK, SYN, FIN, ...).
+    Empty,
+    /// Payload started exactly where the stream ended.
+    InOrder,
+    /// Payload starts beyond the stream end and was buffered.
+    OutOfOrder,
+    /// Every byte of the payload had already been seen.
+    Retransmission,
+    /// Payload repeated some already seen bytes and extended the stream.
+    Overlap,
+    /// Payload came before the first byte captured of a stream whose start was
+    /// missed, so it can't be placed.
+    BeforeStart,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegmentResult {
+    pub kind: SegmentKind,
+    /// Bytes of [`TcpStream::data`] that became contiguous because of this segment.
+    pub appended: Range<usize>,
+    /// Set when missing bytes were given up on; the value is the position in
+    /// [`TcpStream::data`] right after the first gap.
+    pub gap_at: Option<usize>,
+    /// Set when the segment started a new connection on the same addresses and ports,
+    /// replacing the stream the direction had so far.
+    pub restarted: bool,
+}
+
+/// Bytes that were never captured, recorded at the position in [`TcpStream::data`] they
+/// would have occupied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamGap {
+    pub position: usize,
+    pub missing: u64,
+}
+
+/// Ordered byte stream for one direction of a TCP connection.
+#[derive(Debug, Default, Clone)]
+pub struct TcpStream {
+    /// Sequence number of stream offset 0.
+    base_seq: Option<u32>,
+    /// Whether offset 0 follows a captured SYN rather than the first payload seen.
+    handshake: bool,
+    data: Vec<u8>,
+    /// Bytes dropped from the front of `data` once parsed.
+    released: u64,
+    /// Buffered out-of-order payloads keyed by stream offset.
+    pending: BTreeMap<u64, Vec<u8>>,
+    pending_bytes: usize,
+    /// Bytes skipped over by gaps, so `released + data.len() + skipped` is the next
+    /// expected offset.
+    skipped: u64,
+    gaps: Vec<StreamGap>,
+    fin: bool,
+}
+
+impl TcpStream {
+    /// Contiguous reassembled payload in sequence order, from the first byte not
+    /// released yet.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Drops the first `len` bytes of [`data`](Self::data), which the stream's parsers
+    /// are done with, so a long connection doesn't keep all of its payload. Positions
+    /// in `data` shift down by the number of bytes dropped, which is returned.
+    pub fn release(&mut self, len: usize) -> usize {
+        let len = len.min(self.data.len());
+        self.data.drain(..len);
+        self.released += len as u64;
+        for gap in &mut self.gaps {
+            gap.position = gap.position.saturating_sub(len);
+        }
+        len
+    }
+
+    pub fn gaps(&self) -> &[StreamGap] {
+        &self.gaps
+    }
+
+    pub fn saw_fin(&self) -> bool {
+        self.fin
+    }
+
+    /// Number of out-of-order bytes still waiting for a missing range.
+    pub fn pending_bytes(&self) -> usize {
+        self.pending_bytes
+    }
+
+    /// Next sequence number expected in this direction, once the stream has started.
+    pub fn next_seq(&self) -> Option<u32> {
+        self.base_seq
+            .map(|base| base.wrapping_add(self.next_offset() as u32))
+    }
+
+    /// Whether `segment` belongs to a new connection reusing the addresses and ports of
+    /// this one: a SYN with another initial sequence number, or payload too far from
+    /// the stream end to be part of it.
+    pub fn is_new_connection(&self, segment: &TcpSegment) -> bool {
+        let Some(base) = self.base_seq else {
+            return false;
+        };
+        let data_seq = data_seq(segment);
+        if segment.syn {
+            return data_seq != base;
+        }
+        let next = self.next_offset() as i64;
+        !segment.payload.is_empty()
+            && (self.unwrap_offset(data_seq.wrapping_sub(base)) - next).abs() > MAX_WINDOW
+    }
+
+    /// Adds `segment`, whose payload bytes are `payload`.
+    pub fn push(&mut self, segment: &TcpSegment, payload: &[u8]) -> SegmentResult {
+        let restarted = self.is_new_connection(segment);
+        if restarted {
+            *self = TcpStream::default();
+        }
+        let end = self.data.len();
+        let mut result = SegmentResult {
+            kind: SegmentKind::Empty,
+            appended: end..end,
+            gap_at: None,
+            restarted,
+        };
+
+        let data_seq = data_seq(segment);
+        if segment.syn && self.base_seq.is_none() {
+            self.base_seq = Some(data_seq);
+            self.handshake = true;
+        }
+        if segment.fin {
+            self.fin = true;
+        }
+        if payload.is_empty() {
+            return result;
+        }
+
+        // Capture started mid-connection: the first payload defines offset 0.
+        let base = *self.base_seq.get_or_insert(data_seq);
+        let start = self.unwrap_offset(data_seq.wrapping_sub(base));
+        let stop = start + payload.len() as i64;
+        let next = self.next_offset() as i64;
+
+        result.kind = if stop <= next {
+            if start < 0 && !self.handshake {
+                SegmentKind::BeforeStart
+            } else {
+                SegmentKind::Retransmission
+            }
+        } else if start > next {
+            self.buffer(start as u64, payload)
+        } else {
+            let skip = (next - start) as usize;
+            self.data.extend_from_slice(&payload[skip..]);
+            if skip > 0 {
+                SegmentKind::Overlap
+            } else {
+                SegmentKind::InOrder
+            }
+        };
+
+        self.drain_pending();
+        if self.pending_bytes > MAX_PENDING_BYTES {
+            result.gap_at = self.flush_gaps();
+        }
+        result.appended = end..self.data.len();
+        result
+    }
+
+    /// Gives up on every missing range, appending buffered data after recording the gaps.
+    /// Returns the position right after the first gap, if any.
+    pub fn flush_gaps(&mut self) -> Option<usize> {
+        let mut first_gap = None;
+        while let Some((&offset, _)) = self.pending.first_key_value() {
+            let missing = offset.saturating_sub(self.next_offset());
+            if missing > 0 {
+                let position = self.data.len();
+                self.gaps.push(StreamGap { position, missing });
+                self.skipped += missing;
+                first_gap.get_or_insert(position);
+            }
+            self.drain_pending();
+        }
+        first_gap
+    }
+
+    fn next_offset(&self) -> u64 {
+        self.released + self.data.len() as u64 + self.skipped
+    }
+
+    /// Maps a 32-bit relative sequence number onto the 64-bit stream offset closest to
+    /// the current stream end, so wrapped sequence numbers keep increasing.
+    fn unwrap_offset(&self, relative: u32) -> i64 {
+        const WINDOW: i64 = 1 << 32;
+        let next = self.next_offset() as i64;
+        let mut offset = (next & !(WINDOW - 1)) | relative as i64;
+        if offset - next > WINDOW / 2 {
+            offset -= WINDOW;
+        } else if next - offset > WINDOW / 2 {
+            offset += WINDOW;
+        }
+        offset
+    }
+
+    fn buffer(&mut self, offset: u64, payload: &[u8]) -> SegmentKind {
+        match self.pending.get(&offset) {
+            Some(existing) if existing.len() >= payload.len() => SegmentKind::Retransmission,
+            Some(existing) => {
+                self.pending_bytes += payload.len() - existing.len();
+                self.pending.insert(offset, payload.to_vec());
+                SegmentKind::OutOfOrder
+            }
+            None => {
+                self.pending_bytes += payload.len();
+                self.pending.insert(offset, payload.to_vec());
+                SegmentKind::OutOfOrder
+            }
+        }
+    }
+
+    fn drain_pending(&mut self) {
+        while let Some((&offset, _)) = self.pending.first_key_value() {
+            let next = self.next_offset();
+            if offset > next {
+                break;
+            }
+            let payload = self.pending.remove(&offset).unwrap_or_default();
+            self.pending_bytes -= payload.len();
+            let stop = offset + payload.len() as u64;
+            if stop > next {
+                self.data
+                    .extend_from_slice(&payload[(next - offset) as usize..]);
+            }
+        }
+    }
+}
+
+/// Sequence number of the first payload byte; a SYN consumes one before it.
+fn data_seq(segment: &TcpSegment) -> u32 {
+    if segment.syn {
+        segment.seq.wrapping_add(1)
+    } else {
+        segment.seq
+    }
+}
+
+/// Both directions of one TCP conversation.
+#[derive(Debug, Default, Clone)]
+pub struct FlowStreams {
+    pub forward: TcpStream,
+    pub reverse: TcpStream,
+}
+
+impl FlowStreams {
+    pub fn get(&self, direction: StreamDirection) -> &TcpStream {
+        match direction {
+            StreamDirection::Forward => &self.forward,
+            StreamDirection::Reverse => &self.reverse,
+        }
+    }
+
+    pub fn get_mut(&mut self, direction: StreamDirection) -> &mut TcpStream {
+        match direction {
+            StreamDirection::Forward => &mut self.forward,
+            StreamDirection::Reverse => &mut self.reverse,
+        }
+    }
+}
+
+/// Reassembles TCP payloads per flow and direction.
+#[derive(Debug, Default, Clone)]
+pub struct TcpReassembler {
+    flows: HashMap<FlowKey, FlowStreams>,
+}
+
+impl TcpReassembler {
+    pub fn push(&mut self, id: StreamId, segment: &TcpSegment, payload: &[u8]) -> SegmentResult {
+        self.flows
+            .entry(id.key)
+            .or_default()
+            .get_mut(id.direction)
+            .push(segment, payload)
+    }
+
+    pub fn flow(&self, key: &FlowKey) -> Option<&FlowStreams> {
+        self.flows.get(key)
+    }
+
+    pub fn stream(&self, id: StreamId) -> Option<&TcpStream> {
+        self.flow(&id.key).map(|streams| streams.get(id.direction))
+    }
+
+    /// Drops the first `len` bytes of a stream, see [`TcpStream::release`].
+    pub fn release(&mut self, id: StreamId, len: usize) -> usize {
+        self.flows
+            .get_mut(&id.key)
+            .map_or(0, |streams| streams.get_mut(id.direction).release(len))
+    }
+}
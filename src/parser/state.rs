@@ -1,6 +1,17 @@
+use crate::flow::storage::PacketData;
 use crate::flow::{Flow, FlowKey};
 use crate::flow::IPAddress;
-use std::collections::HashMap;
+use crate::layers::PacketContext;
+use crate::layers::tls::TlsStreamState;
+use crate::layers::tls_decrypt::TlsSession;
+use crate::parser::defrag::IpReassembler;
+use crate::parser::dns::DnsStreamState;
+use crate::parser::http::HttpConnection;
+use crate::parser::packets::CaptureRecord;
+use crate::parser::reassembly::{StreamId, TcpReassembler};
+use crate::parser::tcp_analysis::TcpAnalyzer;
+use pcap_parser::Linktype;
+use std::collections::{BTreeMap, HashMap};
 
 #[derive(Default)]
 pub struct ParseState {
@@ -8,6 +19,75 @@ pub struct ParseState {
     pub first_packet_ts: Option<f64>,
     pub packet_count: usize,
     pub name_resolutions: HashMap<IPAddress, Vec<String>>,
+    /// Every interface described by the capture, in order of appearance across sections.
+    /// `Packet::interface` indexes into this list.
+    pub interfaces: Vec<CaptureInterface>,
+    /// Names of the capture files merged into this state, as shown in the source
+    /// column. `Packet::source` indexes into this list.
+    pub sources: Vec<String>,
+    /// Packets dropped because their interface uses a linktype we cannot decode,
+    /// keyed by interface index (counted across all sections of the capture).
+    pub skipped_interfaces: BTreeMap<usize, SkippedInterface>,
+    /// Fragmented IP datagrams still missing parts, with the packets that carried the
+    /// parts received so far.
+    pub ip_fragments: IpReassembler<HeldFragment>,
+    /// Reassembled TCP payload for each flow and direction.
+    pub tcp_streams: TcpReassembler,
+    pub tls_streams: HashMap<StreamId, TlsStreamState>,
+    /// Handshake parameters and record keys of each TLS connection.
+    pub tls_sessions: HashMap<FlowKey, TlsSession>,
+    /// HTTP messages of each connection, paired into transactions.
+    pub http_connections: HashMap<FlowKey, HttpConnection>,
+    /// Message framing of each direction of DNS over TCP.
+    pub dns_streams: HashMap<StreamId, DnsStreamState>,
+    pub tcp_analyzer: TcpAnalyzer,
+}
+
+/// Interface description as read from the capture, kept so exports can reproduce it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureInterface {
+    pub linktype: Linktype,
+    pub snaplen: u32,
+    /// Raw `if_tsresol` value: a power of ten, or of two when the high bit is set.
+    pub ts_resolution: u8,
+    /// `if_tsoffset` in seconds.
+    pub ts_offset: i64,
+    pub name: Option<String>,
+    pub description: Option<String>,
+}
+
+/// A packet carrying part of a fragmented IP datagram, which joins its flow once the
+/// datagram is complete.
+#[derive(Debug)]
+pub struct HeldFragment {
+    pub context: Box<PacketContext>,
+    pub record: CaptureRecord,
+    pub stored: PacketData,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SkippedInterface {
+    pub linktype: Linktype,
+    pub packets: usize,
+}
+
+impl ParseState {
+    pub fn record_unsupported_linktype(&mut self, interface: usize, linktype: Linktype) {
+        self.skipped_interfaces
+            .entry(interface)
+            .or_insert(SkippedInterface {
+                linktype,
+                packets: 0,
+            })
+            .packets += 1;
+    }
+
+    pub fn skipped_packet_count(&self) -> usize {
+        self.skipped_interfaces
+            .values()
+            .map(|skipped| skipped.packets)
+            .sum()
+    }
 }
 
 pub fn update_first_timestamp(first_packet_ts: &mut Option<f64>, timestamp: f64) {
@@ -16,4 +96,4 @@ pub fn update_first_timestamp(first_packet_ts: &mut Option<f64>, timestamp: f64)
         Some(current) if timestamp < *current => *first_packet_ts = Some(timestamp),
         _ => {}
     }
-}
\ No newline at end of file
+}
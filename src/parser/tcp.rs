@@ -1,5 +1,11 @@
-use crate::flow::Protocol;
+use super::dns::DnsDissector;
+use super::http::HttpDissector;
+use super::reassembly::{SegmentResult, StreamDirection, StreamId};
+use super::state::ParseState;
+use crate::flow::{Endpoint, FlowKey, Protocol};
 use crate::layers::PacketContext;
+use crate::layers::dissector::DissectorRegistry;
+use crate::layers::tls::{TlsDissector, TlsParser};
 use etherparse::TcpHeader;
 
 pub fn tag_tcp(header: &TcpHeader, payload_len: usize, context: &mut PacketContext) {
@@ -31,4 +37,154 @@ pub fn looks_like_tls(payload: &[u8]) -> bool {
     let content_type = payload[0];
     let version_major = payload[1];
     (20..=23).contains(&content_type) && version_major == 3
-}
\ No newline at end of file
+}
+
+/// Feeds the packet's TCP segment into its flow's reassembler and expert analysis, then
+/// lets the TLS and HTTP parsers consume whatever became contiguous in that direction,
+/// unless a decode-as rule in `registry` assigns the ports to another dissector. Streams
+/// assigned to DNS, by port or rule, are split into DNS messages instead. Findings
+/// are added to `context` as tags and analysis flags; decrypted TLS payload goes to the
+/// dissectors `registry` has behind TLS. `packet` holds the bytes `context` was decoded
+/// from and `timestamp` is the packet's capture time. Stream bytes every parser is done
+/// with are released afterwards.
+pub fn handle_tcp_segment(
+    context: &mut PacketContext,
+    packet: &[u8],
+    timestamp: f64,
+    tls_parser: &TlsParser,
+    registry: &DissectorRegistry,
+    state: &mut ParseState,
+) {
+    let (Some(segment), Some(src_ip), Some(dst_ip), Some(src_port), Some(dst_port)) = (
+        context.tcp_segment.take(),
+        context.src_ip,
+        context.dst_ip,
+        context.src_port,
+        context.dst_port,
+    ) else {
+        return;
+    };
+
+    let sender = Endpoint::new(src_ip, src_port);
+    let key = FlowKey::from_endpoints(sender, Endpoint::new(dst_ip, dst_port), Protocol::TCP);
+    let id = StreamId {
+        key,
+        direction: StreamDirection::from_sender(&key, sender),
+    };
+
+    if let Some(stream) = state.tcp_streams.stream(id)
+        && stream.is_new_connection(&segment)
+    {
+        // The addresses and ports were reused; what was parsed of the old stream is kept.
+        if let Some(connection) = state.http_connections.get_mut(&key) {
+            connection.finish(id.direction);
+            connection.restart(id.direction);
+        }
+        state.tls_streams.remove(&id);
+        state.dns_streams.remove(&id);
+    }
+    let payload = packet.get(segment.payload.clone()).unwrap_or_default();
+    let result = state.tcp_streams.push(id, &segment, payload);
+    let flags = state
+        .tcp_analyzer
+        .analyze(id, &segment, context.is_ack, &result);
+    context
+        .tags
+        .extend(flags.iter().map(|flag| flag.label().to_string()));
+    context.tcp_analysis = flags;
+    context.tcp_segment = Some(segment);
+    if result.appended.is_empty() {
+        return;
+    }
+    parse_stream(id, &result, timestamp, tls_parser, registry, state, context);
+    release_parsed(id, state);
+}
+
+/// Runs the parsers of the stream `id` on the bytes `result` made contiguous.
+fn parse_stream(
+    id: StreamId,
+    result: &SegmentResult,
+    timestamp: f64,
+    tls_parser: &TlsParser,
+    registry: &DissectorRegistry,
+    state: &mut ParseState,
+    context: &mut PacketContext,
+) {
+    let key = id.key;
+    let (Some(src_port), Some(dst_port)) = (context.src_port, context.dst_port) else {
+        return;
+    };
+    if registry.assigned(Protocol::TCP, src_port, dst_port) == Some(DnsDissector::NAME) {
+        let dns_state = state.dns_streams.entry(id).or_default();
+        if let Some(position) = result.gap_at {
+            dns_state.resync_at(position);
+        }
+        if let Some(stream) = state.tcp_streams.stream(id) {
+            dns_state.parse_stream(stream.data(), context);
+        }
+        return;
+    }
+
+    let forced = registry.forced(Protocol::TCP, src_port, dst_port);
+    let stream_parsers = [TlsDissector::NAME, HttpDissector::NAME];
+    if forced.is_some_and(|name| !stream_parsers.contains(&name)) {
+        return;
+    }
+
+    if forced != Some(TlsDissector::NAME) {
+        let connection = state.http_connections.entry(key).or_default();
+        if let Some(position) = result.gap_at {
+            connection.resync_at(id.direction, position);
+        }
+        if let Some(stream) = state.tcp_streams.stream(id) {
+            connection.parse_stream(stream.data(), id.direction, timestamp, context);
+        }
+    }
+    if forced == Some(HttpDissector::NAME) {
+        return;
+    }
+
+    let tls_state = state.tls_streams.entry(id).or_default();
+    if let Some(position) = result.gap_at {
+        tls_state.resync_at(position);
+    }
+    let session = state.tls_sessions.entry(key).or_default();
+    if let Some(stream) = state.tcp_streams.stream(id) {
+        tls_parser.parse_stream(
+            stream.data(),
+            tls_state,
+            session,
+            id.direction,
+            registry,
+            context,
+        );
+    }
+}
+
+/// Drops the bytes of stream `id` that all of its parsers have read, which is all of
+/// them when the stream has no parser.
+fn release_parsed(id: StreamId, state: &mut ParseState) {
+    let connection = state.http_connections.get(&id.key);
+    let keep_from = [
+        state.dns_streams.get(&id).map(|dns| dns.keep_from()),
+        connection.map(|connection| connection.keep_from(id.direction)),
+        state.tls_streams.get(&id).map(|tls| tls.keep_from()),
+    ]
+    .into_iter()
+    .flatten()
+    .min()
+    .unwrap_or(usize::MAX);
+    let released = state.tcp_streams.release(id, keep_from);
+    if released == 0 {
+        return;
+    }
+    if let Some(dns) = state.dns_streams.get_mut(&id) {
+        dns.released(released);
+    }
+    if let Some(connection) = state.http_connections.get_mut(&id.key) {
+        connection.released(id.direction, released);
+    }
+    if let Some(tls) = state.tls_streams.get_mut(&id) {
+        tls.released(released);
+    }
+}
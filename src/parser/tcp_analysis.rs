@@ -0,0 +1,151 @@
+use super::reassembly::{SegmentKind, SegmentResult, StreamDirection, StreamId};
+use crate::flow::{FlowKey, TcpAnalysisFlag};
+use crate::layers::TcpSegment;
+use std::collections::HashMap;
+
+/// What one side of a connection has sent so far.
+#[derive(Debug, Default, Clone)]
+struct DirectionState {
+    /// Sequence number following the highest byte sent (SYN and FIN count as one).
+    next_seq: Option<u32>,
+    isn: Option<u32>,
+    last_ack: Option<u32>,
+    last_window: u16,
+    /// Window scale shift offered in this side's SYN, if one was captured.
+    window_scale: Option<u8>,
+    /// Duplicate ACKs sent in a row for `last_ack`.
+    dup_acks: usize,
+}
+
+/// Both sides of one connection.
+#[derive(Debug, Default, Clone)]
+struct ConnectionState {
+    forward: DirectionState,
+    reverse: DirectionState,
+}
+
+impl ConnectionState {
+    /// The side sending in `direction`, and the other one.
+    fn split(&mut self, direction: StreamDirection) -> (&mut DirectionState, &DirectionState) {
+        match direction {
+            StreamDirection::Forward => (&mut self.forward, &self.reverse),
+            StreamDirection::Reverse => (&mut self.reverse, &self.forward),
+        }
+    }
+}
+
+/// Wireshark-style TCP expert analysis over both directions of every flow.
+#[derive(Debug, Default, Clone)]
+pub struct TcpAnalyzer {
+    connections: HashMap<FlowKey, ConnectionState>,
+}
+
+impl TcpAnalyzer {
+    /// Classifies `segment` sent in direction `id`. `reassembly` is the result of pushing
+    /// the same segment into the stream reassembler, which tells whether its bytes were
+    /// new. Call once per segment, in capture order.
+    pub fn analyze(
+        &mut self,
+        id: StreamId,
+        segment: &TcpSegment,
+        has_ack: bool,
+        reassembly: &SegmentResult,
+    ) -> Vec<TcpAnalysisFlag> {
+        let (state, reverse) = self
+            .connections
+            .entry(id.key)
+            .or_default()
+            .split(id.direction);
+        if reassembly.restarted {
+            *state = DirectionState::default();
+        }
+        let mut flags = Vec::new();
+
+        let control = segment.syn || segment.fin || segment.rst;
+        let payload_len = segment.payload.len() as u32;
+        let seq_len = payload_len + u32::from(segment.syn) + u32::from(segment.fin);
+        let seq_end = segment.seq.wrapping_add(seq_len);
+
+        if segment.window == 0 && !control {
+            flags.push(TcpAnalysisFlag::ZeroWindow);
+        }
+
+        let keep_alive =
+            !control && payload_len <= 1 && state.next_seq == Some(segment.seq.wrapping_add(1));
+        if keep_alive {
+            flags.push(TcpAnalysisFlag::KeepAlive);
+        } else if payload_len > 0 {
+            if let (Some(ack), Some(scale)) = (reverse.last_ack, scale(state, reverse)) {
+                let window = u32::from(reverse.last_window) << scale;
+                if window > 0 && seq_end == ack.wrapping_add(window) {
+                    flags.push(TcpAnalysisFlag::WindowFull);
+                }
+            }
+
+            if state
+                .next_seq
+                .is_some_and(|next| seq_before(segment.seq, next))
+            {
+                let seen_before = matches!(
+                    reassembly.kind,
+                    SegmentKind::Retransmission | SegmentKind::Overlap
+                );
+                let flag = if reverse.dup_acks >= 2 && reverse.last_ack == Some(segment.seq) {
+                    TcpAnalysisFlag::FastRetransmission
+                } else if seen_before {
+                    TcpAnalysisFlag::Retransmission
+                } else {
+                    // The bytes are new, so this segment fills a hole left by a later one.
+                    TcpAnalysisFlag::OutOfOrder
+                };
+                flags.push(flag);
+            }
+        } else if segment.syn && state.isn == Some(segment.seq) {
+            flags.push(TcpAnalysisFlag::Retransmission);
+        }
+
+        let duplicate_ack = has_ack
+            && !control
+            && payload_len == 0
+            && state.last_ack == Some(segment.ack)
+            && state.last_window == segment.window
+            && state.next_seq == Some(segment.seq);
+        if duplicate_ack {
+            state.dup_acks += 1;
+            flags.push(TcpAnalysisFlag::DuplicateAck);
+        } else if has_ack && state.last_ack != Some(segment.ack) {
+            state.dup_acks = 0;
+        }
+
+        if segment.syn {
+            state.isn = Some(segment.seq);
+            state.window_scale = segment.window_scale;
+        }
+        if has_ack {
+            state.last_ack = Some(segment.ack);
+            state.last_window = segment.window;
+        }
+        if !keep_alive && state.next_seq.is_none_or(|next| seq_before(next, seq_end)) {
+            state.next_seq = Some(seq_end);
+        }
+
+        flags
+    }
+}
+
+/// Shift to apply to windows advertised by `receiver`. Scaling is only in effect when
+/// both SYNs offered it; `None` means the handshake wasn't captured.
+fn scale(sender: &DirectionState, receiver: &DirectionState) -> Option<u32> {
+    if sender.isn.is_none() || receiver.isn.is_none() {
+        return None;
+    }
+    match (sender.window_scale, receiver.window_scale) {
+        (Some(_), Some(shift)) => Some(u32::from(shift.min(14))),
+        _ => Some(0),
+    }
+}
+
+/// Sequence number comparison modulo 2^32 (RFC 1982).
+fn seq_before(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
@@ -1,27 +1,88 @@
-use crate::flow::{IPAddress, Protocol};
-use crate::layers::PacketContext;
+use crate::flow::http::HttpTransaction;
+use crate::flow::storage::PacketData;
+use crate::flow::tls::TlsInfo;
+use crate::flow::tunnel::{Encapsulation, TunnelKind};
+use crate::flow::{Conversation, Endpoint, Flow, FlowKey, IPAddress, Protocol, TcpAnalysisFlag};
+use crate::layers::dissector::{DecodeAs, Dissector, DissectorRegistry, Payload, Registration};
 use crate::layers::tls::TlsParser;
-use crate::parser::decoder::decode_headers;
-use crate::parser::parse_pcap;
-use crate::parser::packets::add_packet;
-use crate::parser::tcp::looks_like_tls;
-use etherparse::PacketBuilder;
+use crate::layers::tls_decrypt::{KeyLog, p_hash, tls12_key_block, tls13_traffic_keys};
+use crate::layers::{IpFragment, PacketContext, TcpSegment};
+use crate::parser::decoder::{decode_headers, decode_link_headers, dissect_packet};
+use crate::parser::defrag::{FragmentKey, IpReassembler};
+use crate::parser::follow::{FollowDirection, FollowFormat, follow_stream, render_bytes};
+use crate::parser::packets::{CaptureRecord, add_packet};
+use crate::parser::pipeline::{BatchSink, FlowBatch, merge_flows};
+use crate::parser::reassembly::{
+    SegmentKind, SegmentResult, StreamDirection, StreamGap, TcpStream,
+};
+use crate::parser::state::ParseState;
+use crate::parser::state::SkippedInterface;
+use crate::parser::tcp::{handle_tcp_segment, looks_like_tls};
+use crate::parser::{
+    Cancelled, ParseOptions, expand_paths, is_stream, parse_capture, parse_capture_incremental,
+    parse_capture_with_workers, parse_captures, parse_pcap, parse_stream,
+};
+use aes_gcm::aead::{Aead, KeyInit, Payload as AeadPayload};
+use aes_gcm::{Aes128Gcm, Nonce};
+use etherparse::{
+    ArpHardwareId, ArpOperation, ArpPacket, EtherType, IpFragOffset, IpNumber, Ipv4Header,
+    Ipv6FragmentHeader, Ipv6Header, PacketBuilder, icmpv4,
+};
+use hmac::Hmac;
 use pcap_parser::pcapng::{NameRecord, NameRecordType, NameResolutionBlock};
-use pcap_parser::NRB_MAGIC;
-use std::collections::HashMap;
+use pcap_parser::{Linktype, NRB_MAGIC};
+use sha2::{Sha256, Sha384};
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::ops::Range;
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
 
 fn build_tcp_packet(
     flags: impl FnOnce(
         etherparse::PacketBuilderStep<etherparse::TcpHeader>,
     ) -> etherparse::PacketBuilderStep<etherparse::TcpHeader>,
     payload: &[u8],
+) -> Vec<u8> {
+    build_tcp_segment(1, flags, payload)
+}
+
+fn build_tcp_segment(
+    seq: u32,
+    flags: impl FnOnce(
+        etherparse::PacketBuilderStep<etherparse::TcpHeader>,
+    ) -> etherparse::PacketBuilderStep<etherparse::TcpHeader>,
+    payload: &[u8],
 ) -> Vec<u8> {
     let builder = PacketBuilder::ethernet2([1, 2, 3, 4, 5, 6], [6, 5, 4, 3, 2, 1]).ipv4(
         [10, 0, 0, 1],
         [10, 0, 0, 2],
         64,
     );
-    let builder = flags(builder.tcp(12345, 80, 1, 64240));
+    let builder = flags(builder.tcp(12345, 80, seq, 64240));
+
+    let mut packet = Vec::with_capacity(builder.size(payload.len()));
+    builder.write(&mut packet, payload).unwrap();
+    packet
+}
+
+/// Builds a packet of the 10.0.0.1:12345 <-> 10.0.0.2:80 conversation with the ACK flag set.
+fn build_conversation_packet(
+    from_client: bool,
+    seq: u32,
+    ack: u32,
+    window: u16,
+    payload: &[u8],
+) -> Vec<u8> {
+    let (src, dst, src_port, dst_port) = if from_client {
+        ([10, 0, 0, 1], [10, 0, 0, 2], 12345, 80)
+    } else {
+        ([10, 0, 0, 2], [10, 0, 0, 1], 80, 12345)
+    };
+    let builder = PacketBuilder::ethernet2([1, 2, 3, 4, 5, 6], [6, 5, 4, 3, 2, 1])
+        .ipv4(src, dst, 64)
+        .tcp(src_port, dst_port, seq, window)
+        .ack(ack);
 
     let mut packet = Vec::with_capacity(builder.size(payload.len()));
     builder.write(&mut packet, payload).unwrap();
@@ -57,6 +118,142 @@ fn build_ipv6_udp_packet(payload: &[u8]) -> Vec<u8> {
     packet
 }
 
+fn write_legacy_pcap(
+    name: &str,
+    magic: u32,
+    big_endian: bool,
+    linktype: u32,
+    records: &[(u32, u32, Vec<u8>)],
+) -> std::path::PathBuf {
+    let u16_bytes = |v: u16| {
+        if big_endian {
+            v.to_be_bytes()
+        } else {
+            v.to_le_bytes()
+        }
+    };
+    let u32_bytes = |v: u32| {
+        if big_endian {
+            v.to_be_bytes()
+        } else {
+            v.to_le_bytes()
+        }
+    };
+
+    let mut buf = Vec::new();
+    buf.extend_from_slice(&u32_bytes(magic));
+    buf.extend_from_slice(&u16_bytes(2)); // version major
+    buf.extend_from_slice(&u16_bytes(4)); // version minor
+    buf.extend_from_slice(&u32_bytes(0)); // thiszone
+    buf.extend_from_slice(&u32_bytes(0)); // sigfigs
+    buf.extend_from_slice(&u32_bytes(65535)); // snaplen
+    buf.extend_from_slice(&u32_bytes(linktype));
+
+    for (ts_sec, ts_frac, data) in records {
+        buf.extend_from_slice(&u32_bytes(*ts_sec));
+        buf.extend_from_slice(&u32_bytes(*ts_frac));
+        buf.extend_from_slice(&u32_bytes(data.len() as u32)); // caplen
+        buf.extend_from_slice(&u32_bytes(data.len() as u32)); // origlen
+        if magic == 0xa1b2_cd34 {
+            buf.extend_from_slice(&u32_bytes(1)); // ifindex
+            buf.extend_from_slice(&u16_bytes(0x0800)); // protocol
+            buf.extend_from_slice(&[0, 0]); // packet type, padding
+        }
+        buf.extend_from_slice(data);
+    }
+
+    let path = std::env::temp_dir().join(name);
+    std::fs::write(&path, buf).expect("write legacy pcap");
+    path
+}
+
+/// Replaces the Ethernet header of `packet` with `link_header`.
+fn with_link_header(link_header: &[u8], packet: &[u8]) -> Vec<u8> {
+    let mut framed = link_header.to_vec();
+    framed.extend_from_slice(&packet[14..]);
+    framed
+}
+
+fn assert_decodes_syn(packet: &[u8], linktype: Linktype) {
+    let ctx = decode_link_headers(packet, linktype)
+        .unwrap_or_else(|err| panic!("decode {linktype:?}: {err}"));
+
+    assert_eq!(ctx.src_ip, Some(IPAddress::V4([10, 0, 0, 1])));
+    assert_eq!(ctx.dst_ip, Some(IPAddress::V4([10, 0, 0, 2])));
+    assert_eq!(ctx.src_port, Some(12345));
+    assert_eq!(ctx.dst_port, Some(80));
+    assert_eq!(ctx.protocol, Some(Protocol::TCP));
+    assert!(ctx.is_syn);
+}
+
+/// Pushes a segment carrying `payload` at `seq` onto `stream`.
+fn push_segment(stream: &mut TcpStream, seq: u32, payload: &[u8]) -> SegmentResult {
+    let segment = TcpSegment {
+        seq,
+        payload: 0..payload.len(),
+        ..Default::default()
+    };
+    stream.push(&segment, payload)
+}
+
+/// A TLS 1.2 ClientHello record with a single cipher suite and no extensions.
+fn build_client_hello_record() -> Vec<u8> {
+    build_client_hello_record_with(&[0x002f], &[])
+}
+
+/// A TLS 1.2 ClientHello record offering `cipher_suites`, with the given extensions.
+fn build_client_hello_record_with(cipher_suites: &[u16], extensions: &[u8]) -> Vec<u8> {
+    let mut body = vec![0x03, 0x03];
+    body.extend_from_slice(&[0x42; 32]); // random
+    body.push(0); // session id
+    body.extend_from_slice(&((cipher_suites.len() * 2) as u16).to_be_bytes());
+    for cipher_suite in cipher_suites {
+        body.extend_from_slice(&cipher_suite.to_be_bytes());
+    }
+    body.extend_from_slice(&[0x01, 0x00]); // compression methods
+    if !extensions.is_empty() {
+        body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        body.extend_from_slice(extensions);
+    }
+
+    let mut handshake = vec![0x01, 0x00];
+    handshake.extend_from_slice(&(body.len() as u16).to_be_bytes());
+    handshake.extend_from_slice(&body);
+
+    let mut record = vec![0x16, 0x03, 0x03];
+    record.extend_from_slice(&(handshake.len() as u16).to_be_bytes());
+    record.extend_from_slice(&handshake);
+    record
+}
+
+/// Decodes `packet` and runs it through stream reassembly, returning the packet's tags.
+fn reassemble_packet(packet: &[u8], state: &mut ParseState) -> Vec<String> {
+    let mut context = decode_headers(packet).expect("decode tcp");
+    handle_tcp_segment(
+        &mut context,
+        packet,
+        0.0,
+        &TlsParser::default(),
+        &DissectorRegistry::default(),
+        state,
+    );
+    context.tags
+}
+
+/// Decodes and reassembles `packet`, returning its TCP analysis flags.
+fn analyze_packet(packet: &[u8], state: &mut ParseState) -> Vec<TcpAnalysisFlag> {
+    let mut context = decode_headers(packet).expect("decode tcp");
+    handle_tcp_segment(
+        &mut context,
+        packet,
+        0.0,
+        &TlsParser::default(),
+        &DissectorRegistry::default(),
+        state,
+    );
+    context.tcp_analysis
+}
+
 fn build_dns_response_payload(v6_ip: [u8; 16]) -> Vec<u8> {
     let mut buf = Vec::new();
 
@@ -101,9 +298,7 @@ fn build_dns_response_payload(v6_ip: [u8; 16]) -> Vec<u8> {
 fn tcp_decode_sets_flags_and_ports() {
     let payload = [];
     let packet = build_tcp_packet(|b| b.syn(), &payload);
-    let tls_parser = TlsParser;
-
-    let ctx = decode_headers(&packet, &tls_parser).expect("decode tcp");
+    let ctx = decode_headers(&packet).expect("decode tcp");
 
     assert_eq!(ctx.src_ip, Some(IPAddress::V4([10, 0, 0, 1])));
     assert_eq!(ctx.dst_ip, Some(IPAddress::V4([10, 0, 0, 2])));
@@ -118,9 +313,7 @@ fn tcp_decode_sets_flags_and_ports() {
 #[test]
 fn udp_decode_sets_protocol_and_ports() {
     let packet = build_udp_packet(&[1, 2, 3]);
-    let tls_parser = TlsParser;
-
-    let ctx = decode_headers(&packet, &tls_parser).expect("decode udp");
+    let ctx = decode_headers(&packet).expect("decode udp");
 
     assert_eq!(ctx.src_port, Some(5353));
     assert_eq!(ctx.dst_port, Some(8053));
@@ -128,16 +321,28 @@ fn udp_decode_sets_protocol_and_ports() {
     assert_eq!(ctx.tags.len(), 0);
 }
 
+fn record_at(timestamp: f64) -> CaptureRecord {
+    CaptureRecord {
+        timestamp,
+        ..Default::default()
+    }
+}
+
 #[test]
 fn add_packet_creates_flow_and_counts_packets() {
     let packet = build_tcp_packet(|b| b.syn(), &[]);
-    let tls_parser = TlsParser;
-    let context = decode_headers(&packet, &tls_parser).expect("decode packet");
+    let context = decode_headers(&packet).expect("decode packet");
 
     let mut flows = HashMap::new();
     let mut packet_count = 0usize;
 
-    add_packet(&packet, context, 1.0, &mut flows, &mut packet_count);
+    add_packet(
+        packet.as_slice().into(),
+        context,
+        record_at(1.0),
+        &mut flows,
+        &mut packet_count,
+    );
 
     assert_eq!(packet_count, 1);
     assert_eq!(flows.len(), 1);
@@ -180,19 +385,23 @@ fn tcp_tls_packets_get_tagged() {
     let tls_payload: [u8; 9] = [0x16, 0x03, 0x03, 0x00, 0x05, 0x01, 0x00, 0x00, 0x00];
 
     let packet = build_tcp_packet(|b| b.syn(), &tls_payload);
-    let tls_parser = TlsParser;
-
     // Sanity: looks_like_tls should be true for this payload.
     assert!(looks_like_tls(&tls_payload));
 
-    let ctx = decode_headers(&packet, &tls_parser).expect("decode tls-ish tcp");
+    let ctx = decode_headers(&packet).expect("decode tls-ish tcp");
     assert_eq!(ctx.protocol, Some(Protocol::TCP));
     // Even if the TLS parser cannot fully classify this tiny record, tags should include at least the TCP flag marker.
     assert!(!ctx.tags.is_empty(), "expected some tags (e.g., SYN) on TLS-looking packet");
 
     let mut flows = HashMap::new();
     let mut packet_count = 0usize;
-    add_packet(&packet, ctx, 1.0, &mut flows, &mut packet_count);
+    add_packet(
+        packet.as_slice().into(),
+        ctx,
+        record_at(1.0),
+        &mut flows,
+        &mut packet_count,
+    );
 
     assert_eq!(packet_count, 1);
     let flow = flows.values().next().unwrap();
@@ -203,14 +412,12 @@ fn tcp_tls_packets_get_tagged() {
 fn decode_ipv6_tcp_and_udp() {
     let tcp_packet = build_ipv6_tcp_packet(&[]);
     let udp_packet = build_ipv6_udp_packet(&[1, 2, 3, 4]);
-    let tls_parser = TlsParser;
-
-    let tcp_ctx = decode_headers(&tcp_packet, &tls_parser).expect("decode ipv6 tcp");
+    let tcp_ctx = decode_headers(&tcp_packet).expect("decode ipv6 tcp");
     assert_eq!(tcp_ctx.protocol, Some(Protocol::TCP));
     assert!(matches!(tcp_ctx.src_ip, Some(IPAddress::V6(_))));
     assert!(matches!(tcp_ctx.dst_ip, Some(IPAddress::V6(_))));
 
-    let udp_ctx = decode_headers(&udp_packet, &tls_parser).expect("decode ipv6 udp");
+    let udp_ctx = decode_headers(&udp_packet).expect("decode ipv6 udp");
     assert_eq!(udp_ctx.protocol, Some(Protocol::UDP));
     assert!(matches!(udp_ctx.src_ip, Some(IPAddress::V6(_))));
     assert!(matches!(udp_ctx.dst_ip, Some(IPAddress::V6(_))));
@@ -230,12 +437,13 @@ fn dns_responses_populate_name_resolutions() {
         is_syn: false,
         is_ack: false,
         tags: Vec::new(),
-        udp_payload: Some(payload.clone()),
+        udp_payload: Some(0..payload.len()),
+        ..Default::default()
     };
 
     let mut resolutions = HashMap::new();
-    crate::parser::dns::handle_dns_response(&context, &mut resolutions);
-    crate::parser::dns::handle_dns_response(&context, &mut resolutions);
+    crate::parser::dns::handle_dns_response(&context, &payload, &mut resolutions);
+    crate::parser::dns::handle_dns_response(&context, &payload, &mut resolutions);
 
     let v4_names = resolutions
         .get(&IPAddress::V4([1, 2, 3, 4]))
@@ -296,3 +504,2566 @@ fn name_resolution_records_ipv4_and_ipv6() {
     assert_eq!(v6_names.len(), 1);
     assert!(v6_names.contains(&"example.local".to_string()));
 }
+
+#[test]
+fn parse_pcap_reads_legacy_microsecond_capture() {
+    let syn = build_tcp_packet(|b| b.syn(), &[]);
+    let udp = build_udp_packet(&[1, 2, 3]);
+    let path = write_legacy_pcap(
+        "wirecrab_legacy_usec_test.pcap",
+        0xa1b2_c3d4,
+        false,
+        1,
+        &[
+            (1_700_000_000, 250_000, syn.clone()),
+            (1_700_000_001, 500_000, udp.clone()),
+        ],
+    );
+
+    let (flows, start_ts, _names) = parse_pcap(&path, |_p| {}).expect("parse legacy pcap");
+
+    assert_eq!(flows.len(), 2);
+    let start = start_ts.expect("expected start timestamp");
+    assert!((start - 1_700_000_000.25).abs() < 1e-6);
+
+    let tcp_flow = flows
+        .values()
+        .find(|flow| flow.protocol == Protocol::TCP)
+        .expect("tcp flow");
+    assert!(tcp_flow.packets[0].tags.contains(&"SYN".to_string()));
+
+    // Packets only point into the file; their bytes are read back on demand.
+    let udp_flow = flows
+        .values()
+        .find(|flow| flow.protocol == Protocol::UDP)
+        .expect("udp flow");
+    assert!(matches!(udp_flow.packets[0].data, PacketData::File { .. }));
+    assert_eq!(udp_flow.packets[0].data.len(), udp.len());
+    assert_eq!(tcp_flow.packets[0].data.load().unwrap().as_ref(), syn);
+    assert_eq!(udp_flow.packets[0].data.load().unwrap().as_ref(), udp);
+    drop(flows);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn parse_pcap_reads_legacy_nanosecond_big_endian_capture() {
+    let packet = build_tcp_packet(|b| b.syn(), &[]);
+    let path = write_legacy_pcap(
+        "wirecrab_legacy_nsec_be_test.pcap",
+        0xa1b2_3c4d,
+        true,
+        1,
+        &[(1_700_000_000, 123_456_789, packet)],
+    );
+
+    let (flows, start_ts, _names) = parse_pcap(&path, |_p| {}).expect("parse legacy pcap");
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(flows.len(), 1);
+    let start = start_ts.expect("expected start timestamp");
+    assert!((start - 1_700_000_000.123_456_7).abs() < 1e-6);
+}
+
+#[test]
+fn parse_pcap_reads_modified_capture_in_either_byte_order() {
+    let syn = build_tcp_packet(|b| b.syn(), &[]);
+    let udp = build_udp_packet(&[1, 2, 3]);
+    for big_endian in [false, true] {
+        let path = write_legacy_pcap(
+            &format!("wirecrab_legacy_modified_{big_endian}_test.pcap"),
+            0xa1b2_cd34,
+            big_endian,
+            1,
+            &[
+                (1_700_000_000, 250_000, syn.clone()),
+                (1_700_000_001, 500_000, udp.clone()),
+            ],
+        );
+
+        let (flows, start_ts, _names) = parse_pcap(&path, |_p| {}).expect("parse modified pcap");
+
+        assert_eq!(flows.len(), 2, "big endian: {big_endian}");
+        let start = start_ts.expect("expected start timestamp");
+        assert!((start - 1_700_000_000.25).abs() < 1e-6);
+        let udp_flow = flows
+            .values()
+            .find(|flow| flow.protocol == Protocol::UDP)
+            .expect("udp flow");
+        assert_eq!(udp_flow.packets[0].data.load().unwrap().as_ref(), udp);
+        drop(flows);
+        let _ = std::fs::remove_file(&path);
+    }
+}
+
+#[test]
+fn parse_pcap_rejects_unknown_magic() {
+    let path = std::env::temp_dir().join("wirecrab_unknown_magic_test.pcap");
+    std::fs::write(&path, [0xde, 0xad, 0xbe, 0xef, 0, 0, 0, 0]).expect("write temp file");
+
+    let result = parse_pcap(&path, |_p| {});
+    let _ = std::fs::remove_file(&path);
+
+    assert!(result.is_err(), "unknown magic should fail to parse");
+}
+
+#[test]
+fn decode_linux_sll_and_sll2() {
+    let packet = build_tcp_packet(|b| b.syn(), &[]);
+
+    let mut sll = [0u8; 16];
+    sll[14..16].copy_from_slice(&0x0800u16.to_be_bytes());
+    assert_decodes_syn(&with_link_header(&sll, &packet), Linktype::LINUX_SLL);
+
+    let mut sll2 = [0u8; 20];
+    sll2[0..2].copy_from_slice(&0x0800u16.to_be_bytes());
+    assert_decodes_syn(&with_link_header(&sll2, &packet), Linktype::LINUX_SLL2);
+}
+
+#[test]
+fn decode_raw_ip() {
+    let packet = build_tcp_packet(|b| b.syn(), &[]);
+    let raw = with_link_header(&[], &packet);
+
+    assert_decodes_syn(&raw, Linktype::RAW);
+    assert_decodes_syn(&raw, Linktype::IPV4);
+}
+
+#[test]
+fn decode_loopback_families_in_either_byte_order() {
+    let packet = build_tcp_packet(|b| b.syn(), &[]);
+
+    assert_decodes_syn(
+        &with_link_header(&2u32.to_le_bytes(), &packet),
+        Linktype::NULL,
+    );
+    assert_decodes_syn(
+        &with_link_header(&2u32.to_be_bytes(), &packet),
+        Linktype::NULL,
+    );
+    assert_decodes_syn(
+        &with_link_header(&2u32.to_be_bytes(), &packet),
+        Linktype::LOOP,
+    );
+
+    let unknown_family = with_link_header(&99u32.to_le_bytes(), &packet);
+    assert!(decode_link_headers(&unknown_family, Linktype::NULL).is_err());
+}
+
+#[test]
+fn parse_capture_decodes_linux_sll_capture() {
+    let mut sll = [0u8; 16];
+    sll[14..16].copy_from_slice(&0x0800u16.to_be_bytes());
+    let packet = with_link_header(&sll, &build_tcp_packet(|b| b.syn(), &[]));
+    let path = write_legacy_pcap(
+        "wirecrab_legacy_sll_test.pcap",
+        0xa1b2_c3d4,
+        false,
+        113,
+        &[(1_700_000_000, 0, packet)],
+    );
+
+    let state = parse_capture(&path, |_p| {}).expect("parse sll capture");
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(state.flows.len(), 1);
+    assert!(state.skipped_interfaces.is_empty());
+}
+
+#[test]
+fn parse_capture_counts_packets_with_unsupported_linktype() {
+    let packet = vec![0u8; 32];
+    let path = write_legacy_pcap(
+        "wirecrab_legacy_unsupported_linktype_test.pcap",
+        0xa1b2_c3d4,
+        false,
+        105,
+        &[
+            (1_700_000_000, 0, packet.clone()),
+            (1_700_000_001, 0, packet),
+        ],
+    );
+
+    let state = parse_capture(&path, |_p| {}).expect("parse capture");
+    let _ = std::fs::remove_file(&path);
+
+    assert!(state.flows.is_empty());
+    assert_eq!(state.skipped_packet_count(), 2);
+    assert_eq!(
+        state.skipped_interfaces.get(&0),
+        Some(&SkippedInterface {
+            linktype: Linktype(105),
+            packets: 2,
+        })
+    );
+}
+
+#[test]
+fn tcp_stream_reorders_out_of_order_segments() {
+    let mut stream = TcpStream::default();
+    let syn = TcpSegment {
+        seq: 1000,
+        syn: true,
+        ..Default::default()
+    };
+
+    assert_eq!(stream.push(&syn, &[]).kind, SegmentKind::Empty);
+    let result = push_segment(&mut stream, 1006, b"world");
+    assert_eq!(result.kind, SegmentKind::OutOfOrder);
+    assert!(stream.data().is_empty());
+    assert_eq!(stream.pending_bytes(), 5);
+
+    let result = push_segment(&mut stream, 1001, b"hello");
+    assert_eq!(result.kind, SegmentKind::InOrder);
+    assert_eq!(result.appended, 0..10);
+    assert_eq!(stream.data(), b"helloworld");
+    assert_eq!(stream.pending_bytes(), 0);
+    assert_eq!(stream.next_seq(), Some(1011));
+}
+
+#[test]
+fn tcp_stream_ignores_retransmissions_and_trims_overlaps() {
+    let mut stream = TcpStream::default();
+
+    let mut push = |seq, payload: &[u8]| push_segment(&mut stream, seq, payload).kind;
+
+    assert_eq!(push(50, b"abcd"), SegmentKind::InOrder);
+    assert_eq!(push(50, b"abcd"), SegmentKind::Retransmission);
+    assert_eq!(push(52, b"cdef"), SegmentKind::Overlap);
+    assert_eq!(push(60, b"xy"), SegmentKind::OutOfOrder);
+    assert_eq!(push(60, b"x"), SegmentKind::Retransmission);
+
+    assert_eq!(stream.data(), b"abcdef");
+}
+
+#[test]
+fn tcp_stream_restarts_on_syn_with_new_initial_sequence_number() {
+    let mut stream = TcpStream::default();
+    let syn = |seq| TcpSegment {
+        seq,
+        syn: true,
+        ..Default::default()
+    };
+
+    stream.push(&syn(1000), &[]);
+    push_segment(&mut stream, 1001, b"old");
+    assert!(!stream.push(&syn(1000), &[]).restarted);
+
+    let result = stream.push(&syn(5000), &[]);
+    assert!(result.restarted);
+    assert!(stream.data().is_empty());
+    let result = push_segment(&mut stream, 5001, b"new");
+    assert_eq!(result.kind, SegmentKind::InOrder);
+    assert_eq!(stream.data(), b"new");
+}
+
+#[test]
+fn tcp_stream_restarts_on_sequence_jump_outside_the_window() {
+    let mut stream = TcpStream::default();
+    push_segment(&mut stream, 1, b"abc");
+
+    let result = push_segment(&mut stream, 0x8000_0000, b"xyz");
+
+    assert!(result.restarted);
+    assert_eq!(result.kind, SegmentKind::InOrder);
+    assert_eq!(stream.data(), b"xyz");
+    assert_eq!(stream.next_seq(), Some(0x8000_0003));
+}
+
+#[test]
+fn tcp_stream_places_nothing_before_the_first_byte_captured_mid_connection() {
+    let mut stream = TcpStream::default();
+    push_segment(&mut stream, 100, b"def");
+
+    assert_eq!(
+        push_segment(&mut stream, 97, b"abc").kind,
+        SegmentKind::BeforeStart
+    );
+    assert_eq!(stream.data(), b"def");
+
+    let mut stream = TcpStream::default();
+    let syn = TcpSegment {
+        seq: 96,
+        syn: true,
+        ..Default::default()
+    };
+    stream.push(&syn, &[]);
+    push_segment(&mut stream, 100, b"def");
+    assert_eq!(push_segment(&mut stream, 97, b"abc").kind, SegmentKind::InOrder);
+    assert_eq!(stream.data(), b"abcdef");
+}
+
+#[test]
+fn tcp_stream_handles_sequence_wraparound() {
+    let mut stream = TcpStream::default();
+
+    push_segment(&mut stream, u32::MAX - 2, b"abc");
+    push_segment(&mut stream, 3, b"ghi");
+    push_segment(&mut stream, 0, b"def");
+
+    assert_eq!(stream.data(), b"abcdefghi");
+    assert_eq!(stream.next_seq(), Some(6));
+}
+
+#[test]
+fn tcp_stream_records_gap_when_flushed() {
+    let mut stream = TcpStream::default();
+    push_segment(&mut stream, 1, b"abc");
+    push_segment(&mut stream, 10, b"xyz");
+
+    assert_eq!(stream.flush_gaps(), Some(3));
+    assert_eq!(stream.data(), b"abcxyz");
+    assert_eq!(
+        stream.gaps(),
+        &[StreamGap {
+            position: 3,
+            missing: 6,
+        }]
+    );
+    assert_eq!(stream.next_seq(), Some(13));
+}
+
+#[test]
+fn tcp_stream_release_drops_bytes_but_keeps_sequence() {
+    let mut stream = TcpStream::default();
+    push_segment(&mut stream, 1, b"abcdef");
+
+    assert_eq!(stream.release(4), 4);
+    assert_eq!(stream.data(), b"ef");
+    assert_eq!(stream.next_seq(), Some(7));
+    assert_eq!(
+        push_segment(&mut stream, 3, b"cd").kind,
+        SegmentKind::Retransmission
+    );
+    assert_eq!(push_segment(&mut stream, 7, b"gh").appended, 2..4);
+    assert_eq!(stream.data(), b"efgh");
+    assert_eq!(stream.release(usize::MAX), 4);
+    assert!(stream.data().is_empty());
+}
+
+#[test]
+fn tls_handshake_split_across_segments_is_tagged_on_last_segment() {
+    let record = build_client_hello_record();
+    let (head, tail) = record.split_at(20);
+    let mut state = ParseState::default();
+
+    reassemble_packet(&build_tcp_segment(100, |b| b.syn(), &[]), &mut state);
+    // The tail arrives first; nothing can be parsed until the head fills the hole.
+    let tail_tags = reassemble_packet(
+        &build_tcp_segment(121, |b| b.ack(1).psh(), tail),
+        &mut state,
+    );
+    let head_tags = reassemble_packet(
+        &build_tcp_segment(101, |b| b.ack(1).psh(), head),
+        &mut state,
+    );
+
+    assert!(!tail_tags.iter().any(|tag| tag.starts_with("Client Hello")));
+    assert!(
+        head_tags.iter().any(|tag| tag.starts_with("Client Hello")),
+        "expected Client Hello tag, got {head_tags:?}"
+    );
+}
+
+#[test]
+fn tls_record_starting_mid_segment_is_found() {
+    // Capture starts mid-connection: the first segment ends with the last bytes of an
+    // earlier record before the ClientHello begins.
+    let mut payload = vec![0xde, 0xad, 0xbe, 0xef, 0x00, 0x17];
+    payload.extend_from_slice(&build_client_hello_record());
+    let mut state = ParseState::default();
+
+    let tags = reassemble_packet(
+        &build_tcp_segment(5000, |b| b.ack(1).psh(), &payload),
+        &mut state,
+    );
+
+    assert!(
+        tags.iter().any(|tag| tag.starts_with("Client Hello")),
+        "expected Client Hello tag, got {tags:?}"
+    );
+}
+
+#[test]
+fn tcp_analysis_flags_out_of_order_and_retransmission() {
+    let mut state = ParseState::default();
+
+    assert!(
+        analyze_packet(
+            &build_conversation_packet(true, 1, 1, 1000, b"aaaa"),
+            &mut state
+        )
+        .is_empty()
+    );
+    assert!(
+        analyze_packet(
+            &build_conversation_packet(true, 9, 1, 1000, b"cccc"),
+            &mut state
+        )
+        .is_empty()
+    );
+    assert_eq!(
+        analyze_packet(
+            &build_conversation_packet(true, 5, 1, 1000, b"bbbb"),
+            &mut state
+        ),
+        vec![TcpAnalysisFlag::OutOfOrder]
+    );
+    assert_eq!(
+        analyze_packet(
+            &build_conversation_packet(true, 1, 1, 1000, b"aaaa"),
+            &mut state
+        ),
+        vec![TcpAnalysisFlag::Retransmission]
+    );
+}
+
+#[test]
+fn tcp_analysis_flags_segments_before_a_mid_connection_start_as_out_of_order() {
+    let mut state = ParseState::default();
+
+    analyze_packet(
+        &build_conversation_packet(true, 9, 1, 1000, b"cccc"),
+        &mut state,
+    );
+
+    assert_eq!(
+        analyze_packet(
+            &build_conversation_packet(true, 5, 1, 1000, b"bbbb"),
+            &mut state
+        ),
+        vec![TcpAnalysisFlag::OutOfOrder]
+    );
+}
+
+#[test]
+fn tls_is_parsed_again_when_a_new_connection_reuses_the_ports() {
+    let record = build_client_hello_record();
+    let mut state = ParseState::default();
+
+    reassemble_packet(&build_tcp_segment(100, |b| b.syn(), &[]), &mut state);
+    let first = reassemble_packet(
+        &build_tcp_segment(101, |b| b.ack(1).psh(), &record),
+        &mut state,
+    );
+    reassemble_packet(&build_tcp_segment(900_000, |b| b.syn(), &[]), &mut state);
+    let second = reassemble_packet(
+        &build_tcp_segment(900_001, |b| b.ack(1).psh(), &record),
+        &mut state,
+    );
+
+    assert!(first.iter().any(|tag| tag.starts_with("Client Hello")));
+    assert!(
+        second.iter().any(|tag| tag.starts_with("Client Hello")),
+        "expected Client Hello tag, got {second:?}"
+    );
+}
+
+#[test]
+fn tcp_analysis_flags_duplicate_acks_and_fast_retransmission() {
+    let mut state = ParseState::default();
+    let server_ack = build_conversation_packet(false, 100, 5, 1000, &[]);
+
+    analyze_packet(
+        &build_conversation_packet(true, 1, 100, 1000, b"aaaa"),
+        &mut state,
+    );
+    analyze_packet(
+        &build_conversation_packet(true, 5, 100, 1000, b"bbbb"),
+        &mut state,
+    );
+    assert!(analyze_packet(&server_ack, &mut state).is_empty());
+    assert_eq!(
+        analyze_packet(&server_ack, &mut state),
+        vec![TcpAnalysisFlag::DuplicateAck]
+    );
+    assert_eq!(
+        analyze_packet(&server_ack, &mut state),
+        vec![TcpAnalysisFlag::DuplicateAck]
+    );
+    assert_eq!(
+        analyze_packet(
+            &build_conversation_packet(true, 5, 100, 1000, b"bbbb"),
+            &mut state
+        ),
+        vec![TcpAnalysisFlag::FastRetransmission]
+    );
+}
+
+#[test]
+fn tcp_analysis_flags_zero_window_and_keep_alive() {
+    let mut state = ParseState::default();
+
+    analyze_packet(
+        &build_conversation_packet(true, 1, 100, 1000, b"aaaa"),
+        &mut state,
+    );
+    assert_eq!(
+        analyze_packet(
+            &build_conversation_packet(false, 100, 5, 0, &[]),
+            &mut state
+        ),
+        vec![TcpAnalysisFlag::ZeroWindow]
+    );
+    assert_eq!(
+        analyze_packet(
+            &build_conversation_packet(true, 4, 100, 1000, b"a"),
+            &mut state
+        ),
+        vec![TcpAnalysisFlag::KeepAlive]
+    );
+}
+
+#[test]
+fn tcp_analysis_flags_window_full_after_handshake() {
+    let mut state = ParseState::default();
+    let syn = build_tcp_segment(0, |b| b.syn(), &[]);
+    let syn_ack = {
+        let builder = PacketBuilder::ethernet2([6, 5, 4, 3, 2, 1], [1, 2, 3, 4, 5, 6])
+            .ipv4([10, 0, 0, 2], [10, 0, 0, 1], 64)
+            .tcp(80, 12345, 0, 8)
+            .syn()
+            .ack(1);
+        let mut packet = Vec::with_capacity(builder.size(0));
+        builder.write(&mut packet, &[]).unwrap();
+        packet
+    };
+
+    analyze_packet(&syn, &mut state);
+    analyze_packet(&syn_ack, &mut state);
+    assert_eq!(
+        analyze_packet(
+            &build_conversation_packet(true, 1, 1, 1000, b"12345678"),
+            &mut state
+        ),
+        vec![TcpAnalysisFlag::WindowFull]
+    );
+}
+
+#[test]
+fn parse_capture_counts_tcp_analysis_per_flow() {
+    let data = build_conversation_packet(true, 1, 1, 1000, b"hello");
+    let path = write_legacy_pcap(
+        "wirecrab_tcp_analysis_test.pcap",
+        0xa1b2_c3d4,
+        false,
+        1,
+        &[
+            (1_700_000_000, 0, data.clone()),
+            (1_700_000_001, 0, data),
+            (
+                1_700_000_002,
+                0,
+                build_conversation_packet(false, 1, 6, 0, &[]),
+            ),
+        ],
+    );
+
+    let state = parse_capture(&path, |_p| {}).expect("parse capture");
+    let _ = std::fs::remove_file(&path);
+
+    let flow = state.flows.values().next().expect("tcp flow");
+    assert_eq!(flow.analysis.retransmissions, 1);
+    assert_eq!(flow.analysis.zero_windows, 1);
+    assert_eq!(flow.analysis.issues(), 2);
+    assert!(
+        flow.packets[1]
+            .tags
+            .contains(&"TCP Retransmission".to_string())
+    );
+}
+
+#[test]
+fn follow_stream_delivers_tcp_payload_in_sequence_order() {
+    let packets = [
+        build_conversation_packet(true, 100, 500, 1024, b"hello "),
+        build_conversation_packet(false, 500, 106, 1024, b"hi"),
+        build_conversation_packet(true, 112, 502, 1024, b"!"),
+        build_conversation_packet(true, 106, 502, 1024, b"world "),
+    ];
+    let mut flows = HashMap::new();
+    let mut packet_count = 0usize;
+    for (index, packet) in packets.iter().enumerate() {
+        let context = decode_headers(packet).expect("decode packet");
+        add_packet(
+            packet.as_slice().into(),
+            context,
+            record_at(index as f64),
+            &mut flows,
+            &mut packet_count,
+        );
+    }
+    let flow = flows.values().next().expect("one flow");
+
+    let followed = follow_stream(flow, &[]);
+    let chunks: Vec<(FollowDirection, &[u8])> = followed
+        .chunks
+        .iter()
+        .map(|chunk| (chunk.direction, chunk.data.as_slice()))
+        .collect();
+    assert_eq!(
+        chunks,
+        vec![
+            (FollowDirection::ClientToServer, &b"hello "[..]),
+            (FollowDirection::ServerToClient, &b"hi"[..]),
+            (FollowDirection::ClientToServer, &b"world !"[..]),
+        ]
+    );
+    assert_eq!(followed.chunks[2].timestamp, 3.0);
+    assert_eq!(
+        followed.payload(FollowDirection::ClientToServer),
+        b"hello world !"
+    );
+    assert_eq!(followed.missing_bytes, 0);
+}
+
+#[test]
+fn follow_formats_render_payload() {
+    let data = b"GET /\r\n\x00\xff";
+    assert_eq!(render_bytes(data, FollowFormat::Ascii, 0), "GET /\n..");
+    assert_eq!(render_bytes(data, FollowFormat::Raw, 0), "474554202f0d0a00ff");
+    assert_eq!(
+        render_bytes(data, FollowFormat::HexDump, 0x20),
+        "00000020  47 45 54 20 2f 0d 0a 00  ff                       GET /...."
+    );
+    assert_eq!(
+        render_bytes("caf\u{e9}\r\n".as_bytes(), FollowFormat::Utf8, 0),
+        "caf\u{e9}\n"
+    );
+}
+
+#[test]
+fn dissect_packet_builds_layers_with_byte_ranges() {
+    let record = build_client_hello_record();
+    let packet = build_tcp_packet(|b| b.psh().ack(7), &record);
+    let tree = dissect_packet(&packet, Linktype::ETHERNET, &DissectorRegistry::default())
+        .expect("dissect tls packet")
+        .tree;
+
+    let names: Vec<&str> = tree
+        .layers
+        .iter()
+        .map(|layer| layer.name.as_str())
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "Frame",
+            "Ethernet II",
+            "Internet Protocol Version 4",
+            "Transmission Control Protocol",
+            "Transport Layer Security",
+        ]
+    );
+
+    let ip = &tree.layers[2];
+    assert_eq!(ip.range, 14..34);
+    let source = ip
+        .children
+        .iter()
+        .find(|f| f.name == "Source Address")
+        .unwrap();
+    assert_eq!(source.value.as_deref(), Some("10.0.0.1"));
+    assert_eq!(&packet[source.range.clone()], &[10, 0, 0, 1]);
+
+    let tcp = &tree.layers[3];
+    let flags = tcp.children.iter().find(|f| f.name == "Flags").unwrap();
+    assert_eq!(flags.value.as_deref(), Some("0x018 (ACK, PSH)"));
+
+    let tls = &tree.layers[4];
+    assert_eq!(tls.range, 54..packet.len());
+    assert_eq!(
+        tls.value.as_deref(),
+        Some("Client Hello (TLS 1.2 (0x0303))")
+    );
+    assert_eq!(tls.children[3].label(), "Handshake Protocol: Client Hello");
+
+    // Only expanded nodes contribute their children.
+    assert_eq!(tree.visible_rows(&HashSet::new()).len(), 5);
+    let expanded = HashSet::from([vec![4], vec![4, 3]]);
+    let rows = tree.visible_rows(&expanded);
+    assert_eq!(rows.len(), 5 + 4 + 2);
+    assert_eq!(rows.last().unwrap().path, vec![4, 3, 1]);
+    assert_eq!(tree.node(&[4, 3, 0]).unwrap().range, 59..60);
+
+    // The fast path used while loading leaves the tree empty.
+    assert!(decode_headers(&packet).unwrap().tree.is_empty());
+}
+
+/// Test dissector claiming UDP port 8053 and handing everything after its first byte on.
+struct OuterDissector;
+
+impl Dissector for OuterDissector {
+    fn name(&self) -> &'static str {
+        "outer"
+    }
+
+    fn registrations(&self) -> Vec<Registration> {
+        vec![Registration::Port(Protocol::UDP, 8053)]
+    }
+
+    fn dissect(&self, payload: &Payload<'_>, context: &mut PacketContext) -> Option<Range<usize>> {
+        context.tags.push(format!("Outer {}", payload.data[0]));
+        Some(1..payload.data.len())
+    }
+}
+
+/// Test dissector for payload of [`OuterDissector`] starting with `in`.
+struct InnerDissector;
+
+impl Dissector for InnerDissector {
+    fn name(&self) -> &'static str {
+        "inner"
+    }
+
+    fn registrations(&self) -> Vec<Registration> {
+        vec![Registration::Parent("outer")]
+    }
+
+    fn heuristic(&self, data: &[u8]) -> bool {
+        data.starts_with(b"in")
+    }
+
+    fn dissect(&self, payload: &Payload<'_>, context: &mut PacketContext) -> Option<Range<usize>> {
+        context.tags.push(format!("Inner {}", payload.data.len()));
+        None
+    }
+}
+
+#[test]
+fn registry_runs_port_and_parent_dissectors() {
+    let mut registry = DissectorRegistry::default();
+    registry.register(OuterDissector);
+    registry.register(InnerDissector);
+
+    let packet = build_udp_packet(b"\x07inner");
+    let mut context = decode_headers(&packet).unwrap();
+    registry.dissect(&mut context, &packet);
+    assert_eq!(context.tags, ["Outer 7", "Inner 5"]);
+    let payload = context.udp_payload.map(|range| &packet[range]);
+    assert_eq!(payload, Some(&b"\x07inner"[..]));
+
+    let packet = build_udp_packet(b"\x07other");
+    let mut context = decode_headers(&packet).unwrap();
+    registry.dissect(&mut context, &packet);
+    assert_eq!(context.tags, ["Outer 7"]);
+}
+
+#[test]
+fn decode_as_forces_dissector_on_port() {
+    let payload =
+        build_dns_response_payload([0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+    let packet = build_udp_packet(&payload);
+
+    let mut context = decode_headers(&packet).unwrap();
+    DissectorRegistry::default().dissect(&mut context, &packet);
+    assert!(context.name_resolutions.is_empty());
+
+    let rule: DecodeAs = "udp.port==5353,dns".parse().unwrap();
+    assert_eq!(rule.to_string(), "udp.port==5353,dns");
+    let registry = DissectorRegistry::with_rules(&[rule]).unwrap();
+    let mut context = decode_headers(&packet).unwrap();
+    registry.dissect(&mut context, &packet);
+    assert!(
+        context
+            .name_resolutions
+            .contains(&(IPAddress::V4([1, 2, 3, 4]), "example.local".to_string()))
+    );
+
+    assert!("udp.port=5353,dns".parse::<DecodeAs>().is_err());
+    let unknown = DissectorRegistry::with_rules(&["tcp.port==80,gopher".parse().unwrap()]);
+    assert!(unknown.is_err_and(|err| err.starts_with("Unknown dissector 'gopher'")));
+}
+
+#[test]
+fn decode_as_other_dissector_disables_tls_stream_parsing() {
+    let registry = DissectorRegistry::with_rules(&["tcp.port==80,dns".parse().unwrap()]).unwrap();
+    let mut state = ParseState::default();
+    let packet = build_tcp_segment(1, |b| b.ack(1).psh(), &build_client_hello_record());
+    let mut context = decode_headers(&packet).unwrap();
+    handle_tcp_segment(
+        &mut context,
+        &packet,
+        0.0,
+        &TlsParser::default(),
+        &registry,
+        &mut state,
+    );
+
+    assert!(
+        !context
+            .tags
+            .iter()
+            .any(|tag| tag.starts_with("Client Hello"))
+    );
+
+    let default_tags = reassemble_packet(&packet, &mut ParseState::default());
+    assert!(default_tags.iter().any(|tag| tag.starts_with("Client Hello")));
+}
+
+fn hex(text: &str) -> Vec<u8> {
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+/// A Server Hello record with random `[0x24; 32]`, selecting TLS 1.3 through the
+/// supported_versions extension when `tls13` is set.
+fn build_server_hello_record(cipher_suite: u16, tls13: bool) -> Vec<u8> {
+    let mut body = vec![0x03, 0x03];
+    body.extend_from_slice(&[0x24; 32]); // random
+    body.push(0); // session id
+    body.extend_from_slice(&cipher_suite.to_be_bytes());
+    body.push(0); // compression method
+    if tls13 {
+        body.extend_from_slice(&[0x00, 0x06, 0x00, 0x2b, 0x00, 0x02, 0x03, 0x04]);
+    }
+
+    let mut handshake = vec![0x02, 0x00];
+    handshake.extend_from_slice(&(body.len() as u16).to_be_bytes());
+    handshake.extend_from_slice(&body);
+
+    let mut record = vec![0x16, 0x03, 0x03];
+    record.extend_from_slice(&(handshake.len() as u16).to_be_bytes());
+    record.extend_from_slice(&handshake);
+    record
+}
+
+fn aes128_gcm_seal(key: &[u8], nonce: &[u8], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let payload = AeadPayload {
+        msg: plaintext,
+        aad,
+    };
+    Aes128Gcm::new_from_slice(key)
+        .unwrap()
+        .encrypt(Nonce::from_slice(nonce), payload)
+        .unwrap()
+}
+
+/// A TLS 1.3 record carrying `plaintext` of `content_type`, encrypted with `secret`.
+fn seal_tls13_record(secret: &[u8], seq: u64, content_type: u8, plaintext: &[u8]) -> Vec<u8> {
+    let (key, mut nonce) = tls13_traffic_keys(0x1301, secret).unwrap();
+    for (byte, seq_byte) in nonce[4..].iter_mut().zip(seq.to_be_bytes()) {
+        *byte ^= seq_byte;
+    }
+    let mut inner = plaintext.to_vec();
+    inner.push(content_type);
+    let mut record = vec![0x17, 0x03, 0x03];
+    record.extend_from_slice(&((inner.len() + 16) as u16).to_be_bytes());
+    let ciphertext = aes128_gcm_seal(&key, &nonce, &record, &inner);
+    record.extend_from_slice(&ciphertext);
+    record
+}
+
+/// Reassembles `packet` with TLS decryption, returning the packet's tags.
+fn decrypt_packet(
+    packet: &[u8],
+    tls_parser: &TlsParser,
+    registry: &DissectorRegistry,
+    state: &mut ParseState,
+) -> Vec<String> {
+    let mut context = decode_headers(packet).expect("decode tcp");
+    handle_tcp_segment(&mut context, packet, 0.0, tls_parser, registry, state);
+    context.tags
+}
+
+/// Test dissector for decrypted TLS payload starting with `ping`.
+struct PingDissector;
+
+impl Dissector for PingDissector {
+    fn name(&self) -> &'static str {
+        "ping"
+    }
+
+    fn registrations(&self) -> Vec<Registration> {
+        vec![Registration::Parent("tls")]
+    }
+
+    fn heuristic(&self, data: &[u8]) -> bool {
+        data.starts_with(b"ping")
+    }
+
+    fn dissect(&self, payload: &Payload<'_>, context: &mut PacketContext) -> Option<Range<usize>> {
+        context.tags.push(format!("Ping {}", payload.data.len()));
+        None
+    }
+}
+
+#[test]
+fn keylog_derives_tls13_traffic_keys() {
+    let mut keylog = KeyLog::default();
+    let added = keylog.add_lines(
+        "# comment\n\
+         CLIENT_RANDOM 4242 0102\n\
+         SERVER_HANDSHAKE_TRAFFIC_SECRET 4242424242424242424242424242424242424242424242424242424242424242 0a0b\n\
+         EXPORTER_SECRET 4242424242424242424242424242424242424242424242424242424242424242 0a0b\n\
+         SERVER_TRAFFIC_SECRET_0 zz 0a0b\n",
+    );
+    assert_eq!(added, 1);
+
+    // RFC 8448, section 3: server handshake traffic keys.
+    let secret = hex("b67b7d690cc16c4e75e54213cb2d37b4e9c912bcded9105d42befd59d391ad38");
+    let (key, iv) = tls13_traffic_keys(0x1301, &secret).unwrap();
+    assert_eq!(key, hex("3fce516009c21727d0f2e4e86ee403bc"));
+    assert_eq!(iv, hex("5d313eb2671276ee13000b30"));
+}
+
+#[test]
+fn tls12_prf_matches_published_vectors() {
+    // P_SHA256 and P_SHA384 test vectors posted to the IETF TLS working group list.
+    let output = p_hash::<Hmac<Sha256>>(
+        &hex("9bbe436ba940f017b17652849a71db35"),
+        b"test label",
+        &hex("a0ba9f936cda311827a6f796ffd5198c"),
+        100,
+    );
+    assert_eq!(
+        output,
+        hex(concat!(
+            "e3f229ba727be17b8d122620557cd453c2aab21d07c3d495329b52d4e61edb5a",
+            "6b301791e90d35c9c9a46b4e14baf9af0fa022f7077def17abfd3797c0564bab",
+            "4fbc91666e9def9b97fce34f796789baa48082d122ee42c5a72e5a5110fff701",
+            "87347b66",
+        ))
+    );
+
+    let output = p_hash::<Hmac<Sha384>>(
+        &hex("b80b733d6ceefcdc71566ea48e5567df"),
+        b"test label",
+        &hex("cd665cf6a8447dd6ff8b27555edb7465"),
+        148,
+    );
+    assert_eq!(
+        output,
+        hex(concat!(
+            "7b0c18e9ced410ed1804f2cfa34a336a1c14dffb4900bb5fd7942107e81c83cd",
+            "e9ca0faa60be9fe34f82b1233c9146a0e534cb400fed2700884f9dc236f80edd",
+            "8bfa961144c9e8d792eca722a7b32fc3d416d473ebc2c5fd4abfdad05d918425",
+            "9b5bf8cd4d90fa0d31e2dec479e4f1a26066f2eea9a69236a3e52655c9e9aee6",
+            "91c8f3a26854308d5eaa3be85e0990703d73e56f",
+        ))
+    );
+}
+
+#[test]
+fn tls12_key_block_matches_known_answers() {
+    // Expected blocks from OpenSSL's TLS1-PRF with label "key expansion" and the server
+    // random before the client random.
+    let master_secret: Vec<u8> = (0x00..0x30).collect();
+    let client_random: [u8; 32] = std::array::from_fn(|i| 0x40 + i as u8);
+    let server_random: [u8; 32] = std::array::from_fn(|i| 0x60 + i as u8);
+
+    // TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256: two 16 byte keys and two 4 byte IVs.
+    let block = tls12_key_block(0xc02f, &master_secret, &client_random, &server_random).unwrap();
+    assert_eq!(
+        block,
+        hex("423c50f2f6b72c3836113ba32c7996015c603cd23c2778884a8cd4c5bd1f53e553838d6edc7e8f68")
+    );
+
+    // TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384: two 32 byte keys and two 4 byte IVs.
+    let block = tls12_key_block(0xc030, &master_secret, &client_random, &server_random).unwrap();
+    assert_eq!(
+        block,
+        hex(concat!(
+            "9498a22079475afce3772b5145a3f652c06381f361b1a147322936f2dab18af4",
+            "7a3753265071673274ef2d9b82d64367336b2998d67b673c5e1b1f03808a3705",
+            "7f6581b540e8a926",
+        ))
+    );
+}
+
+#[test]
+fn tls13_records_are_decrypted_and_handed_to_dissectors() {
+    let client_random = "42".repeat(32);
+    let keylog = format!(
+        "SERVER_HANDSHAKE_TRAFFIC_SECRET {client_random} {}\n\
+         SERVER_TRAFFIC_SECRET_0 {client_random} {}\n",
+        "01".repeat(32),
+        "02".repeat(32)
+    );
+    let mut registry = DissectorRegistry::default();
+    registry.register(PingDissector);
+    // As read from a pcapng Decryption Secrets Block.
+    let mut tls_parser = TlsParser::default();
+    assert_eq!(tls_parser.add_secrets(&keylog), 2);
+
+    let mut finished = vec![20, 0, 0, 32];
+    finished.extend_from_slice(&[0xaa; 32]);
+    let server_hello = build_server_hello_record(0x1301, true);
+    let handshake = seal_tls13_record(&[1; 32], 0, 22, &finished);
+    let application = seal_tls13_record(&[2; 32], 0, 23, b"ping!");
+
+    let mut state = ParseState::default();
+    let client_hello = build_client_hello_record();
+    let mut seq = 1000;
+    let mut packets = vec![build_conversation_packet(
+        true,
+        1,
+        1000,
+        64240,
+        &client_hello,
+    )];
+    for record in [&server_hello, &handshake, &application] {
+        packets.push(build_conversation_packet(false, seq, 1, 64240, record));
+        seq += record.len() as u32;
+    }
+    let tags: Vec<Vec<String>> = packets
+        .iter()
+        .map(|packet| decrypt_packet(packet, &tls_parser, &registry, &mut state))
+        .collect();
+
+    assert!(tags[1].contains(&"Server Hello (Tls12)".to_string()));
+    assert!(tags[2].contains(&"Finished (Tls13)".to_string()));
+    assert!(tags[3].contains(&"Decrypted Application Data (Tls13)".to_string()));
+    assert!(tags[3].contains(&"Ping 5".to_string()));
+
+    // Without the secrets the same records stay opaque.
+    let mut state = ParseState::default();
+    let tags: Vec<Vec<String>> = packets
+        .iter()
+        .map(|packet| decrypt_packet(packet, &TlsParser::default(), &registry, &mut state))
+        .collect();
+    assert!(
+        tags[2..]
+            .iter()
+            .flatten()
+            .all(|tag| !tag.starts_with("Decrypted"))
+    );
+    assert!(!tags[3].contains(&"Ping 5".to_string()));
+}
+
+#[test]
+fn tls12_gcm_records_are_decrypted_after_change_cipher_spec() {
+    let client_random = [0x42; 32];
+    let master_secret = [7; 48];
+    let mut keylog = KeyLog::default();
+    keylog.add_lines(&format!(
+        "CLIENT_RANDOM {} {}",
+        "42".repeat(32),
+        "07".repeat(48)
+    ));
+    let mut registry = DissectorRegistry::default();
+    registry.set_tls_keylog(keylog);
+    let tls_parser = TlsParser::new(registry.tls_keylog().clone());
+
+    // Key block: client key, server key, client IV, server IV.
+    let block = tls12_key_block(0xc02f, &master_secret, &client_random, &[0x24; 32]).unwrap();
+    let (server_key, server_iv) = (&block[16..32], &block[36..40]);
+    let seal = |seq: u64, content_type: u8, plaintext: &[u8]| {
+        let explicit = [0, 0, 0, 0, 0, 0, 0, seq as u8];
+        let mut aad = seq.to_be_bytes().to_vec();
+        aad.extend_from_slice(&[content_type, 3, 3]);
+        aad.extend_from_slice(&(plaintext.len() as u16).to_be_bytes());
+        let nonce = [server_iv, &explicit].concat();
+        let ciphertext = aes128_gcm_seal(server_key, &nonce, &aad, plaintext);
+        let mut record = vec![content_type, 3, 3];
+        record.extend_from_slice(&((explicit.len() + ciphertext.len()) as u16).to_be_bytes());
+        record.extend_from_slice(&explicit);
+        record.extend_from_slice(&ciphertext);
+        record
+    };
+    let mut finished = vec![20, 0, 0, 12];
+    finished.extend_from_slice(&[0xbb; 12]);
+    let mut server_finish = vec![20, 3, 3, 0, 1, 1]; // Change Cipher Spec
+    server_finish.extend_from_slice(&seal(0, 22, &finished));
+    let server_hello = build_server_hello_record(0xc02f, false);
+    let application = seal(1, 23, b"hello");
+
+    let mut state = ParseState::default();
+    let mut seq = 1000;
+    let mut packets = vec![build_conversation_packet(
+        true,
+        1,
+        1000,
+        64240,
+        &build_client_hello_record(),
+    )];
+    for record in [&server_hello, &server_finish, &application] {
+        packets.push(build_conversation_packet(false, seq, 1, 64240, record));
+        seq += record.len() as u32;
+    }
+    let tags: Vec<Vec<String>> = packets
+        .iter()
+        .map(|packet| decrypt_packet(packet, &tls_parser, &registry, &mut state))
+        .collect();
+
+    assert!(tags[2].contains(&"ChangeCipherSpec (Tls12)".to_string()));
+    assert!(tags[2].contains(&"Finished (Tls12)".to_string()));
+    assert!(tags[3].contains(&"Decrypted Application Data (Tls12)".to_string()));
+}
+
+/// Self-signed Ed25519 certificate for `CN=example.com, O=Wirecrab Test`, with the
+/// alternative names `example.com`, `www.example.com` and `192.0.2.1`.
+const TEST_CERTIFICATE: &str = "308201a230820154a00302010202147e000e535583b7793e5dd0824e5a69a6a2fdfb33300506032b6570302e3114301206035504030c0b6578616d706c652e636f6d31163014060355040a0c0d57697265637261622054657374301e170d3236313031363232353530355a170d3336313031333232353530355a302e3114301206035504030c0b6578616d706c652e636f6d31163014060355040a0c0d57697265637261622054657374302a300506032b6570032100d3d17af8d93e26d38c917a70bfa5b21959f24685de275c776753d24a1a65d8baa38183308180301d0603551d0e041604146a2083c66218141c58838178ddfa913521a1d52a301f0603551d230418301680146a2083c66218141c58838178ddfa913521a1d52a300f0603551d130101ff040530030101ff302d0603551d1104263024820b6578616d706c652e636f6d820f7777772e6578616d706c652e636f6d8704c0000201300506032b6570034100c3fc6aa9846536c3593ca72d53d70b5e95be532e763a5b6873a779a04d9342fc04bf280f6087fa5936c8834c082efb55618a019bdb6ee8242b081cba86254405";
+
+fn tls_extension(extension_type: u16, data: &[u8]) -> Vec<u8> {
+    let mut extension = extension_type.to_be_bytes().to_vec();
+    extension.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    extension.extend_from_slice(data);
+    extension
+}
+
+fn alpn_extension(protocols: &[&str]) -> Vec<u8> {
+    let mut list = Vec::new();
+    for protocol in protocols {
+        list.push(protocol.len() as u8);
+        list.extend_from_slice(protocol.as_bytes());
+    }
+    let mut data = (list.len() as u16).to_be_bytes().to_vec();
+    data.extend_from_slice(&list);
+    tls_extension(16, &data)
+}
+
+fn handshake_message(handshake_type: u8, body: &[u8]) -> Vec<u8> {
+    let mut message = vec![handshake_type];
+    message.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+    message.extend_from_slice(body);
+    message
+}
+
+/// Runs `records` through reassembly as one client record followed by server records,
+/// returning the metadata collected for the connection.
+fn tls_info_of(
+    client_record: &[u8],
+    server_records: &[Vec<u8>],
+    tls_parser: &TlsParser,
+) -> TlsInfo {
+    let mut state = ParseState::default();
+    let registry = DissectorRegistry::default();
+    let client = build_conversation_packet(true, 1, 1000, 64240, client_record);
+    decrypt_packet(&client, tls_parser, &registry, &mut state);
+    let mut seq = 1000;
+    for record in server_records {
+        let packet = build_conversation_packet(false, seq, 1, 64240, record);
+        decrypt_packet(&packet, tls_parser, &registry, &mut state);
+        seq += record.len() as u32;
+    }
+    let session = state
+        .tls_sessions
+        .into_values()
+        .next()
+        .expect("TLS session");
+    session.into_info().expect("TLS metadata")
+}
+
+#[test]
+fn tls12_handshake_metadata_is_collected() {
+    let mut server_name = vec![0x00, 0x0e, 0x00, 0x00, 0x0b];
+    server_name.extend_from_slice(b"example.com");
+    let extensions = [
+        tls_extension(0, &server_name),
+        alpn_extension(&["h2", "http/1.1"]),
+    ]
+    .concat();
+    let client_hello = build_client_hello_record_with(&[0x002f], &extensions);
+
+    let certificate = hex(TEST_CERTIFICATE);
+    let mut chain = (certificate.len() as u32).to_be_bytes()[1..].to_vec();
+    chain.extend_from_slice(&certificate);
+    let mut body = (chain.len() as u32).to_be_bytes()[1..].to_vec();
+    body.extend_from_slice(&chain);
+    let handshake = handshake_message(11, &body);
+    let mut certificate_record = vec![0x16, 0x03, 0x03];
+    certificate_record.extend_from_slice(&(handshake.len() as u16).to_be_bytes());
+    certificate_record.extend_from_slice(&handshake);
+
+    let info = tls_info_of(
+        &client_hello,
+        &[build_server_hello_record(0xc02f, false), certificate_record],
+        &TlsParser::default(),
+    );
+
+    assert_eq!(info.version_name().as_deref(), Some("TLS 1.2"));
+    assert_eq!(info.server_name.as_deref(), Some("example.com"));
+    assert_eq!(info.offered_alpn, ["h2", "http/1.1"]);
+    assert_eq!(info.offered_cipher_suites, [0x002f]);
+    assert_eq!(
+        info.cipher_suite_name().as_deref(),
+        Some("TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256")
+    );
+    assert_eq!(info.certificates.len(), 1);
+    let certificate = &info.certificates[0];
+    assert_eq!(certificate.subject, "CN=example.com, O=Wirecrab Test");
+    assert_eq!(certificate.issuer, certificate.subject);
+    assert_eq!(
+        certificate.subject_alt_names,
+        ["example.com", "www.example.com", "192.0.2.1"]
+    );
+}
+
+#[test]
+fn tls13_encrypted_handshake_metadata_is_collected() {
+    let mut tls_parser = TlsParser::default();
+    tls_parser.add_secrets(&format!(
+        "SERVER_HANDSHAKE_TRAFFIC_SECRET {} {}",
+        "42".repeat(32),
+        "01".repeat(32)
+    ));
+
+    let alpn = alpn_extension(&["h2"]);
+    let mut extensions = (alpn.len() as u16).to_be_bytes().to_vec();
+    extensions.extend_from_slice(&alpn);
+    let certificate = hex(TEST_CERTIFICATE);
+    let mut entry = (certificate.len() as u32).to_be_bytes()[1..].to_vec();
+    entry.extend_from_slice(&certificate);
+    entry.extend_from_slice(&[0, 0]); // entry extensions
+    let mut body = vec![0]; // certificate request context
+    body.extend_from_slice(&(entry.len() as u32).to_be_bytes()[1..]);
+    body.extend_from_slice(&entry);
+    let plaintext = [
+        handshake_message(8, &extensions),
+        handshake_message(11, &body),
+    ]
+    .concat();
+
+    let info = tls_info_of(
+        &build_client_hello_record(),
+        &[
+            build_server_hello_record(0x1301, true),
+            seal_tls13_record(&[1; 32], 0, 22, &plaintext),
+        ],
+        &tls_parser,
+    );
+
+    assert_eq!(info.version_name().as_deref(), Some("TLS 1.3"));
+    assert_eq!(info.alpn.as_deref(), Some("h2"));
+    assert_eq!(
+        info.cipher_suite_name().as_deref(),
+        Some("TLS_AES_128_GCM_SHA256")
+    );
+    assert_eq!(info.certificates.len(), 1);
+    assert_eq!(
+        info.certificates[0].subject,
+        "CN=example.com, O=Wirecrab Test"
+    );
+}
+
+#[test]
+fn tls_fingerprints_skip_grease_values() {
+    let mut server_name = vec![0x00, 0x0e, 0x00, 0x00, 0x0b];
+    server_name.extend_from_slice(b"example.com");
+    let extensions = [
+        tls_extension(0x1a1a, &[]),
+        tls_extension(0, &server_name),
+        tls_extension(10, &[0x00, 0x06, 0x2a, 0x2a, 0x00, 0x1d, 0x00, 0x17]),
+        tls_extension(11, &[0x01, 0x00]),
+        tls_extension(13, &[0x00, 0x04, 0x04, 0x03, 0x08, 0x04]),
+        alpn_extension(&["h2"]),
+        tls_extension(43, &[0x06, 0x4a, 0x4a, 0x03, 0x04, 0x03, 0x03]),
+    ]
+    .concat();
+    let client_hello =
+        build_client_hello_record_with(&[0x0a0a, 0x1301, 0x1302, 0xc02f], &extensions);
+
+    let info = tls_info_of(
+        &client_hello,
+        &[build_server_hello_record(0xc02f, false)],
+        &TlsParser::default(),
+    );
+
+    // MD5 of "771,4865-4866-49199,0-10-11-13-16-43,29-23,0".
+    assert_eq!(
+        info.ja3.as_deref(),
+        Some("c9e264cb3675678ee364e81f3b6da7ad")
+    );
+    // SHA-256 prefixes of "1301,1302,c02f" and "000a,000b,000d,002b_0403,0804".
+    assert_eq!(
+        info.ja4.as_deref(),
+        Some("t13d0306h2_40b44b994229_fb71836bce29")
+    );
+    // MD5 of "771,49199,".
+    assert_eq!(
+        info.ja3s.as_deref(),
+        Some("174e7e4992a63f6d419626d97363adb8")
+    );
+}
+
+#[test]
+fn http_body_is_released_from_the_stream_as_it_arrives() {
+    let head = b"HTTP/1.1 200 OK\r\nContent-Length: 3000\r\n\r\n";
+    let mut state = ParseState::default();
+    let mut seq = 1000;
+    for payload in [&head[..], &[b'x'; 1000], &[b'x'; 1000], &[b'x'; 1000]] {
+        let packet = build_conversation_packet(false, seq, 1, 64240, payload);
+        seq += payload.len() as u32;
+        reassemble_packet(&packet, &mut state);
+
+        let key = *state.http_connections.keys().next().expect("HTTP connection");
+        let streams = state.tcp_streams.flow(&key).expect("TCP streams");
+        let retained = streams.forward.data().len() + streams.reverse.data().len();
+        // Only what the TLS parser keeps in case a record header straddles segments.
+        assert!(retained < 5, "{retained} bytes retained");
+    }
+
+    let (_, connection) = state.http_connections.into_iter().next().unwrap();
+    let transactions = connection.into_transactions();
+    let response = transactions[0].response.as_ref().expect("response");
+    assert_eq!(response.body_length, 3000);
+}
+
+/// Runs `segments` of the 10.0.0.1:12345 <-> 10.0.0.2:80 conversation through
+/// reassembly, the `i`th sent at time `i` by the client when its flag is set. Returns
+/// each packet's tags and the HTTP transactions of the connection once it closed.
+fn http_exchange(segments: &[(bool, &[u8])]) -> (Vec<Vec<String>>, Vec<HttpTransaction>) {
+    let mut state = ParseState::default();
+    let registry = DissectorRegistry::default();
+    let (mut client_seq, mut server_seq) = (1, 1000);
+    let mut tags = Vec::new();
+    for (ix, (from_client, payload)) in segments.iter().enumerate() {
+        let (seq, ack) = if *from_client {
+            (&mut client_seq, server_seq)
+        } else {
+            (&mut server_seq, client_seq)
+        };
+        let packet = build_conversation_packet(*from_client, *seq, ack, 64240, payload);
+        *seq += payload.len() as u32;
+        let mut context = decode_headers(&packet).expect("decode tcp");
+        handle_tcp_segment(
+            &mut context,
+            &packet,
+            ix as f64,
+            &TlsParser::default(),
+            &registry,
+            &mut state,
+        );
+        tags.push(context.tags);
+    }
+    let (_, mut connection) = state
+        .http_connections
+        .into_iter()
+        .next()
+        .expect("HTTP connection");
+    for direction in [StreamDirection::Forward, StreamDirection::Reverse] {
+        connection.finish(direction);
+    }
+    (tags, connection.into_transactions())
+}
+
+#[test]
+fn http_pipelined_requests_pair_with_responses_in_order() {
+    let requests = b"GET /index.html HTTP/1.1\r\nHost: example.com\r\n\r\n\
+        HEAD /logo.png HTTP/1.1\r\nHost: example.com\r\n\r\n\
+        POST /upload HTTP/1.1\r\nHost: example.com\r\nContent-Type: text/plain\r\n\
+        Transfer-Encoding: chunked\r\n\r\n";
+    let (tags, transactions) = http_exchange(&[
+        (true, requests),
+        (true, b"4\r\nWire\r\n5;ext=1\r\ncrab!\r\n0\r\nX-Trailer: yes\r\n\r\n"),
+        (
+            false,
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 11\r\n\r\n<p>hi</p>\r\n\
+              HTTP/1.1 200 OK\r\nContent-Length: 5000\r\n\r\n\
+              HTTP/1.1 100 Continue\r\n\r\n",
+        ),
+        (
+            false,
+            b"HTTP/1.1 201 Created\r\nTransfer-Encoding: gzip, chunked\r\n\r\na\r\n0123456789\r",
+        ),
+        (false, b"\n3\r\nabc\r\n0\r\n\r\n"),
+    ]);
+
+    assert_eq!(
+        tags[0],
+        [
+            "GET /index.html HTTP/1.1",
+            "HEAD /logo.png HTTP/1.1",
+            "POST /upload HTTP/1.1"
+        ]
+    );
+    assert!(tags[1].is_empty());
+    assert_eq!(
+        tags[2],
+        [
+            "HTTP/1.1 200 OK",
+            "HTTP/1.1 200 OK",
+            "HTTP/1.1 100 Continue"
+        ]
+    );
+    assert_eq!(tags[3], ["HTTP/1.1 201 Created"]);
+
+    assert_eq!(transactions.len(), 3);
+    let get = &transactions[0];
+    let request = get.request.as_ref().unwrap();
+    assert_eq!(
+        (request.method.as_str(), request.uri.as_str()),
+        ("GET", "/index.html")
+    );
+    assert_eq!(request.host.as_deref(), Some("example.com"));
+    let response = get.response.as_ref().unwrap();
+    assert_eq!(response.status, 200);
+    assert_eq!(response.content_type.as_deref(), Some("text/html"));
+    assert_eq!(response.body_length, 11);
+    assert_eq!(get.response_time(), Some(2.0));
+
+    // The HEAD response announces a length but carries no body.
+    let head = transactions[1].response.as_ref().unwrap();
+    assert_eq!((head.status, head.body_length), (200, 0));
+
+    let post = &transactions[2];
+    let request = post.request.as_ref().unwrap();
+    assert_eq!(request.content_type.as_deref(), Some("text/plain"));
+    assert_eq!(request.body_length, 9);
+    let response = post.response.as_ref().unwrap();
+    assert_eq!(
+        (response.status, response.reason.as_str()),
+        (201, "Created")
+    );
+    assert_eq!(response.body_length, 13);
+    assert_eq!(post.response_time(), Some(3.0));
+}
+
+#[test]
+fn http_response_without_length_ends_with_the_connection() {
+    let (_, transactions) = http_exchange(&[
+        (true, b"GET / HTTP/1.0\r\n\r\n"),
+        (
+            false,
+            b"HTTP/1.0 200 OK\r\nContent-Type: text/plain\r\n\r\nhello",
+        ),
+        (false, b", world"),
+    ]);
+
+    assert_eq!(transactions.len(), 1);
+    let response = transactions[0].response.as_ref().unwrap();
+    assert_eq!(response.version, "HTTP/1.0");
+    assert_eq!(response.body_length, 12);
+}
+
+#[test]
+fn http_parsing_skips_non_http_streams() {
+    let (tags, transactions) = http_exchange(&[
+        (true, &build_client_hello_record()),
+        (false, b"SSH-2.0-OpenSSH_9.6\r\n"),
+    ]);
+
+    assert!(transactions.is_empty());
+    assert!(!tags.concat().iter().any(|tag| tag.contains("HTTP")));
+}
+
+fn dns_name(name: &str) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    for label in name.split('.').filter(|label| !label.is_empty()) {
+        encoded.push(label.len() as u8);
+        encoded.extend_from_slice(label.as_bytes());
+    }
+    encoded.push(0);
+    encoded
+}
+
+/// A DNS message with one question; answers are owned by the question name.
+fn build_dns_message(
+    id: u16,
+    flags: u16,
+    question: (&str, u16),
+    answers: &[(u16, Vec<u8>)],
+) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for word in [id, flags, 1, answers.len() as u16, 0, 0] {
+        buf.extend_from_slice(&word.to_be_bytes());
+    }
+    buf.extend_from_slice(&dns_name(question.0));
+    buf.extend_from_slice(&question.1.to_be_bytes());
+    buf.extend_from_slice(&1u16.to_be_bytes());
+    for (record_type, rdata) in answers {
+        buf.extend_from_slice(&[0xc0, 0x0c]);
+        buf.extend_from_slice(&record_type.to_be_bytes());
+        buf.extend_from_slice(&1u16.to_be_bytes());
+        buf.extend_from_slice(&300u32.to_be_bytes());
+        buf.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        buf.extend_from_slice(rdata);
+    }
+    buf
+}
+
+/// Builds a packet of the 10.0.0.1:40000 <-> 10.0.0.53:53 DNS conversation over UDP.
+fn build_dns_udp_packet(from_client: bool, payload: &[u8]) -> Vec<u8> {
+    let (src, dst, src_port, dst_port) = if from_client {
+        ([10, 0, 0, 1], [10, 0, 0, 53], 40000, 53)
+    } else {
+        ([10, 0, 0, 53], [10, 0, 0, 1], 53, 40000)
+    };
+    let builder = PacketBuilder::ethernet2([1, 2, 3, 4, 5, 6], [6, 5, 4, 3, 2, 1])
+        .ipv4(src, dst, 64)
+        .udp(src_port, dst_port);
+    let mut packet = Vec::with_capacity(builder.size(payload.len()));
+    builder.write(&mut packet, payload).unwrap();
+    packet
+}
+
+#[test]
+fn dns_responses_pair_with_queries_and_measure_latency() {
+    let registry = DissectorRegistry::default();
+    let cname = dns_name("www.example.net");
+    let packets = [
+        (
+            1.0,
+            true,
+            build_dns_message(1, 0x0100, ("www.example.com", 1), &[]),
+        ),
+        (
+            1.001,
+            true,
+            build_dns_message(2, 0x0100, ("missing.example.com", 28), &[]),
+        ),
+        // Retransmission of the first query.
+        (
+            1.010,
+            true,
+            build_dns_message(1, 0x0100, ("www.example.com", 1), &[]),
+        ),
+        (
+            1.020,
+            false,
+            build_dns_message(2, 0x8183, ("missing.example.com", 28), &[]),
+        ),
+        (
+            1.030,
+            false,
+            build_dns_message(
+                1,
+                0x8180,
+                ("WWW.example.com", 1),
+                &[(5, cname), (1, vec![93, 184, 216, 34])],
+            ),
+        ),
+        (
+            2.0,
+            false,
+            build_dns_message(9, 0x8180, ("late.example.com", 1), &[]),
+        ),
+    ];
+
+    let mut flows = HashMap::new();
+    let mut packet_count = 0;
+    let mut resolutions = Vec::new();
+    for (timestamp, from_client, payload) in packets {
+        let packet = build_dns_udp_packet(from_client, &payload);
+        let mut context = decode_headers(&packet).expect("decode udp");
+        registry.dissect(&mut context, &packet);
+        resolutions.append(&mut context.name_resolutions);
+        add_packet(
+            packet.as_slice().into(),
+            context,
+            record_at(timestamp),
+            &mut flows,
+            &mut packet_count,
+        );
+    }
+
+    let flow = flows.values().next().expect("DNS flow");
+    let summary: Vec<_> = flow
+        .dns
+        .iter()
+        .map(|transaction| {
+            (
+                transaction.id,
+                transaction.name.as_str(),
+                transaction.query_type_name(),
+                transaction.rcode_name(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            (
+                1,
+                "www.example.com",
+                "A".to_string(),
+                Some("NOERROR".to_string())
+            ),
+            (
+                2,
+                "missing.example.com",
+                "AAAA".to_string(),
+                Some("NXDOMAIN".to_string())
+            ),
+            (
+                9,
+                "late.example.com",
+                "A".to_string(),
+                Some("NOERROR".to_string())
+            ),
+        ]
+    );
+    let latency = |ix: usize| flow.dns[ix].latency();
+    assert!((latency(0).unwrap() - 0.030).abs() < 1e-9);
+    assert!((latency(1).unwrap() - 0.019).abs() < 1e-9);
+    assert_eq!(latency(2), None);
+    let answers: Vec<_> = flow.dns[0]
+        .response
+        .as_ref()
+        .unwrap()
+        .answers
+        .iter()
+        .map(|record| record.data.as_str())
+        .collect();
+    assert_eq!(answers, ["www.example.net", "93.184.216.34"]);
+    assert_eq!(
+        resolutions,
+        [(
+            IPAddress::V4([93, 184, 216, 34]),
+            "WWW.example.com".to_string()
+        )]
+    );
+}
+
+#[test]
+fn dns_records_are_shown_in_presentation_format() {
+    let mut txt = vec![5];
+    txt.extend_from_slice(b"v=spf");
+    txt.push(3);
+    txt.extend_from_slice(b"all");
+    let mut mx = 10u16.to_be_bytes().to_vec();
+    mx.extend_from_slice(&[0xc0, 0x0c]);
+    let mut srv = [1u16, 5, 5060]
+        .iter()
+        .flat_map(|word| word.to_be_bytes())
+        .collect::<Vec<_>>();
+    srv.extend_from_slice(&dns_name("sip.example.com"));
+    let mut soa = dns_name("ns1.example.com");
+    soa.extend_from_slice(&dns_name("hostmaster.example.com"));
+    for number in [2024010101u32, 7200, 3600, 1209600, 300] {
+        soa.extend_from_slice(&number.to_be_bytes());
+    }
+    let payload = build_dns_message(
+        7,
+        0x8180,
+        ("example.com", 255),
+        &[
+            (16, txt),
+            (15, mx),
+            (33, srv),
+            (6, soa),
+            (99, vec![0xde, 0xad]),
+            (0xff00, vec![1, 2]),
+        ],
+    );
+
+    let message = crate::parser::dns::parse_message(&payload).expect("DNS message");
+    let records: Vec<_> = message
+        .answers
+        .iter()
+        .map(crate::parser::dns::format_record)
+        .collect();
+    assert_eq!(
+        records,
+        [
+            "example.com TXT \"v=spf\" \"all\"",
+            "example.com MX 10 example.com",
+            "example.com SRV 1 5 5060 sip.example.com",
+            "example.com SOA ns1.example.com hostmaster.example.com 2024010101 7200 3600 1209600 300",
+            "example.com SPF dead",
+            "example.com TYPE65280 0102",
+        ]
+    );
+}
+
+#[test]
+fn dns_over_tcp_splits_length_prefixed_messages() {
+    let registry = DissectorRegistry::with_rules(&["tcp.port==80,dns".parse().unwrap()]).unwrap();
+    let frame = |message: Vec<u8>| {
+        let mut framed = (message.len() as u16).to_be_bytes().to_vec();
+        framed.extend_from_slice(&message);
+        framed
+    };
+    let query = frame(build_dns_message(0x42, 0x0100, ("example.org", 1), &[]));
+    let response = frame(build_dns_message(
+        0x42,
+        0x8180,
+        ("example.org", 1),
+        &[(1, vec![192, 0, 2, 7])],
+    ));
+    let (query_start, query_end) = query.split_at(9);
+
+    let mut state = ParseState::default();
+    let mut messages = Vec::new();
+    let mut resolutions = Vec::new();
+    let (mut client_seq, mut server_seq) = (1, 1000);
+    for (from_client, payload) in [
+        (true, query_start),
+        (true, query_end),
+        (false, &response[..]),
+    ] {
+        let (seq, ack) = if from_client {
+            (&mut client_seq, server_seq)
+        } else {
+            (&mut server_seq, client_seq)
+        };
+        let packet = build_conversation_packet(from_client, *seq, ack, 64240, payload);
+        *seq += payload.len() as u32;
+        let mut context = decode_headers(&packet).expect("decode tcp");
+        registry.dissect(&mut context, &packet);
+        handle_tcp_segment(
+            &mut context,
+            &packet,
+            0.0,
+            &TlsParser::default(),
+            &registry,
+            &mut state,
+        );
+        messages.push(context.dns_messages.len());
+        resolutions.append(&mut context.name_resolutions);
+    }
+
+    assert_eq!(messages, [0, 1, 1]);
+    assert!(state.http_connections.is_empty());
+    assert_eq!(
+        resolutions,
+        [(IPAddress::V4([192, 0, 2, 7]), "example.org".to_string())]
+    );
+}
+
+/// Writes a capture of 100 HTTP exchanges, each with a retransmitted response and
+/// preceded by a DNS lookup, all DNS traffic sharing one flow.
+fn write_http_dns_capture(name: &str) -> std::path::PathBuf {
+    let tcp_packet = |from_client: bool, port: u16, seq: u32, ack: u32, payload: &[u8]| {
+        let (src, dst, src_port, dst_port) = if from_client {
+            ([10, 0, 0, 1], [10, 0, 0, 2], port, 80)
+        } else {
+            ([10, 0, 0, 2], [10, 0, 0, 1], 80, port)
+        };
+        let builder = PacketBuilder::ethernet2([1, 2, 3, 4, 5, 6], [6, 5, 4, 3, 2, 1])
+            .ipv4(src, dst, 64)
+            .tcp(src_port, dst_port, seq, 64240)
+            .ack(ack);
+        let mut packet = Vec::with_capacity(builder.size(payload.len()));
+        builder.write(&mut packet, payload).unwrap();
+        packet
+    };
+    let response = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok";
+    let mut packets = Vec::new();
+    for client in 0..100u16 {
+        let request = format!("GET /{client} HTTP/1.1\r\nHost: example.com\r\n\r\n");
+        let request_end = 1 + request.len() as u32;
+        let host = format!("host{client}.example.com");
+        packets.extend([
+            build_dns_udp_packet(true, &build_dns_message(client, 0x0100, (&host, 1), &[])),
+            build_dns_udp_packet(
+                false,
+                &build_dns_message(client, 0x8180, (&host, 1), &[(1, vec![10, 0, 1, 1])]),
+            ),
+            tcp_packet(true, 40000 + client, 1, 1000, request.as_bytes()),
+            tcp_packet(false, 40000 + client, 1000, request_end, response),
+            // Retransmission of the response.
+            tcp_packet(false, 40000 + client, 1000, request_end, response),
+            tcp_packet(
+                true,
+                40000 + client,
+                request_end,
+                1000 + response.len() as u32,
+                b"",
+            ),
+        ]);
+    }
+    let records: Vec<_> = packets
+        .into_iter()
+        .enumerate()
+        .map(|(index, packet)| (1_700_000_000, index as u32 * 100, packet))
+        .collect();
+    write_legacy_pcap(name, 0xa1b2c3d4, false, 1, &records)
+}
+
+#[test]
+fn parallel_parse_matches_sequential_parse() {
+    let path = write_http_dns_capture("wirecrab-parallel.pcap");
+
+    let registry = DissectorRegistry::default();
+    let summarize = |workers: usize| {
+        let state = parse_capture_with_workers(&path, &registry, workers, |_| {}).expect("parse");
+        let mut flows: Vec<String> = state
+            .flows
+            .iter()
+            .map(|(key, flow)| {
+                let packets: Vec<_> = flow
+                    .packets
+                    .iter()
+                    .map(|packet| (packet.timestamp, packet.length, &packet.tags))
+                    .collect();
+                format!(
+                    "{key:?} {} {packets:?} {:?} {:?} {:?}",
+                    flow.timestamp, flow.analysis, flow.http, flow.dns
+                )
+            })
+            .collect();
+        flows.sort();
+        let mut names: Vec<String> = state
+            .name_resolutions
+            .iter()
+            .map(|(ip, names)| format!("{ip:?} {names:?}"))
+            .collect();
+        names.sort();
+        (state.packet_count, flows, names)
+    };
+
+    let sequential = summarize(1);
+    assert_eq!(sequential.0, 600);
+    assert_eq!(sequential.1.len(), 101);
+    assert_eq!(sequential.2.len(), 1);
+    assert_eq!(summarize(8), sequential);
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn incremental_batches_add_up_to_the_parsed_flows() {
+    let path = write_http_dns_capture("wirecrab-incremental.pcap");
+    let mut flows = HashMap::new();
+    let mut batches = 0;
+    let mut send = |batch: FlowBatch| {
+        batches += 1;
+        merge_flows(&mut flows, batch.flows);
+    };
+    let sink = BatchSink {
+        interval: Duration::ZERO,
+        send: &mut send,
+    };
+    let options = ParseOptions {
+        workers: 4,
+        sink: Some(sink),
+        cancel: None,
+        follow: None,
+    };
+    let state = parse_capture_incremental(&path, &DissectorRegistry::default(), options, |_| {})
+        .expect("parse");
+
+    assert_eq!(batches, 600);
+    assert_eq!(flows.len(), state.flows.len());
+    for (key, flow) in &state.flows {
+        let published = &flows[key];
+        let timestamps = |flow: &Flow| -> Vec<f64> {
+            flow.packets.iter().map(|packet| packet.timestamp).collect()
+        };
+        assert_eq!(timestamps(published), timestamps(flow));
+        assert_eq!(published.dns, flow.dns);
+    }
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn cancelled_parse_stops_with_an_error() {
+    let path = write_http_dns_capture("wirecrab-cancelled.pcap");
+    let cancel = AtomicBool::new(true);
+    let options = ParseOptions {
+        cancel: Some(&cancel),
+        ..ParseOptions::default()
+    };
+    let result = parse_capture_incremental(&path, &DissectorRegistry::default(), options, |_| {});
+
+    assert!(result.is_err_and(|error| error.is::<Cancelled>()));
+    std::fs::remove_file(path).ok();
+}
+
+/// Hands out a capture a few bytes per read, like a pipe fed by a live capture.
+struct Trickle {
+    data: Vec<u8>,
+    position: usize,
+}
+
+impl Read for Trickle {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let end = (self.position + 1000).min(self.data.len());
+        let length = (end - self.position).min(buf.len());
+        buf[..length].copy_from_slice(&self.data[self.position..self.position + length]);
+        self.position += length;
+        Ok(length)
+    }
+}
+
+#[test]
+fn streamed_capture_publishes_flows_as_they_arrive() {
+    let path = write_http_dns_capture("wirecrab-stream.pcap");
+    assert!(!is_stream(&path));
+    assert!(is_stream(std::path::Path::new("-")));
+    let input = Trickle {
+        data: std::fs::read(&path).unwrap(),
+        position: 0,
+    };
+
+    let mut flows = HashMap::new();
+    let mut batches = 0;
+    let mut send = |batch: FlowBatch| {
+        batches += 1;
+        merge_flows(&mut flows, batch.flows);
+    };
+    // Batches come from the reader running dry, not from the interval.
+    let sink = BatchSink {
+        interval: Duration::from_secs(3600),
+        send: &mut send,
+    };
+    let options = ParseOptions {
+        sink: Some(sink),
+        ..ParseOptions::default()
+    };
+    let state = parse_stream(input, &DissectorRegistry::default(), options).expect("parse");
+    let from_file = parse_capture(&path, |_| {}).expect("parse file");
+
+    assert_eq!(state.packet_count, from_file.packet_count);
+    assert_eq!(state.flows.len(), from_file.flows.len());
+    assert!(
+        batches > 1,
+        "expected flows before the end, got {batches} batches"
+    );
+    for (key, flow) in &state.flows {
+        assert_eq!(flows[key].packets.len(), flow.packets.len());
+        assert_eq!(flow.http, from_file.flows[key].http);
+        for packet in &flow.packets {
+            assert!(matches!(packet.data, PacketData::Memory(_)));
+        }
+    }
+    std::fs::remove_file(path).ok();
+}
+
+/// A pipe that breaks after `data`.
+struct BrokenPipe {
+    data: Vec<u8>,
+    position: usize,
+}
+
+impl Read for BrokenPipe {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.position == self.data.len() {
+            return Err(std::io::Error::from(std::io::ErrorKind::BrokenPipe));
+        }
+        let length = (self.data.len() - self.position).min(buf.len());
+        buf[..length].copy_from_slice(&self.data[self.position..self.position + length]);
+        self.position += length;
+        Ok(length)
+    }
+}
+
+#[test]
+fn stream_read_error_fails_the_parse() {
+    let path = write_http_dns_capture("wirecrab-broken-pipe.pcap");
+    let mut data = std::fs::read(&path).unwrap();
+    std::fs::remove_file(path).ok();
+    data.truncate(data.len() / 2);
+
+    let input = BrokenPipe { data, position: 0 };
+    let result = parse_stream(input, &DissectorRegistry::default(), ParseOptions::default());
+
+    let error = result.err().expect("the parse fails");
+    assert!(error.to_string().starts_with("Failed to read the capture"), "{error}");
+}
+
+#[test]
+fn merged_captures_interleave_packets_by_timestamp() {
+    let syn = build_tcp_packet(|b| b.syn(), &[]);
+    let udp = build_udp_packet(&[1, 2, 3]);
+    let first = write_legacy_pcap(
+        "wirecrab-merge-a.pcap",
+        0xa1b2_c3d4,
+        false,
+        1,
+        &[(10, 0, syn), (12, 0, udp.clone())],
+    );
+    let second = write_legacy_pcap(
+        "wirecrab-merge-b.pcap",
+        0xa1b2_c3d4,
+        false,
+        1,
+        &[(11, 0, udp.clone()), (13, 0, udp)],
+    );
+    let pattern = std::env::temp_dir().join("wirecrab-merge-?.pcap");
+    let paths = expand_paths(&[pattern]).expect("expand pattern");
+    assert_eq!(paths, [first.clone(), second.clone()]);
+
+    let state = parse_captures(
+        &paths,
+        &DissectorRegistry::default(),
+        ParseOptions::default(),
+        |_| {},
+    )
+    .expect("parse merged captures");
+
+    assert_eq!(
+        state.sources,
+        ["wirecrab-merge-a.pcap", "wirecrab-merge-b.pcap"]
+    );
+    assert_eq!(state.interfaces.len(), 2);
+    assert_eq!(state.packet_count, 4);
+    assert_eq!(state.first_packet_ts, Some(10.0));
+    let udp_flow = state
+        .flows
+        .values()
+        .find(|flow| flow.protocol == Protocol::UDP)
+        .expect("udp flow");
+    let order: Vec<_> = udp_flow
+        .packets
+        .iter()
+        .map(|packet| (packet.timestamp, packet.source, packet.interface))
+        .collect();
+    assert_eq!(order, [(11.0, 1, 1), (12.0, 0, 0), (13.0, 1, 1)]);
+
+    // The same file twice is told apart by its whole path.
+    let twice = parse_captures(
+        &[&first, &first],
+        &DissectorRegistry::default(),
+        ParseOptions::default(),
+        |_| {},
+    )
+    .expect("parse the same capture twice");
+    assert_eq!(
+        twice.sources,
+        [first.display().to_string(), first.display().to_string()]
+    );
+    assert_eq!(twice.packet_count, 4);
+
+    assert!(expand_paths(&[std::env::temp_dir().join("wirecrab-merge-none-*.pcap")]).is_err());
+    std::fs::remove_file(first).ok();
+    std::fs::remove_file(second).ok();
+}
+
+/// Splits the IP payload of the Ethernet `packet` into fragments of datagram `id`, one
+/// per range of `pieces`, for either IP version.
+fn fragment_packet(packet: &[u8], id: u32, pieces: &[Range<usize>]) -> Vec<Vec<u8>> {
+    let (ethernet, ip) = packet.split_at(14);
+    pieces
+        .iter()
+        .map(|piece| {
+            let mut frame = ethernet.to_vec();
+            let offset = IpFragOffset::try_new((piece.start / 8) as u16).unwrap();
+            if ethernet[12..14] == [0x86, 0xdd] {
+                let (mut header, payload) = Ipv6Header::from_slice(ip).unwrap();
+                let more_fragments = piece.end < payload.len();
+                let fragment =
+                    Ipv6FragmentHeader::new(header.next_header, offset, more_fragments, id);
+                header.next_header = IpNumber::IPV6_FRAGMENTATION_HEADER;
+                header.payload_length = (Ipv6FragmentHeader::LEN + piece.len()) as u16;
+                header.write(&mut frame).unwrap();
+                fragment.write(&mut frame).unwrap();
+                frame.extend_from_slice(&payload[piece.clone()]);
+            } else {
+                let (mut header, payload) = Ipv4Header::from_slice(ip).unwrap();
+                header.identification = id as u16;
+                header.more_fragments = piece.end < payload.len();
+                header.fragment_offset = offset;
+                header.total_len = (header.header_len() + piece.len()) as u16;
+                header.header_checksum = header.calc_header_checksum();
+                header.write(&mut frame).unwrap();
+                frame.extend_from_slice(&payload[piece.clone()]);
+            }
+            frame
+        })
+        .collect()
+}
+
+#[test]
+fn fragmented_udp_datagrams_are_reassembled_into_their_flow() {
+    let answers: Vec<(u16, Vec<u8>)> = (0..40).map(|host| (1, vec![10, 0, 1, host])).collect();
+    let response = build_dns_message(7, 0x8180, ("big.example.com", 1), &answers);
+    let query = build_dns_udp_packet(
+        true,
+        &build_dns_message(7, 0x0100, ("big.example.com", 1), &[]),
+    );
+    let datagram = build_dns_udp_packet(false, &response);
+    let length = datagram.len() - 34;
+    let mut fragments = fragment_packet(&datagram, 7, &[0..256, 256..512, 512..length]);
+    // A datagram that never completes, carrying the transport header of another flow.
+    let lost = build_dns_udp_packet(
+        false,
+        &build_dns_message(8, 0x8180, ("lost.example.com", 1), &answers),
+    );
+    let lost = fragment_packet(&lost, 8, std::slice::from_ref(&(0..256))).remove(0);
+    // Received out of order, with a duplicate of the middle fragment in between.
+    let records = vec![
+        (1, 0, query),
+        (2, 0, fragments[2].clone()),
+        (2, 10, fragments[1].clone()),
+        (2, 20, lost),
+        (2, 30, fragments.swap_remove(1)),
+        (2, 40, fragments.swap_remove(0)),
+    ];
+    let path = write_legacy_pcap(
+        "wirecrab_fragments_test.pcap",
+        0xa1b2_c3d4,
+        false,
+        1,
+        &records,
+    );
+
+    let state = parse_capture(&path, |_| {}).expect("parse capture");
+    let _ = std::fs::remove_file(&path);
+
+    let key = |port| {
+        FlowKey::from_endpoints(
+            Endpoint::new(IPAddress::V4([10, 0, 0, 53]), 53),
+            Endpoint::new(IPAddress::V4([10, 0, 0, 1]), port),
+            Protocol::UDP,
+        )
+    };
+    let flow = &state.flows[&key(40000)];
+    let tags: Vec<&[String]> = flow
+        .packets
+        .iter()
+        .map(|packet| packet.tags.as_slice())
+        .collect();
+    assert_eq!(flow.packets.len(), 6);
+    assert_eq!(tags[1], ["IP Fragment"]);
+    assert_eq!(
+        tags[4],
+        ["IP Fragment", "IP Reassembled", "IP Fragment Overlap"]
+    );
+    assert_eq!(flow.dns.len(), 1);
+    let answered = flow.dns[0].response.as_ref().expect("reassembled response");
+    assert_eq!(answered.answers.len(), 40);
+    assert_eq!(state.name_resolutions.len(), 40);
+
+    // Only the first fragment of the lost datagram arrived; it still finds its flow.
+    assert_eq!(
+        state.flows.len(),
+        1,
+        "lost datagram shares the conversation"
+    );
+    assert!(
+        flow.packets
+            .iter()
+            .any(|packet| packet.tags == ["IP Fragment", "IP Reassembly Timeout"])
+    );
+}
+
+#[test]
+fn ipv6_fragments_are_reassembled_and_followed() {
+    let payload: Vec<u8> = (0..3000u32).map(|n| (n % 251) as u8).collect();
+    let packet = build_ipv6_udp_packet(&payload);
+    let length = packet.len() - 54;
+    let records: Vec<(u32, u32, Vec<u8>)> =
+        fragment_packet(&packet, 0x1234_5678, &[1448..length, 0..1448])
+            .into_iter()
+            .enumerate()
+            .map(|(index, frame)| (1, index as u32, frame))
+            .collect();
+    let path = write_legacy_pcap(
+        "wirecrab_ipv6_fragments_test.pcap",
+        0xa1b2_c3d4,
+        false,
+        1,
+        &records,
+    );
+
+    let state = parse_capture(&path, |_| {}).expect("parse capture");
+    let flow = state.flows.values().next().expect("one flow");
+    let followed = follow_stream(flow, &state.interfaces);
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(state.flows.len(), 1);
+    assert_eq!(flow.protocol, Protocol::UDP);
+    assert_eq!(flow.packets.len(), 2);
+    assert_eq!(
+        (flow.packets[0].src_port, flow.packets[0].dst_port),
+        (Some(5353), Some(8053))
+    );
+    assert_eq!(followed.payload(FollowDirection::ClientToServer), payload);
+}
+
+#[test]
+fn ip_reassembler_keeps_first_bytes_and_expires_incomplete_datagrams() {
+    let key = FragmentKey {
+        src: IPAddress::V4([10, 0, 0, 1]),
+        dst: IPAddress::V4([10, 0, 0, 2]),
+        id: 1,
+        protocol: 17,
+    };
+    let fragment = |offset: usize, more_fragments: bool, payload: &[u8]| IpFragment {
+        id: 1,
+        protocol: 17,
+        offset,
+        more_fragments,
+        payload: payload.to_vec(),
+    };
+    let mut reassembler = IpReassembler::default();
+    assert!(
+        reassembler
+            .push(key, &fragment(8, false, b"IJKLMNOP"), 1.0, 'a')
+            .is_none()
+    );
+    assert!(
+        reassembler
+            .push(key, &fragment(4, true, b"xxxxyyyy"), 1.1, 'b')
+            .is_none()
+    );
+    let datagram = reassembler
+        .push(key, &fragment(0, true, b"ABCDzzzz"), 1.2, 'c')
+        .expect("complete datagram");
+    assert_eq!(datagram.payload(), b"ABCDxxxxIJKLMNOP");
+    assert_eq!(datagram.packets, ['a', 'b', 'c']);
+    assert!(datagram.overlapping);
+
+    assert!(
+        reassembler
+            .push(key, &fragment(0, true, b"ABCDEFGH"), 2.0, 'd')
+            .is_none()
+    );
+    let other = FragmentKey { id: 2, ..key };
+    assert!(
+        reassembler
+            .push(other, &fragment(8, false, b"IJ"), 20.0, 'e')
+            .is_none()
+    );
+    assert!(reassembler.expire(32.0).is_empty());
+    let expired = reassembler.expire(32.5);
+    assert_eq!(expired.len(), 1);
+    assert_eq!(
+        (expired[0].key, expired[0].payload()),
+        (key, &b"ABCDEFGH"[..])
+    );
+    let remaining = reassembler.drain();
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(
+        (remaining[0].key, remaining[0].payload()),
+        (other, &b""[..])
+    );
+}
+
+#[test]
+fn ip_reassembler_gives_up_on_the_oldest_datagrams_beyond_its_byte_limit() {
+    // Each datagram's last fragment alone makes it hold 64 KiB.
+    let last_fragment = IpFragment {
+        id: 0,
+        protocol: 17,
+        offset: 65528,
+        more_fragments: false,
+        payload: b"tail".to_vec(),
+    };
+    let key = |id| FragmentKey {
+        src: IPAddress::V4([10, 0, 0, 1]),
+        dst: IPAddress::V4([10, 0, 0, 2]),
+        id,
+        protocol: 17,
+    };
+    let mut reassembler = IpReassembler::default();
+    for id in 0..70 {
+        assert!(reassembler.push(key(id), &last_fragment, 1.0, id).is_none());
+    }
+
+    let given_up: Vec<u32> = reassembler
+        .expire(1.0)
+        .iter()
+        .map(|datagram| datagram.key.id)
+        .collect();
+    assert_eq!(given_up, (0..6).collect::<Vec<_>>());
+    assert!(reassembler.expire(1.0).is_empty());
+    assert_eq!(reassembler.drain().len(), 64);
+}
+
+/// Wraps `inner` in an Ethernet, IPv4 and UDP packet from 172.16.0.1 to 172.16.0.2.
+fn build_udp_tunnel_packet(dst_port: u16, inner: &[u8]) -> Vec<u8> {
+    let builder = PacketBuilder::ethernet2([1, 1, 1, 1, 1, 1], [2, 2, 2, 2, 2, 2])
+        .ipv4([172, 16, 0, 1], [172, 16, 0, 2], 64)
+        .udp(50000, dst_port);
+    let mut packet = Vec::with_capacity(builder.size(inner.len()));
+    builder.write(&mut packet, inner).unwrap();
+    packet
+}
+
+#[test]
+fn vxlan_packets_decode_to_the_inner_flow() {
+    let mut vxlan = vec![0x08, 0, 0, 0, 0x00, 0x13, 0x89, 0];
+    vxlan.extend(build_tcp_packet(|b| b.syn(), &[]));
+    let packet = build_udp_tunnel_packet(4789, &vxlan);
+
+    let ctx = decode_headers(&packet).expect("decode vxlan");
+    assert_eq!(ctx.src_ip, Some(IPAddress::V4([10, 0, 0, 1])));
+    assert_eq!(ctx.dst_ip, Some(IPAddress::V4([10, 0, 0, 2])));
+    assert_eq!((ctx.src_port, ctx.dst_port), (Some(12345), Some(80)));
+    assert_eq!(ctx.protocol, Some(Protocol::TCP));
+    assert!(ctx.is_syn);
+    assert_eq!(
+        ctx.encapsulation,
+        [Encapsulation::Tunnel {
+            kind: TunnelKind::Vxlan { vni: 5001 },
+            src: IPAddress::V4([172, 16, 0, 1]),
+            dst: IPAddress::V4([172, 16, 0, 2]),
+        }]
+    );
+
+    let tree = dissect_packet(&packet, Linktype::ETHERNET, &DissectorRegistry::default())
+        .expect("dissect vxlan packet")
+        .tree;
+    let names: Vec<&str> = tree
+        .layers
+        .iter()
+        .map(|layer| layer.name.as_str())
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "Frame",
+            "Ethernet II",
+            "Internet Protocol Version 4",
+            "User Datagram Protocol",
+            "Virtual eXtensible Local Area Network",
+            "Ethernet II",
+            "Internet Protocol Version 4",
+            "Transmission Control Protocol",
+        ]
+    );
+    assert_eq!(tree.layers[4].range, 42..50);
+    assert_eq!(tree.layers[4].value.as_deref(), Some("VNI: 5001"));
+    let inner = &tree.layers[6];
+    assert_eq!(inner.range, 64..84);
+    let source = inner
+        .children
+        .iter()
+        .find(|f| f.name == "Source Address")
+        .unwrap();
+    assert_eq!(&packet[source.range.clone()], &[10, 0, 0, 1]);
+}
+
+#[test]
+fn gre_packets_decode_with_their_key() {
+    let mut gre = vec![0x20, 0x00, 0x08, 0x00, 0, 0, 0, 7];
+    gre.extend_from_slice(&build_udp_packet(b"inner")[14..]);
+    let builder = PacketBuilder::ethernet2([1, 1, 1, 1, 1, 1], [2, 2, 2, 2, 2, 2]).ipv4(
+        [172, 16, 0, 1],
+        [172, 16, 0, 2],
+        64,
+    );
+    let mut packet = Vec::with_capacity(builder.size(gre.len()));
+    builder.write(&mut packet, IpNumber::GRE, &gre).unwrap();
+
+    let ctx = decode_headers(&packet).expect("decode gre");
+    assert_eq!(ctx.src_ip, Some(IPAddress::V4([192, 168, 1, 10])));
+    assert_eq!((ctx.src_port, ctx.dst_port), (Some(5353), Some(8053)));
+    assert_eq!(
+        ctx.encapsulation,
+        [Encapsulation::Tunnel {
+            kind: TunnelKind::Gre { key: Some(7) },
+            src: IPAddress::V4([172, 16, 0, 1]),
+            dst: IPAddress::V4([172, 16, 0, 2]),
+        }]
+    );
+
+    // A tunnel that doesn't carry a decodable packet stays the outer flow's payload.
+    let packet = build_udp_tunnel_packet(4789, &[0x08, 0, 0, 0, 0, 0, 1, 0, 0xff]);
+    let ctx = decode_headers(&packet).expect("decode broken vxlan");
+    assert_eq!(ctx.src_ip, Some(IPAddress::V4([172, 16, 0, 1])));
+    assert_eq!(ctx.dst_port, Some(4789));
+    assert!(ctx.encapsulation.is_empty());
+}
+
+#[test]
+fn qinq_and_mpls_labels_are_recorded() {
+    let ip = &build_tcp_packet(|b| b.syn(), &[])[14..];
+    let mut packet = vec![2, 2, 2, 2, 2, 2, 1, 1, 1, 1, 1, 1];
+    packet.extend_from_slice(&[0x88, 0xa8, 0x00, 0x64, 0x81, 0x00, 0x00, 0xc8]);
+    // Labels 16 and 17, the second one at the bottom of the stack.
+    packet.extend_from_slice(&[0x88, 0x47, 0x00, 0x01, 0x00, 0x40, 0x00, 0x01, 0x11, 0x40]);
+    packet.extend_from_slice(ip);
+
+    let ctx = decode_headers(&packet).expect("decode mpls");
+    assert_eq!(ctx.src_ip, Some(IPAddress::V4([10, 0, 0, 1])));
+    assert_eq!(ctx.protocol, Some(Protocol::TCP));
+    assert_eq!(
+        ctx.encapsulation,
+        [
+            Encapsulation::Vlan(100),
+            Encapsulation::Vlan(200),
+            Encapsulation::Mpls(16),
+            Encapsulation::Mpls(17),
+        ]
+    );
+
+    let tree = dissect_packet(&packet, Linktype::ETHERNET, &DissectorRegistry::default())
+        .expect("dissect mpls packet")
+        .tree;
+    let labels: Vec<_> = tree
+        .layers
+        .iter()
+        .filter(|layer| layer.name == "MultiProtocol Label Switching")
+        .map(|layer| (layer.range.clone(), layer.value.as_deref()))
+        .collect();
+    assert_eq!(
+        labels,
+        [(22..26, Some("Label: 16")), (26..30, Some("Label: 17"))]
+    );
+    assert_eq!(tree.layers.last().unwrap().range, 50..70);
+}
+
+/// Decodes each Ethernet frame of `packets` and files it, returning the flows.
+fn file_packets(packets: &[Vec<u8>]) -> (HashMap<FlowKey, Flow>, Vec<Option<FlowKey>>) {
+    let mut flows = HashMap::new();
+    let mut packet_count = 0;
+    let keys = packets
+        .iter()
+        .enumerate()
+        .map(|(index, packet)| {
+            let context = decode_headers(packet).expect("decode packet");
+            add_packet(
+                packet.as_slice().into(),
+                context,
+                record_at(index as f64),
+                &mut flows,
+                &mut packet_count,
+            )
+        })
+        .collect();
+    (flows, keys)
+}
+
+#[test]
+fn icmp_echo_exchanges_form_flows_by_identifier() {
+    let echo = |from_client: bool, id: u16| {
+        let (src, dst) = if from_client {
+            ([10, 0, 0, 1], [10, 0, 0, 2])
+        } else {
+            ([10, 0, 0, 2], [10, 0, 0, 1])
+        };
+        let builder =
+            PacketBuilder::ethernet2([1, 2, 3, 4, 5, 6], [6, 5, 4, 3, 2, 1]).ipv4(src, dst, 64);
+        let builder = if from_client {
+            builder.icmpv4_echo_request(id, 1)
+        } else {
+            builder.icmpv4_echo_reply(id, 1)
+        };
+        let mut packet = Vec::new();
+        builder.write(&mut packet, b"ping").unwrap();
+        packet
+    };
+    let packets = [echo(true, 7), echo(false, 7), echo(true, 8)];
+    let (flows, keys) = file_packets(&packets);
+
+    assert_eq!(flows.len(), 2);
+    assert_eq!(keys[0], keys[1]);
+    let flow = &flows[&keys[0].unwrap()];
+    assert_eq!(flow.protocol, Protocol::ICMP);
+    assert_eq!(flow.conversation, Conversation::Echo(7));
+    assert_eq!(flow.source, Endpoint::new(IPAddress::V4([10, 0, 0, 1]), 0));
+    assert_eq!(flow.packets[0].src_port, None);
+    assert_eq!(flow.packets[0].tags, ["ICMP Echo Request"]);
+    assert_eq!(flow.packets[1].tags, ["ICMP Echo Reply"]);
+    assert_eq!(flows[&keys[2].unwrap()].conversation, Conversation::Echo(8));
+
+    let tree = dissect_packet(
+        &packets[0],
+        Linktype::ETHERNET,
+        &DissectorRegistry::default(),
+    )
+    .expect("dissect icmp packet")
+    .tree;
+    let icmp = &tree.layers[3];
+    assert_eq!(icmp.name, "Internet Control Message Protocol");
+    assert_eq!(icmp.value.as_deref(), Some("Echo Request"));
+    let id = icmp
+        .children
+        .iter()
+        .find(|f| f.name == "Identifier")
+        .unwrap();
+    assert_eq!((id.value.as_deref(), id.range.clone()), (Some("7"), 38..40));
+
+    let builder = PacketBuilder::ethernet2([1, 1, 1, 1, 1, 1], [2, 2, 2, 2, 2, 2])
+        .ipv6(
+            [0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+            [0xff, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0xff, 0, 0, 2],
+            255,
+        )
+        .icmpv6_raw(135, 0, [0; 4]);
+    let mut packet = Vec::new();
+    builder.write(&mut packet, &[0; 16]).unwrap();
+    let ctx = decode_headers(&packet).expect("decode icmpv6");
+    assert_eq!(ctx.protocol, Some(Protocol::ICMPV6));
+    assert_eq!(ctx.tags, ["ICMPv6 Neighbor Solicitation"]);
+    assert_eq!(
+        ctx.conversation,
+        Conversation::Message {
+            icmp_type: 135,
+            code: 0
+        }
+    );
+}
+
+#[test]
+fn icmp_errors_join_the_flow_they_quote() {
+    let datagram = build_udp_packet(b"query");
+    let unreachable = |quoted: &[u8]| {
+        let builder = PacketBuilder::ethernet2([6, 5, 4, 3, 2, 1], [1, 2, 3, 4, 5, 6])
+            .ipv4([192, 168, 1, 20], [192, 168, 1, 10], 64)
+            .icmpv4(etherparse::Icmpv4Type::DestinationUnreachable(
+                icmpv4::DestUnreachableHeader::Port,
+            ));
+        let mut packet = Vec::new();
+        builder.write(&mut packet, quoted).unwrap();
+        packet
+    };
+    // The second error quotes a datagram of a flow that wasn't captured.
+    let mut unknown = datagram[14..].to_vec();
+    unknown[20..22].copy_from_slice(&4000u16.to_be_bytes());
+    let packets = [
+        datagram.clone(),
+        unreachable(&datagram[14..]),
+        unreachable(&unknown),
+    ];
+    let (flows, keys) = file_packets(&packets);
+
+    assert_eq!(keys[0], keys[1]);
+    let flow = &flows[&keys[0].unwrap()];
+    assert_eq!(flow.protocol, Protocol::UDP);
+    assert_eq!(flow.packets.len(), 2);
+    assert_eq!(
+        flow.packets[1].tags,
+        ["ICMP Destination Unreachable (Port Unreachable)"]
+    );
+    assert_eq!(flow.packets[1].src_ip, IPAddress::V4([192, 168, 1, 20]));
+
+    let flow = &flows[&keys[2].unwrap()];
+    assert_eq!(flow.protocol, Protocol::ICMP);
+    assert_eq!(
+        flow.conversation,
+        Conversation::Message {
+            icmp_type: 3,
+            code: 3
+        }
+    );
+}
+
+#[test]
+fn arp_and_esp_packets_form_flows() {
+    let arp = |operation, sender: [u8; 4], target: [u8; 4]| {
+        let arp = ArpPacket::new(
+            ArpHardwareId::ETHERNET,
+            EtherType::IPV4,
+            operation,
+            &[1, 2, 3, 4, 5, 6],
+            &sender,
+            &[0; 6],
+            &target,
+        )
+        .unwrap();
+        let builder = PacketBuilder::ethernet2([1, 2, 3, 4, 5, 6], [0xff; 6]).arp(arp);
+        let mut packet = Vec::new();
+        builder.write(&mut packet).unwrap();
+        packet
+    };
+    let esp = |spi: u32| {
+        let builder = PacketBuilder::ethernet2([1, 2, 3, 4, 5, 6], [6, 5, 4, 3, 2, 1]).ipv4(
+            [10, 0, 0, 1],
+            [10, 0, 0, 2],
+            64,
+        );
+        let mut payload = spi.to_be_bytes().to_vec();
+        payload.extend_from_slice(&[0, 0, 0, 1, 0xaa, 0xbb]);
+        let mut packet = Vec::new();
+        builder.write(&mut packet, IpNumber(50), &payload).unwrap();
+        packet
+    };
+    let packets = [
+        arp(ArpOperation::REQUEST, [10, 0, 0, 1], [10, 0, 0, 2]),
+        arp(ArpOperation::REPLY, [10, 0, 0, 2], [10, 0, 0, 1]),
+        arp(ArpOperation::REQUEST, [10, 0, 0, 3], [10, 0, 0, 3]),
+        esp(0x1234),
+        esp(0x5678),
+    ];
+    let (flows, keys) = file_packets(&packets);
+
+    assert_eq!(flows.len(), 4);
+    assert_eq!(keys[0], keys[1]);
+    let flow = &flows[&keys[0].unwrap()];
+    assert_eq!(flow.protocol, Protocol::ARP);
+    assert_eq!(flow.destination.ip, IPAddress::V4([10, 0, 0, 2]));
+    assert_eq!(flow.packets[0].tags, ["ARP Request"]);
+    assert_eq!(flow.packets[1].tags, ["ARP Reply"]);
+    assert_eq!(flows[&keys[2].unwrap()].packets[0].tags, ["Gratuitous ARP"]);
+
+    let flow = &flows[&keys[3].unwrap()];
+    assert_eq!(flow.protocol, Protocol::ESP);
+    assert_eq!(flow.conversation, Conversation::SecurityAssociation(0x1234));
+    assert_ne!(keys[3], keys[4]);
+}
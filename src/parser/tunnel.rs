@@ -0,0 +1,240 @@
+use crate::flow::IPAddress;
+use crate::flow::tunnel::{Encapsulation, TunnelKind};
+use etherparse::{EtherType, IpNumber, LinkExtHeader, NetHeaders, PacketHeaders, PayloadSlice};
+
+/// UDP destination port of VXLAN (RFC 7348).
+pub const VXLAN_PORT: u16 = 4789;
+/// UDP destination port of Geneve (RFC 8926).
+pub const GENEVE_PORT: u16 = 6081;
+
+const ETHER_TYPE_MPLS: u16 = 0x8847;
+const ETHER_TYPE_MPLS_MULTICAST: u16 = 0x8848;
+/// Transparent Ethernet Bridging: the tunnel carries Ethernet frames.
+const ETHER_TYPE_TEB: u16 = 0x6558;
+
+const VXLAN_HEADER_LEN: usize = 8;
+const GENEVE_HEADER_LEN: usize = 8;
+const GRE_HEADER_LEN: usize = 4;
+const MPLS_ENTRY_LEN: usize = 4;
+
+/// Tunnels followed at most, so crafted packets can't nest without end.
+const MAX_TUNNELS: usize = 8;
+
+/// The tunnel header between one level of a packet's headers and the next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TunnelHeader {
+    /// IP directly in IP; there is no header in between.
+    IpInIp,
+    Mpls,
+    Gre,
+    Vxlan,
+    Geneve,
+}
+
+/// A packet's headers level by level, outermost first.
+pub struct Decapsulated<'a> {
+    /// Headers of each level; flows are built from the last one.
+    pub levels: Vec<PacketHeaders<'a>>,
+    /// The tunnel carrying each level after the first, with its header's bytes.
+    pub tunnels: Vec<(TunnelHeader, &'a [u8])>,
+    /// VLAN tags, MPLS labels and tunnels of every level, outermost first.
+    pub encapsulation: Vec<Encapsulation>,
+}
+
+impl<'a> Decapsulated<'a> {
+    /// The innermost headers and everything they were carried in.
+    pub fn into_innermost(mut self) -> (PacketHeaders<'a>, Vec<Encapsulation>) {
+        let headers = self.levels.pop().expect("a packet has at least one level");
+        (headers, self.encapsulation)
+    }
+}
+
+/// One tunnel taken from the payload of a level.
+struct Tunnel<'a> {
+    header: TunnelHeader,
+    bytes: &'a [u8],
+    encapsulation: Vec<Encapsulation>,
+    inner: PacketHeaders<'a>,
+}
+
+/// Follows the VLAN/QinQ tags, MPLS label stacks, GRE, VXLAN, Geneve and IP-in-IP
+/// tunnels of `headers` down to the innermost headers. A tunnel whose payload doesn't
+/// decode is left as the payload of the level carrying it.
+pub fn decapsulate(headers: PacketHeaders<'_>) -> Decapsulated<'_> {
+    let mut decapsulated = Decapsulated {
+        levels: Vec::new(),
+        tunnels: Vec::new(),
+        encapsulation: Vec::new(),
+    };
+    let mut current = headers;
+    loop {
+        decapsulated
+            .encapsulation
+            .extend(current.link_exts.iter().filter_map(|ext| match ext {
+                LinkExtHeader::Vlan(vlan) => Some(Encapsulation::Vlan(vlan.vlan_id.value())),
+                LinkExtHeader::Macsec(_) => None,
+            }));
+        let tunnel = if decapsulated.tunnels.len() < MAX_TUNNELS {
+            open_tunnel(&current)
+        } else {
+            None
+        };
+        decapsulated.levels.push(current);
+        let Some(tunnel) = tunnel else {
+            return decapsulated;
+        };
+        decapsulated.encapsulation.extend(tunnel.encapsulation);
+        decapsulated.tunnels.push((tunnel.header, tunnel.bytes));
+        current = tunnel.inner;
+    }
+}
+
+fn open_tunnel<'a>(headers: &PacketHeaders<'a>) -> Option<Tunnel<'a>> {
+    if let PayloadSlice::Ether(payload) = &headers.payload
+        && headers.net.is_none()
+        && matches!(
+            payload.ether_type.0,
+            ETHER_TYPE_MPLS | ETHER_TYPE_MPLS_MULTICAST
+        )
+    {
+        return mpls(payload.payload);
+    }
+
+    let (src, dst) = match &headers.net {
+        Some(NetHeaders::Ipv4(ip, _)) => (IPAddress::V4(ip.source), IPAddress::V4(ip.destination)),
+        Some(NetHeaders::Ipv6(ip, _)) => (IPAddress::V6(ip.source), IPAddress::V6(ip.destination)),
+        _ => return None,
+    };
+    let tunnel = |kind| Encapsulation::Tunnel { kind, src, dst };
+    match headers.payload {
+        PayloadSlice::Ip(ref ip) if !ip.fragmented => match ip.ip_number {
+            IpNumber::IPV4 | IpNumber::IPV6 => Some(Tunnel {
+                header: TunnelHeader::IpInIp,
+                bytes: &ip.payload[..0],
+                encapsulation: vec![tunnel(TunnelKind::IpInIp)],
+                inner: PacketHeaders::from_ip_slice(ip.payload).ok()?,
+            }),
+            IpNumber::GRE => {
+                let (bytes, key, protocol) = gre_header(ip.payload)?;
+                Some(Tunnel {
+                    header: TunnelHeader::Gre,
+                    bytes,
+                    encapsulation: vec![tunnel(TunnelKind::Gre { key })],
+                    inner: inner_frame(protocol, &ip.payload[bytes.len()..])?,
+                })
+            }
+            _ => None,
+        },
+        PayloadSlice::Udp(data) => {
+            let Some(etherparse::TransportHeader::Udp(udp)) = &headers.transport else {
+                return None;
+            };
+            match udp.destination_port {
+                VXLAN_PORT => {
+                    let bytes = data.get(..VXLAN_HEADER_LEN)?;
+                    // The I flag marks a valid VNI.
+                    if bytes[0] & 0x08 == 0 {
+                        return None;
+                    }
+                    let vni = u32::from_be_bytes([0, bytes[4], bytes[5], bytes[6]]);
+                    Some(Tunnel {
+                        header: TunnelHeader::Vxlan,
+                        bytes,
+                        encapsulation: vec![tunnel(TunnelKind::Vxlan { vni })],
+                        inner: PacketHeaders::from_ethernet_slice(&data[VXLAN_HEADER_LEN..])
+                            .ok()?,
+                    })
+                }
+                GENEVE_PORT => {
+                    let fixed = data.get(..GENEVE_HEADER_LEN)?;
+                    if fixed[0] >> 6 != 0 {
+                        return None;
+                    }
+                    let len = GENEVE_HEADER_LEN + usize::from(fixed[0] & 0x3f) * 4;
+                    let bytes = data.get(..len)?;
+                    let protocol = u16::from_be_bytes([fixed[2], fixed[3]]);
+                    let vni = u32::from_be_bytes([0, fixed[4], fixed[5], fixed[6]]);
+                    Some(Tunnel {
+                        header: TunnelHeader::Geneve,
+                        bytes,
+                        encapsulation: vec![tunnel(TunnelKind::Geneve { vni })],
+                        inner: inner_frame(protocol, &data[len..])?,
+                    })
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Splits off an MPLS label stack and decodes the IP packet below it, recognised by
+/// its version nibble.
+fn mpls(payload: &[u8]) -> Option<Tunnel<'_>> {
+    let mut labels = Vec::new();
+    let mut len = 0;
+    loop {
+        let entry = payload.get(len..len + MPLS_ENTRY_LEN)?;
+        labels.push(Encapsulation::Mpls(
+            u32::from_be_bytes([entry[0], entry[1], entry[2], entry[3]]) >> 12,
+        ));
+        len += MPLS_ENTRY_LEN;
+        if entry[2] & 0x01 != 0 {
+            break;
+        }
+    }
+    let inner = &payload[len..];
+    match inner.first()? >> 4 {
+        4 | 6 => Some(Tunnel {
+            header: TunnelHeader::Mpls,
+            bytes: &payload[..len],
+            encapsulation: labels,
+            inner: PacketHeaders::from_ip_slice(inner).ok()?,
+        }),
+        _ => None,
+    }
+}
+
+/// The GRE header at the start of `payload`, its key and the ether type of what it
+/// carries. Only version 0 without source routing is understood.
+fn gre_header(payload: &[u8]) -> Option<(&[u8], Option<u32>, u16)> {
+    let fixed = payload.get(..GRE_HEADER_LEN)?;
+    let flags = u16::from_be_bytes([fixed[0], fixed[1]]);
+    let protocol = u16::from_be_bytes([fixed[2], fixed[3]]);
+    let (checksum, routing, key, sequence) = (
+        flags & 0x8000 != 0,
+        flags & 0x4000 != 0,
+        flags & 0x2000 != 0,
+        flags & 0x1000 != 0,
+    );
+    if routing || flags & 0x0007 != 0 {
+        return None;
+    }
+    let key_offset = GRE_HEADER_LEN + if checksum { 4 } else { 0 };
+    let len = key_offset + if key { 4 } else { 0 } + if sequence { 4 } else { 0 };
+    let bytes = payload.get(..len)?;
+    let key = key.then(|| {
+        u32::from_be_bytes([
+            bytes[key_offset],
+            bytes[key_offset + 1],
+            bytes[key_offset + 2],
+            bytes[key_offset + 3],
+        ])
+    });
+    Some((bytes, key, protocol))
+}
+
+/// Decodes what a GRE or Geneve tunnel carries, given its ether type. Only payloads
+/// that reach a network header or an MPLS label stack count.
+fn inner_frame(protocol: u16, payload: &[u8]) -> Option<PacketHeaders<'_>> {
+    let headers = match protocol {
+        ETHER_TYPE_TEB => PacketHeaders::from_ethernet_slice(payload).ok()?,
+        _ => PacketHeaders::from_ether_type(EtherType(protocol), payload).ok()?,
+    };
+    let mpls = matches!(
+        &headers.payload,
+        PayloadSlice::Ether(payload)
+            if matches!(payload.ether_type.0, ETHER_TYPE_MPLS | ETHER_TYPE_MPLS_MULTICAST)
+    );
+    (headers.net.is_some() || mpls).then_some(headers)
+}
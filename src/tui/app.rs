@@ -1,21 +1,28 @@
 use std::collections::HashMap;
 use std::io::stdout;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use crossterm::event::{self, Event, KeyCode, KeyEventKind};
 use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
 use crossterm::{execute, terminal};
-use ratatui::Terminal;
 use ratatui::backend::CrosstermBackend;
-use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
 use ratatui::style::{Modifier, Style, Stylize};
-use ratatui::widgets::{Block, Borders, Cell, Gauge, Paragraph, Row, Table};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Cell, Clear, Gauge, Paragraph, Row, Table};
+use ratatui::{Frame, Terminal};
 
 use super::to_color;
 use super::widgets::PacketTableState;
-use crate::flow::{Flow, FlowKey};
-use crate::loader::{FlowLoadController, FlowLoadStatus};
+use crate::export::export_flows;
+use crate::flow::{Flow, FlowKey, IPAddress};
+use crate::layers::dissector::DissectorRegistry;
+use crate::loader::recent::RecentFiles;
+use crate::loader::{FlowLoadController, FlowLoadStatus, display_paths};
+use crate::parser::expand_paths;
+use crate::parser::state::CaptureInterface;
 use crate::tui::theme::flexoki;
 use tracing::{debug, info, warn};
 
@@ -24,6 +31,14 @@ pub struct AppState {
     table_state: ratatui::widgets::TableState,
     filter: String,
     filter_mode: bool,
+    name_resolutions: HashMap<IPAddress, Vec<String>>,
+    interfaces: Vec<CaptureInterface>,
+    /// Result of the last export, shown in the footer.
+    status: Option<String>,
+    /// Path typed into the open prompt, `None` while the prompt is closed.
+    open_input: Option<String>,
+    /// Recent file shown in the open prompt, picked with ↑/↓.
+    recent_index: Option<usize>,
 }
 
 impl AppState {
@@ -38,19 +53,102 @@ impl AppState {
             table_state,
             filter: String::new(),
             filter_mode: false,
+            name_resolutions: HashMap::new(),
+            interfaces: Vec::new(),
+            status: None,
+            open_input: None,
+            recent_index: None,
         }
     }
+
+    /// Forgets the flows of the previous capture before another one loads.
+    fn clear_flows(&mut self) {
+        self.packet_table.set_flows(HashMap::new(), None);
+        self.packet_table.set_sources(Vec::new());
+        self.table_state.select(None);
+        self.name_resolutions.clear();
+        self.interfaces.clear();
+        self.status = None;
+    }
+
+    /// Fills the open prompt with the next older (`older`) or newer recent file.
+    fn browse_recent(&mut self, recent: &[PathBuf], older: bool) {
+        if recent.is_empty() {
+            return;
+        }
+        let index = match (self.recent_index, older) {
+            (None, _) => 0,
+            (Some(index), true) => (index + 1).min(recent.len() - 1),
+            (Some(index), false) => index.saturating_sub(1),
+        };
+        self.recent_index = Some(index);
+        self.open_input = Some(recent[index].display().to_string());
+    }
+
+    /// Writes the flows matching the current filter next to the capture as
+    /// `<name>-filtered.pcapng`.
+    fn export_filtered(&mut self, capture: &Path) {
+        let stem = capture
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| "capture".into());
+        let destination = capture.with_file_name(format!("{stem}-filtered.pcapng"));
+        let flows = match self.packet_table.matching_flows(&self.filter) {
+            Ok(flows) => flows,
+            Err(error) => {
+                self.status = Some(format!("Cannot export: {error}"));
+                return;
+            }
+        };
+        if flows.is_empty() {
+            self.status = Some("Nothing to export: no flows match the filter".into());
+            return;
+        }
+
+        self.status = Some(
+            match export_flows(
+                &destination,
+                flows,
+                &self.interfaces,
+                &self.name_resolutions,
+            ) {
+                Ok(summary) => format!(
+                    "Exported {} packets to {}",
+                    summary.packets,
+                    destination.display()
+                ),
+                Err(error) => {
+                    warn!(error = ?error, "Export failed");
+                    format!("Export failed: {error:#}")
+                }
+            },
+        );
+    }
 }
 
-pub fn run_tui(path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
-    info!(path = ?path, "Starting TUI application");
+/// Runs the terminal interface on `paths`, merged into one timeline when there are
+/// several, and followed as it grows when `follow` is set and there is one.
+pub fn run_tui(
+    paths: Vec<PathBuf>,
+    registry: Arc<DissectorRegistry>,
+    follow: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    info!(paths = ?paths, "Starting TUI application");
     enable_raw_mode()?;
     let mut stdout = stdout();
     execute!(stdout, terminal::EnterAlternateScreen)?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let mut loader = FlowLoadController::new(path);
+    let mut loader = if follow {
+        FlowLoadController::following(paths.clone(), registry)
+    } else {
+        FlowLoadController::with_registry(paths.clone(), registry)
+    };
+    let mut recent = RecentFiles::load_default();
+    for path in &paths {
+        recent.add(path);
+    }
     let mut loading_progress = Some(0.0);
     let mut error_message: Option<String> = None;
 
@@ -61,16 +159,34 @@ pub fn run_tui(path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
     loop {
         // Check loader
         match loader.poll() {
-            FlowLoadStatus::Loading { progress } => {
+            FlowLoadStatus::Loading { progress, batch } => {
                 loading_progress = Some(progress);
                 debug!(progress, "TUI loader progress");
+                if let Some(batch) = batch {
+                    app.packet_table
+                        .merge_flows(batch.flows, batch.start_timestamp);
+                    app.name_resolutions = batch.name_resolutions;
+                    app.interfaces = batch.interfaces;
+                    app.packet_table.set_sources(batch.sources);
+                    if app.table_state.selected().is_none() {
+                        app.table_state.select(Some(0));
+                    }
+                }
             }
             FlowLoadStatus::Ready {
                 flows,
                 start_timestamp,
-                ..
+                name_resolutions,
+                interfaces,
+                sources,
             } => {
-                app = AppState::new(flows, start_timestamp);
+                app.packet_table.set_flows(flows, start_timestamp);
+                app.packet_table.set_sources(sources);
+                app.name_resolutions = name_resolutions;
+                app.interfaces = interfaces;
+                if app.table_state.selected().is_none() && !app.packet_table.is_empty() {
+                    app.table_state.select(Some(0));
+                }
                 loading_progress = None;
                 info!("TUI loader ready");
             }
@@ -82,8 +198,14 @@ pub fn run_tui(path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
             FlowLoadStatus::Idle => {}
         }
 
+        // Flows are listed as soon as the first ones are parsed.
+        let waiting_for_flows = loading_progress.is_some() && app.packet_table.is_empty();
+        // Streams and followed files have no known size, so they show no percentage.
+        let live = loader.is_live();
+        let following = loader.is_following();
+
         terminal.draw(|f| {
-            if let Some(progress) = loading_progress {
+            if let Some(progress) = loading_progress.filter(|_| waiting_for_flows) {
                 let area = f.area();
                 let gauge_area = Layout::default()
                     .direction(Direction::Vertical)
@@ -94,11 +216,23 @@ pub fn run_tui(path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
                     ])
                     .split(area)[1];
 
+                if live {
+                    let waiting = Paragraph::new(format!(
+                        "Waiting for packets on {}…",
+                        display_paths(loader.paths())
+                    ))
+                    .block(Block::default().borders(Borders::ALL).title("Live capture (o: Open)"));
+                    f.render_widget(waiting, gauge_area);
+                    draw_open_prompt(f, &app, recent.paths());
+                    return;
+                }
+
                 let gauge = Gauge::default()
-                    .block(Block::default().borders(Borders::ALL).title("Loading PCAP"))
+                    .block(Block::default().borders(Borders::ALL).title("Loading PCAP (x: Cancel | o: Open)"))
                     .gauge_style(Style::default().fg(to_color(flexoki::BLUE_400)))
                     .percent((progress * 100.0) as u16);
                 f.render_widget(gauge, gauge_area);
+                draw_open_prompt(f, &app, recent.paths());
                 return;
             }
 
@@ -107,6 +241,7 @@ pub fn run_tui(path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
                     .block(Block::default().borders(Borders::ALL).title("Error"))
                     .style(Style::default().fg(to_color(flexoki::RED_400)));
                 f.render_widget(p, f.area());
+                draw_open_prompt(f, &app, recent.paths());
                 return;
             }
 
@@ -120,21 +255,6 @@ pub fn run_tui(path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
                 ])
                 .split(f.area());
 
-            // Filter box
-            let filter_title = if app.filter_mode {
-                "Filter (ESC to exit)"
-            } else {
-                "Filter"
-            };
-            let filter_display = if app.filter.is_empty() && !app.filter_mode {
-                "Type / to start filtering...".to_string()
-            } else {
-                app.filter.clone()
-            };
-            let filter_widget = Paragraph::new(filter_display)
-                .block(Block::default().borders(Borders::ALL).title(filter_title));
-            f.render_widget(filter_widget, chunks[0]);
-
             // Table - get filtered data
             let (rows, widths) = {
                 let (r, w) = app.packet_table.get_filtered_table_data(&app.filter);
@@ -149,6 +269,8 @@ pub fn run_tui(path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
                 "Protocol",
                 "Packets",
                 "Bytes",
+                "Source",
+                "Issues",
             ]
             .iter()
             .map(|h| Cell::from(*h).style(Style::default().add_modifier(Modifier::BOLD)));
@@ -160,17 +282,52 @@ pub fn run_tui(path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
                 .header(header)
                 .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED))
                 .highlight_symbol(">> ")
-                .block(Block::default().borders(Borders::ALL).title("Flows"));
+                .block(Block::default().borders(Borders::ALL).title(match loading_progress {
+                    Some(_) if following => "Flows (following)".to_string(),
+                    Some(_) if live => "Flows (live)".to_string(),
+                    Some(progress) => format!("Flows (loading {:.0}%)", progress * 100.0),
+                    None => "Flows".to_string(),
+                }));
             f.render_stateful_widget(table, chunks[1], &mut app.table_state);
 
+            // Filter box, drawn after the table so it can show the compile error
+            let filter_error = app.packet_table.filter_error().map(ToString::to_string);
+            let filter_title = match (&filter_error, app.filter_mode) {
+                (Some(error), _) => format!("Filter: {error}"),
+                (None, true) => "Filter (ESC to exit)".to_string(),
+                (None, false) => "Filter".to_string(),
+            };
+            let filter_display = if app.filter.is_empty() && !app.filter_mode {
+                "Type / to start filtering...".to_string()
+            } else {
+                app.filter.clone()
+            };
+            let filter_style = if filter_error.is_some() {
+                Style::default().fg(to_color(flexoki::RED_400))
+            } else {
+                Style::default()
+            };
+            let filter_widget = Paragraph::new(filter_display).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(filter_title)
+                    .border_style(filter_style),
+            );
+            f.render_widget(filter_widget, chunks[0]);
+
             // Footer with instructions
             let instructions = if app.filter_mode {
                 Paragraph::new("Type to filter | ESC: Exit filter | Enter: Apply filter")
+            } else if let Some(status) = &app.status {
+                Paragraph::new(status.clone())
             } else {
-                Paragraph::new("↑/↓: Navigate | Enter/Space: Expand/Collapse | /: Filter | q: Quit")
+                Paragraph::new(
+                    "↑/↓: Navigate | Enter/Space: Expand/Collapse | /: Filter | i: Sort by issues | w: Export filtered | o: Open | r: Reload | x: Cancel load | q: Quit",
+                )
             }
             .block(Block::default().borders(Borders::ALL).title("Controls"));
             f.render_widget(instructions, chunks[2]);
+            draw_open_prompt(f, &app, recent.paths());
         })?;
 
         let timeout = tick_rate
@@ -181,7 +338,76 @@ pub fn run_tui(path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
             let Event::Key(key) = event::read()? &&
             key.kind == KeyEventKind::Press 
         {
-            if loading_progress.is_some() || error_message.is_some() {
+            if app.open_input.is_some() {
+                match key.code {
+                    KeyCode::Esc => {
+                        app.open_input = None;
+                        app.recent_index = None;
+                        debug!("Closed open prompt");
+                    }
+                    KeyCode::Enter => {
+                        let input = app.open_input.take().unwrap_or_default();
+                        app.recent_index = None;
+                        let target = PathBuf::from(input.trim());
+                        if !target.as_os_str().is_empty() {
+                            // A pattern such as `ring-*.pcapng` opens every match at once.
+                            match expand_paths(&[target]) {
+                                Ok(targets) => {
+                                    for target in &targets {
+                                        recent.add(target);
+                                    }
+                                    loader.open(targets);
+                                    app.clear_flows();
+                                    loading_progress = Some(0.0);
+                                    error_message = None;
+                                }
+                                Err(error) => app.status = Some(format!("{error:#}")),
+                            }
+                        }
+                    }
+                    KeyCode::Up => app.browse_recent(recent.paths(), false),
+                    KeyCode::Down => app.browse_recent(recent.paths(), true),
+                    KeyCode::Backspace => {
+                        if let Some(input) = &mut app.open_input {
+                            input.pop();
+                        }
+                    }
+                    KeyCode::Char(c) => {
+                        if let Some(input) = &mut app.open_input {
+                            input.push(c);
+                        }
+                    }
+                    _ => {}
+                }
+            } else if !app.filter_mode && matches!(key.code, KeyCode::Char('o' | 'r' | 'x')) {
+                // Switching files works while loading and after errors too
+                match key.code {
+                    KeyCode::Char('o') => {
+                        app.open_input = Some(String::new());
+                        debug!("Opened open prompt");
+                    }
+                    KeyCode::Char('r') if !loader.can_reload() => {
+                        app.status = Some("Standard input can't be reloaded".into());
+                    }
+                    KeyCode::Char('r') => {
+                        loader.reload();
+                        app.clear_flows();
+                        loading_progress = Some(0.0);
+                        error_message = None;
+                    }
+                    _ if loader.is_following() => {
+                        loader.stop_following();
+                        app.status = Some("Stopped following".into());
+                    }
+                    _ => {
+                        if loader.is_loading() {
+                            loader.cancel();
+                            loading_progress = None;
+                            app.status = Some("Loading cancelled".into());
+                        }
+                    }
+                }
+            } else if waiting_for_flows || error_message.is_some() {
                 if key.code == KeyCode::Char('q') || key.code == KeyCode::Esc {
                     info!("TUI quit requested while loading/error state");
                     break;
@@ -209,6 +435,7 @@ pub fn run_tui(path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
                 }
             } else {
                 // Handle normal navigation mode
+                app.status = None;
                 match key.code {
                     KeyCode::Char('q') | KeyCode::Esc => {
                         info!("TUI quit requested");
@@ -230,6 +457,20 @@ pub fn run_tui(path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
                         app.packet_table.toggle_selected_flow(&app.table_state);
                         debug!("Toggled flow details");
                     }
+                    KeyCode::Char('w') => {
+                        if let Some(capture) = loader.paths().first() {
+                            app.export_filtered(capture);
+                        }
+                        debug!(status = ?app.status, "Exported filtered flows");
+                    }
+                    KeyCode::Char('i') => {
+                        app.packet_table.toggle_issue_sort();
+                        app.table_state.select(Some(0));
+                        debug!(
+                            by_issues = app.packet_table.sorted_by_issues(),
+                            "Toggled flow sort order"
+                        );
+                    }
                     _ => {}
                 }
             }
@@ -245,3 +486,39 @@ pub fn run_tui(path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
     info!("TUI application exited");
     Ok(())
 }
+
+/// The open prompt, centred over the rest of the screen, listing the recent files.
+fn draw_open_prompt(f: &mut Frame, app: &AppState, recent: &[PathBuf]) {
+    let Some(input) = &app.open_input else {
+        return;
+    };
+    let mut lines = vec![Line::from(format!("{input}▏"))];
+    if !recent.is_empty() {
+        lines.push(Line::from("Recent (↑/↓):").bold());
+    }
+    for (index, path) in recent.iter().enumerate() {
+        let line = Line::from(format!("  {}", path.display()));
+        lines.push(if app.recent_index == Some(index) {
+            line.reversed()
+        } else {
+            line
+        });
+    }
+
+    let area = f.area();
+    let width = area.width.saturating_sub(4).min(100);
+    let height = (lines.len() as u16 + 2).min(area.height);
+    let prompt_area = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+    let prompt = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Open capture (Enter: Open | ESC: Cancel)"),
+    );
+    f.render_widget(Clear, prompt_area);
+    f.render_widget(prompt, prompt_area);
+}
@@ -19,7 +19,11 @@ pub fn to_color(hex: u32) -> ratatui::style::Color {
 }
 
 #[cfg(not(feature = "tui"))]
-pub fn run_tui(_path: std::path::PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+pub fn run_tui(
+    _paths: Vec<std::path::PathBuf>,
+    _registry: std::sync::Arc<crate::layers::dissector::DissectorRegistry>,
+    _follow: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
     println!("TUI feature is disabled. Rebuild with --features tui to enable the Ratatui TUI.");
     Ok(())
 }
@@ -5,8 +5,9 @@ use ratatui::{
 };
 use std::collections::{HashMap, HashSet};
 
-use crate::flow::filter::{FlowFilter, FlowFormatter};
+use crate::flow::filter::{FilterError, FlowFilter, FlowFormatter};
 use crate::flow::{Flow, FlowKey};
+use crate::parser::pipeline::merge_flows;
 use crate::tui::theme::flexoki;
 use crate::tui::to_color;
 
@@ -16,31 +17,108 @@ pub struct PacketTableState {
     flows: HashMap<FlowKey, Flow>,
     row_to_flow_map: Vec<Option<FlowKey>>, // Maps table row index to flow key
     start_timestamp: Option<f64>,
+    /// Names of the capture files, which `Packet::source` indexes.
+    sources: Vec<String>,
+    sort_by_issues: bool,
+    filter_error: Option<FilterError>,
 }
 
 impl PacketTableState {
     pub fn new(flows: HashMap<FlowKey, Flow>, start_timestamp: Option<f64>) -> Self {
-        let mut flow_order: Vec<FlowKey> = flows.keys().copied().collect();
+        let flow_order: Vec<FlowKey> = flows.keys().copied().collect();
 
-        // Sort by timestamp (oldest first)
-        flow_order.sort_unstable_by(|a, b| {
+        let mut state = Self {
+            expanded_flows: HashSet::new(),
+            flow_order,
+            flows,
+            row_to_flow_map: Vec::new(),
+            start_timestamp,
+            sources: Vec::new(),
+            sort_by_issues: false,
+            filter_error: None,
+        };
+        state.sort_flow_order();
+        state
+    }
+
+    /// Adds flows published while the capture is loading, extending those already shown.
+    pub fn merge_flows(&mut self, updates: HashMap<FlowKey, Flow>, start_timestamp: Option<f64>) {
+        for key in updates.keys() {
+            if !self.flows.contains_key(key) {
+                self.flow_order.push(*key);
+            }
+        }
+        merge_flows(&mut self.flows, updates);
+        self.start_timestamp = start_timestamp;
+        self.sort_flow_order();
+    }
+
+    /// Replaces the flows, keeping expanded rows and the sort order.
+    pub fn set_flows(&mut self, flows: HashMap<FlowKey, Flow>, start_timestamp: Option<f64>) {
+        self.flow_order = flows.keys().copied().collect();
+        self.expanded_flows.retain(|key| flows.contains_key(key));
+        self.flows = flows;
+        self.start_timestamp = start_timestamp;
+        self.sort_flow_order();
+    }
+
+    pub fn set_sources(&mut self, sources: Vec<String>) {
+        self.sources = sources;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.flows.is_empty()
+    }
+
+    /// Switches between oldest-first ordering and most TCP issues first.
+    pub fn toggle_issue_sort(&mut self) {
+        self.sort_by_issues = !self.sort_by_issues;
+        self.sort_flow_order();
+    }
+
+    pub fn sorted_by_issues(&self) -> bool {
+        self.sort_by_issues
+    }
+
+    /// Flows matching `filter`, in display order.
+    pub fn matching_flows(&self, filter: &str) -> Result<Vec<&Flow>, FilterError> {
+        let flow_filter =
+            FlowFilter::new(filter, self.start_timestamp, false, None).with_sources(&self.sources);
+        if let Some(error) = flow_filter.error() {
+            return Err(error.clone());
+        }
+        Ok(self
+            .flow_order
+            .iter()
+            .filter_map(|key| self.flows.get(key))
+            .filter(|flow| flow_filter.matches_flow(flow))
+            .collect())
+    }
+
+    /// Compile error of the filter last passed to `get_filtered_table_data`.
+    pub fn filter_error(&self) -> Option<&FilterError> {
+        self.filter_error.as_ref()
+    }
+
+    fn sort_flow_order(&mut self) {
+        let flows = &self.flows;
+        let sort_by_issues = self.sort_by_issues;
+        self.flow_order.sort_unstable_by(|a, b| {
             let flow_a = flows.get(a);
             let flow_b = flows.get(b);
             match (flow_a, flow_b) {
+                (Some(fa), Some(fb)) if sort_by_issues => fb
+                    .analysis
+                    .issues()
+                    .cmp(&fa.analysis.issues())
+                    .then(fa.timestamp.total_cmp(&fb.timestamp)),
+                // Sort by timestamp (oldest first)
                 (Some(fa), Some(fb)) => fa.timestamp.total_cmp(&fb.timestamp),
                 (Some(_), None) => std::cmp::Ordering::Less,
                 (None, Some(_)) => std::cmp::Ordering::Greater,
                 (None, None) => std::cmp::Ordering::Equal,
             }
         });
-
-        Self {
-            expanded_flows: HashSet::new(),
-            flow_order,
-            flows,
-            row_to_flow_map: Vec::new(),
-            start_timestamp,
-        }
     }
 
     pub fn get_selected_flow_key(
@@ -64,6 +142,9 @@ impl PacketTableState {
     }
 
     pub fn next_flow(&mut self, table_state: &mut ratatui::widgets::TableState) {
+        if self.flow_order.is_empty() {
+            return;
+        }
         let i = match table_state.selected() {
             Some(i) => {
                 if i >= self.flow_order.len() - 1 {
@@ -78,6 +159,9 @@ impl PacketTableState {
     }
 
     pub fn previous_flow(&mut self, table_state: &mut ratatui::widgets::TableState) {
+        if self.flow_order.is_empty() {
+            return;
+        }
         let i = match table_state.selected() {
             Some(i) => {
                 if i == 0 {
@@ -94,8 +178,10 @@ impl PacketTableState {
     pub fn get_filtered_table_data(&'_ mut self, filter: &str) -> (Vec<Row<'_>>, Vec<Constraint>) {
         let mut rows = Vec::new();
         let mut row_to_flow_map = Vec::new();
-        let flow_filter = FlowFilter::new(filter, self.start_timestamp, false, None);
+        let flow_filter =
+            FlowFilter::new(filter, self.start_timestamp, false, None).with_sources(&self.sources);
         let timestamp_origin = flow_filter.timestamp_origin();
+        self.filter_error = flow_filter.error().cloned();
 
         for flow_key in self.flow_order.clone() {
             if let Some(flow) = self.flows.get(&flow_key) {
@@ -106,9 +192,15 @@ impl PacketTableState {
                 let timestamp_str = FlowFormatter::timestamp(flow.timestamp, timestamp_origin);
                 let endpoint_a_ip = FlowFormatter::ip_address(&flow.source.ip, false, None);
                 let endpoint_b_ip = FlowFormatter::ip_address(&flow.destination.ip, false, None);
-                let endpoint_a_port = FlowFormatter::port(flow.source.port);
-                let endpoint_b_port = FlowFormatter::port(flow.destination.port);
-                let protocol_str = FlowFormatter::protocol(&flow.protocol);
+                let (endpoint_a_port, endpoint_b_port) = if flow.protocol.has_ports() {
+                    (
+                        FlowFormatter::port(flow.source.port),
+                        FlowFormatter::port(flow.destination.port),
+                    )
+                } else {
+                    Default::default()
+                };
+                let protocol_str = FlowFormatter::flow_protocol(flow);
                 let total_bytes = flow.total_bytes();
 
                 let main_row = Row::new(vec![
@@ -120,13 +212,20 @@ impl PacketTableState {
                     Cell::from(protocol_str),
                     Cell::from(flow.packets.len().to_string()),
                     Cell::from(total_bytes.to_string()),
+                    Cell::from(flow_sources(flow, &self.sources)),
+                    Cell::from(flow.analysis.issues().to_string()),
                 ]);
 
                 rows.push(main_row);
                 row_to_flow_map.push(Some(flow_key));
 
                 if self.expanded_flows.contains(&flow_key) {
-                    for packet in &flow.packets {
+                    // Expanded flows only list the packets the filter selects.
+                    for packet in flow
+                        .packets
+                        .iter()
+                        .filter(|packet| flow_filter.matches_packet(flow, packet))
+                    {
                         let packet_row = Row::new(vec![
                             Cell::from(format!(
                                 "  {}",
@@ -143,6 +242,10 @@ impl PacketTableState {
                             Cell::from(""),
                             Cell::from(""),
                             Cell::from(packet.length.to_string()),
+                            Cell::from(
+                                self.sources.get(packet.source).cloned().unwrap_or_default(),
+                            ),
+                            Cell::from(packet.tags.join(", ")),
                         ])
                         .style(Style::default().fg(to_color(flexoki::BASE_500)));
                         rows.push(packet_row);
@@ -162,7 +265,21 @@ impl PacketTableState {
             Constraint::Length(8),  // Protocol
             Constraint::Length(8),  // Packets
             Constraint::Length(10), // Bytes
+            Constraint::Length(20), // Source
+            Constraint::Min(8),     // Issues / packet tags
         ];
         (rows, widths)
     }
 }
+
+/// The capture files the packets of `flow` were read from, in the order given.
+fn flow_sources(flow: &Flow, sources: &[String]) -> String {
+    let mut indices: Vec<usize> = flow.packets.iter().map(|packet| packet.source).collect();
+    indices.sort_unstable();
+    indices.dedup();
+    indices
+        .into_iter()
+        .filter_map(|index| sources.get(index).map(String::as_str))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -267,6 +267,7 @@ fn generate_mock_flows() -> HashMap<FlowKey, Flow> {
         source: ep1,
         destination: ep2,
         packets: generate_mock_packets(50),
+        ..Default::default()
     };
 
     let key1 = FlowKey::from_endpoints(ep1, ep2, Protocol::TCP);
@@ -287,6 +288,7 @@ fn generate_mock_flows() -> HashMap<FlowKey, Flow> {
         source: ep3,
         destination: ep4,
         packets: generate_mock_packets(10),
+        ..Default::default()
     };
 
     let key2 = FlowKey::from_endpoints(ep3, ep4, Protocol::UDP);
@@ -307,6 +309,7 @@ fn generate_mock_flows() -> HashMap<FlowKey, Flow> {
         source: ep5,
         destination: ep6,
         packets: generate_mock_packets(15),
+        ..Default::default()
     };
 
     let key3 = FlowKey::from_endpoints(ep5, ep6, Protocol::Other(1));
@@ -324,8 +327,9 @@ fn generate_mock_packets(count: usize) -> Vec<Packet> {
             src_port: Some(443),
             dst_port: Some(12345),
             length: 64 + (i % 1000) as u16,
-            data: (0..64).map(|b| (b % 255) as u8).collect(),
+            data: (0..64).map(|b| (b % 255) as u8).collect::<Vec<u8>>().into(),
             tags: vec!["SYN-ACK".to_string(), "TLS".to_string()],
+            ..Default::default()
         })
         .collect()
 }